@@ -0,0 +1,40 @@
+//! Encoding microphone audio for input_audio_buffer.append, at the chunk sizes devices deliver
+//!
+//! `cargo bench --bench audio_encode` compares the encoder against the one it replaced, which
+//! built a Vec per sample and a pcm16 buffer per chunk.
+
+use base64::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+use hotline::audio_utils::{base64_encode_audio, SERVER_SAMPLE_RATE};
+
+/// The encoder as it was, for comparison
+fn allocating_encode(samples: &[f32]) -> String {
+    let audio_data: Vec<u8> = samples
+        .iter()
+        .map(|sample| (sample * i16::MAX as f32) as i16)
+        .flat_map(|sample| sample.to_le_bytes().to_vec())
+        .collect();
+
+    BASE64_STANDARD.encode(&audio_data)
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("base64_encode_audio");
+    // A small-buffer device's callback, a typical one, and a long append
+    for ms in [5, 20, 100] {
+        let samples: Vec<f32> = (0..SERVER_SAMPLE_RATE as usize * ms / 1000).map(|n| (n as f32 * 0.01).sin() * 0.5).collect();
+        group.throughput(Throughput::Elements(samples.len() as u64));
+        group.bench_with_input(BenchmarkId::new("streaming", format!("{} ms", ms)), &samples, |b, samples| {
+            b.iter(|| base64_encode_audio(black_box(samples)))
+        });
+        group.bench_with_input(BenchmarkId::new("allocating", format!("{} ms", ms)), &samples, |b, samples| {
+            b.iter(|| allocating_encode(black_box(samples)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+
+// Samples kept in memory, a minute of audio at the server rate; the rest waits on disk
+const MEMORY_SAMPLES: usize = SERVER_SAMPLE_RATE as usize * 60;
+// Samples read back from disk at a time, a second of audio
+const READ_BACK_SAMPLES: usize = SERVER_SAMPLE_RATE as usize;
+
+const SAMPLE_BYTES: u64 = std::mem::size_of::<f32>() as u64;
+
+/// Audio waiting for room in a ring buffer, e.g. while playback is paused or a long answer streams in faster than real time
+///
+/// The first minute is kept in memory. Beyond that samples are appended to a temporary file and
+/// read back in order as the memory drains, so however long the wait nothing is dropped and
+/// memory stays bounded. The file is emptied once it's read back, and removed with the backlog.
+#[derive(Default)]
+pub struct AudioBacklog {
+    memory: VecDeque<f32>,
+    spill: Option<Spill>,
+}
+
+/// The temporary file holding the samples that didn't fit in memory, as little-endian f32
+struct Spill {
+    file: File,
+    path: PathBuf,
+    read: u64,      // Bytes read back so far
+    written: u64,   // Bytes written so far
+}
+
+impl AudioBacklog {
+    /// Adds samples after those already waiting
+    pub fn extend(&mut self, samples: &[f32]) {
+        // Nothing goes to memory while older samples wait on disk, they'd be played out of order
+        let room = match self.spilled() {
+            0 => MEMORY_SAMPLES.saturating_sub(self.memory.len()),
+            _ => 0,
+        };
+        let (kept, rest) = samples.split_at(room.min(samples.len()));
+        self.memory.extend(kept);
+        if rest.is_empty() {
+            return;
+        }
+
+        // A full disk is no reason to lose the audio, it's kept in memory after all
+        if let Err(e) = self.write_spill(rest) {
+            tracing::warn!(error = %e, samples = rest.len(), "failed to spill playback audio to disk");
+            match self.read_spill(usize::MAX) {
+                Ok(()) => self.memory.extend(rest),
+                Err(e) => eprintln!("\n[playback audio lost, the spill file can't be read: {}]", e),
+            }
+        }
+    }
+
+    /// Takes the next sample to play
+    pub fn pop(&mut self) -> Option<f32> {
+        if self.memory.is_empty() && self.spilled() > 0 {
+            if let Err(e) = self.read_spill(READ_BACK_SAMPLES) {
+                eprintln!("\n[playback audio lost, the spill file can't be read: {}]", e);
+                self.clear();
+            }
+        }
+        self.memory.pop_front()
+    }
+
+    /// Drops everything waiting, e.g. when playback is stopped
+    pub fn clear(&mut self) {
+        self.memory.clear();
+        if let Some(spill) = &mut self.spill {
+            spill.empty();
+        }
+    }
+
+    /// Samples waiting on disk
+    fn spilled(&self) -> usize {
+        self.spill.as_ref().map_or(0, |spill| ((spill.written - spill.read) / SAMPLE_BYTES) as usize)
+    }
+
+    fn write_spill(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        if self.spill.is_none() {
+            self.spill = Some(Spill::create()?);
+            tracing::info!(buffered_secs = MEMORY_SAMPLES / SERVER_SAMPLE_RATE as usize, "playback backlog spilling to disk");
+        }
+        let spill = self.spill.as_mut().unwrap();
+
+        let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        spill.file.seek(SeekFrom::Start(spill.written))?;
+        spill.file.write_all(&bytes)?;
+        spill.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Moves up to `max` samples from disk into memory
+    fn read_spill(&mut self, max: usize) -> std::io::Result<()> {
+        let count = self.spilled().min(max);
+        let Some(spill) = self.spill.as_mut().filter(|_| count > 0) else {
+            return Ok(());
+        };
+
+        let mut bytes = vec![0; count * SAMPLE_BYTES as usize];
+        spill.file.seek(SeekFrom::Start(spill.read))?;
+        spill.file.read_exact(&mut bytes)?;
+        spill.read += bytes.len() as u64;
+        self.memory.extend(bytes.chunks_exact(SAMPLE_BYTES as usize).map(|sample| f32::from_le_bytes(sample.try_into().unwrap())));
+
+        if spill.read == spill.written {
+            spill.empty();
+        }
+        Ok(())
+    }
+}
+
+impl Spill {
+    fn create() -> std::io::Result<Self> {
+        // Not a name another user could guess and create first
+        let path = std::env::temp_dir().join(format!("hotline-playback-{}.f32", uuid::Uuid::new_v4()));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(Self { file, path, read: 0, written: 0 })
+    }
+
+    /// Gives the disk space back, the file is reused for the next spill
+    fn empty(&mut self) {
+        if let Err(e) = self.file.set_len(0) {
+            tracing::debug!(error = %e, "failed to truncate the playback spill file");
+        }
+        self.read = 0;
+        self.written = 0;
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counting up, so any sample out of order or lost shows
+    fn ramp(start: usize, count: usize) -> Vec<f32> {
+        (start..start + count).map(|n| n as f32).collect()
+    }
+
+    fn pop_all(backlog: &mut AudioBacklog) -> Vec<f32> {
+        std::iter::from_fn(|| backlog.pop()).collect()
+    }
+
+    #[test]
+    fn spills_past_memory_and_plays_back_in_order() {
+        let mut backlog = AudioBacklog::default();
+        backlog.extend(&ramp(0, MEMORY_SAMPLES - 10));
+        assert!(backlog.spill.is_none());
+        backlog.extend(&ramp(MEMORY_SAMPLES - 10, 3 * READ_BACK_SAMPLES));
+        assert_eq!(backlog.memory.len(), MEMORY_SAMPLES);
+        assert_eq!(backlog.spilled(), 3 * READ_BACK_SAMPLES - 10);
+
+        let played = pop_all(&mut backlog);
+        assert_eq!(played, ramp(0, MEMORY_SAMPLES - 10 + 3 * READ_BACK_SAMPLES));
+        assert_eq!(backlog.spilled(), 0);
+    }
+
+    #[test]
+    fn interleaved_pops_and_extends_keep_the_order() {
+        let mut backlog = AudioBacklog::default();
+        let mut next = 0;
+        let mut played = Vec::new();
+        backlog.extend(&ramp(next, MEMORY_SAMPLES + 100));
+        next += MEMORY_SAMPLES + 100;
+
+        // Room frees up in memory while older samples still wait on disk, new ones go after them
+        for _ in 0..5 {
+            played.extend((0..READ_BACK_SAMPLES / 2).map_while(|_| backlog.pop()));
+            backlog.extend(&ramp(next, 1000));
+            next += 1000;
+        }
+        played.extend(pop_all(&mut backlog));
+        assert_eq!(played, ramp(0, next));
+
+        // The spill file is reused once drained
+        backlog.extend(&ramp(0, MEMORY_SAMPLES + 5));
+        assert_eq!(backlog.spilled(), 5);
+        assert_eq!(pop_all(&mut backlog), ramp(0, MEMORY_SAMPLES + 5));
+    }
+
+    #[test]
+    fn clear_drops_memory_and_spill_alike() {
+        let mut backlog = AudioBacklog::default();
+        backlog.extend(&ramp(0, MEMORY_SAMPLES + READ_BACK_SAMPLES));
+        let path = backlog.spill.as_ref().unwrap().path.clone();
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("hotline-playback-"));
+
+        backlog.clear();
+        assert_eq!(backlog.pop(), None);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        // Still usable afterwards, and the file goes with the backlog
+        backlog.extend(&[1.0, 2.0]);
+        assert_eq!(pop_all(&mut backlog), [1.0, 2.0]);
+        drop(backlog);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,149 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audio_utils::{
+    base64_decode_audio, base64_encode_audio, convert_audio_to_server, initialize_audio_stream,
+    initialize_input_stream, PlaybackCommand, SERVER_SAMPLE_RATE,
+};
+
+// Below this the microphone is most likely muted or the wrong device
+const SILENCE_THRESHOLD_DBFS: f32 = -60.0;
+
+/// Loopback test: records from the microphone, plays it back and reports what was found
+///
+/// Goes through the same conversion path used during a call (downmix, resample to the server
+/// format, pcm16 base64 and back) so device and driver problems show up before spending API time.
+pub fn test_audio(seconds: f32) -> Result<(), Box<dyn std::error::Error>> {
+    print_devices()?;
+
+    let (sample_receiver, _input) = initialize_input_stream();
+
+    println!("\nRecording for {:.1} seconds, say something...", seconds);
+    let mut recorded = Vec::new();
+    let (mut input_sample_rate, mut channels) = (SERVER_SAMPLE_RATE, 1);
+    let deadline = Instant::now() + Duration::from_secs_f32(seconds);
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match sample_receiver.recv_timeout(remaining) {
+            Ok(chunk) => {
+                // Start over if the device changed mid-recording, the statistics need a single format
+                if (chunk.sample_rate, chunk.channels) != (input_sample_rate, channels) {
+                    recorded.clear();
+                    (input_sample_rate, channels) = (chunk.sample_rate, chunk.channels);
+                }
+                recorded.extend(chunk.samples);
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => break,
+            Err(e) => return Err(format!("Input stream stopped: {}", e).into()),
+        }
+    }
+
+    // Level statistics on the raw recording
+    let frames = recorded.len() / channels.max(1) as usize;
+    let actual_rate = frames as f32 / seconds;
+    let peak = recorded.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let rms = (recorded.iter().map(|sample| sample * sample).sum::<f32>() / recorded.len().max(1) as f32).sqrt();
+    let clipped = recorded.iter().filter(|sample| sample.abs() >= 0.999).count();
+
+    println!("\nInput levels");
+    println!("  frames recorded: {} (~{:.0} Hz, device reports {} Hz)", frames, actual_rate, input_sample_rate);
+    println!("  peak: {:.1} dBFS, rms: {:.1} dBFS, clipped samples: {}", to_dbfs(peak), to_dbfs(rms), clipped);
+    if to_dbfs(rms) < SILENCE_THRESHOLD_DBFS {
+        println!("  warning: the recording is silent, check the microphone and its volume");
+    }
+    if clipped > 0 {
+        println!("  warning: the input is clipping, lower the microphone gain");
+    }
+
+    // Run the recording through the same path as a call
+    let server_samples = convert_audio_to_server(&recorded, input_sample_rate, channels);
+    let round_trip = base64_decode_audio(&base64_encode_audio(&server_samples));
+    let expected_length = frames as f32 * SERVER_SAMPLE_RATE as f32 / input_sample_rate as f32;
+    let length_error = (server_samples.len() as f32 - expected_length).abs() / expected_length.max(1.0);
+    let max_error = server_samples
+        .iter()
+        .zip(&round_trip)
+        .fold(0.0f32, |max, (a, b)| max.max((a - b).abs()));
+
+    println!("\nServer format ({} Hz mono pcm16)", SERVER_SAMPLE_RATE);
+    println!("  samples: {} (expected ~{:.0})", server_samples.len(), expected_length);
+    println!("  pcm16 round trip max error: {:.6}", max_error);
+    if length_error > 0.01 || round_trip.len() != server_samples.len() || max_error > 2.0 / i16::MAX as f32 {
+        println!("  error: the conversion path is not producing the expected audio");
+    } else {
+        println!("  ok");
+    }
+
+    // Play back what the server would have received
+    let output = initialize_audio_stream()?;
+    println!("\nPlaying the recording back at {} Hz...", output.sample_rate);
+    output.sender.send(PlaybackCommand::Play(round_trip))?;
+    thread::sleep(Duration::from_secs_f32(seconds + 0.5));
+
+    println!("Done. If the playback sounded too fast, too slow or distorted, check the device sample rates above.");
+    Ok(())
+}
+
+/// Prints the default input and output devices with their configurations
+fn print_devices() -> Result<(), Box<dyn std::error::Error>> {
+    let host = cpal::default_host();
+    println!("Audio host: {}", host.id().name());
+
+    match host.default_input_device() {
+        Some(device) => {
+            let config = device.default_input_config()?;
+            println!(
+                "Input:  {} ({} Hz, {} channels, {})",
+                device.name()?, config.sample_rate().0, config.channels(), config.sample_format()
+            );
+        }
+        None => println!("Input:  no device available"),
+    }
+
+    match host.default_output_device() {
+        Some(device) => {
+            let config = device.default_output_config()?;
+            println!(
+                "Output: {} ({} Hz, {} channels, {})",
+                device.name()?, config.sample_rate().0, config.channels(), config.sample_format()
+            );
+        }
+        None => println!("Output: no device available"),
+    }
+
+    Ok(())
+}
+
+fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-6).log10()
+}
+
+/// Lists every input and output device of the audio host, the defaults marked
+pub fn list_devices() -> Result<(), Box<dyn std::error::Error>> {
+    let host = cpal::default_host();
+    println!("Audio host: {}", host.id().name());
+
+    let default_input = host.default_input_device().and_then(|device| device.name().ok());
+    println!("\nInputs");
+    for device in host.input_devices()? {
+        let name = device.name().unwrap_or_else(|_| "unknown device".to_string());
+        let marker = if Some(&name) == default_input.as_ref() { "*" } else { " " };
+        match device.default_input_config() {
+            Ok(config) => println!("{} {} ({} Hz, {} channels)", marker, name, config.sample_rate().0, config.channels()),
+            Err(e) => println!("{} {} (unusable: {})", marker, name, e),
+        }
+    }
+
+    let default_output = host.default_output_device().and_then(|device| device.name().ok());
+    println!("\nOutputs (names for --mirror-output and --virtual-mic)");
+    for device in host.output_devices()? {
+        let name = device.name().unwrap_or_else(|_| "unknown device".to_string());
+        let marker = if Some(&name) == default_output.as_ref() { "*" } else { " " };
+        match device.default_output_config() {
+            Ok(config) => println!("{} {} ({} Hz, {} channels)", marker, name, config.sample_rate().0, config.channels()),
+            Err(e) => println!("{} {} (unusable: {})", marker, name, e),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Silence inserted where a stream resumes after a gap, so the splice doesn't sound garbled
+pub const GAP_SILENCE_MS: u32 = 30;
+
+/// What to do with an incoming audio delta
+#[derive(Debug, PartialEq)]
+pub enum DeltaVerdict {
+    Play,
+    PlayAfterGap,           // Play, but insert GAP_SILENCE_MS of silence first
+    Drop(&'static str),     // Drop it, with the reason for the log
+}
+
+/// Orders audio deltas per (item_id, content_index) stream
+///
+/// Deltas carry no sequence numbers, but after a network hiccup the same event can show up
+/// twice, late (after the stream finished) or interleaved with another item's stream. These are
+/// detected here so playback isn't garbled.
+///
+/// Repeats are told by their event id, never by their audio: the same chunk twice is ordinary,
+/// e.g. in silence. What's kept is per response, bounded by the responses of the call.
+#[derive(Debug, Default)]
+pub struct AudioSequencer {
+    current: Option<(String, u64)>,             // Stream that last received a delta
+    interrupted: HashSet<(String, u64)>,        // Streams that another stream cut into before they finished
+    finished: HashSet<(String, u64)>,           // Streams that received response.audio.done or were truncated
+    finished_before: HashSet<(String, u64)>,    // Those of the previous response, whose deltas can still straggle in
+    response_id: Option<String>,                // Response being played
+    previous_response_id: Option<String>,       // And the one before, stragglers of which don't start a new one
+    seen_event_ids: HashSet<String>,            // Deltas of the current response
+    anomalies: usize,
+}
+
+impl AudioSequencer {
+    /// Checks a `response.audio.delta` event
+    pub fn check(&mut self, event: &Value) -> DeltaVerdict {
+        let stream = stream_key(event);
+        let verdict = self.classify(event, &stream);
+
+        match verdict {
+            DeltaVerdict::Drop(reason) => {
+                self.anomalies += 1;
+                eprintln!("\nAudio anomaly ({} so far): dropped {} delta for item {}", self.anomalies, reason, stream.0);
+            }
+            DeltaVerdict::PlayAfterGap => {
+                self.anomalies += 1;
+                eprintln!("\nAudio anomaly ({} so far): gap in item {}, inserting silence", self.anomalies, stream.0);
+            }
+            DeltaVerdict::Play => {}
+        }
+
+        if !matches!(verdict, DeltaVerdict::Drop(_)) {
+            // Parts of the same item are buffered apart by the player, switching between them isn't a splice
+            if let Some(previous) = self.current.replace(stream.clone()) {
+                if previous.0 != stream.0 && !self.finished.contains(&previous) {
+                    self.interrupted.insert(previous);
+                }
+            }
+        }
+
+        verdict
+    }
+
+    /// Marks a stream as complete, later deltas for it are dropped
+    pub fn finish(&mut self, item_id: &str, content_index: u64) {
+        self.finished.insert((item_id.to_string(), content_index));
+    }
+
+    /// Forgets per-response state once a response is done
+    pub fn reset_response(&mut self) {
+        self.seen_event_ids.clear();
+        self.interrupted.clear();
+        self.finished_before = std::mem::take(&mut self.finished);
+        self.previous_response_id = self.response_id.take();
+    }
+
+    fn classify(&mut self, event: &Value, stream: &(String, u64)) -> DeltaVerdict {
+        // A response.done can get lost with the connection, a new response starts afresh anyway
+        let response_id = event["response_id"].as_str();
+        if response_id.is_some() && response_id != self.response_id.as_deref() && response_id != self.previous_response_id.as_deref() {
+            if self.response_id.is_some() {
+                self.reset_response();
+            }
+            self.response_id = response_id.map(str::to_string);
+        }
+
+        if let Some(event_id) = event["event_id"].as_str() {
+            if !self.seen_event_ids.insert(event_id.to_string()) {
+                return DeltaVerdict::Drop("duplicate");
+            }
+        }
+
+        if self.finished.contains(stream) || self.finished_before.contains(stream) {
+            return DeltaVerdict::Drop("late");
+        }
+
+        if self.interrupted.remove(stream) {
+            return DeltaVerdict::PlayAfterGap;
+        }
+
+        DeltaVerdict::Play
+    }
+}
+
+fn stream_key(event: &Value) -> (String, u64) {
+    (
+        event["item_id"].as_str().unwrap_or_default().to_string(),
+        event["content_index"].as_u64().unwrap_or(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn delta(event_id: &str, response_id: &str, item_id: &str, audio: &str) -> Value {
+        json!({"type": "response.audio.delta", "event_id": event_id, "response_id": response_id, "item_id": item_id, "content_index": 0, "delta": audio})
+    }
+
+    #[test]
+    fn repeated_audio_is_played() {
+        let mut sequencer = AudioSequencer::default();
+        for event_id in ["a", "b", "c"] {
+            assert_eq!(sequencer.check(&delta(event_id, "resp_1", "item_1", "AAAA")), DeltaVerdict::Play);
+        }
+    }
+
+    #[test]
+    fn repeated_event_is_dropped() {
+        let mut sequencer = AudioSequencer::default();
+        assert_eq!(sequencer.check(&delta("a", "resp_1", "item_1", "AAAA")), DeltaVerdict::Play);
+        assert_eq!(sequencer.check(&delta("a", "resp_1", "item_1", "AAAA")), DeltaVerdict::Drop("duplicate"));
+    }
+
+    #[test]
+    fn event_ids_are_forgotten_per_response() {
+        let mut sequencer = AudioSequencer::default();
+        sequencer.check(&delta("a", "resp_1", "item_1", "AAAA"));
+        sequencer.reset_response();
+        assert!(sequencer.seen_event_ids.is_empty());
+
+        // Without a response.done, the next response does the same
+        sequencer.check(&delta("b", "resp_2", "item_2", "AAAA"));
+        sequencer.check(&delta("c", "resp_3", "item_3", "AAAA"));
+        assert_eq!(sequencer.seen_event_ids, HashSet::from(["c".to_string()]));
+    }
+
+    #[test]
+    fn late_deltas_are_dropped_into_the_next_response() {
+        let mut sequencer = AudioSequencer::default();
+        sequencer.check(&delta("a", "resp_1", "item_1", "AAAA"));
+        sequencer.finish("item_1", 0);
+        assert_eq!(sequencer.check(&delta("b", "resp_1", "item_1", "AAAA")), DeltaVerdict::Drop("late"));
+
+        sequencer.reset_response();
+        sequencer.check(&delta("c", "resp_2", "item_2", "AAAA"));
+        assert_eq!(sequencer.check(&delta("d", "resp_1", "item_1", "AAAA")), DeltaVerdict::Drop("late"));
+
+        // Two responses on, nothing is kept of the first
+        sequencer.finish("item_2", 0);
+        sequencer.reset_response();
+        sequencer.reset_response();
+        assert!(sequencer.finished.is_empty() && sequencer.finished_before.is_empty());
+    }
+
+    #[test]
+    fn resumed_stream_gets_a_gap() {
+        let mut sequencer = AudioSequencer::default();
+        sequencer.check(&delta("a", "resp_1", "item_1", "AAAA"));
+        sequencer.check(&delta("b", "resp_1", "item_2", "AAAA"));
+        assert_eq!(sequencer.check(&delta("c", "resp_1", "item_1", "AAAA")), DeltaVerdict::PlayAfterGap);
+        assert_eq!(sequencer.check(&delta("d", "resp_1", "item_1", "AAAA")), DeltaVerdict::Play);
+    }
+}
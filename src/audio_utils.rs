@@ -1,85 +1,797 @@
 use base64::prelude::*;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use ringbuf::{traits::{Consumer, Observer, Producer, Split}, HeapRb};
+use crate::audio_backlog::AudioBacklog;
+use crate::clock_drift::{DriftEstimator, StreamResampler};
+use crate::rtp::{RtpOutput, RtpSender};
+use ringbuf::{traits::{Consumer, Observer, Producer, Split}, HeapCons, HeapProd, HeapRb};
 
 pub const SERVER_SAMPLE_RATE: u32 = 24000; // The sample rate of the audio data coming from OpenAI
 const RING_BUFFER_CAPACITY: usize = 240_000; // 10 seconds of audio at 24,000 Hz
+const INPUT_MIX_BUFFER_CAPACITY: usize = 12_000; // Microphone audio mixed into mirrors, 0.5 seconds at 24,000 Hz
+const OUTPUT_STALL_TIMEOUT: Duration = Duration::from_secs(2); // A device asking for no audio this long is gone, even without an error
+const OUTPUT_RETRY_INTERVAL: Duration = Duration::from_secs(2); // Between looks for a device to fail over to
+pub const FADE_OUT: Duration = Duration::from_millis(20); // Audio cut off by Stop ramps down this long instead of clicking
 
-/// Initializes the audio stream and returns the audio sender and output sample rate.
+/// Commands accepted by the audio playback thread
+pub enum PlaybackCommand {
+    Play(Vec<f32>),         // Queue resampled samples for playback
+    Stop,                   // Drop everything that hasn't been played yet, after a short fade out
+    Pause,                  // Output silence, keeping queued samples for later
+    Resume,                 // Carry on where playback was paused
+    AddOutput { device: String, mix_input: bool },  // Mirror playback to the output device whose name contains this, optionally with the microphone mixed in
+    AddRtpOutput(RtpOutput),    // Also send playback as RTP
+    MixInput(Vec<f32>),     // Microphone samples at SERVER_SAMPLE_RATE for mirrors that mix them in
+    Sleep,                  // Close the output streams, queued samples are dropped
+    Wake,                   // Reopen them, also done by the next Play
+}
+
+/// Handle to the audio playback thread
+pub struct AudioOutput {
+    pub sender: mpsc::Sender<PlaybackCommand>,      // Playback commands, with audio at SERVER_SAMPLE_RATE
+    pub sample_rate: u32,                           // Sample rate of the default output device
+    pub played_samples: Arc<AtomicUsize>,           // Samples (at SERVER_SAMPLE_RATE) that actually reached the device
+    pub thread: Option<thread::JoinHandle<()>>,     // Playback thread, ends once the sender is dropped
+}
+
+/// Resamples a mono stream to a device one frame at a time, by linear interpolation
+#[derive(Default)]
+struct FrameResampler {
+    position: f64,      // Between `previous` and `current`, 0.0 to 1.0
+    previous: f32,
+    current: f32,
+}
+
+impl FrameResampler {
+    /// The next output sample, `step` input samples further along; None once the input runs dry
+    fn next(&mut self, step: f64, mut pop: impl FnMut() -> Option<f32>) -> Option<f32> {
+        self.position += step;
+        while self.position >= 1.0 {
+            let Some(sample) = pop() else {
+                self.position = 1.0 - step;     // Picks up from the next sample once there is one
+                return None;
+            };
+            self.previous = self.current;
+            self.current = sample;
+            self.position -= 1.0;
+        }
+        Some(self.previous + (self.current - self.previous) * self.position as f32)
+    }
+}
+
+/// Fills interleaved frames from a mono stream, the same sample on every channel of a frame
+///
+/// Every device, the primary one and mirrors alike, resamples the mono stream frame by frame like
+/// this, so a mirror with more or fewer channels plays the same audio at the same pace.
+fn fill_frames(data: &mut [f32], channels: usize, resampler: &mut FrameResampler, step: f64, mut pop: impl FnMut() -> Option<f32>) {
+    for frame in data.chunks_mut(channels.max(1)) {
+        frame.fill(resampler.next(step, &mut pop).unwrap_or(0.0));
+    }
+}
+
+/// Ramps interleaved audio down to silence, its last frame silent
+pub fn fade_out(samples: &mut [f32], channels: usize) {
+    let frames = samples.len() / channels.max(1);
+    for (index, frame) in samples.chunks_mut(channels.max(1)).enumerate() {
+        let gain = 1.0 - (index + 1) as f32 / frames as f32;
+        frame.iter_mut().for_each(|sample| *sample *= gain);
+    }
+}
+
+/// The start of what was still buffered when Stop came, played faded out before the buffer is dropped
+struct FadeOut {
+    samples: Vec<f32>,      // At SERVER_SAMPLE_RATE, allocated once as the callback mustn't
+    position: usize,
+}
+
+impl FadeOut {
+    fn new() -> Self {
+        Self { samples: Vec::with_capacity(Self::length()), position: 0 }
+    }
+
+    fn length() -> usize {
+        (SERVER_SAMPLE_RATE as f64 * FADE_OUT.as_secs_f64()) as usize
+    }
+
+    /// Takes the audio to fade out from the buffer, then empties it
+    fn start(&mut self, consumer: &mut HeapCons<f32>) {
+        self.samples.clear();
+        self.samples.extend(consumer.pop_iter().take(Self::length()));
+        fade_out(&mut self.samples, 1);
+        self.position = 0;
+        consumer.clear();
+    }
+
+    fn pop(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied()?;
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+/// One output device being played to
+///
+/// Audio is buffered as it comes from the server, mono at SERVER_SAMPLE_RATE, and only resampled
+/// to the device's rate and channels in its callback. Every device resamples from the original
+/// rather than from another device's copy, and the ring buffers outlive the stream, so when the
+/// device fails a new stream (possibly on another device) picks up right where the old one stopped.
+struct OutputSink {
+    stream: Option<cpal::Stream>,           // Kept alive while playing, None while waiting for a device after a failure
+    device_name: String,
+    producer: HeapProd<f32>,
+    consumer: Arc<Mutex<HeapCons<f32>>>,    // Drained by the stream callback
+    backlog: AudioBacklog,                  // Samples that didn't fit in the ring buffer yet, e.g. while paused
+    clear_requested: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    played_samples: Option<Arc<AtomicUsize>>,
+    input: Option<HeapProd<f32>>,           // Microphone mixed into the output
+    input_consumer: Arc<Mutex<Option<HeapCons<f32>>>>,
+    step: Arc<AtomicU64>,                   // Server samples per device frame (f64 bits), following the device's actual clock
+    device_rate: u32,
+    device_frames: Arc<AtomicUsize>,        // Frames the device consumed since last checked, played or silent
+    last_frames: Instant,                   // When the device last consumed any
+    failed: Arc<AtomicBool>,                // Set by the stream's error callback
+    retry_at: Instant,                      // Next attempt at finding a device after a failure
+    clock: DriftEstimator,
+}
+
+impl OutputSink {
+    /// Builds and starts a stream on the device, counting played samples if a counter is given
+    fn open(
+        device: &cpal::Device,
+        paused: Arc<AtomicBool>,
+        played_samples: Option<Arc<AtomicUsize>>,
+        mix_input: bool,
+    ) -> Result<(Self, cpal::SupportedStreamConfig), Box<dyn std::error::Error>> {
+        // Create the ring buffer
+        let audio_buffer = HeapRb::<f32>::new(RING_BUFFER_CAPACITY);
+        let (producer, consumer) = audio_buffer.split();
+
+        // The microphone is live, keep only a short buffer of it so it doesn't lag behind
+        let (input_producer, input_consumer) = match mix_input {
+            true => {
+                let (producer, consumer) = HeapRb::<f32>::new(INPUT_MIX_BUFFER_CAPACITY).split();
+                (Some(producer), Some(consumer))
+            }
+            false => (None, None),
+        };
+
+        let mut sink = Self {
+            stream: None,
+            device_name: String::new(),
+            producer,
+            consumer: Arc::new(Mutex::new(consumer)),
+            backlog: AudioBacklog::default(),
+            clear_requested: Arc::new(AtomicBool::new(false)),
+            paused,
+            played_samples,
+            input: input_producer,
+            input_consumer: Arc::new(Mutex::new(input_consumer)),
+            step: Arc::new(AtomicU64::new(1.0f64.to_bits())),
+            device_rate: SERVER_SAMPLE_RATE,
+            device_frames: Arc::new(AtomicUsize::new(0)),
+            last_frames: Instant::now(),
+            failed: Arc::new(AtomicBool::new(false)),
+            retry_at: Instant::now(),
+            clock: DriftEstimator::new("Output device", SERVER_SAMPLE_RATE),
+        };
+        let config = sink.start(device)?;
+        Ok((sink, config))
+    }
+
+    /// Plays the buffered audio on a device, replacing the stream that was playing it
+    fn start(&mut self, device: &cpal::Device) -> Result<cpal::SupportedStreamConfig, Box<dyn std::error::Error>> {
+        let config = device.default_output_config()?;
+        tracing::info!(device = device.name().unwrap_or_default(), rate = config.sample_rate().0, channels = config.channels(), "opening output stream");
+
+        // Some backends allow only one stream per device
+        self.stream = None;
+        let channels = config.channels() as usize;
+        self.device_rate = config.sample_rate().0;
+        self.clock = DriftEstimator::new(&format!("Output device {}", device.name()?), self.device_rate);
+        self.update_step();
+
+        let stream = device.build_output_stream(
+            &config.clone().into(),
+            {
+                let clear_requested = self.clear_requested.clone();
+                let device_frames = self.device_frames.clone();
+                let paused = self.paused.clone();
+                let played_samples = self.played_samples.clone();
+                let consumer = self.consumer.clone();
+                let input_consumer = self.input_consumer.clone();
+                let step = self.step.clone();
+                let mut resampler = FrameResampler::default();
+                let mut fade = FadeOut::new();
+                let mut input_resampler = FrameResampler::default();
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    device_frames.fetch_add(data.len() / channels, Ordering::Relaxed);
+
+                    // Only held elsewhere while a failed stream hands over, play silence meanwhile
+                    let Ok(mut consumer) = consumer.try_lock() else {
+                        data.fill(0.0);
+                        return;
+                    };
+
+                    // Only the consumer side can empty the buffer, so Stop is handled here
+                    // Paused, nothing is being heard that could click
+                    if clear_requested.swap(false, Ordering::Relaxed) {
+                        if paused.load(Ordering::Relaxed) {
+                            consumer.clear();
+                        } else {
+                            fade.start(&mut consumer);
+                        }
+                    }
+
+                    if paused.load(Ordering::Relaxed) {
+                        data.fill(0.0);
+                        return;
+                    }
+
+                    // What fades out was already given up on as unheard, so only the samples after it count as played
+                    let step = f64::from_bits(step.load(Ordering::Relaxed));
+                    let mut played = 0;
+                    fill_frames(data, channels, &mut resampler, step, || fade.pop().or_else(|| consumer.try_pop().inspect(|_| played += 1)));
+                    if let Some(played_samples) = &played_samples {
+                        played_samples.fetch_add(played, Ordering::Relaxed);
+                    }
+
+                    if let Ok(mut input_consumer) = input_consumer.try_lock() {
+                        if let Some(input_consumer) = input_consumer.as_mut() {
+                            for frame in data.chunks_mut(channels) {
+                                let Some(input) = input_resampler.next(step, || input_consumer.try_pop()) else { break };
+                                frame.iter_mut().for_each(|sample| *sample = (*sample + input).clamp(-1.0, 1.0));
+                            }
+                        }
+                    }
+                }
+            },
+            {
+                let failed = self.failed.clone();
+                move |err| {
+                    eprintln!("An error occurred on the output stream: {}", err);
+                    failed.store(true, Ordering::Relaxed);
+                }
+            },
+            None,
+        )?;
+        stream.play()?;
+
+        self.stream = Some(stream);
+        self.device_name = device.name()?;
+        self.failed.store(false, Ordering::Relaxed);
+        self.last_frames = Instant::now();
+        Ok(config)
+    }
+
+    /// Whether the stream reported an error or the device stopped asking for audio
+    fn has_failed(&self) -> bool {
+        self.stream.is_some() && (self.failed.load(Ordering::Relaxed) || self.last_frames.elapsed() > OUTPUT_STALL_TIMEOUT)
+    }
+
+    /// Moves playback to another device after a failure, keeping the buffered audio
+    ///
+    /// The primary device fails over to the system default, or any other device if that's the
+    /// one failing; a mirror only to the device it mirrors to, e.g. once it's plugged back in.
+    fn fail_over(&mut self, primary: bool) {
+        if self.retry_at > Instant::now() {
+            return;
+        }
+        self.retry_at = Instant::now() + OUTPUT_RETRY_INTERVAL;
+
+        let failed_device = std::mem::take(&mut self.device_name);
+        if self.stream.take().is_some() {
+            eprintln!("\n[output device {} failed]", failed_device);
+        }
+
+        let host = cpal::default_host();
+        let mut candidates: Vec<cpal::Device> = host.default_output_device().into_iter().collect();
+        candidates.extend(host.output_devices().into_iter().flatten());
+        let name = |device: &cpal::Device| device.name().unwrap_or_default();
+        if primary {
+            // The failing device last, it may work again after all
+            candidates.sort_by_key(|device| name(device) == failed_device);
+        } else {
+            candidates.retain(|device| name(device) == failed_device);
+        }
+
+        for device in candidates {
+            match self.start(&device) {
+                Ok(_) => {
+                    println!("\n[playing on {}]", self.device_name);
+                    return;
+                }
+                Err(e) => tracing::info!(device = name(&device), error = %e, "output device unusable"),
+            }
+        }
+        // Tried again later, under the same name
+        self.device_name = failed_device;
+    }
+
+    /// Follows the measured clock of the device, so playback neither starves nor piles up
+    fn update_step(&self) {
+        let step = SERVER_SAMPLE_RATE as f64 / (self.device_rate as f64 * self.clock.rate_factor());
+        self.step.store(step.to_bits(), Ordering::Relaxed);
+    }
+
+    fn queue(&mut self, samples: &[f32]) {
+        self.backlog.extend(samples);
+    }
+
+    /// Tops the ring buffer up from the backlog as the device drains it
+    fn refill(&mut self) {
+        let frames = self.device_frames.swap(0, Ordering::Relaxed);
+        if frames > 0 {
+            self.last_frames = Instant::now();
+        }
+        self.clock.add_frames(frames);
+        self.update_step();
+
+        while !self.producer.is_full() {
+            let Some(sample) = self.backlog.pop() else { break };
+            self.producer.try_push(sample).unwrap();
+        }
+    }
+
+    /// Queues microphone samples if this sink mixes them in, dropping what doesn't fit
+    fn mix_input(&mut self, samples: &[f32]) {
+        if let Some(producer) = &mut self.input {
+            producer.push_slice(samples);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.backlog.clear();
+        self.clear_requested.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Initializes the audio stream and returns a handle to the playback thread.
 ///
 /// This function sets up the audio device, configures the output stream, and starts a separate
-/// thread to handle audio playback. It returns a sender for audio samples and the output sample rate.
-pub fn initialize_audio_stream() -> (mpsc::Sender<Vec<f32>>, u32) {
+/// thread to handle audio playback, failing if there's no output device or it can't be opened.
+/// The handle holds a sender for playback commands, the output sample rate and a counter of
+/// samples that have actually reached the device (used to work out what the user heard).
+///
+/// Samples are sent as they come from the server, each device (the default one and mirrors added
+/// with `PlaybackCommand::AddOutput`) resamples them to its own rate and channel count, as does
+/// every RTP output added with `PlaybackCommand::AddRtpOutput`.
+pub fn initialize_audio_stream() -> Result<AudioOutput, String> {
     // Initialize audio components
     let host = cpal::default_host();
     let device = host
         .default_output_device()
-        .expect("No output device available");
-    let config = device.default_output_config().unwrap();
-    let output_sample_rate = config.sample_rate().0;
+        .ok_or("No output device available")?;
+    let config = device.default_output_config().map_err(|e| format!("Output device unusable: {}", e))?;
+    start_playback(Some(device), config.sample_rate().0)
+}
+
+/// Starts the playback thread without a local output device, e.g. on a server or in CI
+///
+/// Nothing plays locally and no samples are counted as heard, but mirrors and RTP outputs can
+/// still be added, so a call can be played only over RTP.
+pub fn headless_audio_stream() -> Result<AudioOutput, String> {
+    start_playback(None, SERVER_SAMPLE_RATE)
+}
 
-    // Create a standard channel for audio samples
-    let (audio_sender, audio_receiver) = mpsc::channel::<Vec<f32>>();
+/// Starts the playback thread, playing on `device` (None for no local device) and the outputs added later
+fn start_playback(device: Option<cpal::Device>, output_sample_rate: u32) -> Result<AudioOutput, String> {
+    let (opened_sender, opened_receiver) = mpsc::channel::<Result<(), String>>();
 
-    // Clone the device and config to move into the audio thread
-    let device_clone = device.clone();
-    let config_clone = config.clone();
+    // Create a standard channel for playback commands
+    let (audio_sender, audio_receiver) = mpsc::channel::<PlaybackCommand>();
 
-    // Start the audio playback thread (synchronous)
-    thread::spawn(move || {
-        // Use the cloned device and config to build the output stream
-        let device = device_clone;
-        let config = config_clone;
+    // Shared between the playback thread and the output callbacks
+    let played_samples = Arc::new(AtomicUsize::new(0));
+    let paused = Arc::new(AtomicBool::new(false));
+    let played_samples_clone = played_samples.clone();
 
-        // Create the ring buffer
-        let audio_buffer = HeapRb::<f32>::new(RING_BUFFER_CAPACITY);
-        let (mut producer, mut consumer) = audio_buffer.split();
+    // Start the audio playback thread (synchronous), streams can't leave the thread that built them
+    let playback_thread = thread::spawn(move || {
+        let headless = device.is_none();
+        let primary = match device.map(|device| OutputSink::open(&device, paused.clone(), Some(played_samples_clone.clone()), false)).transpose() {
+            Ok(primary) => primary.map(|(primary, _)| primary),
+            Err(e) => {
+                let _ = opened_sender.send(Err(format!("Failed to open the output device: {}", e)));
+                return;
+            }
+        };
+        let _ = opened_sender.send(Ok(()));
+        let mut sinks: Vec<OutputSink> = primary.into_iter().collect();
+        let mut asleep = false;
+        let mut mirrors: Vec<(String, bool)> = Vec::new();     // Reopened along with the primary device after sleeping
+        let mut rtp_outputs: Vec<RtpSender> = Vec::new();      // No device to close, they stay while sleeping
 
-        let stream = device
-            .build_output_stream(
-                &config.into(),
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    for sample in data.iter_mut() {
-                        *sample = consumer.try_pop().unwrap_or(0.0);
+        // Continuously receive playback commands and push samples into the ring buffers
+        loop {
+            let command = audio_receiver.recv_timeout(Duration::from_millis(20));
+
+            // Sleeping means no streams, anything to play opens them again
+            if asleep && matches!(command, Ok(PlaybackCommand::Play(_) | PlaybackCommand::Wake)) {
+                // The device may have changed while asleep, and failed over since it was first opened
+                let primary = match headless {
+                    true => Ok(None),
+                    false => cpal::default_host()
+                        .default_output_device()
+                        .ok_or_else(|| "no output device available".into())
+                        .and_then(|device| OutputSink::open(&device, paused.clone(), Some(played_samples_clone.clone()), false))
+                        .map(Some),
+                };
+                match primary {
+                    Ok(primary) => {
+                        asleep = false;
+                        sinks.extend(primary.map(|(primary, _)| primary));
+                        for (device, mix_input) in &mirrors {
+                            match open_mirror(device, paused.clone(), *mix_input) {
+                                Ok(sink) => sinks.push(sink),
+                                Err(e) => eprintln!("Failed to mirror playback to {}: {}", device, e),
+                            }
+                        }
                     }
+                    Err(e) => eprintln!("Failed to reopen the output device: {}", e),
+                }
+            }
+
+            match command {
+                Ok(PlaybackCommand::Play(samples)) => {
+                    sinks.iter_mut().for_each(|sink| sink.queue(&samples));
+                    rtp_outputs.iter_mut().for_each(|output| output.queue(&samples));
+                }
+                Ok(PlaybackCommand::Stop) => {
+                    sinks.iter_mut().for_each(OutputSink::clear);
+                    rtp_outputs.iter_mut().for_each(RtpSender::clear);
+                }
+                Ok(PlaybackCommand::Pause) => {
+                    paused.store(true, Ordering::Relaxed);
+                    rtp_outputs.iter_mut().for_each(RtpSender::pause);
+                }
+                Ok(PlaybackCommand::Resume) => paused.store(false, Ordering::Relaxed),
+                Ok(PlaybackCommand::AddOutput { device, mix_input }) => match open_mirror(&device, paused.clone(), mix_input) {
+                    Ok(sink) => {
+                        sinks.push(sink);
+                        mirrors.push((device, mix_input));
+                    }
+                    Err(e) => eprintln!("Failed to mirror playback to {}: {}", device, e),
+                },
+                Ok(PlaybackCommand::AddRtpOutput(output)) => match RtpSender::new(output.clone()) {
+                    Ok(sender) => rtp_outputs.push(sender),
+                    Err(e) => eprintln!("Failed to send playback to {}: {}", output.address, e),
                 },
-                |err| eprintln!("An error occurred on the output stream: {}", err),
-                None,
-            )
-            .unwrap();
-
-        stream.play().unwrap();
-
-        // Continuously receive audio samples and push them into the ring buffer
-        while let Ok(samples) = audio_receiver.recv() {
-            for sample in samples {
-                // Handle buffer full situation
-                if producer.is_full() {
-                    eprintln!("Warning: Audio buffer is full, dropping sample.");
-                } else {
-                    producer.try_push(sample).unwrap();
+                Ok(PlaybackCommand::MixInput(samples)) => sinks.iter_mut().for_each(|sink| sink.mix_input(&samples)),
+                Ok(PlaybackCommand::Sleep) => {
+                    tracing::info!("closing output streams");
+                    sinks.clear();
+                    asleep = true;
+                }
+                Ok(PlaybackCommand::Wake) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            sinks.iter_mut().for_each(OutputSink::refill);
+            if !paused.load(Ordering::Relaxed) {
+                rtp_outputs.iter_mut().for_each(RtpSender::send_due);
+            }
+            for (index, sink) in sinks.iter_mut().enumerate() {
+                if sink.stream.is_none() || sink.has_failed() {
+                    sink.fail_over(index == 0 && !headless);
+                }
+            }
+        }
+    });
+
+    opened_receiver.recv().map_err(|_| "The playback thread died".to_string())??;
+    Ok(AudioOutput {
+        sender: audio_sender,
+        sample_rate: output_sample_rate,
+        played_samples,
+        thread: Some(playback_thread),
+    })
+}
+
+/// Opens an extra output device, matched by name, to mirror playback to
+fn open_mirror(name: &str, paused: Arc<AtomicBool>, mix_input: bool) -> Result<OutputSink, Box<dyn std::error::Error>> {
+    let host = cpal::default_host();
+    let device = host
+        .output_devices()?
+        .find(|device| device.name().is_ok_and(|device_name| device_name.contains(name)))
+        .ok_or_else(|| {
+            let available = host
+                .output_devices()
+                .map(|devices| devices.filter_map(|device| device.name().ok()).collect::<Vec<_>>().join(", "))
+                .unwrap_or_default();
+            format!("no output device matches, available devices: {}", available)
+        })?;
+
+    let (sink, config) = OutputSink::open(&device, paused, None, mix_input)?;
+
+    println!("Mirroring playback to {} ({} Hz, {} channels)", device.name()?, config.sample_rate().0, config.channels());
+    Ok(sink)
+}
+
+/// Commands accepted by the microphone thread
+pub enum InputCommand {
+    Close,      // Close the input stream, e.g. while idle
+    Open,       // Open it again
+}
+
+/// Microphone samples as captured, interleaved at the device's native rate
+pub struct InputChunk {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+// How often the input device is checked for having changed
+const INPUT_DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Initializes the microphone stream and returns the receiver of captured chunks, with a sender of commands.
+///
+/// Like playback, capture runs on its own thread which keeps the stream alive. Each chunk carries
+/// the rate and channel count it was captured with, use `convert_audio_to_server` before sending it.
+/// The thread watches the default input device and rebuilds the stream when it changes (a new device
+/// was plugged in, or the OS switched its sample rate) or fails, so audio is never converted with
+/// stale parameters. When the device fails or goes away, any other input device takes over.
+/// The thread stops once the command sender is dropped.
+pub fn initialize_input_stream() -> (mpsc::Receiver<InputChunk>, mpsc::Sender<InputCommand>) {
+    let (sample_sender, sample_receiver) = mpsc::channel::<InputChunk>();
+    let (command_sender, command_receiver) = mpsc::channel::<InputCommand>();
+
+    thread::spawn(move || {
+        let mut current: Option<(cpal::Stream, InputDeviceInfo)> = None;
+        let failed = Arc::new(AtomicBool::new(false));
+        let mut closed = false;
+
+        loop {
+            if closed {
+                if current.take().is_some() {
+                    tracing::info!("closing input stream");
+                }
+                match command_receiver.recv() {
+                    Ok(InputCommand::Open) => closed = false,
+                    Ok(InputCommand::Close) => {}
+                    Err(_) => break,
+                }
+                continue;
+            }
+
+            // After a failure any other device will do, the failed one may linger in the list
+            let failure = failed.swap(false, Ordering::Relaxed);
+            let failed_name = current.as_ref().filter(|_| failure).map(|(_, info)| info.name.clone());
+            if let Some(name) = &failed_name {
+                eprintln!("\n[input device {} failed, looking for another]", name);
+            }
+
+            let host = cpal::default_host();
+            let device = host
+                .default_input_device()
+                .filter(|device| device.name().ok() != failed_name)
+                .or_else(|| host.input_devices().ok()?.find(|device| device.name().ok() != failed_name))
+                .or_else(|| host.default_input_device());
+            let info = device.as_ref().and_then(InputDeviceInfo::of);
+
+            let changed = match (&current, &info) {
+                (Some((_, running)), Some(info)) => running != info,
+                (None, Some(_)) => true,
+                (_, None) => false,     // Keep whatever runs until a device shows up
+            };
+
+            if changed || failure {
+                if let (Some(device), Some(info)) = (device, info) {
+                    if let Some((_, previous)) = &current {
+                        println!("\nInput device changed: {} -> {}", previous, info);
+                    }
+                    tracing::info!(device = %info, "opening input stream");
+
+                    // Drop the old stream first, some backends only allow one per device
+                    current = None;
+                    match build_input_stream(&device, &info, sample_sender.clone(), failed.clone()) {
+                        Ok(stream) => current = Some((stream, info)),
+                        Err(e) => eprintln!("Failed to open the input device {}: {}", info, e),
+                    }
                 }
             }
+
+            // Commands wake the thread early, otherwise it's time to check the device again
+            match command_receiver.recv_timeout(INPUT_DEVICE_POLL_INTERVAL) {
+                Ok(InputCommand::Close) => closed = true,
+                Ok(InputCommand::Open) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
         }
     });
 
-    // Return the sender and output sample rate
-    (audio_sender, output_sample_rate)
+    (sample_receiver, command_sender)
+}
+
+/// What identifies the input configuration a stream was built for
+#[derive(Debug, Clone, PartialEq)]
+struct InputDeviceInfo {
+    name: String,
+    config: cpal::SupportedStreamConfig,
+}
+
+impl InputDeviceInfo {
+    fn of(device: &cpal::Device) -> Option<Self> {
+        Some(Self {
+            name: device.name().ok()?,
+            config: device.default_input_config().ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for InputDeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({} Hz, {} channels)", self.name, self.config.sample_rate().0, self.config.channels())
+    }
+}
+
+fn build_input_stream(
+    device: &cpal::Device,
+    info: &InputDeviceInfo,
+    sample_sender: mpsc::Sender<InputChunk>,
+    failed: Arc<AtomicBool>,
+) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
+    let sample_rate = info.config.sample_rate().0;
+    let channels = info.config.channels();
+
+    let stream = device.build_input_stream(
+        &info.config.clone().into(),
+        move |data: &[f32], _: &cpal::InputCallbackInfo| {
+            // The receiver going away just means nobody is listening anymore
+            let _ = sample_sender.send(InputChunk { samples: data.to_vec(), sample_rate, channels });
+        },
+        move |err| {
+            eprintln!("An error occurred on the input stream: {}", err);
+            failed.store(true, Ordering::Relaxed);
+        },
+        None,
+    )?;
+    stream.play()?;
+
+    Ok(stream)
+}
+
+/// Reads an audio file (WAV, FLAC, MP3, Ogg Vorbis) as interleaved samples with its rate and channel count
+pub fn read_audio(path: &std::path::Path) -> Result<(Vec<f32>, u32, u16), Box<dyn std::error::Error>> {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+    use symphonia::core::errors::Error as DecodeError;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    let file = std::fs::File::open(path)?;
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        MediaSourceStream::new(Box::new(file), Default::default()),
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format.tracks().iter().find(|track| track.codec_params.codec != CODEC_TYPE_NULL).ok_or("no audio track")?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut samples = Vec::new();
+    let mut format_of = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A damaged frame is skipped, like players do
+            Err(DecodeError::DecodeError(e)) => {
+                tracing::debug!(error = e, "skipping undecodable audio frame");
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let spec = *decoded.spec();
+        format_of.get_or_insert((spec.rate, spec.channels.count() as u16));
+
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+    }
+
+    let (sample_rate, channels) = format_of.ok_or("no audio in the file")?;
+    Ok((samples, sample_rate, channels))
+}
+
+/// Brings speech to a common loudness, so quiet and hot recordings sound alike to the model
+///
+/// The level is measured over the parts louder than silence, so pauses don't make a recording
+/// look quiet. The gain is limited to keep the peaks from clipping and noise from being blown up.
+pub fn normalize_loudness(samples: &mut [f32]) {
+    const TARGET_DBFS: f32 = -20.0;
+    const SILENCE_DBFS: f32 = -50.0;
+    const MAX_GAIN: f32 = 10.0;
+    const MAX_PEAK: f32 = 0.95;
+    let frame = SERVER_SAMPLE_RATE as usize / 50;
+
+    let active: Vec<f32> = samples
+        .chunks(frame)
+        .map(|frame| frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32)
+        .filter(|power| 10.0 * power.max(1e-12).log10() > SILENCE_DBFS)
+        .collect();
+    if active.is_empty() {
+        return;
+    }
+
+    let level = 10.0 * (active.iter().sum::<f32>() / active.len() as f32).log10();
+    let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let gain = 10f32.powf((TARGET_DBFS - level) / 20.0).min(MAX_GAIN).min(MAX_PEAK / peak);
+    samples.iter_mut().for_each(|sample| *sample *= gain);
 }
 
 // Handling User Input -> Server
+// Function to downmix interleaved input samples to mono and resample them to the server sample rate
+//
+// The server always gets pcm16 at 24 kHz, whatever the device rate. At 24 kHz the samples pass
+// through as they are, at any other rate they are resampled band-limited (see StreamResampler):
+// 8 and 16 kHz microphones are upsampled without adding images, 44.1 and 48 kHz ones are
+// low-passed before decimating. G.711 would take 8 kHz audio without resampling, but its 8-bit
+// companding is noisier than a clean upsample, so it is never picked.
+pub fn convert_audio_to_server(samples: &[f32], input_sample_rate: u32, channels: u16) -> Vec<f32> {
+    let mono = downmix(samples, channels);
+    match input_sample_rate {
+        SERVER_SAMPLE_RATE => mono,
+        rate => StreamResampler::process_all(&mono, SERVER_SAMPLE_RATE as f64 / rate as f64),
+    }
+}
+
+// Function to average interleaved samples down to mono
+pub fn downmix(samples: &[f32], channels: u16) -> Vec<f32> {
+    samples
+        .chunks(channels.max(1) as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+// Samples converted to pcm16 at a time when encoding, 3 × 256 so each block is whole base64 groups
+const ENCODE_BLOCK_SAMPLES: usize = 768;
+
 // Function to convert f32 audio samples to i16 PCM in base64 format
+//
+// Called for every microphone append, so the pcm16 goes through a block on the stack and
+// straight into the output, sized up front: the returned String is the only allocation.
+// See benches/audio_encode.rs.
 pub fn base64_encode_audio(samples: &[f32]) -> String {
-    let audio_data: Vec<u8> = samples
-        .iter()
-        .map(|sample| (sample * i16::MAX as f32) as i16)
-        .flat_map(|sample| sample.to_le_bytes().to_vec())
-        .collect();
+    let mut encoded = String::with_capacity((samples.len() * 2).div_ceil(3) * 4);
+    let mut block = [0u8; ENCODE_BLOCK_SAMPLES * 2];
 
-    BASE64_STANDARD.encode(&audio_data)
+    for chunk in samples.chunks(ENCODE_BLOCK_SAMPLES) {
+        for (bytes, sample) in block.chunks_exact_mut(2).zip(chunk) {
+            bytes.copy_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+        }
+        // Only the last block can be short, so padding only ever ends the string
+        BASE64_STANDARD.encode_string(&block[..chunk.len() * 2], &mut encoded);
+    }
+    encoded
+}
+
+/// Bytes of audio in base64, counted without decoding it
+pub fn base64_audio_bytes(base64_audio_data: &str) -> usize {
+    let padding = base64_audio_data.bytes().rev().take_while(|byte| *byte == b'=').count();
+    (base64_audio_data.len() / 4 * 3).saturating_sub(padding)
 }
 
 // Handling Server -> User Output
@@ -96,34 +808,269 @@ pub fn base64_decode_audio(base64_audio_data: &str) -> Vec<f32> {
         .collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-// Basic resampling function, may replace this with rubato (https://crates.io/crates/rubato)
-pub fn resample_audio(samples: &[f32], current_sample_rate: u32, target_sample_rate: u32) -> Vec<f32> {
-    // I have no idea why I need to multiply by 2.0, perhaps it's because the audio data is stereo?
-    // but I haven't found any documentation confirming that. I just tried it and it worked.
-    // The other possiblity is there is an error in the resampling or encoding/decoding
-    let resample_ratio = (target_sample_rate as f32 / current_sample_rate as f32) * 2.0;
-   
-    let output_length = (samples.len() as f32 * resample_ratio) as usize;
-    let mut resampled_audio = Vec::with_capacity(output_length);
-    
-    // Loop through the output length to generate resampled audio
-    for i in 0..output_length {
-        // Calculate the corresponding index in the input samples
-        let index = i as f32 / resample_ratio;
-        let index_floor = index.floor() as usize;
-        let index_ceil = index.ceil() as usize;
-        
-        // If the ceiling index is out of bounds, use the last sample
-        if index_ceil >= samples.len() {
-            resampled_audio.push(samples[samples.len() - 1]);
-        } else {
-            // Perform linear interpolation between the floor and ceiling samples
-            let t = index - index_floor as f32; // weight for interpolation
-            let sample = samples[index_floor] * (1.0 - t) + samples[index_ceil] * t;
-            resampled_audio.push(sample);
+    // Device rates microphones commonly run at
+    const RATES: [u32; 7] = [8_000, 16_000, 22_050, 24_000, 32_000, 44_100, 48_000];
+
+    /// One second of a sine at `frequency`, interleaved over `channels`
+    fn tone(frequency: f64, rate: u32, channels: u16) -> Vec<f32> {
+        (0..rate)
+            .map(|n| (0.5 * (2.0 * std::f64::consts::PI * frequency * n as f64 / rate as f64).sin()) as f32)
+            .flat_map(|sample| std::iter::repeat_n(sample, channels as usize))
+            .collect()
+    }
+
+    /// Amplitude of the `frequency` component of server audio, away from the edges (Goertzel)
+    fn amplitude(samples: &[f32], frequency: f64) -> f64 {
+        let samples = &samples[samples.len() / 4..samples.len() * 3 / 4];
+        let omega = 2.0 * std::f64::consts::PI * frequency / SERVER_SAMPLE_RATE as f64;
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &sample in samples {
+            let s0 = sample as f64 + 2.0 * omega.cos() * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        (s1 * s1 + s2 * s2 - 2.0 * omega.cos() * s1 * s2).sqrt() * 2.0 / samples.len() as f64
+    }
+
+    #[test]
+    fn every_rate_converts_to_server_length_and_pitch() {
+        for rate in RATES {
+            for channels in [1, 2] {
+                let server = convert_audio_to_server(&tone(1000.0, rate, channels), rate, channels);
+
+                assert_eq!(server.len(), SERVER_SAMPLE_RATE as usize, "{} Hz, {} channels", rate, channels);
+                let level = amplitude(&server, 1000.0);
+                assert!((level - 0.5).abs() < 0.01, "{} Hz, {} channels: 1 kHz at {}", rate, channels, level);
+            }
+        }
+    }
+
+    #[test]
+    fn mirrors_get_every_channel_of_a_frame_alike() {
+        let samples = tone(1000.0, SERVER_SAMPLE_RATE, 1);
+        for (rate, channels) in [(24_000, 1), (48_000, 2), (44_100, 6), (16_000, 2)] {
+            let step = SERVER_SAMPLE_RATE as f64 / rate as f64;
+            let mut resampler = FrameResampler::default();
+            let mut source = samples.iter().copied();
+            let mut data = vec![0.0; rate as usize * channels];
+            fill_frames(&mut data, channels, &mut resampler, step, || source.next());
+
+            assert!(data.chunks(channels).all(|frame| frame.iter().all(|&sample| sample == frame[0])), "{} Hz, {} channels", rate, channels);
+
+            // A second of frames takes a second of audio, give or take the interpolation's lag
+            assert!(source.count() <= 1, "{} Hz, {} channels", rate, channels);
+            let mono: Vec<f32> = data.iter().step_by(channels).copied().collect();
+            let server = StreamResampler::process_all(&mono, step);
+            let level = amplitude(&server, 1000.0);
+            assert!((level - 0.5).abs() < 0.02, "{} Hz, {} channels: 1 kHz at {}", rate, channels, level);
+        }
+    }
+
+    #[test]
+    fn server_rate_passes_through_unchanged() {
+        let samples = tone(1000.0, SERVER_SAMPLE_RATE, 1);
+        assert_eq!(convert_audio_to_server(&samples, SERVER_SAMPLE_RATE, 1), samples);
+    }
+
+    #[test]
+    fn upsampling_adds_no_images() {
+        // Images of 1 kHz land at the input rate ± 1 kHz, folded into the server band
+        for (rate, images) in [(8_000, [7_000.0, 9_000.0]), (16_000, [9_000.0, 7_000.0])] {
+            let server = convert_audio_to_server(&tone(1000.0, rate, 1), rate, 1);
+            for image in images {
+                let level = amplitude(&server, image);
+                assert!(level < 0.0005, "{} Hz: image at {} Hz is {}", rate, image, level);
+            }
+        }
+    }
+
+    #[test]
+    fn downsampling_filters_out_what_the_server_cant_carry() {
+        // 18 kHz is above the server's Nyquist frequency and would fold back to 6 kHz
+        for rate in [32_000, 44_100, 48_000] {
+            let server = convert_audio_to_server(&tone(18_000.0, rate, 1), rate, 1);
+            let level = amplitude(&server, 6_000.0);
+            assert!(level < 0.0005, "{} Hz: alias at 6 kHz is {}", rate, level);
+        }
+    }
+
+    #[test]
+    fn streaming_in_chunks_matches_the_whole_clip() {
+        for rate in RATES {
+            let samples = tone(1000.0, rate, 1);
+            let ratio = SERVER_SAMPLE_RATE as f64 / rate as f64;
+            let whole = StreamResampler::process_all(&samples, ratio);
+
+            let mut resampler = StreamResampler::default();
+            let streamed: Vec<f32> = samples.chunks(rate as usize / 100).flat_map(|chunk| resampler.process(chunk, ratio)).collect();
+
+            assert!(streamed.len() <= whole.len());
+            assert!(streamed.len() + 100 > whole.len(), "{} Hz: only {} of {} samples out", rate, streamed.len(), whole.len());
+            for (index, (streamed, whole)) in streamed.iter().zip(&whole).enumerate() {
+                assert!((streamed - whole).abs() < 1e-6, "{} Hz: sample {} differs", rate, index);
+            }
+        }
+    }
+
+    #[test]
+    fn base64_audio_bytes_counts_without_decoding() {
+        for samples in 0..8 {
+            let encoded = base64_encode_audio(&vec![0.25; samples]);
+            assert_eq!(base64_audio_bytes(&encoded), samples * 2);
+        }
+    }
+
+    #[test]
+    fn base64_encoding_matches_the_allocating_encoder() {
+        // As it was before encoding went through a block
+        let reference = |samples: &[f32]| {
+            let audio_data: Vec<u8> = samples.iter().flat_map(|sample| ((sample * i16::MAX as f32) as i16).to_le_bytes()).collect();
+            BASE64_STANDARD.encode(audio_data)
+        };
+
+        // Around the block size, where a short last block pads, and past full scale
+        let samples: Vec<f32> = (0..3 * ENCODE_BLOCK_SAMPLES + 7).map(|n| ((n as f32 * 0.37).sin() * 1.2).clamp(-1.5, 1.5)).collect();
+        for length in [0, 1, 2, 3, ENCODE_BLOCK_SAMPLES - 1, ENCODE_BLOCK_SAMPLES, ENCODE_BLOCK_SAMPLES + 1, 2 * ENCODE_BLOCK_SAMPLES + 5, samples.len()] {
+            let encoded = base64_encode_audio(&samples[..length]);
+            assert_eq!(encoded, reference(&samples[..length]), "{} samples", length);
+            assert_eq!(encoded.len(), encoded.capacity(), "sized up front");
+        }
+    }
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/audio").join(name)
+    }
+
+    /// The sine each fixture holds, at half scale times `scale`
+    fn fixture_tone(rate: u32, scale: f32) -> impl Iterator<Item = f32> {
+        (0..rate as usize / 10).map(move |n| scale * 0.5 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / rate as f32).sin())
+    }
+
+    fn rms_dbfs(samples: &[f32]) -> f32 {
+        10.0 * (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).log10()
+    }
+
+    #[test]
+    fn reads_wav_and_flac_at_their_own_format() {
+        let (samples, rate, channels) = read_audio(&fixture("tone_16k_stereo.wav")).unwrap();
+        assert_eq!((rate, channels, samples.len()), (16_000, 2, 3_200));
+        for ((left, right), expected) in samples.chunks(2).map(|frame| (frame[0], frame[1])).zip(fixture_tone(16_000, 1.0)) {
+            assert!((left - expected).abs() < 1e-4 && (right - expected / 2.0).abs() < 1e-4);
+        }
+
+        let (samples, rate, channels) = read_audio(&fixture("tone_24k_float.wav")).unwrap();
+        assert_eq!((rate, channels), (24_000, 1));
+        assert_eq!(samples.len(), 2_400);
+        assert!(samples.iter().zip(fixture_tone(24_000, 1.0)).all(|(sample, expected)| (sample - expected).abs() < 1e-4));
+
+        let (samples, rate, channels) = read_audio(&fixture("tone_8k.flac")).unwrap();
+        assert_eq!((rate, channels, samples.len()), (8_000, 1, 800));
+        assert!(samples.iter().zip(fixture_tone(8_000, 1.0)).all(|(sample, expected)| (sample - expected).abs() < 1e-4));
+    }
+
+    #[test]
+    fn files_that_arent_audio_are_an_error() {
+        assert!(read_audio(&fixture("not_audio.wav")).is_err());
+        assert!(read_audio(&fixture("missing.wav")).is_err());
+        // The extension is only a hint, the content decides
+        assert!(read_audio(&fixture("README.md")).is_err());
+    }
+
+    #[test]
+    fn normalizes_a_file_to_the_target_loudness() {
+        let (samples, rate, channels) = read_audio(&fixture("tone_16k_stereo.wav")).unwrap();
+        let mut server = convert_audio_to_server(&samples, rate, channels);
+        // Downmixed to three quarters of half scale
+        assert!((rms_dbfs(&server) + 11.5).abs() < 0.5, "{}", rms_dbfs(&server));
+        normalize_loudness(&mut server);
+        assert!((rms_dbfs(&server) + 20.0).abs() < 0.5, "{}", rms_dbfs(&server));
+
+        // Pauses don't count towards the level
+        let (mut samples, ..) = read_audio(&fixture("tone_24k_float.wav")).unwrap();
+        samples.extend(vec![0.0; samples.len() * 3]);
+        normalize_loudness(&mut samples);
+        assert!((rms_dbfs(&samples[..2_400]) + 20.0).abs() < 0.5, "{}", rms_dbfs(&samples[..2_400]));
+    }
+
+    #[test]
+    fn normalizing_keeps_silence_noise_and_peaks_in_check() {
+        let mut silence = vec![0.0; 2_400];
+        normalize_loudness(&mut silence);
+        assert!(silence.iter().all(|sample| *sample == 0.0));
+
+        // A very quiet recording is raised by at most MAX_GAIN, not up to the target
+        let mut quiet: Vec<f32> = fixture_tone(24_000, 0.01).collect();
+        normalize_loudness(&mut quiet);
+        assert!((quiet.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())) - 0.05).abs() < 1e-3);
+
+        // A quiet one with a click isn't raised past clipping
+        let mut clicked: Vec<f32> = fixture_tone(24_000, 0.1).collect();
+        clicked[100] = 0.9;
+        normalize_loudness(&mut clicked);
+        assert!(clicked.iter().all(|sample| sample.abs() <= 0.95 + 1e-6));
+        assert!((clicked[100] - 0.95).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fade_out_ramps_every_channel_of_a_frame_alike() {
+        let mut mono = vec![1.0; 4];
+        fade_out(&mut mono, 1);
+        assert_eq!(mono, [0.75, 0.5, 0.25, 0.0]);
+
+        // Frames are faded, not samples, so the channels stay in step
+        let mut stereo = [1.0, -0.5].repeat(4);
+        fade_out(&mut stereo, 2);
+        assert_eq!(stereo, [0.75, -0.375, 0.5, -0.25, 0.25, -0.125, 0.0, -0.0]);
+
+        let mut surround = vec![0.5; 6 * 480];
+        fade_out(&mut surround, 6);
+        let gains: Vec<f32> = surround.chunks(6).map(|frame| frame[0] / 0.5).collect();
+        assert!(surround.chunks(6).all(|frame| frame.iter().all(|&sample| sample == frame[0])));
+        assert!(gains.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(gains[0] < 1.0 && gains[0] > 0.99);
+        assert_eq!(gains[479], 0.0);
+
+        // Nothing to fade, or a single frame, which ends silent
+        fade_out(&mut [], 2);
+        let mut single = [0.8, 0.8];
+        fade_out(&mut single, 2);
+        assert_eq!(single, [0.0, 0.0]);
+        let mut no_channels = [1.0, 1.0];
+        fade_out(&mut no_channels, 0);
+        assert_eq!(no_channels, [0.5, 0.0]);
+    }
+
+    #[test]
+    fn frame_resampler_interpolates_and_picks_up_after_running_dry() {
+        // Counting up, so interpolated, skipped or repeated samples show
+        let resample = |step: f64, count: usize| {
+            let mut resampler = FrameResampler::default();
+            let mut source = (1..=8).map(|n| n as f32);
+            std::iter::from_fn(|| resampler.next(step, || source.next())).take(count).collect::<Vec<_>>()
+        };
+        // A sample behind, as each output lies between the last two read
+        assert_eq!(resample(1.0, 100), [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(resample(0.5, 7), [0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+        assert_eq!(resample(2.0, 100), [1.0, 3.0, 5.0, 7.0]);
+
+        // Running dry leaves it where it was, the audio goes on where it stopped
+        for step in [0.25, 0.5, 1.0] {
+            let mut resampler = FrameResampler::default();
+            let mut queued: std::collections::VecDeque<f32> = (1..=3).map(|n| n as f32).collect();
+            let mut output = Vec::new();
+            output.extend(std::iter::from_fn(|| resampler.next(step, || queued.pop_front())));
+            assert_eq!(resampler.next(step, || queued.pop_front()), None);
+            queued.extend((4..=8).map(|n| n as f32));
+            output.extend(std::iter::from_fn(|| resampler.next(step, || queued.pop_front())));
+
+            let mut resampler = FrameResampler::default();
+            let mut source = (1..=8).map(|n| n as f32);
+            let uninterrupted: Vec<f32> = std::iter::from_fn(|| resampler.next(step, || source.next())).collect();
+            assert_eq!(output, uninterrupted, "step {}", step);
         }
     }
-    
-    resampled_audio
 }
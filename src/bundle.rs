@@ -0,0 +1,134 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::audio_utils::base64_decode_audio;
+use crate::export::Transcript;
+use crate::recorder::wav_bytes;
+use crate::storage::{read_file, write_file, Encryption};
+use crate::usage::UsageTracker;
+
+/// Packs everything known about a call into one zip archive, with a manifest describing its files
+///
+/// The transcript is always included. Per-item audio, the session configuration and usage
+/// analytics come from the protocol dump, when one was saved.
+pub fn write_bundle(
+    path: &Path,
+    transcript: &Transcript,
+    markdown: &str,
+    dump: Option<&Path>,
+    encryption: Option<&Encryption>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files: Vec<(String, &str, Vec<u8>)> = vec![
+        ("transcript.json".to_string(), "Transcript as saved at the end of the call", serde_json::to_vec_pretty(transcript)?),
+        ("transcript.md".to_string(), "Transcript rendered as Markdown", markdown.as_bytes().to_vec()),
+    ];
+
+    if let Some(dump) = dump {
+        let events = load_events(dump, encryption)?;
+
+        if let Some(session) = events.iter().rev().find(|event| event["type"] == "session.created" || event["type"] == "session.updated") {
+            files.push(("session.json".to_string(), "Session configuration last acknowledged by the server", serde_json::to_vec_pretty(&session["session"])?));
+        }
+
+        files.push(("analytics.json".to_string(), "Turns, token usage, estimated cost, sentiment and talk-over", serde_json::to_vec_pretty(&analytics(&events))?));
+
+        for (index, (item_id, samples)) in item_audio(&events).into_iter().enumerate() {
+            files.push((format!("audio/{:03}-{}.wav", index + 1, item_id), "Assistant audio of one item (24 kHz mono)", wav_bytes(&samples)?));
+        }
+    }
+
+    let manifest = serde_json::json!({
+        "generator": format!("hotline {}", env!("CARGO_PKG_VERSION")),
+        "created_at": SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        "metadata": transcript.metadata,
+        "files": files.iter().map(|(name, description, data)| serde_json::json!({
+            "name": name,
+            "description": description,
+            "bytes": data.len(),
+        })).collect::<Vec<_>>(),
+    });
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    for (name, _, data) in &files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(data)?;
+    }
+
+    write_file(path, &zip.finish()?.into_inner(), encryption)?;
+    println!("Saved {} ({} files)", path.display(), files.len() + 1);
+    Ok(())
+}
+
+fn load_events(path: &Path, encryption: Option<&Encryption>) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+    let data = String::from_utf8(read_file(path, encryption)?)?;
+
+    let events = data
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Value>, _>>()?;
+    Ok(events)
+}
+
+/// Usage totals over every response of the call
+fn analytics(events: &[Value]) -> Value {
+    let mut usage = UsageTracker::default();
+    let mut statuses: HashMap<String, usize> = HashMap::new();
+
+    for event in events.iter().filter(|event| event["type"] == "response.done") {
+        usage.add_response_usage(&event["response"]["usage"]);
+        *statuses.entry(event["response"]["status"].as_str().unwrap_or("unknown").to_string()).or_default() += 1;
+    }
+
+    // Labels per role, from `dial --sentiment`
+    let mut sentiment: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    for event in events.iter().filter(|event| event["type"] == "local.sentiment") {
+        let role = event["role"].as_str().unwrap_or("unknown").to_string();
+        let label = event["sentiment"]["label"].as_str().unwrap_or("unknown").to_string();
+        *sentiment.entry(role).or_default().entry(label).or_default() += 1;
+    }
+
+    // Overlapping speech, from `dial --talk-over`
+    let talk_overs: Vec<&Value> = events.iter().filter(|event| event["type"] == "local.talk_over").map(|event| &event["talk_over"]).collect();
+
+    serde_json::json!({
+        "responses": statuses.values().sum::<usize>(),
+        "responses_by_status": statuses,
+        "user_turns": events.iter().filter(|event| event["type"] == "input_audio_buffer.speech_stopped"
+            || (event["type"] == "conversation.item.create" && event["item"]["role"] == "user")).count(),
+        "total_tokens": usage.total_tokens(),
+        "cost_usd": usage.cost_usd(),
+        "summary": usage.summary(),
+        "sentiment": sentiment,
+        "talk_over": {
+            "count": talk_overs.len(),
+            "interruptions": talk_overs.iter().filter(|talk_over| talk_over["interrupted"] == true).count(),
+            "overlap_ms": talk_overs.iter().filter_map(|talk_over| talk_over["overlap_ms"].as_u64()).sum::<u64>(),
+        },
+    })
+}
+
+/// Assistant audio per item, in the order the items started
+fn item_audio(events: &[Value]) -> Vec<(String, Vec<f32>)> {
+    let mut items: Vec<(String, Vec<f32>)> = Vec::new();
+
+    for event in events.iter().filter(|event| event["type"] == "response.audio.delta") {
+        let item_id = event["item_id"].as_str().unwrap_or_default();
+        let samples = base64_decode_audio(event["delta"].as_str().unwrap_or_default());
+
+        match items.iter_mut().find(|(id, _)| id == item_id) {
+            Some((_, audio)) => audio.extend(samples),
+            None => items.push((item_id.to_string(), samples)),
+        }
+    }
+
+    items
+}
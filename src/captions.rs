@@ -0,0 +1,83 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::Stylize;
+use crossterm::terminal;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::full_screen;
+use crate::text_layout::{truncate, wrap};
+
+// Captions kept for the pane
+const CAPACITY: usize = 200;
+// How often the pane is redrawn while waiting for keys
+const PANE_REFRESH: Duration = Duration::from_millis(250);
+
+/// A translated sentence of the assistant's speech
+struct Caption {
+    original: String,
+    translation: String,
+}
+
+/// Language of the captions, None while they're off, and the latest captions
+static CAPTIONS: Mutex<(Option<String>, VecDeque<Caption>)> = Mutex::new((None, VecDeque::new()));
+
+/// Turns captions on in a language, or off with None
+pub fn set_language(language: Option<&str>) {
+    CAPTIONS.lock().unwrap().0 = language.map(str::to_string);
+}
+
+pub fn add(original: &str, translation: &str) {
+    let mut captions = CAPTIONS.lock().unwrap();
+    if captions.1.len() == CAPACITY {
+        captions.1.pop_front();
+    }
+    captions.1.push_back(Caption { original: original.to_string(), translation: translation.to_string() });
+}
+
+/// Shows the captions on the alternate screen as they come in, until the user leaves
+///
+/// The translation is in full, the original it was made from dimmed under it. The call goes on
+/// meanwhile, only the transcript is out of sight.
+pub fn pane() -> std::io::Result<()> {
+    if CAPTIONS.lock().unwrap().0.is_none() {
+        println!("[captions are off, turn them on with /captions <language>]");
+        return Ok(());
+    }
+
+    full_screen::show(|out| loop {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+
+        // Newest at the bottom, as many as fit
+        let (language, lines) = {
+            let captions = CAPTIONS.lock().unwrap();
+            let mut lines = Vec::new();
+            for caption in captions.1.iter().rev() {
+                let mut block: Vec<String> = wrap(&caption.translation, width).into_iter().map(|line| line.bold().to_string()).collect();
+                block.extend(wrap(&caption.original, width).into_iter().map(|line| line.dim().to_string()));
+                block.push(String::new());
+                if lines.len() + block.len() > height.saturating_sub(1) {
+                    break;
+                }
+                block.reverse();
+                lines.extend(block);
+            }
+            lines.reverse();
+            (captions.0.clone().unwrap_or_default(), lines)
+        };
+
+        let header = format!("Captions in {}: q return to the call", language);
+        full_screen::draw(out, std::iter::once(truncate(&header, width).dim().to_string()).chain(lines))?;
+
+        if !event::poll(PANE_REFRESH)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+            return Ok(());
+        }
+    })
+}
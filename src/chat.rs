@@ -0,0 +1,99 @@
+use url::Url;
+
+use crate::gateway::Gateway;
+
+/// Model for the one-off requests of a call, e.g. summaries and translations
+pub const DEFAULT_CHAT_MODEL: &str = "gpt-4o-mini";
+
+/// Chat Completions, next to the realtime endpoint of a gateway
+///
+/// A call makes the odd request that needs no realtime session: a recap, a translation, a reply
+/// while the realtime service is down. They go where the call goes and authenticate the same
+/// way, so a call through a gateway doesn't reach out to the OpenAI API behind its back.
+#[derive(Clone)]
+pub struct ChatCompletions {
+    gateway: Gateway,
+    http: reqwest::Client,
+}
+
+impl ChatCompletions {
+    pub fn new(gateway: Gateway) -> Self {
+        Self { gateway, http: reqwest::Client::new() }
+    }
+
+    /// Sends a request and returns the content of the first choice, trimmed
+    pub async fn complete(&self, request: &serde_json::Value) -> Result<String, Box<dyn std::error::Error>> {
+        let mut post = self.http.post(chat_url(&self.gateway.url)?).json(request);
+        for (name, value) in self.gateway.auth_headers()? {
+            post = post.header(name, value);
+        }
+
+        let response = post.send().await?;
+        if !response.status().is_success() {
+            return Err(format!("Chat Completions answered {}: {}", response.status(), response.text().await?).into());
+        }
+        let body: serde_json::Value = response.json().await?;
+        let content = body["choices"][0]["message"]["content"].as_str().ok_or("Chat Completions response has no content")?;
+        Ok(content.trim().to_string())
+    }
+}
+
+/// The Chat Completions URL next to a realtime WebSocket URL, e.g. wss://host/v1/realtime to https://host/v1/chat/completions
+fn chat_url(realtime_url: &str) -> Result<Url, Box<dyn std::error::Error>> {
+    let mut url = Url::parse(realtime_url)?;
+    let scheme = match url.scheme() {
+        "wss" => "https",
+        "ws" => "http",
+        _ => return Err(format!("{} isn't a WebSocket URL", realtime_url).into()),
+    };
+    url.set_scheme(scheme).map_err(|_| format!("Can't use {} over HTTP", realtime_url))?;
+
+    let path = url.path().trim_end_matches('/');
+    let Some(base) = path.strip_suffix("/realtime") else {
+        return Err(format!("Can't tell where Chat Completions are from {}, its path doesn't end in /realtime", realtime_url).into());
+    };
+    url.set_path(&format!("{}/chat/completions", base));
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::AuthScheme;
+
+    #[test]
+    fn chat_url_sits_next_to_the_realtime_one() {
+        assert_eq!(chat_url("wss://api.openai.com/v1/realtime").unwrap().as_str(), "https://api.openai.com/v1/chat/completions");
+        assert_eq!(chat_url("ws://localhost:8080/proxy/v1/realtime/").unwrap().as_str(), "http://localhost:8080/proxy/v1/chat/completions");
+        // The query is the gateway's, e.g. an API version, and stays
+        assert_eq!(
+            chat_url("wss://gw.example.com/openai/realtime?api-version=2024-10-01").unwrap().as_str(),
+            "https://gw.example.com/openai/chat/completions?api-version=2024-10-01"
+        );
+
+        assert!(chat_url("https://api.openai.com/v1/realtime").is_err());
+        assert!(chat_url("wss://gw.example.com/socket").is_err());
+    }
+
+    #[test]
+    fn requests_authenticate_like_the_handshake() {
+        let gateway = Gateway {
+            url: "wss://gw.example.com/v1/realtime".to_string(),
+            api_key: Some("sk-test".to_string()),
+            auth: AuthScheme::Header,
+            auth_header: "api-key".to_string(),
+            headers: vec![("X-Team".to_string(), "voice".to_string())],
+            subprotocols: vec!["realtime".to_string()],
+        };
+        assert_eq!(
+            gateway.auth_headers().unwrap(),
+            vec![("api-key".to_string(), "sk-test".to_string()), ("X-Team".to_string(), "voice".to_string())]
+        );
+
+        let bearer = Gateway { auth: AuthScheme::Bearer, ..gateway.clone() };
+        assert_eq!(bearer.auth_headers().unwrap()[0], ("Authorization".to_string(), "Bearer sk-test".to_string()));
+
+        let none = Gateway { auth: AuthScheme::None, api_key: None, ..gateway };
+        assert_eq!(none.auth_headers().unwrap(), vec![("X-Team".to_string(), "voice".to_string())]);
+    }
+}
@@ -1,127 +1,608 @@
 use futures::stream::{SplitSink, SplitStream};
 use tokio::net::TcpStream;
-use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{connect_async, tungstenite::client::IntoClientRequest, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::{handshake::client::Request, Message};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use futures::{SinkExt, StreamExt};
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 use uuid::Uuid;
-use url::Url;
 
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
-use crate::handle_events::handle_events;
+use crate::audio_utils::{base64_audio_bytes, base64_encode_audio, convert_audio_to_server, headless_audio_stream, initialize_audio_stream, normalize_loudness, read_audio, SERVER_SAMPLE_RATE};
+use crate::commands::{Command, InternalCommand};
+use crate::chat::{ChatCompletions, DEFAULT_CHAT_MODEL};
+use crate::gateway::{AuthScheme, Gateway};
+use crate::fallback::TextFallback;
+use crate::conversation::{ConversationItem, ConversationItemRole, ConversationTracker, ItemKind, Sentiment, TalkOver, side_channel_tag, SIDE_CHANNEL_METADATA, SUMMARY_METADATA, TRANSLATION_METADATA, CHAPTER_METADATA, CAPTION_METADATA};
+use crate::handle_events::{handle_events, Cue, InterruptionMode, LoopGuard, MicPolicy, NotificationSettings, TextStyle};
+use crate::metadata::SessionMetadata;
+use crate::pending::PendingOperations;
+use crate::recorder::Recorder;
+use crate::rtp::RtpOutput;
+use crate::text_layout::truncate;
+use crate::thinking_sound::ThinkingSound;
+use crate::session_summary::ErrorTracker;
+use crate::usage::{Budget, UsageTracker};
 
 // Defaults
-const DEFAULT_URL: &str = "wss://api.openai.com/v1/realtime";
-const DEFAULT_MODEL: &str = "gpt-4o-realtime-preview-2024-10-01";
+pub const DEFAULT_URL: &str = "wss://api.openai.com/v1/realtime";
+pub const DEFAULT_MODEL: &str = "gpt-4o-realtime-preview-2024-10-01";
+pub const DEFAULT_EVENT_BUFFER: usize = 100;
+
+// Longer user messages are sent as several conversation items
+const MAX_MESSAGE_CHARS: usize = 8000;
+
+// Audio clips are sent as a single event, which the server caps at 15 MB (a little over 3 minutes of base64 pcm16)
+const MAX_CLIP_SECONDS: usize = 180;
+
+/// Most audio the API accepts in one input_audio_buffer.append, 15 MiB of pcm16
+pub const MAX_APPEND_BYTES: usize = 15 * 1024 * 1024;
+
+// Reconnecting after a dropped connection, waiting a little longer before each attempt
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+
+// With the summarized replay policy, the last few items are replayed as they were and the rest summarized
+const RECAP_RECENT_ITEMS: usize = 4;
+const RECAP_LINE_WIDTH: usize = 200;        // Per item, in the request for the summary or in its place
+const SUMMARY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Session parameters a model accepts, checked before connecting rather than left to a server error
+#[derive(Debug, Clone)]
+pub struct ModelLimits {
+    pub temperature: RangeInclusive<f32>,
+    pub speed: RangeInclusive<f32>,
+}
+
+/// Limits of the realtime models by name prefix, None for models (e.g. behind a gateway) we know nothing about
+pub fn model_limits(model: &str) -> Option<ModelLimits> {
+    const REALTIME_MODELS: [&str; 3] = ["gpt-4o-realtime", "gpt-4o-mini-realtime", "gpt-realtime"];
+
+    REALTIME_MODELS.iter().any(|prefix| model.starts_with(prefix)).then_some(ModelLimits {
+        temperature: 0.6..=1.2,
+        speed: 0.25..=1.5,
+    })
+}
+
+/// Fails with what to change if the session configuration is outside what the model accepts
+fn check_session_config(config: &SessionConfig, model: &str) -> Result<(), String> {
+    let Some(limits) = model_limits(model) else {
+        tracing::info!(model, "no known limits for this model, the session configuration is sent unchecked");
+        return Ok(());
+    };
+
+    let temperature = config.temperature;
+    if !limits.temperature.contains(&temperature) {
+        return Err(format!(
+            "{} takes a temperature from {} to {}, not {}",
+            model, limits.temperature.start(), limits.temperature.end(), temperature
+        ));
+    }
+
+    match config.speed {
+        Some(speed) if !limits.speed.contains(&speed) => {
+            Err(format!("{} takes a speech speed from {} to {}, not {}", model, limits.speed.start(), limits.speed.end(), speed))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Builds the WebSocket handshake request for the Realtime API
+pub fn realtime_request(url: &str, api_key: &str, model: &str) -> Result<Request, Box<dyn std::error::Error>> {
+    Gateway {
+        url: url.to_string(),
+        api_key: Some(api_key.to_string()),
+        ..Gateway::default()
+    }
+    .request(model)
+}
 
 
 // Define structs for various types used in the API
 
+/// Output modality of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modality {
+    Text,
+    Audio,      // Always paired with Text, the API sends the transcript along
+}
+
+/// Voice of audio responses, can't be changed once the assistant has spoken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Voice {
+    Alloy,
+    Ash,
+    Ballad,
+    Coral,
+    Echo,
+    Sage,
+    Shimmer,
+    Verse,
+}
+
+impl Voice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Alloy => "alloy",
+            Self::Ash => "ash",
+            Self::Ballad => "ballad",
+            Self::Coral => "coral",
+            Self::Echo => "echo",
+            Self::Sage => "sage",
+            Self::Shimmer => "shimmer",
+            Self::Verse => "verse",
+        }
+    }
+}
+
+/// Encoding of input and output audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    Pcm16,      // 16-bit PCM at 24 kHz, mono, little-endian
+    G711Ulaw,   // 8 kHz telephony
+    G711Alaw,   // 8 kHz telephony
+}
+
+/// How the model picks tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolChoice {
+    Auto,
+    None,
+    Required,
+}
+
+/// What carries over into the new session when the connection drops mid-call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReplayPolicy {
+    Full,           // Replay every message, as `/switch` does
+    Summarized,     // Replay the last few messages, earlier ones summarized
+    Fresh,          // Start over, with a note that the earlier conversation was lost
+}
+
+impl ReplayPolicy {
+    /// Splits the history for a new session: the items replayed first, those summarized after
+    /// them, and the recent ones replayed last
+    ///
+    /// Pinned items are replayed in full whatever the policy.
+    fn split(self, history: &[ConversationItem]) -> (Vec<ConversationItem>, Vec<ConversationItem>, Vec<ConversationItem>) {
+        match self {
+            Self::Full => (history.to_vec(), Vec::new(), Vec::new()),
+            Self::Summarized => {
+                let (earlier, recent) = history.split_at(history.len().saturating_sub(RECAP_RECENT_ITEMS));
+                let (pinned, earlier) = earlier.iter().cloned().partition(|item| item.pinned);
+                (pinned, earlier, recent.to_vec())
+            }
+            Self::Fresh => (history.iter().filter(|item| item.pinned).cloned().collect(), Vec::new(), Vec::new()),
+        }
+    }
+}
+
+/// Limit on the tokens of a single response, the API takes 1 to 4096 or "inf"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxTokens {
+    Limit(u32),
+    Inf,
+}
+
+impl MaxTokens {
+    pub const MAX_LIMIT: u32 = 4096;
+}
+
+impl std::str::FromStr for MaxTokens {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "inf" {
+            return Ok(Self::Inf);
+        }
+
+        match value.parse() {
+            Ok(limit @ 1..=Self::MAX_LIMIT) => Ok(Self::Limit(limit)),
+            _ => Err(format!("Expected a number of tokens from 1 to {} or \"inf\", got {:?}", Self::MAX_LIMIT, value)),
+        }
+    }
+}
+
+impl Serialize for MaxTokens {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Limit(limit) => serializer.serialize_u32(*limit),
+            Self::Inf => serializer.serialize_str("inf"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MaxTokens {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Either a number or "inf", checked against the same bounds as the command line
+        match Value::deserialize(deserializer)? {
+            Value::Number(number) => number.to_string().parse().map_err(serde::de::Error::custom),
+            Value::String(text) => text.parse().map_err(serde::de::Error::custom),
+            other => Err(serde::de::Error::custom(format!("Expected a number of tokens or \"inf\", got {}", other))),
+        }
+    }
+}
+
 /// Represents the configuration for a session with the OpenAI Realtime API
-#[derive(Debug, Serialize, Deserialize)]
-struct SessionConfig {
-    modalities: Vec<String>,        // Supported modalities (e.g., "text", "audio")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionConfig {
+    modalities: Vec<Modality>,      // Supported modalities (e.g., "text", "audio")
     instructions: String,           // Custom instructions for the AI
-    voice: String,                  // Voice type for audio responses
-    input_audio_format: String,     // Format of input audio (e.g., "pcm16")
-    output_audio_format: String,    // Format of output audio
+    voice: Voice,                   // Voice type for audio responses
+    input_audio_format: AudioFormat,    // Format of input audio (e.g., "pcm16")
+    output_audio_format: AudioFormat,   // Format of output audio
     input_audio_transcription: Option<Value>,  // Configuration for audio transcription
     turn_detection: Option<Value>,  // Configuration for turn detection in conversations
     tools: Vec<Value>,              // Available tools or functions for the AI to use
-    tool_choice: String,            // How the AI should choose tools
+    tool_choice: ToolChoice,        // How the AI should choose tools
     temperature: f32,               // Controls randomness in AI responses
-    max_response_output_tokens: u32,  // Maximum number of tokens in AI responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f32>,             // How fast spoken responses are, the server's default when None
+    max_response_output_tokens: MaxTokens,  // Maximum number of tokens in AI responses
 }
 
 // Default SessionConfig implementation
 impl Default for SessionConfig {
     fn default() -> Self {
         Self {
-            modalities: vec!["text".to_string(), "audio".to_string()],
+            modalities: vec![Modality::Text, Modality::Audio],
             instructions: String::new(),
-            voice: "alloy".to_string(),
-            input_audio_format: "pcm16".to_string(),
-            output_audio_format: "pcm16".to_string(),
-            input_audio_transcription: None,
-            turn_detection: None,
+            voice: Voice::Alloy,
+            input_audio_format: AudioFormat::Pcm16,
+            output_audio_format: AudioFormat::Pcm16,
+            input_audio_transcription: Some(serde_json::json!({"model": "whisper-1"})),
+            turn_detection: Some(serde_json::json!({"type": "server_vad"})),
             tools: Vec::new(),
-            tool_choice: "auto".to_string(),
+            tool_choice: ToolChoice::Auto,
             temperature: 0.8,
-            max_response_output_tokens: 4096,
+            speed: None,
+            max_response_output_tokens: MaxTokens::Limit(MaxTokens::MAX_LIMIT),
         }
     }
 }
 
+/// Builds up a configuration for `RealtimeClientBuilder::session()`, from the defaults
+///
+/// Nothing is checked here, build() checks the whole of it against the model.
+impl SessionConfig {
+    pub fn modalities(mut self, modalities: Vec<Modality>) -> Self {
+        self.modalities = modalities;
+        self
+    }
+
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    pub fn voice(mut self, voice: Voice) -> Self {
+        self.voice = voice;
+        self
+    }
+
+    pub fn audio_format(mut self, input: AudioFormat, output: AudioFormat) -> Self {
+        self.input_audio_format = input;
+        self.output_audio_format = output;
+        self
+    }
+
+    /// Transcription of the user's audio, e.g. `{"model": "whisper-1"}`, or None for none
+    pub fn input_audio_transcription(mut self, transcription: Option<Value>) -> Self {
+        self.input_audio_transcription = transcription;
+        self
+    }
+
+    /// Turn detection, e.g. `{"type": "server_vad"}`, or None to end turns by hand
+    pub fn turn_detection(mut self, turn_detection: Option<Value>) -> Self {
+        self.turn_detection = turn_detection;
+        self
+    }
+
+    pub fn tools(mut self, tools: Vec<Value>, tool_choice: ToolChoice) -> Self {
+        self.tools = tools;
+        self.tool_choice = tool_choice;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    pub fn max_response_output_tokens(mut self, max_tokens: MaxTokens) -> Self {
+        self.max_response_output_tokens = max_tokens;
+        self
+    }
+}
+
+/// State of a call to carry it on later, e.g. in another process, see RealtimeClient::snapshot()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSnapshot {
+    pub model: String,
+    pub session: SessionConfig,             // As requested, the server's own defaults aside
+    #[serde(default)]
+    pub metadata: SessionMetadata,
+    pub items: Vec<ConversationItem>,
+}
+
 /// Main client for interacting with the OpenAI Realtime API
 pub struct RealtimeClient {
-    url: String,                                                    // WebSocket URL
-    api_key: String,                                                // OpenAI API key
+    gateway: Gateway,                                               // WebSocket URL, API key and handshake headers
 
     is_connected: bool,                                             // Connection status
+    model: String,                                                  // Model of the current (or last) connection
 
     ws_read: Option<SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>>,    // WebSocket read stream
     ws_write: Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>,   // WebSocket write stream
 
     session_config: SessionConfig,                                  // Current session configuration
+    acknowledged_session: Arc<Mutex<Option<Value>>>,                // Session as last confirmed by the server, updated by the read task
+    next_response_modalities: Option<Vec<Modality>>,                // Modalities override for the next response only
+    session_metadata: SessionMetadata,                              // Attached to every response the client requests
+    translation_language: Option<String>,                           // Assistant messages are translated into it, for tutoring
+    captions_language: Option<String>,                              // Assistant speech is captioned in it, sentence by sentence
+    usage: Arc<Mutex<UsageTracker>>,                                // Token usage, shared with the event handler
+    errors: Arc<Mutex<ErrorTracker>>,                               // Errors run into, shared with the event handler
+    conversation: Arc<Mutex<ConversationTracker>>,                  // Local model of the conversation, shared with the event handler
+    earlier: EarlierSessions,                                       // Conversation of the sessions left by reconnecting or switching
+    recorder: Arc<Mutex<Recorder>>,                                 // Protocol dump and audio recording, shared with the event handler
+    pending: Arc<Mutex<PendingOperations>>,                         // Operations the server hasn't acknowledged, tracked by the event handler
     event_sender: mpsc::Sender<Value>,                              // Event sender
+    command_sender: mpsc::Sender<Command>,                          // Command sender, shared with the event handler
+    command_receiver: Option<mpsc::Receiver<Command>>,              // Command receiver, taken by the caller driving the client
+    next_ping: u64,                                                 // Id of the next heartbeat ping, echoed back in its pong
+    event_handler: Option<JoinHandle<()>>,                          // Dispatches events to the subscribers, awaited on shutdown
+    reader: Option<JoinHandle<()>>,                                 // Read task of the current connection
 }
 
-impl RealtimeClient {
-    /// Creates a new RealtimeClient with default configuration
-    pub fn new(url: Option<&str>, api_key: Option<&str>) -> Self {
+/// The conversation of the sessions a call has left, kept for its transcript
+///
+/// A new session starts by replaying the conversation so far, or some of it, as text: audio goes
+/// by its transcript and summarized items are gone. The transcript should have what was actually
+/// said, so the items of each session left are kept as they were and the replays skipped.
+#[derive(Debug, Default)]
+struct EarlierSessions {
+    items: Vec<ConversationItem>,
+    replayed: usize,        // Items opening the current session that stand in for earlier ones
+}
 
-        let (event_sender, event_receiver) = mpsc::channel(100);
-        
-        // Spawn a task to handle events
-        tokio::spawn(handle_events(event_receiver));
-        
-        let url = url.unwrap_or(DEFAULT_URL);
+impl EarlierSessions {
+    /// Sets aside the items of the session being left, before the next one replays them
+    fn leave(&mut self, history: &[ConversationItem]) {
+        self.items.extend(history.iter().skip(self.replayed).cloned());
+        self.replayed = 0;
+    }
+
+    /// Earlier items, then those of the current session after its replays
+    fn transcript(&self, current: &[ConversationItem]) -> Vec<ConversationItem> {
+        self.items.iter().chain(current.iter().skip(self.replayed)).cloned().collect()
+    }
+}
+
+/// Cheap to clone handle for driving a call from other tasks and threads
+///
+/// The client stays with the task running the call, which owns the connection and carries out
+/// commands one at a time, in the order they were queued. A handle only queues commands, so
+/// the microphone, timers and input reader can all send to the server or hang up without the
+/// client being moved into any of them or locked. Sending fails once the call has ended.
+#[derive(Clone)]
+pub struct ClientHandle {
+    commands: mpsc::Sender<Command>,
+}
+
+impl ClientHandle {
+    /// Queues a command for the call
+    pub async fn send(&self, command: Command) -> Result<(), String> {
+        self.commands.send(command).await.map_err(|_| "The call has ended".to_string())
+    }
+
+    /// Queues microphone audio (base64 pcm16), from a thread outside the runtime
+    pub fn append_audio_blocking(&self, base64_audio_data: String) -> Result<(), String> {
+        self.commands.blocking_send(Command::Internal(InternalCommand::AppendAudio(base64_audio_data))).map_err(|_| "The call has ended".to_string())
+    }
+
+    /// Notes microphone audio held back as quiet by the local VAD, from a thread outside the runtime
+    pub fn gated_audio_blocking(&self, samples: usize) -> Result<(), String> {
+        self.commands.blocking_send(Command::Internal(InternalCommand::GatedAudio(samples))).map_err(|_| "The call has ended".to_string())
+    }
+
+    /// Ends a manual turn after the microphone audio queued before it, from a thread outside the runtime
+    pub fn commit_blocking(&self) -> Result<(), String> {
+        self.commands.blocking_send(Command::Internal(InternalCommand::Commit)).map_err(|_| "The call has ended".to_string())
+    }
+
+    /// Ends the call, the client disconnects once the commands queued before are done
+    pub async fn hang_up(&self) -> Result<(), String> {
+        self.send(Command::Quit).await
+    }
+}
+
+/// Why a client couldn't be built
+#[derive(Debug)]
+pub enum BuildError {
+    NoRuntime,                  // Built outside a Tokio runtime, which the event handler runs on
+    MissingApiKey,              // The gateway takes a key, but none was given nor found in OPENAI_API_KEY
+    InvalidUrl(String),         // Not a ws:// or wss:// URL
+    InvalidSession(String),     // The session configuration is outside what the model accepts
+    NoAudioOutput(String),      // The assistant has nothing to play on
+}
 
-        // Get the API key from the argument or environment variable
-        let api_key = api_key
-            .map(|key| key.to_string())
-            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
-            .expect("API key must be provided either as an argument or in the environment variable OPENAI_API_KEY");
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NoRuntime => write!(f, "A RealtimeClient has to be built within a Tokio runtime"),
+            Self::MissingApiKey => write!(f, "API key must be provided either to the builder or in the environment variable OPENAI_API_KEY"),
+            Self::InvalidUrl(url) => write!(f, "Not a WebSocket URL: {}", url),
+            Self::InvalidSession(reason) => write!(f, "Invalid session configuration: {}", reason),
+            Self::NoAudioOutput(reason) => write!(f, "No audio output: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
 
+/// Configures a RealtimeClient, see `RealtimeClient::builder()`
+///
+/// Nothing happens until `build()`, which checks the configuration and only then starts the
+/// event handler and the playback thread. The client connects on `connect()`.
+pub struct RealtimeClientBuilder {
+    gateway: Gateway,
+    model: String,              // Connected to by connect(None)
+    session: SessionConfig,
+    event_buffer: usize,        // Events queued for the event handler, and for each of its subscribers, before senders wait
+    headless: bool,             // Plays on no local output device
+}
+
+impl Default for RealtimeClientBuilder {
+    fn default() -> Self {
         Self {
-            url: url.to_string(),
-            api_key,
+            gateway: Gateway::default(),
+            model: DEFAULT_MODEL.to_string(),
+            session: SessionConfig::default(),
+            event_buffer: DEFAULT_EVENT_BUFFER,
+            headless: false,
+        }
+    }
+}
+
+impl RealtimeClientBuilder {
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.gateway.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.gateway.url = url.into();
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn session(mut self, session: SessionConfig) -> Self {
+        self.session = session;
+        self
+    }
+
+    pub fn event_buffer(mut self, capacity: usize) -> Self {
+        self.event_buffer = capacity.max(1);
+        self
+    }
+
+    /// Plays on no local output device, for servers and CI; mirrors and RTP outputs can still be added
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    /// Connects through a self-hosted or proxying gateway, replacing any URL or API key set before
+    pub fn transport(mut self, gateway: Gateway) -> Self {
+        self.gateway = gateway;
+        self
+    }
+
+    /// Checks the configuration, then starts the event handler and opens the default output device unless headless
+    pub fn build(self) -> Result<RealtimeClient, BuildError> {
+        let runtime = tokio::runtime::Handle::try_current().map_err(|_| BuildError::NoRuntime)?;
+        if self.gateway.auth != AuthScheme::None && self.gateway.api_key.is_none() {
+            return Err(BuildError::MissingApiKey);
+        }
+        if !url::Url::parse(&self.gateway.url).is_ok_and(|url| matches!(url.scheme(), "ws" | "wss")) {
+            return Err(BuildError::InvalidUrl(self.gateway.url));
+        }
+        check_session_config(&self.session, &self.model).map_err(BuildError::InvalidSession)?;
+        let audio = match self.headless {
+            true => headless_audio_stream(),
+            false => initialize_audio_stream(),
+        }
+        .map_err(BuildError::NoAudioOutput)?;
+
+        let (event_sender, event_receiver) = mpsc::channel(self.event_buffer);
+        let (command_sender, command_receiver) = mpsc::channel(100);
+        let usage = Arc::new(Mutex::new(UsageTracker::default()));
+        let errors = Arc::new(Mutex::new(ErrorTracker::default()));
+        let conversation = Arc::new(Mutex::new(ConversationTracker::default()));
+        let recorder = Arc::new(Mutex::new(Recorder::default()));
+        let pending = Arc::new(Mutex::new(PendingOperations::default()));
+
+        // Spawn a task to handle events, playing audio on the default output device
+        let event_handler = runtime.spawn(handle_events(
+            event_receiver,
+            command_sender.clone(),
+            usage.clone(),
+            errors.clone(),
+            conversation.clone(),
+            recorder.clone(),
+            pending.clone(),
+            audio,
+            self.event_buffer,
+        ));
+
+        Ok(RealtimeClient {
+            gateway: self.gateway,
 
             is_connected: false,
+            model: self.model,
 
             ws_read: None,
             ws_write: None,
-            session_config: SessionConfig::default(),
-            event_sender
-        }
+            session_config: self.session,
+            acknowledged_session: Arc::new(Mutex::new(None)),
+            next_response_modalities: None,
+            session_metadata: SessionMetadata::default(),
+            translation_language: None,
+            captions_language: None,
+            usage,
+            errors,
+            conversation,
+            recorder,
+            pending,
+            event_sender,
+            command_sender,
+            command_receiver: Some(command_receiver),
+            next_ping: 0,
+            event_handler: Some(event_handler),
+            reader: None,
+            earlier: EarlierSessions::default(),
+        })
+    }
+}
+
+impl RealtimeClient {
+    /// Starts building a client, for the OpenAI API with the key in OPENAI_API_KEY unless told otherwise
+    pub fn builder() -> RealtimeClientBuilder {
+        RealtimeClientBuilder::default()
     }
 
     /// Establishes a WebSocket connection with the OpenAI Realtime API
     pub async fn connect(&mut self, model: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
         if self.is_connected {
-            return Err("RealtimeClient is already , use .disconnect() first".into());
+            return Err("RealtimeClient is already connected, use .disconnect() first".into());
         }
 
-        // Clone the URL and parse it into a URL object
-        let mut url = Url::parse(&self.url)?;
-
-        // Add the model parameter to the URL if provided
-        url.query_pairs_mut().append_pair("model", model.unwrap_or(DEFAULT_MODEL));
-
-        // Create a new WebSocket client request from the URL
-        let mut request = url.into_client_request()?;
-        
-        // Add the necessary headers to the request
-        let headers = request.headers_mut();
-        headers.insert(
-            "Authorization",
-            format!("Bearer {}", self.api_key).parse().unwrap(),
-        );
-        headers.insert("OpenAI-Beta", "realtime=v1".parse().unwrap());
+        // Without one, the model of the last connection or the one built with
+        let model = model.unwrap_or(&self.model).to_string();
+        check_session_config(&self.session_config, &model)?;
+        let request = self.gateway.request(&model)?;
+        self.model = model.clone();
 
         let (ws_stream, _) = connect_async(request).await?;
+        tracing::info!(model, "connected");
 
         // Split the WebSocket stream into read and write halves
         let (ws_write, ws_read) = ws_stream.split();
@@ -130,22 +611,29 @@ impl RealtimeClient {
         self.ws_write = Some(ws_write);
 
         self.is_connected = true;
-        
-        self.start_handling_messages().await?;  // Start handling incoming messages
 
-        self.update_session().await?;  // Send session configuration
+        // A connection that doesn't get going is let go of, so connecting can be tried again
+        if let Err(e) = self.start_session(&model).await {
+            self.drop_connection();
+            return Err(e);
+        }
         Ok(())
     }
 
     /// Closes the WebSocket connection
     pub async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if self.is_connected {
+            // The server answers with a Close frame of its own, which isn't a dropped connection
+            if let Some(reader) = self.reader.take() {
+                reader.abort();
+            }
             if let Some(ws_write) = &mut self.ws_write {
                 ws_write.send(Message::Close(None)).await?;
             }
             self.ws_write = None;
             self.ws_read = None;
             self.is_connected = false;
+            *self.acknowledged_session.lock().unwrap() = None;   // The next connection starts a new session
         } 
         else {
             return Err("RealtimeClient is not connected".into());
@@ -153,14 +641,229 @@ impl RealtimeClient {
         Ok(())
     }
 
-    /// Sends the current session configuration to the API
+    /// Moves the call to a fresh session with another model and/or instructions, replaying the conversation so far
+    ///
+    /// Only the text of each message carries over, audio is replayed as its transcript.
+    pub async fn switch_session(&mut self, model: &str, instructions: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let history = self.conversation.lock().unwrap().items().to_vec();
+
+        // Better to stay in the current session than to find out after leaving it
+        check_session_config(&self.session_config, model)?;
+
+        self.disconnect().await?;
+        if let Some(instructions) = instructions {
+            self.session_config.instructions = instructions.to_string();
+        }
+
+        // The replayed items come back with new ids, so start the local model over
+        self.conversation.lock().unwrap().clear();
+        self.earlier.leave(&history);
+        self.connect(Some(model)).await?;
+
+        self.earlier.replayed = self.replay_history(&history).await?;
+        Ok(())
+    }
+
+    /// Checkpoints the call: model, session configuration, metadata and the conversation so far
+    pub fn snapshot(&self) -> ConversationSnapshot {
+        ConversationSnapshot {
+            model: self.model.clone(),
+            session: self.session_config.clone(),
+            metadata: self.session_metadata.clone(),
+            items: self.conversation.lock().unwrap().items().to_vec(),
+        }
+    }
+
+    /// Carries on a call from a snapshot, in a fresh session replaying its conversation
+    ///
+    /// Connects if need be, a current session is left. As with switch_session(), only the text of
+    /// each message carries over and the items get new ids. The call goes on with the snapshot's
+    /// model and session configuration; to carry on with other settings, change `snapshot.session` first.
+    pub async fn restore(&mut self, snapshot: ConversationSnapshot) -> Result<(), Box<dyn std::error::Error>> {
+        let model = if snapshot.model.is_empty() { DEFAULT_MODEL } else { &snapshot.model };
+        if self.is_connected {
+            self.disconnect().await?;
+        }
+
+        self.session_metadata = snapshot.metadata;
+        self.session_config = snapshot.session;
+        let history = self.conversation.lock().unwrap().items().to_vec();
+        self.conversation.lock().unwrap().clear();
+        self.earlier.leave(&history);
+        self.connect(Some(model)).await?;
+
+        // The snapshot's items go in the transcript as they were, like those of a session left behind
+        self.earlier.replayed = self.replay_history(&snapshot.items).await?;
+        self.earlier.items.extend(snapshot.items);
+        Ok(())
+    }
+
+    /// Opens a new session after the connection dropped, carrying the conversation over per `policy`
+    ///
+    /// The server keeps nothing of a dropped session, so whatever carries over is replayed as text.
+    pub async fn reconnect(&mut self, policy: ReplayPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        let history = self.conversation.lock().unwrap().items().to_vec();
+        let model = self.model.clone();
+
+        // The socket is already gone, there is nothing to close
+        self.drop_connection();
+
+        tracing::info!(?policy, "connection dropped, reconnecting");
+        let mut attempt = 1;
+        while let Err(e) = self.connect(Some(&model)).await {
+            tracing::debug!(attempt, error = %e, "reconnect attempt failed");
+            if attempt == RECONNECT_ATTEMPTS {
+                return Err(format!("Could not reconnect after {} attempts: {}", attempt, e).into());
+            }
+            eprintln!("Reconnect attempt {} failed: {}", attempt, e);
+            tokio::time::sleep(RECONNECT_BACKOFF * attempt).await;
+            attempt += 1;
+        }
+        // Only now, so the conversation is still there should reconnecting fail
+        self.conversation.lock().unwrap().clear();
+        self.earlier.leave(&history);
+
+        let (first, earlier, recent) = policy.split(&history);
+        let mut replayed = self.replay_history(&first).await?;
+        match policy {
+            ReplayPolicy::Full => {}
+            ReplayPolicy::Summarized => {
+                if let Some(summary) = self.summarize(&earlier).await {
+                    self.send_system_message(&format!(
+                        "The connection dropped and this is a new session. Summary of the earlier conversation:\n{}",
+                        summary
+                    )).await?;
+                    replayed += 1;
+                }
+                replayed += self.replay_history(&recent).await?;
+            }
+            ReplayPolicy::Fresh if history.is_empty() => {}
+            ReplayPolicy::Fresh => {
+                self.send_system_message(
+                    "The connection dropped and this is a new session, the earlier conversation was lost \
+                     (apart from any messages above). If the user refers to it, ask them to recap."
+                ).await?;
+                replayed += 1;
+            }
+        }
+        self.earlier.replayed = replayed;
+        Ok(())
+    }
+
+    /// Summarizes items for a new session with Chat Completions, or condenses them line by line should that fail
+    async fn summarize(&self, items: &[ConversationItem]) -> Option<String> {
+        let lines = items
+            .iter()
+            .map(|item| truncate(&item.plain_line(), RECAP_LINE_WIDTH))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return None;
+        }
+
+        let request = serde_json::json!({
+            "model": DEFAULT_CHAT_MODEL,
+            "messages": [
+                {
+                    "role": "system",
+                    "content": "Summarize this conversation between a user and a voice assistant so the assistant can carry on from it: \
+                                what was discussed, decided or promised, and anything the user asked it to remember. A few sentences, plain text."
+                },
+                {"role": "user", "content": lines.join("\n")}
+            ]
+        });
+        match tokio::time::timeout(SUMMARY_TIMEOUT, self.chat_completions().complete(&request)).await {
+            Ok(Ok(summary)) if !summary.is_empty() => Some(summary),
+            result => {
+                let error = result.map_or_else(|e| e.to_string(), |result| result.err().map_or("empty summary".to_string(), |e| e.to_string()));
+                tracing::warn!(%error, "failed to summarize the conversation, replaying it condensed");
+                Some(lines.join("\n"))
+            }
+        }
+    }
+
+    /// The whole conversation of the call, with the items of earlier sessions as they were rather than as replayed
+    pub fn transcript(&self) -> Vec<ConversationItem> {
+        self.earlier.transcript(self.conversation.lock().unwrap().items())
+    }
+
+    /// Answers a typed message through the text fallback while the realtime service is down
+    ///
+    /// The message and the reply join the conversation as if the server had created them, so they
+    /// are in the transcript and replayed into the session once it can be reached again.
+    pub async fn reply_in_text(&mut self, fallback: &TextFallback, text: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let items = self.conversation.lock().unwrap().items().to_vec();
+        let reply = fallback.reply(&self.session_config.instructions, &items, text).await?;
+
+        for (role, content_type, text) in [("user", "input_text", text), ("assistant", "text", reply.as_str())] {
+            self.event_sender.send(serde_json::json!({
+                "type": "conversation.item.created",
+                "item": {
+                    "id": format!("fallback_{}", &Uuid::new_v4().simple().to_string()[..23]),
+                    "type": "message",
+                    "role": role,
+                    "status": "completed",
+                    "content": [{"type": content_type, "text": text}]
+                }
+            })).await.map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+        }
+        Ok(reply)
+    }
+
+    /// Pings the server, the event handler times the pong to show the connection health
+    pub async fn ping(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(ws_write) = &mut self.ws_write else {
+            return Ok(());  // Reconnecting, nothing to measure
+        };
+
+        let id = self.next_ping;
+        self.next_ping += 1;
+        self.event_sender.send(serde_json::json!({"type": "local.ping", "id": id})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+        ws_write.send(Message::Ping(id.to_be_bytes().to_vec())).await?;
+
+        Ok(())
+    }
+
+    /// Model of the current connection, or the one connect(None) connects to
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Sends the session configuration to the API, only the fields that differ from what the server acknowledged
     pub async fn update_session(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let data = serde_json::to_value(serde_json::json!({"session": self.session_config}))?;
-        self.send("session.update", Some(data)).await?;
+        let Value::Object(desired) = serde_json::to_value(&self.session_config)? else {
+            unreachable!("SessionConfig serializes to an object");
+        };
+
+        let session = changed_fields(desired, self.acknowledged_session.lock().unwrap().as_ref());
+        if session.is_empty() {
+            return Ok(());
+        }
+        self.send("session.update", Some(serde_json::json!({"session": session}))).await?;
 
         Ok(())
     }
 
+    /// Chat Completions through the same gateway, for the one-off requests of the call
+    pub fn chat_completions(&self) -> ChatCompletions {
+        ChatCompletions::new(self.gateway.clone())
+    }
+
+    /// Session configuration as requested, whether or not the server has it yet
+    pub fn session_config(&self) -> &SessionConfig {
+        &self.session_config
+    }
+
+    /// Instructions as requested, whether or not the server has them yet
+    pub fn instructions(&self) -> &str {
+        &self.session_config.instructions
+    }
+
+    /// The session configuration as last acknowledged by the server (`session.created`/`session.updated`)
+    pub fn session(&self) -> Option<Value> {
+        self.acknowledged_session.lock().unwrap().clone()
+    }
+
     /// Sends a message with the specified content to the API
     /// 
     pub async fn send_user_message_content(&mut self, content: Vec<Value>) -> Result<(), Box<dyn std::error::Error>> {
@@ -177,71 +880,866 @@ impl RealtimeClient {
         Ok(())
     }
 
-    /// Requests the API to generate a response
-    pub async fn create_response(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.send("response.create", None).await?;
-        
-        Ok(())
-    }
+    /// Sends a typed message, splitting text longer than MAX_MESSAGE_CHARS into several items
+    ///
+    /// Every part but the last tells the model more is coming, and a response is only
+    /// requested after the final part.
+    pub async fn send_user_text(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let chunks = split_text(text, MAX_MESSAGE_CHARS);
+        if chunks.len() == 1 {
+            return self.send_user_message_content(vec![serde_json::json!({"type": "input_text", "text": text})]).await;
+        }
 
-    /// Input audio buffer append
-    pub async fn input_audio_buffer_append(&mut self, base64_audio_data: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.send("input_audio_buffer.append", Some(serde_json::json!({
-            "audio": base64_audio_data
-        }))).await?;
+        let count = chunks.len();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let marker = if index + 1 < count {
+                format!("[Part {} of {}, the message continues in the next part. Do not reply yet.]", index + 1, count)
+            } else {
+                format!("[Part {} of {}, end of message.]", index + 1, count)
+            };
 
-        Ok(())
+            self.send("conversation.item.create", Some(serde_json::json!({
+                "item": {
+                    "type": "message",
+                    "role": "user",
+                    "content": [{"type": "input_text", "text": format!("{}\n{}", marker, chunk)}]
+                }
+            }))).await?;
+        }
+
+        self.create_response().await
     }
 
-    /// Input audio buffer commit
-    pub async fn input_audio_buffer_commit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.send("input_audio_buffer.commit", None).await?;
+    /// Sends an audio file as a user message, as if it had been spoken, and asks for a response
+    ///
+    /// The clip is a content part of its own item rather than going through the input audio
+    /// buffer, so it doesn't mix with the microphone or trip the server VAD. It's brought to
+    /// a common loudness first. Returns its duration.
+    pub async fn send_user_audio(&mut self, path: &std::path::Path) -> Result<std::time::Duration, Box<dyn std::error::Error>> {
+        let (samples, sample_rate, channels) = read_audio(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let mut audio = convert_audio_to_server(&samples, sample_rate, channels);
+        normalize_loudness(&mut audio);
+        if audio.len() > MAX_CLIP_SECONDS * SERVER_SAMPLE_RATE as usize {
+            return Err(format!("{} is longer than {} seconds, too long to send as one message", path.display(), MAX_CLIP_SECONDS).into());
+        }
 
-        Ok(())
+        self.send_user_message_content(vec![serde_json::json!({"type": "input_audio", "audio": base64_encode_audio(&audio)})]).await?;
+        Ok(std::time::Duration::from_secs_f64(audio.len() as f64 / SERVER_SAMPLE_RATE as f64))
     }
 
-    // Private methods
-
-    /// Starts handling incoming messages in a separate task
-    async fn start_handling_messages(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let event_sender = self.event_sender.clone();
-        let mut ws_read = self.ws_read.take().expect("WebSocket read stream is not initialized");
+    /// Sets the function definitions offered to the model, takes effect on connect or the next session update
+    pub fn set_tools(&mut self, tools: Vec<Value>) {
+        self.session_config.tools = tools;
+    }
 
-        tokio::spawn(async move {
-            while let Some(message) = ws_read.next().await {
-            match message {
-                Ok(Message::Text(text)) => {
-                if let Ok(value) = serde_json::from_str::<Value>(&text) {
-                    if event_sender.send(value).await.is_err() {
-                    eprintln!("Error sending event through channel");
-                    break;
-                    }
-                }
-                }
-                Err(e) => {
-                eprintln!("Error receiving WebSocket message: {}", e);
-                break;
-                }
-                _ => {}
-            }
+    /// Returns the result of a function call to the model, without asking for a response
+    pub async fn send_function_call_output(&mut self, call_id: &str, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.send("conversation.item.create", Some(serde_json::json!({
+            "item": {
+                "type": "function_call_output",
+                "call_id": call_id,
+                "output": output
             }
-        });
+        }))).await?;
 
         Ok(())
     }
 
-    /// Sends an event to WebSocket server
-    async fn send(&mut self, event_type: &str, data: Option<Value>) -> Result<(), Box<dyn std::error::Error>> {
-        let mut event = serde_json::json!({
-            "type": event_type,
-            "event_id": Uuid::new_v4().to_string(),
-        });
+    /// Overrides the modalities (e.g. only "text") used for the next response
+    ///
+    /// With server turn detection the server creates the responses to spoken turns, without the
+    /// override, so it's told to hold off until the next turn is committed and the client responds.
+    pub async fn set_next_response_modalities(&mut self, modalities: Vec<Modality>) -> Result<(), Box<dyn std::error::Error>> {
+        self.next_response_modalities = Some(modalities);
+        self.hold_server_responses(true).await
+    }
+
+    /// Has the server hold off responding to spoken turns, or respond again
+    async fn hold_server_responses(&mut self, hold: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(Value::Object(turn_detection)) = &mut self.session_config.turn_detection else {
+            return Ok(());  // Manual turns, the client responds anyway
+        };
+        if (turn_detection.get("create_response") == Some(&Value::Bool(false))) == hold {
+            return Ok(());
+        }
+        turn_detection.insert("create_response".to_string(), (!hold).into());
+        self.update_session().await?;
+
+        self.event_sender.send(serde_json::json!({"type": "local.hold_responses", "held": hold})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Sets the caller, purpose and custom key/values attached to responses
+    pub fn set_session_metadata(&mut self, metadata: SessionMetadata) {
+        self.session_metadata = metadata;
+    }
+
+    /// Sets a spending limit, once reached the session is hung up and no new responses are requested
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.usage.lock().unwrap().set_budget(budget);
+    }
+
+    /// Summary of the tokens used (and their cost) so far
+    pub fn usage_summary(&self) -> String {
+        self.usage.lock().unwrap().summary()
+    }
+
+    /// Inserts a system message to steer the assistant mid-call, without asking for a response
+    pub async fn send_system_message(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.send("conversation.item.create", Some(serde_json::json!({
+            "item": {
+                "type": "message",
+                "role": "system",
+                "content": [{"type": "input_text", "text": text}]
+            }
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Requests the API to generate a response
+    pub async fn create_response(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_budget()?;
+
+        let mut response = serde_json::Map::new();
+
+        // A modalities override only applies to a single turn
+        let overridden = self.next_response_modalities.take();
+        if let Some(modalities) = &overridden {
+            response.insert("modalities".to_string(), serde_json::json!(modalities));
+        }
+        if !self.session_metadata.is_empty() {
+            response.insert("metadata".to_string(), Value::Object(self.session_metadata.to_json()));
+        }
+
+        let data = (!response.is_empty()).then(|| serde_json::json!({"response": response}));
+        self.send("response.create", data).await?;
+
+        // Spoken turns are the server's to respond to again
+        if overridden.is_some() {
+            self.hold_server_responses(false).await?;
+        }
+        Ok(())
+    }
 
-        if let Some(data) = data {
-            event.as_object_mut().unwrap().extend(data.as_object().unwrap().clone());
+    /// Asks again after a response came back empty, with a nudge added to the instructions of this response only
+    pub async fn retry_response(&mut self, nudge: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_budget()?;
+
+        let mut response = serde_json::Map::new();
+        let instructions = format!("{}\n\n{}", self.session_config.instructions, nudge);
+        response.insert("instructions".to_string(), instructions.trim().into());
+        if !self.session_metadata.is_empty() {
+            response.insert("metadata".to_string(), Value::Object(self.session_metadata.to_json()));
         }
 
+        self.send("response.create", Some(serde_json::json!({"response": response}))).await?;
+
+        Ok(())
+    }
+
+    /// Asks a question on the side channel: an out-of-band, text-only response that
+    /// is not spoken and doesn't become part of the conversation
+    pub async fn ask_side_channel(&mut self, question: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_budget()?;
+
+        // Out-of-band responses don't see the conversation, so pass the recent turns along
+        let context = self.conversation.lock().unwrap().recent_text(10);
+
+        let mut metadata = self.session_metadata.to_json();
+        metadata.insert(SIDE_CHANNEL_METADATA.0.to_string(), SIDE_CHANNEL_METADATA.1.into());
+
+        self.send("response.create", Some(serde_json::json!({
+            "response": {
+                "conversation": "none",
+                "modalities": ["text"],
+                "metadata": metadata,
+                "instructions": "Briefly answer the user's question about the conversation below. Do not continue the conversation.",
+                "input": [{
+                    "type": "message",
+                    "role": "user",
+                    "content": [{
+                        "type": "input_text",
+                        "text": format!("Conversation so far:\n{}\n\nQuestion: {}", context, question)
+                    }]
+                }]
+            }
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Translates every finished assistant message into this language, shown next to the original
+    pub async fn set_translation(&mut self, language: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        self.translation_language = language.map(str::to_string);
+        self.event_sender.send(serde_json::json!({"type": "local.translation", "language": language})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Asks for the translation of an item's text on the side channel, tagged with the item it is for
+    pub async fn translate_item(&mut self, item_id: &str, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(language) = self.translation_language.clone() else {
+            return Ok(());
+        };
+        self.translate(TRANSLATION_METADATA, item_id, text, &language).await
+    }
+
+    /// Captions the assistant's speech in this language, shown in the /captions pane, or stops with None
+    pub async fn set_captions(&mut self, language: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        self.captions_language = language.map(str::to_string);
+        self.event_sender.send(serde_json::json!({"type": "local.captions", "language": language})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Asks for the translation of a sentence of the assistant's speech, tagged with its caption id
+    pub async fn translate_caption(&mut self, caption_id: &str, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(language) = self.captions_language.clone() else {
+            return Ok(());
+        };
+        self.translate(CAPTION_METADATA, caption_id, text, &language).await
+    }
+
+    /// Translates text on the side channel, tagged with what it's for
+    async fn translate(&mut self, tag: &str, id: &str, text: &str, language: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_budget()?;
+
+        let mut metadata = self.session_metadata.to_json();
+        metadata.insert(SIDE_CHANNEL_METADATA.0.to_string(), side_channel_tag(tag, id).into());
+
+        self.send("response.create", Some(serde_json::json!({
+            "response": {
+                "conversation": "none",
+                "modalities": ["text"],
+                "metadata": metadata,
+                "instructions": format!("Translate the user's message into {}. Reply with the translation only.", language),
+                "input": [{
+                    "type": "message",
+                    "role": "user",
+                    "content": [{"type": "input_text", "text": text}]
+                }]
+            }
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Titles the call in chapters every few turns, for navigating long calls afterwards
+    pub async fn set_chapters(&mut self, enabled: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.chapters", "enabled": enabled})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Asks for a title for the chapter in progress as the call ends, the event handler hangs up once it's in
+    pub async fn title_last_chapter(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.last_chapter"})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Asks on the side channel for a title for the chapter starting with this item
+    pub async fn title_chapter(&mut self, first_item_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(text) = self.conversation.lock().unwrap().text_since(first_item_id) else {
+            return Ok(());  // Replaced by a reconnect or switch since
+        };
+        self.check_budget()?;
+
+        let mut metadata = self.session_metadata.to_json();
+        metadata.insert(SIDE_CHANNEL_METADATA.0.to_string(), side_channel_tag(CHAPTER_METADATA, first_item_id).into());
+
+        self.send("response.create", Some(serde_json::json!({
+            "response": {
+                "conversation": "none",
+                "modalities": ["text"],
+                "metadata": metadata,
+                "instructions": "Give the part of a conversation below a chapter title of at most six words. Reply with the title only.",
+                "input": [{
+                    "type": "message",
+                    "role": "user",
+                    "content": [{"type": "input_text", "text": text}]
+                }]
+            }
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Asks for a short spoken summary of the call and its action items
+    ///
+    /// Like the side channel it's out-of-band, so it sees the transcript rather than the conversation,
+    /// but its text is kept in the transcript. The event handler hangs up once it has been played.
+    pub async fn request_summary(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_budget()?;
+
+        let transcript = self.conversation.lock().unwrap().recent_text(usize::MAX);
+
+        let mut metadata = self.session_metadata.to_json();
+        metadata.insert(SUMMARY_METADATA.0.to_string(), SUMMARY_METADATA.1.into());
+
+        self.send("response.create", Some(serde_json::json!({
+            "response": {
+                "conversation": "none",
+                "modalities": ["audio", "text"],
+                "metadata": metadata,
+                "instructions": "Summarize the call below in a few spoken sentences, then list any action items. Be brief.",
+                "input": [{
+                    "type": "message",
+                    "role": "user",
+                    "content": [{
+                        "type": "input_text",
+                        "text": format!("Call transcript:\n{}", transcript)
+                    }]
+                }]
+            }
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Cancels the in-progress response
+    pub async fn cancel_response(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.send("response.cancel", None).await?;
+
+        Ok(())
+    }
+
+    /// Truncates an assistant audio item to the part that was actually played
+    pub async fn truncate_item(&mut self, item_id: &str, content_index: u64, audio_end_ms: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.send("conversation.item.truncate", Some(serde_json::json!({
+            "item_id": item_id,
+            "content_index": content_index,
+            "audio_end_ms": audio_end_ms
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Stops the assistant: playback stops locally and the event handler
+    /// follows up with CancelResponse / TruncateItem commands as needed
+    pub async fn interrupt(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.interrupt"})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Session as last acknowledged by the server, kept up to date by the read task
+    pub fn shared_session(&self) -> Arc<Mutex<Option<Value>>> {
+        self.acknowledged_session.clone()
+    }
+
+    /// Local model of the conversation
+    pub fn conversation(&self) -> Arc<Mutex<ConversationTracker>> {
+        self.conversation.clone()
+    }
+
+    /// Token usage of the call
+    pub fn usage(&self) -> Arc<Mutex<UsageTracker>> {
+        self.usage.clone()
+    }
+
+    /// Errors of the call by kind
+    pub fn errors(&self) -> Arc<Mutex<ErrorTracker>> {
+        self.errors.clone()
+    }
+
+    /// Protocol dump and audio recording of the call
+    pub fn recorder(&self) -> Arc<Mutex<Recorder>> {
+        self.recorder.clone()
+    }
+
+    /// Operations sent that the server hasn't acknowledged yet
+    pub fn pending(&self) -> Arc<Mutex<PendingOperations>> {
+        self.pending.clone()
+    }
+
+    /// Changes what happens when the user starts speaking over the assistant
+    pub async fn set_interruption_mode(&mut self, mode: InterruptionMode) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.interruption_mode", "mode": mode.as_str()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Chooses how streamed assistant text is printed
+    pub async fn set_text_style(&mut self, style: TextStyle) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.text_style", "style": style.as_str()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Holds or resumes playback, queued audio is played on resume
+    pub async fn set_paused(&mut self, paused: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.pause", "paused": paused})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Stops or resumes sending the microphone to the model, the local recording goes on and marks what's muted
+    pub async fn set_muted(&mut self, muted: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.mute", "muted": muted})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Plays the assistant faster or slower than it speaks, keeping its pitch
+    pub async fn set_playback_speed(&mut self, speed: f32) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.playback_speed", "speed": speed})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Watches for the user and the assistant speaking at once, optionally adapting the interruption mode to it
+    pub async fn set_talk_over_detection(&mut self, adapt: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.talk_over_detection", "adapt": adapt})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Marks an item as talked over, shown in the transcript and kept in exports
+    pub async fn record_talk_over(&mut self, item_id: &str, talk_over: &TalkOver) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.talk_over", "item_id": item_id, "talk_over": talk_over})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Has every finished turn classified by the sentiment/emotion model at this URL
+    pub async fn set_sentiment_endpoint(&mut self, url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.sentiment_endpoint", "url": url})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Labels an item with its sentiment, shown in the transcript and kept in exports
+    pub async fn set_sentiment(&mut self, item_id: &str, role: &str, sentiment: &Sentiment) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.sentiment", "item_id": item_id, "role": role, "sentiment": sentiment})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Pins or unpins an item, pinned items are never condensed or dropped when the conversation carries over
+    pub async fn set_pinned(&mut self, item_id: &str, pinned: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.pin", "item_id": item_id, "pinned": pinned})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Asks for the audio devices to be closed after this long without speech or messages
+    pub async fn set_idle_timeout(&mut self, timeout: std::time::Duration) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.idle_timeout", "seconds": timeout.as_secs()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Closes the output devices while idle, or opens them again; the connection stays up either way
+    pub async fn set_audio_asleep(&mut self, asleep: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.idle", "idle": asleep})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Plays a sound while waiting for the assistant to start answering
+    pub async fn set_thinking_sound(&mut self, sound: &ThinkingSound) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.thinking_sound", "sound": sound.to_string()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Voice requested for audio responses
+    pub fn voice(&self) -> Voice {
+        self.session_config.voice
+    }
+
+    /// Changes the instructions mid-call, marking the change in the transcript
+    pub async fn change_instructions(&mut self, instructions: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.session_config.instructions = instructions.to_string();
+        self.update_session().await?;
+
+        self.event_sender.send(serde_json::json!({"type": "local.instructions", "instructions": instructions})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Sets the instructions, takes effect on connect or the next session update
+    pub fn set_instructions(&mut self, instructions: &str) {
+        self.session_config.instructions = instructions.to_string();
+    }
+
+    /// Sets the voice of audio responses, takes effect on connect or the next session update
+    pub fn set_voice(&mut self, voice: Voice) {
+        self.session_config.voice = voice;
+    }
+
+    /// Leaves ending turns to the user (see commit_turn()) instead of the server's voice detection, takes effect on connect or the next session update
+    pub fn set_manual_turns(&mut self) {
+        self.session_config.turn_detection = None;
+    }
+
+    /// Ends the user's turn with manual turns: sends what the microphone picked up and asks for a response
+    pub async fn commit_turn(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.session_config.turn_detection.is_some() {
+            return Err("The server ends turns itself, only manual turns are committed".into());
+        }
+        self.input_audio_buffer_commit().await?;
+        self.create_response().await
+    }
+
+    /// Ends the turn after this long without speech with manual turns, as a safety net for a forgotten /commit
+    pub async fn set_auto_commit(&mut self, after: std::time::Duration) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.auto_commit", "seconds": after.as_secs_f64()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Tells the event handler of microphone audio the local VAD didn't send, so silence is still timed
+    pub async fn note_gated_audio(&mut self, samples: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.gated_audio", "samples": samples})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Sets the modalities of responses, e.g. only text, takes effect on connect or the next session update
+    pub fn set_modalities(&mut self, modalities: Vec<Modality>) {
+        self.session_config.modalities = modalities;
+    }
+
+    /// Sets the sampling temperature, takes effect on connect or the next session update
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.session_config.temperature = temperature;
+    }
+
+    /// Sets how fast the model speaks (1.0 being its natural pace), takes effect on connect or the next session update
+    pub fn set_speech_speed(&mut self, speed: f32) {
+        self.session_config.speed = Some(speed);
+    }
+
+    /// Limits the length of each response, takes effect on connect or the next session update
+    pub fn set_max_response_output_tokens(&mut self, max_tokens: MaxTokens) {
+        self.session_config.max_response_output_tokens = max_tokens;
+    }
+
+    /// Plays the DTMF tones of phone keys into the outgoing audio, after what's already queued
+    pub async fn press_keys(&mut self, keys: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.dtmf", "keys": keys})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Mirrors playback to another output device, matched by name, optionally mixing in the microphone
+    pub async fn add_output_device(&mut self, name: &str, mix_input: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.add_output", "device": name, "mix_input": mix_input})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Also sends the assistant's audio as RTP, e.g. to a networked speaker
+    pub async fn add_rtp_output(&mut self, output: &RtpOutput) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.add_rtp_output", "output": output})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Turns other applications down to `level` (0.0 to 1.0) of their volume while the assistant speaks, Linux only
+    pub async fn set_ducking(&mut self, level: f64) -> Result<(), Box<dyn std::error::Error>> {
+        if !cfg!(target_os = "linux") {
+            return Err("Ducking other applications is only supported on Linux".into());
+        }
+
+        self.event_sender.send(serde_json::json!({"type": "local.ducking", "level": level})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Shows the call in the system tray, with mute and hang up at hand, needs the tray feature on Linux
+    pub async fn enable_tray(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !cfg!(all(feature = "tray", target_os = "linux")) {
+            return Err("The tray icon needs a build with --features tray, and is only supported on Linux".into());
+        }
+
+        self.event_sender.send(serde_json::json!({"type": "local.tray"})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Writes per-turn latencies (CSV) to this file
+    pub async fn set_latency_log(&mut self, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.latency_log", "path": path})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Serves the live transcript to WebSocket viewers on this address
+    pub async fn serve_transcript(&mut self, address: std::net::SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.transcript_ws", "address": address.to_string()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Chooses which events raise desktop notifications
+    pub async fn set_notifications(&mut self, settings: NotificationSettings) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.notifications", "settings": settings})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Marks the assistant starting and stopping to speak with these cues, none turns them off
+    pub async fn set_cues(&mut self, cues: &[Cue]) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.cues", "cues": cues})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Chooses whether the microphone streams while the assistant responds
+    pub async fn set_mic_policy(&mut self, policy: MicPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.mic_policy", "policy": policy.as_str()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Watches the microphone for the assistant's own voice, warning or holding it when found
+    pub async fn set_loop_guard(&mut self, guard: LoopGuard) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.loop_guard", "action": guard.as_str()})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Asks again, up to this many times a turn, when a response comes back with neither audio nor text
+    pub async fn set_empty_response_retries(&mut self, retries: u8) -> Result<(), Box<dyn std::error::Error>> {
+        self.event_sender.send(serde_json::json!({"type": "local.empty_response_retries", "retries": retries})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Returns a handle other tasks can use to drive the call
+    pub fn handle(&self) -> ClientHandle {
+        ClientHandle { commands: self.command_sender.clone() }
+    }
+
+    /// Takes the command receiver, can only be called once
+    pub fn take_command_receiver(&mut self) -> Option<mpsc::Receiver<Command>> {
+        self.command_receiver.take()
+    }
+
+    /// Input audio buffer append, checked with `check_audio_chunk` first: a bad chunk fails with BadAudioChunk and isn't sent
+    pub async fn input_audio_buffer_append(&mut self, base64_audio_data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        check_audio_chunk(base64_audio_data)?;
+        self.send("input_audio_buffer.append", Some(serde_json::json!({
+            "audio": base64_audio_data
+        }))).await?;
+
+        Ok(())
+    }
+
+    /// Input audio buffer commit
+    pub async fn input_audio_buffer_commit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.send("input_audio_buffer.commit", None).await?;
+
+        Ok(())
+    }
+
+    // Private methods
+
+    /// Ends the call and stops everything the client started, resolving once it all has
+    ///
+    /// Disconnects if still connected, stops the read task and closes the event channel, so each
+    /// subscriber handles what it was already sent and stops, followed by the playback thread.
+    /// Cancel safe: dropping the future part way still stops it all, only without waiting for it.
+    pub async fn shutdown(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // The server may be gone already, which is no reason not to stop
+        if self.is_connected {
+            if let Err(e) = self.disconnect().await {
+                tracing::info!(error = %e, "disconnect failed during shutdown");
+            }
+        }
+
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+            let _ = reader.await;
+        }
+
+        // Dropping the client drops the last event sender
+        let event_handler = self.event_handler.take();
+        drop(self);
+        if let Some(event_handler) = event_handler {
+            event_handler.await?;
+        }
+        Ok(())
+    }
+
+    /// Refuses new responses once the budget is used up
+    fn check_budget(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.usage.lock().unwrap().is_exceeded() {
+            return Err(format!("Budget exceeded, refusing to create a new response: {}", self.usage_summary()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Recreates conversation items from their text, audio is replayed as its transcript, returning how many were
+    async fn replay_history(&mut self, items: &[ConversationItem]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut replayed = 0;
+        for item in items {
+            let text = item.text().trim().to_string();
+            if text.is_empty() {
+                continue;
+            }
+
+            let (role, content_type) = match item.role {
+                ConversationItemRole::User => ("user", "input_text"),
+                ConversationItemRole::Assistant => ("assistant", "text"),
+                // Whatever else it was, it's replayed as context rather than lost
+                ConversationItemRole::System | ConversationItemRole::Tool | ConversationItemRole::Unknown(_) => ("system", "input_text"),
+            };
+            // Calls are replayed as calls, so the model still sees which tools it already used
+            let mut new_item = match &item.kind {
+                ItemKind::Message => serde_json::json!({
+                    "type": "message",
+                    "role": role,
+                    "content": [{"type": content_type, "text": text}]
+                }),
+                ItemKind::FunctionCall { name, call_id } => serde_json::json!({
+                    "type": "function_call",
+                    "name": name,
+                    "call_id": call_id,
+                    "arguments": text
+                }),
+                ItemKind::FunctionCallOutput { call_id } => serde_json::json!({
+                    "type": "function_call_output",
+                    "call_id": call_id,
+                    "output": text
+                }),
+            };
+
+            // A pinned item stays pinned, under an id of our choosing (at most 32 characters) pinned ahead of it
+            if item.pinned {
+                let item_id = format!("pin_{}", &Uuid::new_v4().simple().to_string()[..28]);
+                self.set_pinned(&item_id, true).await?;
+                new_item["id"] = item_id.into();
+            }
+
+            self.send("conversation.item.create", Some(serde_json::json!({"item": new_item}))).await?;
+            replayed += 1;
+        }
+
+        Ok(replayed)
+    }
+
+    /// Gets a connection just opened going: tells the event handler, starts reading and configures the session
+    async fn start_session(&mut self, model: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Lets the event handler compare what the server serves with what was asked for
+        self.event_sender.send(serde_json::json!({"type": "local.connected", "model": model})).await
+            .map_err(|e| format!("Failed to send event to local handler: {}", e))?;
+
+        self.start_handling_messages().await?;  // Start handling incoming messages
+
+        self.update_session().await  // Send session configuration
+    }
+
+    /// Forgets the current connection without closing it, e.g. once it's gone
+    fn drop_connection(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+        self.ws_write = None;
+        self.ws_read = None;
+        self.is_connected = false;
+        *self.acknowledged_session.lock().unwrap() = None;
+    }
+
+    /// Starts handling incoming messages in a separate task
+    async fn start_handling_messages(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let event_sender = self.event_sender.clone();
+        let command_sender = self.command_sender.clone();
+        let acknowledged_session = self.acknowledged_session.clone();
+        let mut ws_read = self.ws_read.take().expect("WebSocket read stream is not initialized");
+
+        self.reader = Some(tokio::spawn(async move {
+            while let Some(message) = ws_read.next().await {
+            match message {
+                Ok(Message::Text(text)) => {
+                if let Ok(value) = serde_json::from_str::<Value>(&text) {
+                    tracing::trace!(event = %value["type"], "received");
+                    if value["type"] == "session.created" || value["type"] == "session.updated" {
+                        *acknowledged_session.lock().unwrap() = Some(value["session"].clone());
+                    }
+                    if event_sender.send(value).await.is_err() {
+                    eprintln!("Error sending event through channel");
+                    break;
+                    }
+                }
+                }
+                Ok(Message::Pong(payload)) => {
+                if let Ok(id) = <[u8; 8]>::try_from(payload.as_slice()) {
+                    let _ = event_sender.send(serde_json::json!({"type": "local.pong", "id": u64::from_be_bytes(id)})).await;
+                }
+                }
+                // The server ending the session, e.g. at its time limit, drops the call all the same
+                Ok(Message::Close(frame)) => {
+                let reason = match frame {
+                    Some(frame) if !frame.reason.is_empty() => format!("closed by the server ({}): {}", frame.code, frame.reason),
+                    Some(frame) => format!("closed by the server ({})", frame.code),
+                    None => "closed by the server".to_string(),
+                };
+                eprintln!("WebSocket connection {}", reason);
+                let _ = event_sender.send(serde_json::json!({"type": "local.disconnected", "reason": reason})).await;
+                let _ = command_sender.send(Command::Internal(InternalCommand::Reconnect)).await;
+                break;
+                }
+                Err(e) => {
+                eprintln!("Error receiving WebSocket message: {}", e);
+                let _ = event_sender.send(serde_json::json!({"type": "local.disconnected", "reason": e.to_string()})).await;
+                let _ = command_sender.send(Command::Internal(InternalCommand::Reconnect)).await;
+                break;
+                }
+                _ => {}
+            }
+            }
+        }));
+
+        Ok(())
+    }
+
+    /// Sends an event to WebSocket server
+    async fn send(&mut self, event_type: &str, data: Option<Value>) -> Result<(), Box<dyn std::error::Error>> {
+        let event = build_event(event_type, data)?;
+
         if let Some(ws_write) = &mut self.ws_write {
+            tracing::debug!(event = event_type, "sending");
             ws_write.send(Message::Text(serde_json::to_string(&event)?)).await?;
         } else {
             return Err(format!("Cannot send {} - client is not connected", event_type).into());
@@ -254,4 +1752,505 @@ impl RealtimeClient {
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+impl Drop for RealtimeClient {
+    /// The read task would otherwise outlive the client until the server closes the connection
+    fn drop(&mut self) {
+        if let Some(reader) = &self.reader {
+            reader.abort();
+        }
+    }
+}
+
+/// An audio chunk the server would refuse, see `check_audio_chunk`
+#[derive(Debug)]
+pub struct BadAudioChunk(pub String);
+
+impl std::fmt::Display for BadAudioChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BadAudioChunk {}
+
+/// Checks base64 audio is whole pcm16 samples within the size of an append, returning its length in bytes
+///
+/// The server would only answer a bad chunk with an error that doesn't say which append it was.
+pub fn check_audio_chunk(base64_audio_data: &str) -> Result<usize, BadAudioChunk> {
+    let length = base64_audio_data.len();
+    let data = base64_audio_data.trim_end_matches('=');
+    let alphabet = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'+' || *byte == b'/';
+    if !length.is_multiple_of(4) || length - data.len() > 2 || !data.as_bytes().iter().all(alphabet) {
+        return Err(BadAudioChunk(format!("audio chunk of {} characters isn't valid base64", length)));
+    }
+
+    let bytes = base64_audio_bytes(base64_audio_data);
+    if !bytes.is_multiple_of(2) {
+        return Err(BadAudioChunk(format!("audio chunk of {} bytes isn't whole pcm16 samples", bytes)));
+    }
+    if bytes > MAX_APPEND_BYTES {
+        return Err(BadAudioChunk(format!("audio chunk of {} bytes is over the {} bytes an append takes", bytes, MAX_APPEND_BYTES)));
+    }
+    Ok(bytes)
+}
+
+/// Builds a client event: its type, a fresh event_id and the fields of the payload
+///
+/// The payload must be an object and can't set the type or event_id itself.
+pub fn build_event(event_type: &str, data: Option<Value>) -> Result<Value, String> {
+    let mut event = serde_json::Map::new();
+    event.insert("type".to_string(), event_type.into());
+    event.insert("event_id".to_string(), Uuid::new_v4().to_string().into());
+
+    match data {
+        None => {}
+        Some(Value::Object(fields)) => {
+            if let Some(reserved) = ["type", "event_id"].into_iter().find(|key| fields.contains_key(*key)) {
+                return Err(format!("The payload of {} can't set {:?}", event_type, reserved));
+            }
+            event.extend(fields);
+        }
+        Some(other) => return Err(format!("The payload of {} must be an object, got {}", event_type, other)),
+    }
+
+    Ok(Value::Object(event))
+}
+
+/// Splits text into chunks of at most `max_chars` characters, preferring to break after a newline
+fn split_text(text: &str, max_chars: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+
+    while rest.chars().count() > max_chars {
+        let limit = rest.char_indices().nth(max_chars).map(|(index, _)| index).unwrap_or(rest.len());
+
+        // Break after the last newline, or else the last space, in the second half of the chunk
+        let split = rest[..limit]
+            .rfind('\n')
+            .or_else(|| rest[..limit].rfind(' '))
+            .map(|index| index + 1)
+            .filter(|&index| index > limit / 2)
+            .unwrap_or(limit);
+
+        chunks.push(&rest[..split]);
+        rest = &rest[split..];
+    }
+
+    chunks.push(rest);
+    chunks
+}
+
+/// The session fields that don't hold on the server yet, all of them before it acknowledged any
+fn changed_fields(desired: serde_json::Map<String, Value>, acknowledged: Option<&Value>) -> serde_json::Map<String, Value> {
+    match acknowledged {
+        Some(acknowledged) => desired.into_iter().filter(|(field, value)| !matches_acknowledged(value, &acknowledged[field])).collect(),
+        None => desired,
+    }
+}
+
+/// Whether a session field we'd send already holds on the server
+///
+/// The server echoes objects with extra defaults filled in (e.g. turn detection thresholds),
+/// so only the keys we set are compared, and numbers loosely since we keep them as f32.
+pub fn matches_acknowledged(desired: &Value, acknowledged: &Value) -> bool {
+    match (desired, acknowledged) {
+        (Value::Object(desired), Value::Object(acknowledged)) => desired
+            .iter()
+            .all(|(key, value)| matches_acknowledged(value, acknowledged.get(key).unwrap_or(&Value::Null))),
+        (Value::Array(desired), Value::Array(acknowledged)) => {
+            desired.len() == acknowledged.len() && desired.iter().zip(acknowledged).all(|(a, b)| matches_acknowledged(a, b))
+        }
+        (Value::Number(desired), Value::Number(acknowledged)) => {
+            (desired.as_f64().unwrap_or_default() - acknowledged.as_f64().unwrap_or_default()).abs() < 1e-4
+        }
+        _ => desired == acknowledged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Payloads as the client sends them, one per outbound event type
+    fn outbound_events() -> Vec<(&'static str, Option<Value>)> {
+        vec![
+            ("session.update", Some(serde_json::json!({"session": serde_json::to_value(SessionConfig::default()).unwrap()}))),
+            ("conversation.item.create", Some(serde_json::json!({
+                "item": {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "Hello"}]}
+            }))),
+            ("conversation.item.create", Some(serde_json::json!({
+                "item": {"type": "function_call_output", "call_id": "call_1", "output": "42"}
+            }))),
+            ("conversation.item.truncate", Some(serde_json::json!({"item_id": "item_1", "content_index": 0, "audio_end_ms": 1500}))),
+            ("response.create", None),
+            ("response.create", Some(serde_json::json!({"response": {"modalities": ["text"]}}))),
+            ("response.cancel", None),
+            ("input_audio_buffer.append", Some(serde_json::json!({"audio": "AAAA"}))),
+            ("input_audio_buffer.commit", None),
+        ]
+    }
+
+    #[test]
+    fn envelope_has_type_event_id_and_payload() {
+        for (event_type, data) in outbound_events() {
+            let event = build_event(event_type, data.clone()).unwrap();
+            let fields = event.as_object().unwrap();
+
+            assert_eq!(event["type"], event_type);
+            assert!(Uuid::parse_str(event["event_id"].as_str().unwrap()).is_ok(), "{}", event);
+
+            // Every payload field is carried over as is, next to the envelope
+            let payload = data.as_ref().and_then(Value::as_object).cloned().unwrap_or_default();
+            assert_eq!(fields.len(), payload.len() + 2, "{}", event);
+            for (key, value) in &payload {
+                assert_eq!(&event[key], value, "{} in {}", key, event_type);
+            }
+        }
+    }
+
+    #[test]
+    fn event_ids_are_unique() {
+        let ids: HashSet<String> = (0..1000)
+            .map(|_| build_event("response.create", None).unwrap()["event_id"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(ids.len(), 1000);
+    }
+
+    #[test]
+    fn non_object_payloads_are_rejected() {
+        for payload in [serde_json::json!([1, 2]), serde_json::json!("text"), serde_json::json!(3), Value::Null] {
+            assert!(build_event("response.create", Some(payload)).is_err());
+        }
+    }
+
+    #[test]
+    fn payload_cannot_override_the_envelope() {
+        assert!(build_event("response.create", Some(serde_json::json!({"type": "response.cancel"}))).is_err());
+        assert!(build_event("response.create", Some(serde_json::json!({"event_id": "mine"}))).is_err());
+    }
+
+    #[test]
+    fn session_enums_use_the_api_names() {
+        assert_eq!(serde_json::to_value([Modality::Text, Modality::Audio]).unwrap(), serde_json::json!(["text", "audio"]));
+        assert_eq!(serde_json::to_value([AudioFormat::Pcm16, AudioFormat::G711Ulaw, AudioFormat::G711Alaw]).unwrap(), serde_json::json!(["pcm16", "g711_ulaw", "g711_alaw"]));
+        assert_eq!(serde_json::to_value([ToolChoice::Auto, ToolChoice::None, ToolChoice::Required]).unwrap(), serde_json::json!(["auto", "none", "required"]));
+        for voice in [Voice::Alloy, Voice::Ash, Voice::Ballad, Voice::Coral, Voice::Echo, Voice::Sage, Voice::Shimmer, Voice::Verse] {
+            assert_eq!(serde_json::to_value(voice).unwrap(), voice.as_str());
+            assert_eq!(serde_json::from_value::<Voice>(voice.as_str().into()).unwrap(), voice);
+        }
+        assert!(serde_json::from_value::<Voice>("nova".into()).is_err());
+    }
+
+    #[test]
+    fn default_session_serializes_as_the_api_takes_it() {
+        let session = serde_json::to_value(SessionConfig::default()).unwrap();
+        assert_eq!(session["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(session["voice"], "alloy");
+        assert_eq!(session["input_audio_format"], "pcm16");
+        assert_eq!(session["tool_choice"], "auto");
+        assert!(session.get("speed").is_none());
+    }
+
+    #[test]
+    fn acknowledged_fields_match_despite_server_defaults() {
+        let desired = serde_json::json!({"type": "server_vad", "threshold": 0.6});
+        assert!(matches_acknowledged(&desired, &serde_json::json!({"type": "server_vad", "threshold": 0.6000000238418579, "silence_duration_ms": 500})));
+        assert!(!matches_acknowledged(&desired, &serde_json::json!({"type": "server_vad", "threshold": 0.5})));
+        assert!(!matches_acknowledged(&desired, &serde_json::json!({"type": "semantic_vad"})));
+
+        assert!(matches_acknowledged(&serde_json::json!(["text", "audio"]), &serde_json::json!(["text", "audio"])));
+        assert!(!matches_acknowledged(&serde_json::json!(["text", "audio"]), &serde_json::json!(["text"])));
+        assert!(!matches_acknowledged(&serde_json::json!("alloy"), &Value::Null));
+    }
+
+    #[test]
+    fn echoed_session_leaves_nothing_to_send() {
+        let Value::Object(desired) = serde_json::to_value(SessionConfig::default()).unwrap() else { unreachable!() };
+        let mut acknowledged = Value::Object(desired.clone());
+        acknowledged["turn_detection"]["prefix_padding_ms"] = 300.into();
+        acknowledged["id"] = "sess_1".into();
+        assert!(changed_fields(desired.clone(), Some(&acknowledged)).is_empty());
+
+        acknowledged["voice"] = "echo".into();
+        let changed = changed_fields(desired.clone(), Some(&acknowledged));
+        assert_eq!(changed.keys().collect::<Vec<_>>(), ["voice"]);
+
+        assert_eq!(changed_fields(desired.clone(), None), desired);
+    }
+
+    #[test]
+    fn max_tokens_takes_a_limit_or_inf() {
+        assert_eq!("inf".parse(), Ok(MaxTokens::Inf));
+        assert_eq!("1".parse(), Ok(MaxTokens::Limit(1)));
+        assert_eq!("4096".parse(), Ok(MaxTokens::Limit(MaxTokens::MAX_LIMIT)));
+        for wrong in ["0", "4097", "-1", "infinite", ""] {
+            assert!(wrong.parse::<MaxTokens>().is_err(), "{:?}", wrong);
+        }
+
+        assert_eq!(serde_json::to_value(MaxTokens::Limit(200)).unwrap(), serde_json::json!(200));
+        assert_eq!(serde_json::to_value(MaxTokens::Inf).unwrap(), serde_json::json!("inf"));
+        assert_eq!(serde_json::from_value::<MaxTokens>(serde_json::json!(200)).unwrap(), MaxTokens::Limit(200));
+        assert_eq!(serde_json::from_value::<MaxTokens>(serde_json::json!("inf")).unwrap(), MaxTokens::Inf);
+        assert!(serde_json::from_value::<MaxTokens>(serde_json::json!(5000)).is_err());
+        assert!(serde_json::from_value::<MaxTokens>(serde_json::json!(1.5)).is_err());
+        assert!(serde_json::from_value::<MaxTokens>(serde_json::json!(null)).is_err());
+    }
+
+    #[test]
+    fn transcript_keeps_earlier_sessions_as_they_were() {
+        let item = |id: &str, text: &str| ConversationItem::new(&serde_json::json!({
+            "id": id, "type": "message", "role": "user", "content": [{"type": "input_text", "text": text}]
+        }));
+        let ids = |items: Vec<ConversationItem>| items.into_iter().map(|item| item.id).collect::<Vec<_>>();
+        let mut earlier = EarlierSessions::default();
+        let first = [item("item_1", "one"), item("item_2", "two"), item("item_3", "three")];
+        assert_eq!(ids(earlier.transcript(&first)), ["item_1", "item_2", "item_3"]);
+
+        // Reconnected with a summary and the last item replayed
+        earlier.leave(&first);
+        earlier.replayed = 2;
+        let second = [item("item_4", "summary"), item("item_5", "three"), item("item_6", "four")];
+        assert_eq!(ids(earlier.transcript(&second)), ["item_1", "item_2", "item_3", "item_6"]);
+
+        // Again, replaying all of it: the replays of the first reconnect aren't kept twice
+        earlier.leave(&second);
+        earlier.replayed = 3;
+        let third = [item("item_7", "summary"), item("item_8", "three"), item("item_9", "four"), item("item_10", "five")];
+        assert_eq!(ids(earlier.transcript(&third)), ["item_1", "item_2", "item_3", "item_6", "item_10"]);
+    }
+
+    #[test]
+    fn snapshot_survives_saving() {
+        let session = SessionConfig { instructions: "Be brief.".to_string(), voice: Voice::Verse, ..SessionConfig::default() };
+        let snapshot = ConversationSnapshot {
+            model: "gpt-4o-mini-realtime-preview".to_string(),
+            session,
+            metadata: SessionMetadata::new(Some("Ada".to_string()), None, Vec::new()).unwrap(),
+            items: vec![ConversationItem::new(&serde_json::json!({
+                "id": "item_1", "type": "message", "role": "user", "content": [{"type": "input_text", "text": "Hello"}]
+            }))],
+        };
+
+        let saved = serde_json::to_vec(&snapshot).unwrap();
+        let loaded: ConversationSnapshot = serde_json::from_slice(&saved).unwrap();
+        assert_eq!(loaded.model, snapshot.model);
+        assert_eq!(serde_json::to_value(&loaded.session).unwrap(), serde_json::to_value(&snapshot.session).unwrap());
+        assert_eq!(serde_json::to_value(&loaded.metadata).unwrap(), serde_json::to_value(&snapshot.metadata).unwrap());
+        assert_eq!(loaded.items[0].id, "item_1");
+        assert_eq!(loaded.items[0].text(), "Hello");
+
+        // Snapshots from before metadata was kept still load
+        let mut older = serde_json::to_value(&snapshot).unwrap();
+        older.as_object_mut().unwrap().remove("metadata");
+        assert!(serde_json::from_value::<ConversationSnapshot>(older).unwrap().metadata.is_empty());
+    }
+
+    #[test]
+    fn building_outside_a_runtime_fails() {
+        let built = RealtimeClient::builder().api_key("sk-test").build();
+        assert!(matches!(built, Err(BuildError::NoRuntime)));
+    }
+
+    #[tokio::test]
+    async fn builder_checks_the_configuration_before_starting() {
+        let keyless = Gateway { api_key: None, ..Gateway::default() };
+        assert!(matches!(RealtimeClient::builder().transport(keyless.clone()).build(), Err(BuildError::MissingApiKey)));
+        // Unless the gateway takes none
+        let open = Gateway { auth: AuthScheme::None, ..keyless };
+        assert!(RealtimeClient::builder().transport(open).headless(true).build().is_ok());
+
+        let built = RealtimeClient::builder().api_key("sk-test").url("https://api.openai.com/v1/realtime").build();
+        assert!(matches!(built, Err(BuildError::InvalidUrl(url)) if url == "https://api.openai.com/v1/realtime"));
+
+        let too_hot = SessionConfig::default().instructions("Be brief.").temperature(2.5);
+        let built = RealtimeClient::builder().api_key("sk-test").session(too_hot).build();
+        assert!(matches!(built, Err(BuildError::InvalidSession(reason)) if reason.contains("temperature")));
+
+        // Headless, a sound configuration builds without an output device
+        let session = SessionConfig::default().voice(Voice::Verse).speed(1.2).max_response_output_tokens(MaxTokens::Inf);
+        let client = RealtimeClient::builder().api_key("sk-test").model("gpt-4o-realtime-preview").session(session).headless(true).build().unwrap();
+        assert_eq!(client.model(), "gpt-4o-realtime-preview");
+    }
+
+    #[test]
+    fn session_builder_sets_what_it_says() {
+        let session = SessionConfig::default()
+            .modalities(vec![Modality::Text])
+            .instructions("Be brief.")
+            .turn_detection(None)
+            .temperature(0.6)
+            .speed(1.5);
+        let sent = serde_json::to_value(&session).unwrap();
+        assert_eq!(sent["modalities"], serde_json::json!(["text"]));
+        assert_eq!(sent["instructions"], "Be brief.");
+        assert_eq!(sent["turn_detection"], Value::Null);
+        assert_eq!(sent["speed"], 1.5);
+        assert!((sent["temperature"].as_f64().unwrap() - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn session_config_is_checked_against_known_models() {
+        let model = "gpt-4o-realtime-preview-2024-12-17";
+        assert_eq!(check_session_config(&SessionConfig::default(), model), Ok(()));
+        assert_eq!(check_session_config(&SessionConfig::default().temperature(0.6).speed(0.25), model), Ok(()));
+        assert_eq!(check_session_config(&SessionConfig::default().temperature(1.2).speed(1.5), "gpt-realtime"), Ok(()));
+
+        let error = check_session_config(&SessionConfig::default().temperature(0.5), model).unwrap_err();
+        assert_eq!(error, format!("{} takes a temperature from 0.6 to 1.2, not 0.5", model));
+        let error = check_session_config(&SessionConfig::default().speed(2.0), "gpt-4o-mini-realtime-preview").unwrap_err();
+        assert_eq!(error, "gpt-4o-mini-realtime-preview takes a speech speed from 0.25 to 1.5, not 2");
+
+        // Models it knows nothing about, e.g. behind a gateway, are left to the server
+        assert!(model_limits("my-gateway-model").is_none());
+        assert_eq!(check_session_config(&SessionConfig::default().temperature(2.5).speed(4.0), "my-gateway-model"), Ok(()));
+    }
+
+    #[test]
+    fn audio_chunks_are_checked_before_sending() {
+        assert_eq!(check_audio_chunk(&base64_encode_audio(&[0.5; 3])).unwrap(), 6);
+        assert_eq!(check_audio_chunk(&base64_encode_audio(&[0.5; 4])).unwrap(), 8);
+        assert_eq!(check_audio_chunk("").unwrap(), 0);
+
+        // Not base64: cut short, off the alphabet, padded too much or in the middle
+        for bad in ["AAAAAA", "AA-_AAAA", "A===", "AA==AAAA", "AAA AAAA", "AAAAAAÄ"] {
+            let error = check_audio_chunk(bad).unwrap_err();
+            assert!(error.to_string().contains("isn't valid base64"), "{}: {}", bad, error);
+        }
+        // Half a sample
+        assert!(check_audio_chunk("AA==").unwrap_err().to_string().contains("isn't whole pcm16 samples"));
+
+        let too_long = base64_encode_audio(&vec![0.0; MAX_APPEND_BYTES / 2 + 1]);
+        assert!(check_audio_chunk(&too_long).unwrap_err().to_string().contains("an append takes"));
+        assert!(check_audio_chunk(&base64_encode_audio(&vec![0.0; MAX_APPEND_BYTES / 2])).is_ok());
+    }
+
+    #[test]
+    fn voices_are_named_alike_everywhere() {
+        use clap::ValueEnum;
+        for voice in Voice::value_variants() {
+            assert_eq!(serde_json::to_value(voice).unwrap(), voice.as_str());
+            assert_eq!(voice.to_possible_value().unwrap().get_name(), voice.as_str());
+        }
+    }
+
+    #[test]
+    fn pinned_items_are_replayed_in_full_whatever_the_policy() {
+        let history: Vec<ConversationItem> = (1..=8)
+            .map(|n| {
+                let mut item = ConversationItem::new(&serde_json::json!({"id": format!("item_{}", n), "type": "message", "role": "user"}));
+                item.pinned = n == 2 || n == 7;
+                item
+            })
+            .collect();
+        let ids = |items: Vec<ConversationItem>| items.into_iter().map(|item| item.id).collect::<Vec<_>>();
+        let split = |policy: ReplayPolicy| {
+            let (first, summarized, recent) = policy.split(&history);
+            (ids(first), ids(summarized), ids(recent))
+        };
+
+        let (first, summarized, recent) = split(ReplayPolicy::Full);
+        assert_eq!((first.len(), summarized.len(), recent.len()), (8, 0, 0));
+
+        // A pinned item among the recent ones is replayed there, in its place
+        let (first, summarized, recent) = split(ReplayPolicy::Summarized);
+        assert_eq!(first, ["item_2"]);
+        assert_eq!(summarized, ["item_1", "item_3", "item_4"]);
+        assert_eq!(recent, ["item_5", "item_6", "item_7", "item_8"]);
+
+        let (first, summarized, recent) = split(ReplayPolicy::Fresh);
+        assert_eq!((first, summarized.len(), recent.len()), (vec!["item_2".to_string(), "item_7".to_string()], 0, 0));
+    }
+
+    #[tokio::test]
+    async fn a_connection_that_fails_to_start_can_be_tried_again() {
+        // Counts the connections dialed, keeping each one open
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let handshakes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = handshakes.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                tokio::spawn(async move { while let Some(Ok(_)) = socket.next().await {} });
+            }
+        });
+
+        let mut client = RealtimeClient::builder().api_key("sk-test").url(url).headless(true).build().unwrap();
+        // With the event handler gone, each connection fails once the socket is open
+        let handler = client.event_handler.take().unwrap();
+        handler.abort();
+        let _ = handler.await;
+
+        for attempt in 1..=2 {
+            let e = client.connect(None).await.unwrap_err();
+            assert!(e.to_string().contains("local handler"), "{}", e);
+            assert!(!client.is_connected && client.ws_write.is_none() && client.reader.is_none());
+            // Dialed again rather than taken as still connected
+            assert_eq!(handshakes.load(std::sync::atomic::Ordering::SeqCst), attempt);
+        }
+    }
+
+    /// A WebSocket server taking one connection, passing on the events the client sends
+    async fn local_server() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (sent, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(Message::Text(text))) = socket.next().await {
+                let _ = sent.send(serde_json::from_str::<Value>(&text).unwrap());
+            }
+        });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn restoring_carries_on_with_the_snapshots_session() {
+        let (url, mut received) = local_server().await;
+        let mut client = RealtimeClient::builder().api_key("sk-test").url(url).headless(true).build().unwrap();
+        let snapshot = ConversationSnapshot {
+            model: "gpt-4o-mini-realtime-preview".to_string(),
+            session: SessionConfig::default().instructions("Be brief.").voice(Voice::Verse),
+            metadata: SessionMetadata::default(),
+            items: Vec::new(),
+        };
+        client.restore(snapshot).await.unwrap();
+
+        assert_eq!(client.model(), "gpt-4o-mini-realtime-preview");
+        assert_eq!(client.instructions(), "Be brief.");
+        let update = received.recv().await.unwrap();
+        assert_eq!(update["type"], "session.update");
+        assert_eq!((&update["session"]["instructions"], &update["session"]["voice"]), (&"Be brief.".into(), &"verse".into()));
+    }
+
+    #[tokio::test]
+    async fn a_modalities_override_holds_server_responses_until_the_next_turn() {
+        let (url, mut received) = local_server().await;
+        let mut client = RealtimeClient::builder().api_key("sk-test").url(url).headless(true).build().unwrap();
+        client.connect(None).await.unwrap();
+        assert_eq!(received.recv().await.unwrap()["type"], "session.update");
+
+        // The server is told not to respond to the next spoken turn itself
+        client.set_next_response_modalities(vec![Modality::Text]).await.unwrap();
+        let held = received.recv().await.unwrap();
+        assert_eq!(held["session"]["turn_detection"], serde_json::json!({"type": "server_vad", "create_response": false}));
+
+        // Responding to it with the override lets go
+        client.create_response().await.unwrap();
+        let response = received.recv().await.unwrap();
+        assert_eq!((&response["type"], &response["response"]["modalities"]), (&"response.create".into(), &serde_json::json!(["text"])));
+        let released = received.recv().await.unwrap();
+        assert_eq!(released["session"]["turn_detection"]["create_response"], true);
+
+        // Without turn detection the client responds anyway, nothing to hold
+        client.set_manual_turns();
+        client.set_next_response_modalities(vec![Modality::Text]).await.unwrap();
+        client.create_response().await.unwrap();
+        assert_eq!(received.recv().await.unwrap()["response"]["modalities"], serde_json::json!(["text"]));
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::{Duration, Instant};
+
+// Startup bursts (buffers filling up) would skew the estimate
+const WARMUP: Duration = Duration::from_secs(5);
+// Callback timing jitters by milliseconds, so the rate only means something over a long stretch
+const MIN_MEASUREMENT: Duration = Duration::from_secs(60);
+// Drift worth telling the user about, 200 ppm is 0.7 seconds an hour
+const WARN_PPM: f64 = 200.0;
+// Beyond this it's a misreported sample rate rather than drift, compensation is capped
+const MAX_COMPENSATION_PPM: f64 = 1000.0;
+
+/// Estimates how fast a device clock runs compared to the system clock
+///
+/// Devices run off their own crystals, so "48 kHz" is a little more or less in practice. Over an
+/// hour-long call the difference adds up to seconds of latency between what is captured, what the
+/// server gets at 24 kHz and what is played. The rate is a least-squares fit of the frames counted
+/// against time, which averages out callback jitter.
+pub struct DriftEstimator {
+    device: String,                 // For the warning
+    nominal_rate: f64,              // Frames per second the device claims
+    start: Option<Instant>,         // First callback
+    origin: Option<(f64, f64)>,     // Time and frames at the end of the warmup, the fit is relative to it
+    frames: f64,                    // Counted since start
+    sums: [f64; 5],                 // n, Σt, Σr, Σt², Σtr with r the frames beyond the nominal rate
+    warned: bool,
+}
+
+impl DriftEstimator {
+    pub fn new(device: &str, nominal_rate: u32) -> Self {
+        Self {
+            device: device.to_string(),
+            nominal_rate: nominal_rate as f64,
+            start: None,
+            origin: None,
+            frames: 0.0,
+            sums: [0.0; 5],
+            warned: false,
+        }
+    }
+
+    /// Records frames the device just delivered or consumed
+    pub fn add_frames(&mut self, frames: usize) {
+        let elapsed = self.start.get_or_insert_with(Instant::now).elapsed();
+        self.frames += frames as f64;
+        if elapsed < WARMUP {
+            return;
+        }
+
+        let (t0, f0) = *self.origin.get_or_insert((elapsed.as_secs_f64(), self.frames));
+        let t = elapsed.as_secs_f64() - t0;
+        let residual = (self.frames - f0) - self.nominal_rate * t;
+
+        let [n, sum_t, sum_r, sum_tt, sum_tr] = &mut self.sums;
+        *n += 1.0;
+        *sum_t += t;
+        *sum_r += residual;
+        *sum_tt += t * t;
+        *sum_tr += t * residual;
+
+        if !self.warned {
+            if let Some(ppm) = self.drift_ppm().filter(|ppm| ppm.abs() >= WARN_PPM) {
+                self.warned = true;
+                tracing::info!(device = self.device, ppm, "clock drift");
+                println!(
+                    "\n[{} runs {:.0} ppm {} (about {:.1} s per hour), compensating]",
+                    self.device,
+                    ppm.abs(),
+                    if ppm > 0.0 { "fast" } else { "slow" },
+                    ppm.abs() * 3600.0 / 1e6,
+                );
+            }
+        }
+    }
+
+    /// Parts per million the device runs fast (positive) or slow, once measured long enough
+    pub fn drift_ppm(&self) -> Option<f64> {
+        let [n, sum_t, sum_r, sum_tt, sum_tr] = self.sums;
+        let measured = self.start?.elapsed().checked_sub(WARMUP)?;
+        if measured < MIN_MEASUREMENT {
+            return None;
+        }
+
+        let denominator = n * sum_tt - sum_t * sum_t;
+        if denominator <= 0.0 {
+            return None;
+        }
+        let excess_rate = (n * sum_tr - sum_t * sum_r) / denominator;
+        Some(excess_rate / self.nominal_rate * 1e6)
+    }
+
+    /// Actual rate of the device relative to the nominal one, 1.0 until measured
+    pub fn rate_factor(&self) -> f64 {
+        let ppm = self.drift_ppm().unwrap_or(0.0).clamp(-MAX_COMPENSATION_PPM, MAX_COMPENSATION_PPM);
+        1.0 + ppm / 1e6
+    }
+}
+
+// Zero crossings of the interpolation kernel on each side, more is a sharper filter
+const KERNEL_ZERO_CROSSINGS: usize = 16;
+// Passband as a share of the lower of the two Nyquist frequencies, the rest is the transition band
+const PASSBAND: f64 = 0.95;
+// The kernel is tabulated at this many points per input sample and interpolated in between
+const KERNEL_RESOLUTION: usize = 256;
+// Drift compensation moves the cutoff a little, not enough to be worth a new table
+const KERNEL_TOLERANCE: f64 = 0.002;
+
+/// Band-limited resampler keeping its position across chunks, so ratios a hair off 1.0 aren't lost to rounding
+///
+/// Each output sample is interpolated with a Blackman windowed sinc. Upsampling (e.g. a 16 kHz
+/// microphone to the server's 24 kHz) keeps the input band and adds no images above it, which
+/// linear interpolation leaves as a metallic haze; downsampling (e.g. 48 kHz) low-passes first
+/// so nothing above the new Nyquist frequency folds back. Output lags input by half the kernel
+/// width, a millisecond or two, so each chunk's last few samples come out with the next one.
+#[derive(Default)]
+pub struct StreamResampler {
+    position: f64,              // Of the next output sample, in input samples from the start of `history`
+    history: Vec<f32>,          // Input still needed by the kernel
+    kernel: Option<Kernel>,     // For the current ratio
+}
+
+impl StreamResampler {
+    /// Resamples a chunk by the output/input length ratio
+    pub fn process(&mut self, samples: &[f32], ratio: f64) -> Vec<f32> {
+        self.history.extend_from_slice(samples);
+
+        let cutoff = ratio.min(1.0) * PASSBAND;
+        if self.kernel.as_ref().is_none_or(|kernel| (kernel.cutoff - cutoff).abs() > cutoff * KERNEL_TOLERANCE) {
+            self.kernel = Some(Kernel::new(cutoff));
+        }
+        let kernel = self.kernel.as_ref().unwrap();
+        let half_width = kernel.half_width;
+        let step = 1.0 / ratio;
+
+        let mut resampled = Vec::with_capacity((samples.len() as f64 * ratio) as usize + 1);
+        while (self.position.floor() as isize + half_width) < self.history.len() as isize {
+            let center = self.position.floor() as isize;
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for index in (center - half_width + 1)..=(center + half_width) {
+                let weight = kernel.at(self.position - index as f64);
+                weights += weight;
+                // Before the first chunk is silence
+                if index >= 0 {
+                    sum += self.history[index as usize] as f64 * weight;
+                }
+            }
+            // Normalized, so a constant signal comes out unchanged whatever the fractional position
+            resampled.push((sum / weights) as f32);
+            self.position += step;
+        }
+
+        // Drop what no later output reaches back to
+        let consumed = (self.position.floor() as isize - half_width + 1).clamp(0, self.history.len() as isize) as usize;
+        self.history.drain(..consumed);
+        self.position -= consumed as f64;
+        resampled
+    }
+
+    /// Resamples a whole clip, including its last samples
+    pub fn process_all(samples: &[f32], ratio: f64) -> Vec<f32> {
+        let mut resampler = Self::default();
+        let mut resampled = resampler.process(samples, ratio);
+
+        // Silence after the end lets the kernel reach past the last sample
+        let half_width = resampler.kernel.as_ref().map_or(0, |kernel| kernel.half_width as usize);
+        resampled.extend(resampler.process(&vec![0.0; half_width + 1], ratio));
+        resampled.truncate((samples.len() as f64 * ratio) as usize);
+        resampled
+    }
+}
+
+/// Blackman windowed sinc, cutoff relative to the input Nyquist frequency
+struct Kernel {
+    cutoff: f64,
+    half_width: isize,      // In input samples, wider for a lower cutoff to keep the same zero crossings
+    table: Vec<f64>,        // From the center outwards, KERNEL_RESOLUTION points per input sample
+}
+
+impl Kernel {
+    fn new(cutoff: f64) -> Self {
+        let half_width = (KERNEL_ZERO_CROSSINGS as f64 / cutoff).ceil();
+        let table = (0..=half_width as usize * KERNEL_RESOLUTION)
+            .map(|point| {
+                let x = point as f64 / KERNEL_RESOLUTION as f64;
+                let sinc = match x * cutoff {
+                    0.0 => 1.0,
+                    t => (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t),
+                };
+                let phase = std::f64::consts::PI * x / half_width;
+                sinc * (0.42 + 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+            })
+            .collect();
+
+        Self { cutoff, half_width: half_width as isize, table }
+    }
+
+    /// Value at `x` input samples from the center
+    fn at(&self, x: f64) -> f64 {
+        let point = x.abs() * KERNEL_RESOLUTION as f64;
+        let index = point as usize;
+        match (self.table.get(index), self.table.get(index + 1)) {
+            (Some(a), Some(b)) => a + (b - a) * point.fract(),
+            _ => 0.0,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::client::Modality;
+use crate::conversation::{Sentiment, TalkOver};
+use crate::handle_events::{InterruptionMode, MicPolicy};
+use crate::time_stretch;
+use crate::tools::ToolCall;
+use std::path::PathBuf;
+use tracing::Level;
+
+/// How long or pasted typed messages are checked before they're sent, see `dial --confirm-typed`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Confirmation {
+    Review,     // Show what's being sent, in short
+    Ask,        // Only send once the user says yes
+}
+
+/// Commands driving a call, typed by the user on stdin or raised internally
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    SendText(String),                                   // Plain line, sent as a user message
+    SendAudio(PathBuf),                                 // Audio file, sent as a user message of audio
+    SetModalities(Vec<Modality>, Option<String>),       // Modalities for the next response, with an optional message to send
+    System(String),                                     // System message inserted into the conversation
+    Ask(String),                                        // Side channel question, answered in text outside the conversation
+    Interrupt,                                          // Stop the assistant mid-response
+    SetInterruptionMode(InterruptionMode),              // What user speech does to the assistant
+    ShowSession,                                        // Print the session configuration acknowledged by the server
+    ShowPending,                                        // Print the operations the server hasn't acknowledged yet
+    Inspect,                                            // Browse the transcript items and their details, handled by the stdin reader
+    Logs(Level, Option<String>),                        // Show recent logs at this level and above, optionally of one module, handled by the stdin reader
+    Captions,                                           // Show the live captions pane, handled by the stdin reader
+    SetCaptions(Option<String>),                        // Caption the assistant's speech in this language, or stop
+    EditInstructions,                                   // Edit the instructions in $EDITOR, handled by the stdin reader
+    SetInstructions(String),                            // Change the instructions mid-call
+    Switch(String, Option<String>),                     // Continue in a new session with this model and optional instructions
+    ApproveTool(Option<String>),                        // Run the tool call waiting for approval, optionally with edited JSON arguments
+    DenyTool(Option<String>),                           // Refuse the tool call waiting for approval, with an optional reason for the model
+    Pause,                                              // Stop the microphone and hold playback
+    Resume,                                             // Undo Pause
+    Mute,                                               // Stop sending the microphone, it's still recorded locally, off the record
+    Unmute,                                             // Undo Mute
+    Commit,                                             // End the turn, sending what was said, with --manual-turns
+    Talk,                                               // Start talking with --push-to-talk, or stop and end the turn
+    Pin(Option<usize>),                                 // Pin the item with this number in /inspect, the last one by default
+    SetSpeed(f32),                                      // Playback speed of the assistant, MIN_SPEED to MAX_SPEED
+    Unpin(Option<usize>),                               // Undo Pin
+    Quit,                                               // Hang up and exit
+
+    Internal(InternalCommand),                          // Raised by the event handler or audio capture rather than typed
+}
+
+/// Commands raised internally, by the event handler or audio capture rather than typed
+///
+/// Kept apart from the typed ones so they can be told apart by type, e.g. to ignore them quietly
+/// while only typed messages work, without a list of them to keep up to date.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InternalCommand {
+    AppendAudio(String),                                                // Base64 pcm16 microphone audio
+    GatedAudio(usize),                                                  // Samples of microphone audio the local VAD held back as quiet
+    CancelResponse,                                                     // Cancel the in-progress response
+    RetryResponse(String),                                              // The response came back empty, ask again with this nudge
+    TruncateItem { item_id: String, content_index: u64, audio_end_ms: u64 },  // Drop the unheard part of an audio item
+    RunTools(Vec<ToolCall>),                                            // Function calls of a finished response
+    SetMicOpen(bool),                                                   // Whether microphone audio is sent, per the duplex policy
+    SetMicPolicy(MicPolicy),                                            // Change the duplex policy, e.g. once the microphone hears the assistant
+    Reconnect,                                                          // The connection dropped, open a new session
+    Heartbeat,                                                          // Time to ping the server
+    FallbackVoice(String),                                              // The server rejected the voice, with its error message
+    Sleep,                                                              // Idle for a while, close the audio devices
+    Wake,                                                               // Activity or input, reopen them if closed
+    Commit,                                                             // The user went quiet for --auto-commit or released push-to-talk, end the turn
+    RespondToTurn,                                                      // The server committed a turn it was told not to respond to
+    TimeUp,                                                             // The call reached --time-limit, hang up
+    TranslateItem { item_id: String, text: String },                    // Finished assistant message, to translate for tutoring
+    TitleChapter(String),                                               // Enough turns since this item for a chapter, to title
+    TranslateCaption { caption_id: String, text: String },              // Sentence of the assistant's speech, to caption
+    SetSentiment { item_id: String, role: String, sentiment: Sentiment },   // Label from the sentiment classifier
+    RecordTalkOver { item_id: String, talk_over: TalkOver },            // The user spoke while this item played
+}
+
+/// Parses a line of user input into a Command
+///
+/// Lines starting with `/` are commands, anything else is sent to the model as text.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+
+    let Some(rest) = line.strip_prefix('/') else {
+        return Ok(Command::SendText(line.to_string()));
+    };
+
+    let (name, args) = match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, Some(args.trim().to_string()).filter(|a| !a.is_empty())),
+        None => (rest, None),
+    };
+
+    match name {
+        // Text-only replies stay silent, handy when you suddenly can't have the assistant talking out loud
+        "text" => Ok(Command::SetModalities(vec![Modality::Text], args)),
+        // The API always pairs audio with its transcript, so "audio" means a spoken reply
+        "audio" => Ok(Command::SetModalities(vec![Modality::Audio, Modality::Text], args)),
+        "send-audio" => args.map(|path| Command::SendAudio(PathBuf::from(path))).ok_or_else(|| "Usage: /send-audio <audio file>".to_string()),
+        "system" => args.map(Command::System).ok_or_else(|| "Usage: /system <text>".to_string()),
+        "ask" => args.map(Command::Ask).ok_or_else(|| "Usage: /ask <question>".to_string()),
+        "stop" => Ok(Command::Interrupt),
+        "interrupt" => match args.as_deref().map(str::parse) {
+            Some(Ok(mode)) => Ok(Command::SetInterruptionMode(mode)),
+            Some(Err(e)) => Err(e),
+            None => Err("Usage: /interrupt <cancel|playback|off>".to_string()),
+        },
+        "session" => Ok(Command::ShowSession),
+        "pending" => Ok(Command::ShowPending),
+        "inspect" => Ok(Command::Inspect),
+        "logs" => {
+            let mut args = args.as_deref().unwrap_or_default().split_whitespace().peekable();
+            let level = match args.peek().and_then(|arg| arg.parse::<Level>().ok()) {
+                Some(level) => {
+                    args.next();
+                    level
+                }
+                None => Level::TRACE,
+            };
+            Ok(Command::Logs(level, args.next().map(str::to_string)))
+        }
+        "captions" => match args.as_deref() {
+            None => Ok(Command::Captions),
+            Some("off") => Ok(Command::SetCaptions(None)),
+            Some(language) => Ok(Command::SetCaptions(Some(language.to_string()))),
+        },
+        "instructions" => match args.as_deref() {
+            Some("edit") => Ok(Command::EditInstructions),
+            _ => Err("Usage: /instructions edit".to_string()),
+        },
+        "switch" => match args.as_deref().map(|args| args.split_once(char::is_whitespace).unwrap_or((args, ""))) {
+            Some((model, instructions)) => {
+                let instructions = Some(instructions.trim().to_string()).filter(|i| !i.is_empty());
+                Ok(Command::Switch(model.to_string(), instructions))
+            }
+            None => Err("Usage: /switch <model> [instructions]".to_string()),
+        },
+        "approve" => Ok(Command::ApproveTool(None)),
+        "edit" => match args {
+            Some(arguments) => match serde_json::from_str::<serde_json::Value>(&arguments) {
+                Ok(value) if value.is_object() => Ok(Command::ApproveTool(Some(arguments))),
+                _ => Err("The edited arguments must be a JSON object".to_string()),
+            },
+            None => Err("Usage: /edit <JSON arguments>".to_string()),
+        },
+        "deny" => Ok(Command::DenyTool(args)),
+        "pause" => Ok(Command::Pause),
+        "resume" => Ok(Command::Resume),
+        "mute" => Ok(Command::Mute),
+        "unmute" => Ok(Command::Unmute),
+        "commit" | "send" => Ok(Command::Commit),
+        "talk" => Ok(Command::Talk),
+        "pin" | "unpin" => {
+            let number = args.map(|number| number.parse::<usize>()).transpose().map_err(|_| format!("Usage: /{} [item number]", name))?;
+            Ok(if name == "pin" { Command::Pin(number) } else { Command::Unpin(number) })
+        }
+        "speed" => match args {
+            Some(speed) => time_stretch::parse_speed(&speed).map(Command::SetSpeed),
+            None => Err(format!("Usage: /speed <{} to {}>", time_stretch::MIN_SPEED, time_stretch::MAX_SPEED)),
+        },
+        "quit" | "exit" => Ok(Command::Quit),
+        _ => Err(format!("Unknown command: /{}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_take_an_optional_item_number() {
+        assert_eq!(parse_command("/pin"), Ok(Command::Pin(None)));
+        assert_eq!(parse_command("/pin 3"), Ok(Command::Pin(Some(3))));
+        assert_eq!(parse_command("/unpin 12"), Ok(Command::Unpin(Some(12))));
+        assert_eq!(parse_command("/unpin"), Ok(Command::Unpin(None)));
+        assert_eq!(parse_command("/pin last"), Err("Usage: /pin [item number]".to_string()));
+        assert_eq!(parse_command("/unpin -1"), Err("Usage: /unpin [item number]".to_string()));
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use toml::{Table, Value};
+
+/// Environment variable overriding where the config file is looked for
+pub const CONFIG_ENV: &str = "HOTLINE_CONFIG";
+
+// Options of every command, which the sections can't set as they're read before any section applies
+const GLOBAL_KEYS: [&str; 4] = ["config", "profile", "log-level", "log"];
+
+/// Settings from the config file, all optional
+///
+/// ```toml
+/// log-level = "info"
+///
+/// [log]               # Levels per module, like --log
+/// client = "debug"
+///
+/// [dial]              # Defaults for `dial` and `chat`, named like their flags
+/// voice = "verse"
+/// notify = ["error", "disconnect"]
+/// event-buffer = 500  # Events queued for the display, more for a slow terminal
+/// header = { X-Team = "voice" }   # Pairs, like --header X-Team=voice
+///
+/// [profiles.work]     # Picked with --profile work, on top of [dial]
+/// caller = "Ann"
+/// transcript = "call.json"
+///
+/// [shortcuts.standup] # Started with `hotline standup`, on top of [dial] and its profile
+/// description = "Daily standup"
+/// profile = "work"
+/// greeting = "Ask what everyone worked on yesterday"
+/// time-limit = 15
+/// transcript = "standups/{date}.json"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub log_level: Option<String>,
+    log: Table,
+    dial: Table,
+    profiles: HashMap<String, Table>,
+    shortcuts: HashMap<String, Table>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Config {
+    /// Reads the config file: `path`, `$HOTLINE_CONFIG` or hotline/config.toml in the user's config directory
+    ///
+    /// Only a file that was asked for has to exist, without one the defaults apply.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let (path, required) = match path.map(PathBuf::from).or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from)) {
+            Some(path) => (path, true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        if !required && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let config: Self = toml::from_str(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        Ok(Self { path, ..config })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The [log] table in the syntax of --log, e.g. `client=debug,audio=warn`
+    pub fn log(&self) -> Option<String> {
+        if self.log.is_empty() {
+            return None;
+        }
+        Some(self.log.iter().map(|(module, level)| format!("{}={}", module, level.as_str().unwrap_or_default())).collect::<Vec<_>>().join(","))
+    }
+
+    /// The [dial] options and those of `profile` as command line arguments, to go before the typed ones
+    pub fn dial_arguments(&self, profile: Option<&str>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut arguments = to_arguments(&self.dial, "dial")?;
+        if let Some(profile) = profile {
+            let options = self.profiles.get(profile).ok_or_else(|| {
+                let mut known: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+                known.sort();
+                format!("No profile {:?} in the config (known: {})", profile, if known.is_empty() { "none".to_string() } else { known.join(", ") })
+            })?;
+            arguments.extend(to_arguments(options, &format!("profiles.{}", profile))?);
+        }
+        Ok(arguments)
+    }
+
+    /// The options of a shortcut as command line arguments, None if there's no such shortcut
+    ///
+    /// Its `profile` (or the one given, which wins) applies first, over the [dial] defaults.
+    pub fn shortcut_arguments(&self, name: &str, profile: Option<&str>) -> Option<Result<Vec<String>, Box<dyn std::error::Error>>> {
+        let mut options = self.shortcuts.get(name)?.clone();
+        options.remove("description");
+        let own_profile = options.remove("profile");
+
+        let profile = profile.or(own_profile.as_ref().and_then(Value::as_str));
+        Some(self.dial_arguments(profile).and_then(|mut arguments| {
+            arguments.extend(to_arguments(&options, &format!("shortcuts.{}", name))?);
+            Ok(arguments)
+        }))
+    }
+
+    /// Each section of dial options as command line arguments, named like `profiles.work`
+    ///
+    /// A shortcut's own options only, its description and profile aside.
+    pub fn sections(&self) -> Vec<(String, Result<Vec<String>, String>)> {
+        self.tables()
+            .into_iter()
+            .map(|(section, options)| {
+                let mut options = options.clone();
+                if section.starts_with("shortcuts.") {
+                    options.remove("description");
+                    if let Some(profile) = options.remove("profile") {
+                        if !profile.as_str().is_some_and(|profile| self.profiles.contains_key(profile)) {
+                            return (section, Err(format!("profile {} isn't defined under [profiles]", profile)));
+                        }
+                    }
+                }
+                let arguments = to_arguments(&options, &section).map_err(|e| e.to_string());
+                (section, arguments)
+            })
+            .collect()
+    }
+
+    /// Keys that have no effect where they are
+    pub fn key_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (section, options) in self.tables() {
+            for key in options.keys() {
+                if GLOBAL_KEYS.contains(&key.as_str()) && !(section.starts_with("shortcuts.") && key == "profile") {
+                    warnings.push(format!("[{}] {} has no effect here, it's read before any section applies", section, key));
+                }
+            }
+        }
+        warnings
+    }
+
+    /// The sections of dial options: [dial], then the profiles and shortcuts by name
+    fn tables(&self) -> Vec<(String, &Table)> {
+        let mut tables: Vec<(String, &Table)> = self.profiles.iter().map(|(name, options)| (format!("profiles.{}", name), options)).collect();
+        tables.extend(self.shortcuts.iter().map(|(name, options)| (format!("shortcuts.{}", name), options)));
+        tables.sort_by(|(a, _), (b, _)| a.cmp(b));
+        tables.insert(0, ("dial".to_string(), &self.dial));
+        tables
+    }
+
+    /// Shortcuts named like one of `commands`, which could never be started, sorted
+    pub fn shadowed_shortcuts(&self, commands: &[String]) -> Vec<&str> {
+        let mut shadowed: Vec<_> = self.shortcuts.keys().map(String::as_str).filter(|name| commands.iter().any(|command| command == name)).collect();
+        shadowed.sort();
+        shadowed
+    }
+
+    /// Names of the shortcuts with their descriptions, sorted
+    pub fn shortcuts(&self) -> Vec<(&str, &str)> {
+        let mut shortcuts: Vec<_> = self
+            .shortcuts
+            .iter()
+            .map(|(name, options)| (name.as_str(), options.get("description").and_then(Value::as_str).unwrap_or_default()))
+            .collect();
+        shortcuts.sort();
+        shortcuts
+    }
+}
+
+/// Fills in `{date}` (e.g. 2024-05-31) and `{time}` (e.g. 0930) of a file name, in UTC
+pub fn expand_template(template: &Path) -> PathBuf {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (year, month, day) = civil_date(secs / 86400);
+    let time = format!("{:02}{:02}", secs / 3600 % 24, secs / 60 % 60);
+
+    PathBuf::from(
+        template
+            .to_string_lossy()
+            .replace("{date}", &format!("{}-{:02}-{:02}", year, month, day))
+            .replace("{time}", &time),
+    )
+}
+
+/// Year, month and day of a day number since 1970-01-01, in the proleptic Gregorian calendar
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Counted from 0000-03-01 in 400-year eras, so leap days fall at the end of each year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// hotline/config.toml under XDG_CONFIG_HOME, ~/.config or APPDATA
+fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_dir.join("hotline").join("config.toml"))
+}
+
+/// `voice = "verse"` becomes `--voice=verse`, `true` a bare flag, a list one argument per element
+/// and a table one `KEY=VALUE` argument per entry, for the options taking pairs
+fn to_arguments(options: &Table, section: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut arguments = Vec::new();
+    for (name, value) in options {
+        let values = match value {
+            Value::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => arguments.push(format!("--{}", name)),
+                Value::Boolean(false) => {}
+                Value::String(text) => arguments.push(format!("--{}={}", name, text)),
+                Value::Integer(_) | Value::Float(_) => arguments.push(format!("--{}={}", name, value)),
+                Value::Table(pairs) => {
+                    for (key, value) in pairs {
+                        match value {
+                            Value::String(text) => arguments.push(format!("--{}={}={}", name, key, text)),
+                            Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => arguments.push(format!("--{}={}={}", name, key, value)),
+                            other => return Err(format!("Unsupported value for {}.{} in [{}]: {}", name, key, section, other).into()),
+                        }
+                    }
+                }
+                other => return Err(format!("Unsupported value for {} in [{}]: {}", name, section, other).into()),
+            }
+        }
+    }
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(toml: &str) -> Table {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn options_become_arguments() {
+        let options = table(
+            r#"
+            voice = "verse"
+            text-only = true
+            no-mic = false
+            temperature = 0.6
+            time-limit = 30
+            allow-read = ["notes", "docs"]
+            header = { X-Team = "voice", X-Priority = 2 }
+            "#,
+        );
+        let mut arguments = to_arguments(&options, "dial").unwrap();
+        arguments.sort();
+        assert_eq!(
+            arguments,
+            [
+                "--allow-read=docs",
+                "--allow-read=notes",
+                "--header=X-Priority=2",
+                "--header=X-Team=voice",
+                "--temperature=0.6",
+                "--text-only",
+                "--time-limit=30",
+                "--voice=verse"
+            ]
+        );
+    }
+
+    #[test]
+    fn values_that_are_no_argument_are_refused() {
+        let error = to_arguments(&table("meta = { caller = { name = \"Ada\" } }"), "profiles.work").unwrap_err();
+        assert!(error.to_string().contains("meta.caller in [profiles.work]"));
+        assert!(to_arguments(&table("when = 2024-05-31"), "dial").is_err());
+    }
+
+    #[test]
+    fn shortcuts_named_like_commands_are_found() {
+        let config: Config = toml::from_str(
+            r#"
+            [shortcuts.standup]
+            greeting = "Morning"
+            [shortcuts.dial]
+            voice = "verse"
+            [shortcuts.history]
+            "#,
+        )
+        .unwrap();
+        let commands = ["dial", "chat", "history", "help"].map(String::from);
+        assert_eq!(config.shadowed_shortcuts(&commands), ["dial", "history"]);
+        assert!(Config::default().shadowed_shortcuts(&commands).is_empty());
+    }
+
+    #[test]
+    fn dates_are_civil() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(59), (1970, 3, 1));
+        // Leap days, including the one of a century divisible by 400
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(11_017), (2000, 3, 1));
+        assert_eq!(civil_date(19_782), (2024, 2, 29));
+        assert_eq!(civil_date(19_874), (2024, 5, 31));
+        assert_eq!(civil_date(20_088), (2024, 12, 31));
+        assert_eq!(civil_date(20_089), (2025, 1, 1));
+        // 2100 isn't a leap year
+        assert_eq!(civil_date(47_540), (2100, 2, 28));
+        assert_eq!(civil_date(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn templates_get_the_date_and_time() {
+        let expanded = expand_template(Path::new("standups/{date}-{time}.json")).to_string_lossy().to_string();
+        let name = expanded.strip_prefix("standups/").unwrap().strip_suffix(".json").unwrap();
+        // e.g. 2024-05-31-0930
+        let parts: Vec<&str> = name.split('-').collect();
+        assert_eq!(parts.iter().map(|part| part.len()).collect::<Vec<_>>(), [4, 2, 2, 4]);
+        assert!(parts.iter().all(|part| part.chars().all(|c| c.is_ascii_digit())));
+        assert!(parts[0].parse::<u64>().unwrap() >= 2024);
+
+        assert_eq!(expand_template(Path::new("call.json")), Path::new("call.json"));
+    }
+
+    #[test]
+    fn sections_are_checked_on_their_own() {
+        let config: Config = toml::from_str(
+            r#"
+            [dial]
+            voice = "verse"
+            log-level = "debug"
+            [profiles.work]
+            caller = "Ann"
+            [shortcuts.standup]
+            description = "Daily standup"
+            profile = "work"
+            time-limit = 15
+            [shortcuts.retro]
+            profile = "home"
+            "#,
+        )
+        .unwrap();
+
+        let sections = config.sections();
+        let names: Vec<&str> = sections.iter().map(|(section, _)| section.as_str()).collect();
+        assert_eq!(names, ["dial", "profiles.work", "shortcuts.retro", "shortcuts.standup"]);
+        // A shortcut's description and profile aren't dial options, an unknown profile is an error
+        assert_eq!(sections[3].1, Ok(vec!["--time-limit=15".to_string()]));
+        assert_eq!(sections[2].1, Err("profile \"home\" isn't defined under [profiles]".to_string()));
+
+        // Global options do nothing in a section, a shortcut's profile is its own key
+        assert_eq!(config.key_warnings(), ["[dial] log-level has no effect here, it's read before any section applies"]);
+    }
+
+    #[test]
+    fn dial_options_apply_under_their_profile() {
+        let config: Config = toml::from_str(
+            r#"
+            [dial]
+            voice = "verse"
+            event-buffer = 500
+            [profiles.remote]
+            event-buffer = 2000
+            "#,
+        )
+        .unwrap();
+
+        // The profile's come after, so they win
+        assert_eq!(config.dial_arguments(None).unwrap(), ["--event-buffer=500", "--voice=verse"]);
+        assert_eq!(config.dial_arguments(Some("remote")).unwrap(), ["--event-buffer=500", "--voice=verse", "--event-buffer=2000"]);
+        assert_eq!(config.dial_arguments(Some("work")).unwrap_err().to_string(), "No profile \"work\" in the config (known: remote)");
+    }
+}
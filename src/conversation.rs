@@ -0,0 +1,792 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::usage::response_cost;
+
+/// Placeholder text for user audio that couldn't be transcribed
+pub const TRANSCRIPTION_FAILED: &str = "[transcription failed]";
+
+/// Metadata marking out-of-band side channel responses, which are not part of the conversation
+pub const SIDE_CHANNEL_METADATA: (&str, &str) = ("hotline", "side_channel");
+
+/// Metadata marking the end-of-call summary, an out-of-band response that is kept in the transcript
+pub const SUMMARY_METADATA: (&str, &str) = ("hotline", "summary");
+
+/// Side channel tag of out-of-band translations, followed by the id of the item translated
+pub const TRANSLATION_METADATA: &str = "translation_of";
+
+/// Side channel tag of out-of-band chapter titles, followed by the id of the chapter's first item
+pub const CHAPTER_METADATA: &str = "chapter_of";
+
+/// Side channel tag of out-of-band caption translations, followed by the caption's id
+pub const CAPTION_METADATA: &str = "caption_of";
+
+/// Metadata value of a side channel response about something in particular, e.g. `side_channel translation_of item_1`
+///
+/// It goes under the side channel's own key, so all the other keys the API allows are left to the session metadata.
+pub fn side_channel_tag(tag: &str, id: &str) -> String {
+    format!("{} {} {}", SIDE_CHANNEL_METADATA.1, tag, id)
+}
+
+/// The id a side channel response is tagged with, None if it has another tag or none
+fn side_channel_tagged<'a>(response: &'a Value, tag: &str) -> Option<&'a str> {
+    let value = response["metadata"][SIDE_CHANNEL_METADATA.0].as_str()?;
+    value.strip_prefix(SIDE_CHANNEL_METADATA.1)?.strip_prefix(' ')?.strip_prefix(tag)?.strip_prefix(' ')
+}
+
+/// Returns true if the `response` object of an event belongs to the side channel
+pub fn is_side_channel_response(response: &Value) -> bool {
+    response["metadata"][SIDE_CHANNEL_METADATA.0]
+        .as_str()
+        .is_some_and(|value| value == SIDE_CHANNEL_METADATA.1 || value.starts_with(&format!("{} ", SIDE_CHANNEL_METADATA.1)))
+}
+
+/// Returns true if the `response` object of an event is the end-of-call summary
+pub fn is_summary_response(response: &Value) -> bool {
+    response["metadata"][SUMMARY_METADATA.0] == SUMMARY_METADATA.1
+}
+
+/// Returns true if the `response` object of an event is out-of-band, whatever it's for
+///
+/// Ours are told by their metadata. Any other the server says isn't in a conversation: its
+/// conversation_id is null (rather than missing, as in events made up locally).
+pub fn is_out_of_band_response(response: &Value) -> bool {
+    is_side_channel_response(response) || is_summary_response(response) || response.get("conversation_id").is_some_and(Value::is_null)
+}
+
+/// The item an out-of-band translation is for, None for any other response
+pub fn translated_item(response: &Value) -> Option<&str> {
+    side_channel_tagged(response, TRANSLATION_METADATA)
+}
+
+/// The first item of the chapter an out-of-band title is for, None for any other response
+pub fn chapter_item(response: &Value) -> Option<&str> {
+    side_channel_tagged(response, CHAPTER_METADATA)
+}
+
+/// The caption an out-of-band translation is for, None for any other response
+pub fn captioned_sentence(response: &Value) -> Option<&str> {
+    side_channel_tagged(response, CAPTION_METADATA)
+}
+
+/// Role of a conversation item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversationItemRole {
+    User,
+    Assistant,
+    System,
+    Tool,               // Function call outputs, ours rather than the user's or the server's
+    #[serde(untagged)]
+    Unknown(String),    // A role the server added since, shown as is
+}
+
+impl From<&str> for ConversationItemRole {
+    fn from(role: &str) -> Self {
+        match role {
+            "user" => Self::User,
+            "assistant" => Self::Assistant,
+            "system" => Self::System,
+            _ => {
+                tracing::warn!(role, "unknown conversation item role");
+                Self::Unknown(role.to_string())
+            }
+        }
+    }
+}
+
+/// Status of a conversation item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationItemStatus {
+    InProgress,
+    Completed,
+    Incomplete,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl From<&str> for ConversationItemStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "in_progress" => Self::InProgress,
+            "completed" => Self::Completed,
+            "incomplete" => Self::Incomplete,
+            _ => {
+                tracing::warn!(status, "unknown conversation item status");
+                Self::Unknown(status.to_string())
+            }
+        }
+    }
+}
+
+/// Type of a content part within a conversation item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    InputText,
+    InputAudio,
+    Text,
+    Audio,
+    #[serde(untagged)]
+    Unknown(String),    // Kept for its text, if it has any
+}
+
+impl From<&str> for ContentType {
+    fn from(content_type: &str) -> Self {
+        match content_type {
+            "input_text" => Self::InputText,
+            "input_audio" => Self::InputAudio,
+            "text" => Self::Text,
+            "audio" => Self::Audio,
+            _ => {
+                tracing::warn!(content_type, "unknown content type");
+                Self::Unknown(content_type.to_string())
+            }
+        }
+    }
+}
+
+/// What a conversation item is, a message or a step of a function call
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ItemKind {
+    #[default]
+    Message,
+    FunctionCall { name: String, call_id: String },     // The arguments are the item's text
+    FunctionCallOutput { call_id: String },             // The output is the item's text
+}
+
+impl ItemKind {
+    pub fn is_message(&self) -> bool {
+        *self == Self::Message
+    }
+}
+
+/// A content part, text is the transcript for audio parts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemContent {
+    pub content_type: ContentType,
+    pub text: String,
+}
+
+/// Label from the sentiment/emotion classifier, see `dial --sentiment`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sentiment {
+    pub label: String,      // e.g. positive, neutral, joy or anger, whatever the classifier uses
+    pub score: f64,         // Its confidence, 0 to 1
+}
+
+/// The user speaking while an assistant item played, see `dial --talk-over`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TalkOver {
+    pub at_ms: u64,         // How far into the item's audio it started
+    pub overlap_ms: u64,    // Both speaking
+    pub speech_ms: u64,     // The user's whole burst of speech
+    pub interrupted: bool,  // The assistant was stopped meanwhile
+}
+
+/// What the response that produced an assistant item cost, see `response.done`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TurnUsage {
+    pub input_tokens: u64,      // The conversation so far, as the model read it for this turn
+    pub output_tokens: u64,
+    pub cost_usd: f64,          // Estimated, see UsageTracker
+}
+
+/// A message or function call in the conversation, as tracked locally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationItem {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "ItemKind::is_message")]
+    pub kind: ItemKind,
+    pub role: ConversationItemRole,
+    pub status: ConversationItemStatus,
+    pub content: Vec<ItemContent>,
+    pub truncated: bool,                // The unheard tail of the audio was dropped
+    #[serde(default)]
+    pub pinned: bool,                   // Carried over in full whenever the conversation is condensed or replayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,    // Tutoring translation of the text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<String>,        // Title of the chapter starting with this item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentiment: Option<Sentiment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub talk_overs: Vec<TalkOver>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TurnUsage>,       // Of the response that produced the item, on its first item only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,   // Instructions changed to these mid-call, just before this item
+}
+
+impl ConversationItem {
+    /// Builds an item from the `item` object of a server event
+    ///
+    /// Function calls are the assistant's, with their arguments as text, and function call
+    /// outputs are the tool's, with the output as text.
+    pub fn new(item: &Value) -> Self {
+        let text = |field: &str, content_type| vec![ItemContent { content_type, text: item[field].as_str().unwrap_or_default().to_string() }];
+        let call_id = item["call_id"].as_str().unwrap_or_default().to_string();
+
+        let (kind, role, content) = match item["type"].as_str().unwrap_or("message") {
+            "function_call" => (
+                ItemKind::FunctionCall { name: item["name"].as_str().unwrap_or_default().to_string(), call_id },
+                ConversationItemRole::Assistant,
+                text("arguments", ContentType::Text),
+            ),
+            "function_call_output" => (ItemKind::FunctionCallOutput { call_id }, ConversationItemRole::Tool, text("output", ContentType::InputText)),
+            _ => {
+                let content = item["content"]
+                    .as_array()
+                    .map(|parts| {
+                        parts
+                            .iter()
+                            .map(|part| ItemContent {
+                                content_type: ContentType::from(part["type"].as_str().unwrap_or_default()),
+                                text: part["text"].as_str().or(part["transcript"].as_str()).unwrap_or_default().to_string(),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (ItemKind::Message, ConversationItemRole::from(item["role"].as_str().unwrap_or_default()), content)
+            }
+        };
+
+        Self {
+            id: item["id"].as_str().unwrap_or_default().to_string(),
+            kind,
+            role,
+            status: ConversationItemStatus::from(item["status"].as_str().unwrap_or("completed")),
+            content,
+            truncated: false,
+            pinned: false,
+            translation: None,
+            chapter: None,
+            sentiment: None,
+            talk_overs: Vec::new(),
+            usage: None,
+            instructions: None,
+        }
+    }
+
+    /// All content parts joined together
+    pub fn text(&self) -> String {
+        self.content.iter().map(|part| part.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// One line of plain text saying who said or did what, e.g. for context in out-of-band requests
+    pub fn plain_line(&self) -> String {
+        match &self.kind {
+            ItemKind::Message => match &self.role {
+                ConversationItemRole::Unknown(role) => format!("{}: {}", role, self.text().trim()),
+                role => format!("{:?}: {}", role, self.text().trim()),
+            },
+            ItemKind::FunctionCall { name, .. } => format!("Assistant called {}({})", name, self.text().trim()),
+            ItemKind::FunctionCallOutput { .. } => format!("Function output: {}", self.text().trim()),
+        }
+    }
+}
+
+// Bounds on what's kept for inspecting items, so a long call doesn't keep every event it had
+const MAX_HISTORIES: usize = 500;           // Items, the earliest dropped first
+const MAX_HISTORY_EVENTS: usize = 200;      // Events per item, the earliest dropped first
+
+/// What happened to an item during the call, for inspecting it
+#[derive(Debug, Clone)]
+pub struct ItemHistory {
+    pub created: Duration,                      // Into the call
+    pub statuses: Vec<(Duration, String)>,      // Status changes, relative to `created`
+    pub usage: Option<Value>,                   // Token usage of the response that produced the item
+    pub events: Vec<Value>,                     // The latest events about the item, audio payloads elided
+    pub dropped_events: usize,                  // Earlier events no longer kept
+}
+
+/// Keeps a local model of the conversation, built from server events
+#[derive(Debug, Default)]
+pub struct ConversationTracker {
+    items: Vec<ConversationItem>,
+    side_channel_responses: HashSet<String>,    // Out-of-band responses whose items are skipped
+    histories: HashMap<String, ItemHistory>,    // Per item id
+    pins: HashSet<String>,                      // Ids of pinned items, possibly not created yet
+    changed_instructions: Option<String>,       // Changed since the last item, marked on the next one
+    started: Option<Instant>,                   // First event seen
+}
+
+impl ConversationTracker {
+    pub fn items(&self) -> &[ConversationItem] {
+        &self.items
+    }
+
+    /// Updates the conversation from a server event, other events are ignored
+    pub fn handle_event(&mut self, event: &Value) {
+        self.update_history(event);
+
+        match event["type"].as_str().unwrap_or_default() {
+            "response.created" if is_side_channel_response(&event["response"]) => {
+                self.side_channel_responses.insert(event["response"]["id"].as_str().unwrap_or_default().to_string());
+            }
+            "response.output_item.added" if self.side_channel_responses.contains(event["response_id"].as_str().unwrap_or_default()) => {}
+            "conversation.item.created" | "response.output_item.added"
+                if matches!(event["item"]["type"].as_str(), Some("message" | "function_call" | "function_call_output"))
+                    && !self.items.iter().any(|item| event["item"]["id"] == item.id.as_str()) =>
+            {
+                let mut item = ConversationItem::new(&event["item"]);
+                item.pinned = self.pins.contains(&item.id);
+                item.instructions = self.changed_instructions.take();
+                self.items.push(item);
+            }
+            "response.output_item.done" => {
+                if let Some(item) = self.item_mut(event["item"]["id"].as_str().unwrap_or_default()) {
+                    item.status = ConversationItemStatus::from(event["item"]["status"].as_str().unwrap_or("completed"));
+                    // Arguments only stream in as deltas, the finished item has them whole
+                    if let (ItemKind::FunctionCall { .. }, Some(arguments)) = (&item.kind, event["item"]["arguments"].as_str()) {
+                        item.content = vec![ItemContent { content_type: ContentType::Text, text: arguments.to_string() }];
+                    }
+                }
+            }
+            "response.content_part.added" => {
+                if let Some(item) = self.item_mut(event["item_id"].as_str().unwrap_or_default()) {
+                    item.content.push(ItemContent {
+                        content_type: ContentType::from(event["part"]["type"].as_str().unwrap_or_default()),
+                        text: String::new(),
+                    });
+                }
+            }
+            "response.text.delta" | "response.audio_transcript.delta" => {
+                // Once truncated, the rest of the transcript was never heard
+                let content_index = event["content_index"].as_u64().unwrap_or(0) as usize;
+                if let Some(part) = self
+                    .item_mut(event["item_id"].as_str().unwrap_or_default())
+                    .filter(|item| !item.truncated)
+                    .and_then(|item| item.content.get_mut(content_index))
+                {
+                    part.text.push_str(event["delta"].as_str().unwrap_or_default());
+                }
+            }
+            "conversation.item.input_audio_transcription.completed" => {
+                let content_index = event["content_index"].as_u64().unwrap_or(0) as usize;
+                if let Some(part) = self
+                    .item_mut(event["item_id"].as_str().unwrap_or_default())
+                    .and_then(|item| item.content.get_mut(content_index))
+                {
+                    part.text = event["transcript"].as_str().unwrap_or_default().to_string();
+                }
+            }
+            "conversation.item.input_audio_transcription.failed" => {
+                // Don't leave the entry blank forever, the audio itself is still in the conversation
+                self.set_text(
+                    event["item_id"].as_str().unwrap_or_default(),
+                    event["content_index"].as_u64().unwrap_or(0) as usize,
+                    TRANSCRIPTION_FAILED,
+                );
+            }
+            "conversation.item.truncated" => {
+                if let Some(item) = self.item_mut(event["item_id"].as_str().unwrap_or_default()) {
+                    item.truncated = true;
+                }
+            }
+            "conversation.item.deleted" => {
+                self.items.retain(|item| event["item_id"] != item.id.as_str());
+            }
+            "response.done" => {
+                let text = event["response"]["output"][0]["content"][0]["text"].as_str().unwrap_or_default().trim().to_string();
+                if let Some(item) = translated_item(&event["response"]).and_then(|item_id| self.items.iter_mut().find(|item| item.id == item_id)) {
+                    item.translation = Some(text);
+                } else if let Some(item) = chapter_item(&event["response"]).and_then(|item_id| self.items.iter_mut().find(|item| item.id == item_id)) {
+                    item.chapter = Some(text.trim_matches('"').to_string());
+                }
+
+                // Charged to the turn's first message, so a response with several items counts once
+                let usage = &event["response"]["usage"];
+                if !usage.is_null() {
+                    let mut outputs = event["response"]["output"].as_array().into_iter().flatten().filter_map(|output| output["id"].as_str());
+                    if let Some(index) = outputs.find_map(|item_id| self.items.iter().position(|item| item.id == item_id)) {
+                        self.items[index].usage = Some(TurnUsage {
+                            input_tokens: usage["input_tokens"].as_u64().unwrap_or_default(),
+                            output_tokens: usage["output_tokens"].as_u64().unwrap_or_default(),
+                            cost_usd: response_cost(usage),
+                        });
+                    }
+                }
+            }
+            // Raised locally by RealtimeClient::set_sentiment()
+            "local.sentiment" => {
+                if let (Some(item), Ok(sentiment)) = (
+                    self.item_mut(event["item_id"].as_str().unwrap_or_default()),
+                    serde_json::from_value(event["sentiment"].clone()),
+                ) {
+                    item.sentiment = Some(sentiment);
+                }
+            }
+            // Raised locally by RealtimeClient::record_talk_over()
+            "local.talk_over" => {
+                if let (Some(item), Ok(talk_over)) = (
+                    self.item_mut(event["item_id"].as_str().unwrap_or_default()),
+                    serde_json::from_value(event["talk_over"].clone()),
+                ) {
+                    item.talk_overs.push(talk_over);
+                }
+            }
+            // Raised locally by RealtimeClient::change_instructions()
+            "local.instructions" => {
+                self.changed_instructions = event["instructions"].as_str().map(str::to_string);
+            }
+            // Raised locally by RealtimeClient::set_pinned(), also ahead of replaying a pinned item
+            "local.pin" => {
+                let item_id = event["item_id"].as_str().unwrap_or_default();
+                let pinned = event["pinned"].as_bool().unwrap_or(true);
+                match pinned {
+                    true => self.pins.insert(item_id.to_string()),
+                    false => self.pins.remove(item_id),
+                };
+                if let Some(item) = self.item_mut(item_id) {
+                    item.pinned = pinned;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets every item, e.g. before the history is replayed into a new session
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.side_channel_responses.clear();
+        self.histories.clear();
+        self.pins.clear();
+    }
+
+    /// Timing, usage and raw events of an item
+    pub fn history(&self, item_id: &str) -> Option<&ItemHistory> {
+        self.histories.get(item_id)
+    }
+
+    /// The last few items as plain text, to give out-of-band requests some context
+    pub fn recent_text(&self, count: usize) -> String {
+        self.items[self.items.len().saturating_sub(count)..]
+            .iter()
+            .map(ConversationItem::plain_line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The items from this one on as plain text, None if there is no such item
+    pub fn text_since(&self, item_id: &str) -> Option<String> {
+        let first = self.items.iter().position(|item| item.id == item_id)?;
+        Some(
+            self.items[first..]
+                .iter()
+                .map(ConversationItem::plain_line)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    /// Replaces the text of an item
+    pub fn set_text(&mut self, item_id: &str, content_index: usize, text: &str) {
+        if let Some(part) = self.item_mut(item_id).and_then(|item| item.content.get_mut(content_index)) {
+            part.text = text.to_string();
+        }
+    }
+
+    /// Keeps only the part of an interrupted item that was actually heard, later deltas are ignored
+    pub fn set_heard_text(&mut self, item_id: &str, content_index: usize, text: &str) {
+        self.set_text(item_id, content_index, text);
+        if let Some(item) = self.item_mut(item_id) {
+            item.truncated = true;
+        }
+    }
+
+    /// Records the event in the history of the item it is about
+    fn update_history(&mut self, event: &Value) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let event_type = event["type"].as_str().unwrap_or_default();
+
+        // Usage is only known for the response as a whole
+        if event_type == "response.done" {
+            for output in event["response"]["output"].as_array().into_iter().flatten() {
+                if let Some(history) = self.histories.get_mut(output["id"].as_str().unwrap_or_default()) {
+                    history.usage = Some(event["response"]["usage"].clone());
+                }
+            }
+            return;
+        }
+
+        let Some(item_id) = event["item_id"].as_str().or(event["item"]["id"].as_str()) else {
+            return;
+        };
+        if !self.histories.contains_key(item_id) && !matches!(event_type, "conversation.item.created" | "response.output_item.added") {
+            return;
+        }
+
+        if !self.histories.contains_key(item_id) && self.histories.len() >= MAX_HISTORIES {
+            let earliest = self.histories.iter().min_by_key(|(_, history)| history.created).map(|(id, _)| id.clone());
+            self.histories.remove(&earliest.unwrap_or_default());
+        }
+        let history = self.histories.entry(item_id.to_string()).or_insert_with(|| ItemHistory {
+            created: started.elapsed(),
+            statuses: Vec::new(),
+            usage: None,
+            events: Vec::new(),
+            dropped_events: 0,
+        });
+
+        let status = match event_type {
+            "conversation.item.created" | "response.output_item.added" | "response.output_item.done" => {
+                event["item"]["status"].as_str().unwrap_or("completed").to_string()
+            }
+            "conversation.item.truncated" => "truncated".to_string(),
+            "conversation.item.input_audio_transcription.completed" => "transcribed".to_string(),
+            "conversation.item.input_audio_transcription.failed" => "transcription failed".to_string(),
+            _ => String::new(),
+        };
+        if !status.is_empty() && history.statuses.last().is_none_or(|(_, last)| *last != status) {
+            history.statuses.push((started.elapsed().saturating_sub(history.created), status));
+        }
+
+        // Audio deltas are large and unreadable, keep their size only
+        let mut event = event.clone();
+        if event_type == "response.audio.delta" {
+            event["delta"] = format!("<{} base64 characters>", event["delta"].as_str().unwrap_or_default().len()).into();
+        }
+        if history.events.len() == MAX_HISTORY_EVENTS {
+            history.events.remove(0);
+            history.dropped_events += 1;
+        }
+        history.events.push(event);
+    }
+
+    fn item_mut(&mut self, item_id: &str) -> Option<&mut ConversationItem> {
+        self.items.iter_mut().find(|item| item.id == item_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn unknown_values_are_kept() {
+        assert_eq!(ConversationItemRole::from("developer"), ConversationItemRole::Unknown("developer".to_string()));
+        assert_eq!(ConversationItemStatus::from("queued"), ConversationItemStatus::Unknown("queued".to_string()));
+        assert_eq!(ContentType::from("input_image"), ContentType::Unknown("input_image".to_string()));
+        assert_eq!(ConversationItemRole::from("assistant"), ConversationItemRole::Assistant);
+    }
+
+    #[test]
+    fn unexpected_items_do_not_panic() {
+        let item = ConversationItem::new(&json!({
+            "id": "item_1",
+            "type": "message",
+            "role": "developer",
+            "status": "queued",
+            "content": [{"type": "input_image", "text": "a chart"}, {"transcript": "no type at all"}]
+        }));
+        assert_eq!(item.role, ConversationItemRole::Unknown("developer".to_string()));
+        assert_eq!(item.status, ConversationItemStatus::Unknown("queued".to_string()));
+        assert_eq!(item.content[0].content_type, ContentType::Unknown("input_image".to_string()));
+        assert_eq!(item.text(), "a chart no type at all");
+        assert_eq!(item.plain_line(), "developer: a chart no type at all");
+
+        // No id, role or content
+        let item = ConversationItem::new(&json!({"type": "message"}));
+        assert_eq!(item.id, "");
+        assert!(item.content.is_empty());
+    }
+
+    #[test]
+    fn unknown_values_survive_saving() {
+        let mut tracker = ConversationTracker::default();
+        tracker.handle_event(&json!({
+            "type": "conversation.item.created",
+            "item": {"id": "item_1", "type": "message", "role": "developer", "content": [{"type": "input_image"}]}
+        }));
+        tracker.handle_event(&json!({"type": "response.content_part.added", "item_id": "item_1", "part": {"type": "hologram"}}));
+        tracker.handle_event(&json!({"type": "response.output_item.done", "item": {"id": "item_1", "status": "archived"}}));
+
+        let saved = serde_json::to_value(tracker.items()).unwrap();
+        assert_eq!(saved[0]["role"], "developer");
+        assert_eq!(saved[0]["status"], "archived");
+        assert_eq!(saved[0]["content"][1]["content_type"], "hologram");
+
+        let loaded: Vec<ConversationItem> = serde_json::from_value(saved).unwrap();
+        assert_eq!(loaded[0].role, ConversationItemRole::Unknown("developer".to_string()));
+        assert_eq!(loaded[0].status, ConversationItemStatus::Unknown("archived".to_string()));
+        assert_eq!(loaded[0].content[1].content_type, ContentType::Unknown("hologram".to_string()));
+    }
+
+    #[test]
+    fn item_history_keeps_the_latest_events() {
+        let mut tracker = ConversationTracker::default();
+        tracker.handle_event(&json!({"type": "conversation.item.created", "item": {"id": "item_1", "type": "message", "role": "assistant"}}));
+        for index in 0..MAX_HISTORY_EVENTS + 50 {
+            tracker.handle_event(&json!({"type": "response.audio_transcript.delta", "item_id": "item_1", "delta": index.to_string()}));
+        }
+
+        let history = tracker.history("item_1").unwrap();
+        assert_eq!(history.events.len(), MAX_HISTORY_EVENTS);
+        assert_eq!(history.dropped_events, 51);     // The item.created too
+        assert_eq!(history.events.last().unwrap()["delta"], (MAX_HISTORY_EVENTS + 49).to_string());
+        assert_eq!(history.statuses.len(), 1);
+    }
+
+    #[test]
+    fn item_histories_are_bounded() {
+        let mut tracker = ConversationTracker::default();
+        for index in 0..MAX_HISTORIES + 10 {
+            tracker.handle_event(&json!({"type": "conversation.item.created", "item": {"id": format!("item_{}", index), "type": "message", "role": "user"}}));
+        }
+        assert_eq!(tracker.histories.len(), MAX_HISTORIES);
+        assert!(tracker.history(&format!("item_{}", MAX_HISTORIES + 9)).is_some());
+    }
+
+    #[test]
+    fn out_of_band_responses_are_told_apart() {
+        assert!(is_out_of_band_response(&json!({"metadata": {"hotline": "side_channel translation_of item_1"}})));
+        assert!(is_out_of_band_response(&json!({"metadata": {"hotline": "summary"}})));
+        assert!(is_out_of_band_response(&json!({"conversation_id": null})));
+        assert!(!is_out_of_band_response(&json!({"conversation_id": "conv_1", "metadata": {"caller": "Ada"}})));
+        assert!(!is_out_of_band_response(&json!({"id": "resp_1"})));
+    }
+
+    #[test]
+    fn side_channel_tags_share_the_marker_key() {
+        let tagged = |tag, id| json!({"metadata": {SIDE_CHANNEL_METADATA.0: side_channel_tag(tag, id)}});
+        let translation = tagged(TRANSLATION_METADATA, "item_1");
+        assert!(is_side_channel_response(&translation));
+        assert_eq!(translated_item(&translation), Some("item_1"));
+        assert_eq!((chapter_item(&translation), captioned_sentence(&translation)), (None, None));
+        assert_eq!(chapter_item(&tagged(CHAPTER_METADATA, "item_2")), Some("item_2"));
+        assert_eq!(captioned_sentence(&tagged(CAPTION_METADATA, "caption_3")), Some("caption_3"));
+
+        // Untagged, or someone else's value under the key
+        let plain = json!({"metadata": {"hotline": "side_channel"}});
+        assert!(is_side_channel_response(&plain));
+        assert_eq!(translated_item(&plain), None);
+        assert!(!is_side_channel_response(&json!({"metadata": {"hotline": "side_channels"}})));
+        assert!(!is_side_channel_response(&json!({"metadata": {"hotline": "summary"}})));
+    }
+
+    #[test]
+    fn response_usage_is_charged_to_the_first_item_of_the_turn() {
+        let mut tracker = ConversationTracker::default();
+        for (id, role) in [("item_1", "user"), ("item_2", "assistant"), ("item_3", "assistant")] {
+            tracker.handle_event(&json!({"type": "conversation.item.created", "item": {"id": id, "type": "message", "role": role}}));
+        }
+        let usage = json!({"input_tokens": 120, "output_tokens": 30, "input_token_details": {"text_tokens": 120}, "output_token_details": {"text_tokens": 30}});
+        tracker.handle_event(&json!({
+            "type": "response.done",
+            "response": {"output": [{"id": "item_2"}, {"id": "item_3"}], "usage": usage}
+        }));
+        // Out of band, its output never joins the conversation
+        tracker.handle_event(&json!({"type": "response.done", "response": {"output": [{"id": "item_9"}], "usage": usage}}));
+
+        let items = tracker.items();
+        assert_eq!(items[1].usage, Some(TurnUsage { input_tokens: 120, output_tokens: 30, cost_usd: crate::usage::response_cost(&usage) }));
+        assert!(items[1].usage.as_ref().unwrap().cost_usd > 0.0);
+        assert_eq!((&items[0].usage, &items[2].usage), (&None, &None));
+
+        // A response without usage, e.g. cancelled before it reported any, leaves the turn alone
+        tracker.handle_event(&json!({"type": "response.done", "response": {"output": [{"id": "item_3"}]}}));
+        assert_eq!(tracker.items()[2].usage, None);
+    }
+
+    #[test]
+    fn function_calls_and_outputs_are_items_of_their_own() {
+        let call = ConversationItem::new(&json!({
+            "id": "item_1", "type": "function_call", "name": "weather", "call_id": "call_1", "arguments": "{\"city\":\"Oslo\"}"
+        }));
+        assert_eq!(call.kind, ItemKind::FunctionCall { name: "weather".to_string(), call_id: "call_1".to_string() });
+        assert_eq!(call.role, ConversationItemRole::Assistant);
+        assert_eq!(call.text(), "{\"city\":\"Oslo\"}");
+        assert_eq!(call.plain_line(), "Assistant called weather({\"city\":\"Oslo\"})");
+
+        let output = ConversationItem::new(&json!({"id": "item_2", "type": "function_call_output", "call_id": "call_1", "output": "12°C"}));
+        assert_eq!(output.kind, ItemKind::FunctionCallOutput { call_id: "call_1".to_string() });
+        assert_eq!(output.role, ConversationItemRole::Tool);
+        assert_eq!(output.content[0].content_type, ContentType::InputText);
+        assert_eq!(output.plain_line(), "Function output: 12°C");
+
+        // No type is a message
+        assert!(ConversationItem::new(&json!({"role": "user"})).kind.is_message());
+    }
+
+    #[test]
+    fn item_kinds_survive_saving() {
+        let items = [
+            ConversationItem::new(&json!({"type": "message", "role": "user", "content": [{"type": "input_text", "text": "Hi"}]})),
+            ConversationItem::new(&json!({"type": "function_call", "name": "weather", "call_id": "call_1", "arguments": "{}"})),
+            ConversationItem::new(&json!({"type": "function_call_output", "call_id": "call_1", "output": "12°C"})),
+        ];
+
+        let saved = serde_json::to_value(&items).unwrap();
+        // Messages are saved as before calls were items, so older transcripts load the same
+        assert!(saved[0].get("kind").is_none());
+        assert_eq!(saved[1]["kind"], json!({"type": "function_call", "name": "weather", "call_id": "call_1"}));
+        assert_eq!(saved[2]["kind"], json!({"type": "function_call_output", "call_id": "call_1"}));
+        assert_eq!(saved[2]["role"], "tool");
+
+        let loaded: Vec<ConversationItem> = serde_json::from_value(saved).unwrap();
+        assert_eq!(loaded.iter().map(|item| item.kind.clone()).collect::<Vec<_>>(), items.map(|item| item.kind));
+        assert_eq!(loaded[2].role, ConversationItemRole::Tool);
+    }
+
+    #[test]
+    fn finished_function_calls_fill_in_their_arguments() {
+        let mut tracker = ConversationTracker::default();
+        tracker.handle_event(&json!({
+            "type": "response.output_item.added",
+            "item": {"id": "item_1", "type": "function_call", "name": "weather", "call_id": "call_1", "arguments": "", "status": "in_progress"}
+        }));
+        assert_eq!(tracker.items()[0].text(), "");
+        assert_eq!(tracker.items()[0].status, ConversationItemStatus::InProgress);
+
+        tracker.handle_event(&json!({
+            "type": "response.output_item.done",
+            "item": {"id": "item_1", "type": "function_call", "status": "completed", "arguments": "{\"city\":\"Oslo\"}"}
+        }));
+        assert_eq!(tracker.items()[0].text(), "{\"city\":\"Oslo\"}");
+        assert_eq!(tracker.items()[0].status, ConversationItemStatus::Completed);
+
+        // A message's text isn't replaced by a stray arguments field
+        tracker.handle_event(&json!({
+            "type": "conversation.item.created",
+            "item": {"id": "item_2", "type": "message", "role": "assistant", "content": [{"type": "text", "text": "Sunny"}]}
+        }));
+        tracker.handle_event(&json!({"type": "response.output_item.done", "item": {"id": "item_2", "arguments": "{}"}}));
+        assert_eq!(tracker.items()[1].text(), "Sunny");
+    }
+
+    #[test]
+    fn pins_apply_to_items_created_before_or_after() {
+        let created = |id: &str| json!({"type": "conversation.item.created", "item": {"id": id, "type": "message", "role": "user"}});
+        let mut tracker = ConversationTracker::default();
+        tracker.handle_event(&created("item_1"));
+        tracker.handle_event(&json!({"type": "local.pin", "item_id": "item_1", "pinned": true}));
+        // Pinned ahead of being replayed, under the id it's created with
+        tracker.handle_event(&json!({"type": "local.pin", "item_id": "pin_1", "pinned": true}));
+        tracker.handle_event(&created("pin_1"));
+        tracker.handle_event(&created("item_2"));
+        assert_eq!(tracker.items().iter().map(|item| item.pinned).collect::<Vec<_>>(), [true, true, false]);
+
+        tracker.handle_event(&json!({"type": "local.pin", "item_id": "item_1", "pinned": false}));
+        assert!(!tracker.items()[0].pinned);
+
+        // Saved with the item, transcripts from before pins load unpinned
+        let saved = serde_json::to_value(tracker.items()).unwrap();
+        assert_eq!(saved[1]["pinned"], true);
+        let loaded: ConversationItem = serde_json::from_value(json!({
+            "id": "item_1", "role": "user", "status": "completed", "content": [], "truncated": false
+        }))
+        .unwrap();
+        assert!(!loaded.pinned);
+
+        // A new session starts with no pins
+        tracker.clear();
+        tracker.handle_event(&created("pin_1"));
+        assert!(!tracker.items()[0].pinned);
+    }
+}
@@ -0,0 +1,174 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use crossterm::style::Stylize;
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use url::Url;
+
+use crate::client::{realtime_request, DEFAULT_MODEL, DEFAULT_URL};
+
+// How long each network step may take before it counts as hanging
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of a single check
+enum Check {
+    Ok(String),
+    Fail(String, &'static str),     // What went wrong and a hint on fixing it
+    Skipped(&'static str),
+}
+
+/// Runs the startup health checks and prints a diagnosis report
+///
+/// Each step depends on the previous ones (no point checking the key without a connection),
+/// so the first failure is usually the one to fix.
+pub async fn doctor() -> Result<(), Box<dyn std::error::Error>> {
+    println!("hotline doctor\n");
+    let mut failures = 0;
+
+    let api_key = std::env::var("OPENAI_API_KEY").ok().filter(|key| !key.is_empty());
+    failures += report("API key present", match &api_key {
+        Some(key) => {
+            let suffix = key.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect::<String>();
+            Check::Ok(format!("OPENAI_API_KEY is set (...{})", suffix))
+        }
+        None => Check::Fail("OPENAI_API_KEY is not set".to_string(), "export OPENAI_API_KEY=sk-..."),
+    });
+
+    let reachable = check_reachability().await;
+    let network_ok = matches!(reachable, Check::Ok(_));
+    failures += report("Network reachability", reachable);
+
+    failures += report("TLS handshake and API key", match (&api_key, network_ok) {
+        (Some(key), true) => check_realtime_handshake(key).await,
+        _ => Check::Skipped("needs an API key and network access"),
+    });
+
+    failures += report("Audio output", check_audio_device(false));
+    failures += report("Audio input", check_audio_device(true));
+
+    println!();
+    if failures == 0 {
+        println!("{}", "Everything looks good.".green());
+    } else {
+        println!("{}", format!("{} check(s) failed, see the hints above.", failures).red());
+    }
+
+    Ok(())
+}
+
+/// Prints a check and returns 1 if it failed
+fn report(name: &str, check: Check) -> usize {
+    match check {
+        Check::Ok(detail) => {
+            println!("{} {}: {}", "[ ok ]".green(), name, detail);
+            0
+        }
+        Check::Fail(detail, hint) => {
+            println!("{} {}: {}", "[fail]".red(), name, detail);
+            println!("       hint: {}", hint);
+            1
+        }
+        Check::Skipped(reason) => {
+            println!("{} {}: skipped, {}", "[skip]".dim(), name, reason);
+            0
+        }
+    }
+}
+
+/// Resolves the realtime endpoint and opens a TCP connection to it
+async fn check_reachability() -> Check {
+    let url = Url::parse(DEFAULT_URL).unwrap();
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addresses = match timeout(NETWORK_TIMEOUT, tokio::net::lookup_host((host.as_str(), port))).await {
+        Ok(Ok(addresses)) => addresses.collect::<Vec<_>>(),
+        Ok(Err(e)) => return Check::Fail(format!("could not resolve {}: {}", host, e), "check your DNS settings or internet connection"),
+        Err(_) => return Check::Fail(format!("resolving {} timed out", host), "check your DNS settings or internet connection"),
+    };
+
+    let Some(address) = addresses.first() else {
+        return Check::Fail(format!("{} resolved to no addresses", host), "check your DNS settings");
+    };
+
+    match timeout(NETWORK_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => Check::Ok(format!("connected to {} ({})", host, address)),
+        Ok(Err(e)) => Check::Fail(format!("could not connect to {}: {}", address, e), "a firewall or proxy may be blocking port 443"),
+        Err(_) => Check::Fail(format!("connecting to {} timed out", address), "a firewall or proxy may be blocking port 443"),
+    }
+}
+
+/// Opens a realtime session, which checks TLS, the API key and access to the model in one go
+async fn check_realtime_handshake(api_key: &str) -> Check {
+    let request = match realtime_request(DEFAULT_URL, api_key, DEFAULT_MODEL) {
+        Ok(request) => request,
+        Err(e) => return Check::Fail(format!("could not build the request: {}", e), "check the API key for stray characters"),
+    };
+
+    let mut ws_stream = match timeout(NETWORK_TIMEOUT, connect_async(request)).await {
+        Ok(Ok((ws_stream, _))) => ws_stream,
+        Ok(Err(WsError::Http(response))) => {
+            let status = response.status();
+            let hint = match status.as_u16() {
+                401 => "the API key is invalid or revoked, create a new one",
+                403 | 404 => "the API key has no access to the realtime model",
+                429 => "rate limited or out of quota, check your billing",
+                _ => "the API returned an unexpected error, try again later",
+            };
+            return Check::Fail(format!("the API rejected the connection with HTTP {}", status), hint);
+        }
+        Ok(Err(WsError::Tls(e))) => return Check::Fail(format!("TLS handshake failed: {}", e), "a proxy may be intercepting TLS, or system certificates are missing"),
+        Ok(Err(e)) => return Check::Fail(format!("connection failed: {}", e), "check your internet connection"),
+        Err(_) => return Check::Fail("the handshake timed out".to_string(), "this is the classic \"it just hangs\", check proxies and firewalls"),
+    };
+
+    // The server greets every new connection with session.created
+    let result = match timeout(NETWORK_TIMEOUT, ws_stream.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => {
+            let event: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+            match event["type"].as_str() {
+                Some("session.created") => Check::Ok(format!("session created with {}", event["session"]["model"].as_str().unwrap_or(DEFAULT_MODEL))),
+                _ => Check::Fail(format!("unexpected first event: {}", text), "the API may be having issues, try again later"),
+            }
+        }
+        Ok(_) => Check::Fail("the connection closed without a session".to_string(), "the API may be having issues, try again later"),
+        Err(_) => Check::Fail("connected but no session was created".to_string(), "the API may be having issues, try again later"),
+    };
+
+    let _ = ws_stream.send(Message::Close(None)).await;
+    result
+}
+
+/// Checks the default device exists and a stream can be built on it
+fn check_audio_device(input: bool) -> Check {
+    let host = cpal::default_host();
+    let hint = "check the device is plugged in and selected as the system default";
+
+    let device = if input { host.default_input_device() } else { host.default_output_device() };
+    let Some(device) = device else {
+        return Check::Fail("no default device".to_string(), hint);
+    };
+    let name = device.name().unwrap_or_else(|_| "unknown device".to_string());
+
+    let config = if input { device.default_input_config() } else { device.default_output_config() };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => return Check::Fail(format!("{}: {}", name, e), hint),
+    };
+    let summary = format!("{} ({} Hz, {} channels, {})", name, config.sample_rate().0, config.channels(), config.sample_format());
+
+    let on_error = |err| eprintln!("An error occurred on the stream: {}", err);
+    let stream = if input {
+        device.build_input_stream(&config.into(), |_: &[f32], _: &cpal::InputCallbackInfo| {}, on_error, None)
+    } else {
+        device.build_output_stream(&config.into(), |data: &mut [f32], _: &cpal::OutputCallbackInfo| data.fill(0.0), on_error, None)
+    };
+
+    match stream {
+        Ok(_) => Check::Ok(summary),
+        Err(e) => Check::Fail(format!("{}: could not open a stream: {}", summary, e), "another application may hold the device exclusively"),
+    }
+}
@@ -0,0 +1,110 @@
+use std::f32::consts::TAU;
+
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+
+/// Keys that can be pressed, a comma being a pause
+pub const KEYS: &str = "0123456789*#ABCD,";
+
+// Each key sounds this long and is followed by this much silence, well above what exchanges need (40 ms each)
+const TONE_MS: u32 = 100;
+const GAP_MS: u32 = 100;
+// What a comma waits, e.g. for a menu to take the previous key
+const PAUSE_MS: u32 = 500;
+// Of each of the two frequencies, so together they peak below full scale
+const AMPLITUDE: f32 = 0.35;
+// Fades in and out so the tones don't click
+const RAMP_MS: u32 = 5;
+
+/// Row and column frequencies of a key on the telephone keypad
+fn frequencies(key: char) -> Option<(f32, f32)> {
+    const ROWS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+    const COLUMNS: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+    const KEYPAD: [&str; 4] = ["123A", "456B", "789C", "*0#D"];
+
+    KEYPAD.iter().enumerate().find_map(|(row, keys)| keys.find(key).map(|column| (ROWS[row], COLUMNS[column])))
+}
+
+/// DTMF tones of a sequence of keys, mono at SERVER_SAMPLE_RATE
+///
+/// Every tone and gap is a whole number of samples long, so the timing doesn't drift however
+/// many keys are pressed.
+pub fn tones(keys: &str) -> Result<Vec<f32>, String> {
+    let samples = |ms: u32| (SERVER_SAMPLE_RATE * ms / 1000) as usize;
+    let mut output = Vec::new();
+
+    for key in keys.chars().filter(|key| !key.is_whitespace()).map(|key| key.to_ascii_uppercase()) {
+        if key == ',' {
+            output.resize(output.len() + samples(PAUSE_MS), 0.0);
+            continue;
+        }
+        let (low, high) = frequencies(key).ok_or_else(|| format!("{} is not a telephone key, expected some of {}", key, KEYS))?;
+
+        let (length, ramp) = (samples(TONE_MS), samples(RAMP_MS));
+        output.extend((0..length).map(|index| {
+            let time = index as f32 / SERVER_SAMPLE_RATE as f32;
+            let envelope = (index.min(length - 1 - index) as f32 / ramp as f32).min(1.0);
+            AMPLITUDE * envelope * ((TAU * low * time).sin() + (TAU * high * time).sin())
+        }));
+        output.resize(output.len() + samples(GAP_MS), 0.0);
+    }
+
+    if output.is_empty() {
+        return Err("no keys to press".to_string());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Amplitude of the `frequency` component of a tone (Goertzel)
+    fn amplitude(samples: &[f32], frequency: f32) -> f32 {
+        let coefficient = 2.0 * (TAU * frequency / SERVER_SAMPLE_RATE as f32).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &sample in samples {
+            let s0 = sample + coefficient * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        (s1 * s1 + s2 * s2 - coefficient * s1 * s2).sqrt() * 2.0 / samples.len() as f32
+    }
+
+    #[test]
+    fn every_key_has_its_row_and_column() {
+        assert_eq!(frequencies('1'), Some((697.0, 1209.0)));
+        assert_eq!(frequencies('5'), Some((770.0, 1336.0)));
+        assert_eq!(frequencies('9'), Some((852.0, 1477.0)));
+        assert_eq!(frequencies('0'), Some((941.0, 1336.0)));
+        assert_eq!(frequencies('#'), Some((941.0, 1477.0)));
+        assert_eq!(frequencies('D'), Some((941.0, 1633.0)));
+        assert!(KEYS.chars().filter(|key| *key != ',').all(|key| frequencies(key).is_some()));
+        assert_eq!(frequencies('E'), None);
+    }
+
+    #[test]
+    fn tones_are_timed_to_the_sample() {
+        let samples = |ms: u32| (SERVER_SAMPLE_RATE * ms / 1000) as usize;
+        let tones = tones("12, 3").unwrap();
+        assert_eq!(tones.len(), 3 * samples(TONE_MS + GAP_MS) + samples(PAUSE_MS));
+
+        // Each key sounds its two frequencies and nothing else of the keypad's, then goes quiet
+        let three = &tones[2 * samples(TONE_MS + GAP_MS) + samples(PAUSE_MS)..][..samples(TONE_MS)];
+        assert!(amplitude(three, 697.0) > 0.3 && amplitude(three, 1477.0) > 0.3);
+        assert!(amplitude(three, 770.0) < 0.05 && amplitude(three, 1209.0) < 0.05);
+        assert!(tones[samples(TONE_MS)..samples(TONE_MS + GAP_MS)].iter().all(|sample| *sample == 0.0));
+
+        // Faded in and out, and below full scale
+        assert_eq!(tones[0], 0.0);
+        assert!(tones[samples(TONE_MS) - 1].abs() < 1e-6);
+        assert!(tones.iter().all(|sample| sample.abs() <= 2.0 * AMPLITUDE));
+    }
+
+    #[test]
+    fn keys_are_checked() {
+        assert_eq!(tones("a").unwrap(), tones("A").unwrap());
+        assert_eq!(tones("1 2").unwrap(), tones("12").unwrap());
+        assert!(tones("1x").unwrap_err().contains("X is not a telephone key"));
+        assert_eq!(tones(" ").unwrap_err(), "no keys to press");
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::bundle::write_bundle;
+use crate::chat::{ChatCompletions, DEFAULT_CHAT_MODEL};
+use crate::conversation::{ContentType, ConversationItem, ConversationItemRole, ItemContent, ItemKind, TurnUsage};
+use crate::gateway::Gateway;
+use crate::metadata::SessionMetadata;
+use crate::storage::{read_file, write_file, Encryption};
+use crate::text_layout::truncate;
+
+/// Transcript file saved at the end of a call
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    #[serde(default)]
+    pub metadata: SessionMetadata,
+    pub items: Vec<ConversationItem>,
+}
+
+/// Loads a transcript saved at the end of a call, decrypting it if needed
+pub fn load_transcript(path: &Path, encryption: Option<&Encryption>) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let data = read_file(path, encryption)?;
+
+    // Older transcripts are a bare list of items
+    match serde_json::from_slice::<Vec<ConversationItem>>(&data) {
+        Ok(items) => Ok(Transcript { metadata: SessionMetadata::default(), items }),
+        Err(_) => Ok(serde_json::from_slice(&data)?),
+    }
+}
+
+/// Renders a transcript as Markdown
+pub fn transcript_markdown(metadata: &SessionMetadata, items: &[ConversationItem]) -> String {
+    let mut markdown = String::from("# Transcript\n");
+
+    if !metadata.is_empty() {
+        markdown.push('\n');
+        for (key, value) in metadata.pairs() {
+            markdown.push_str(&format!("- **{}:** {}\n", key, value));
+        }
+    }
+
+    for item in items {
+        let speaker = match &item.role {
+            ConversationItemRole::User => "User",
+            ConversationItemRole::Assistant => "Assistant",
+            ConversationItemRole::System => "System",
+            ConversationItemRole::Tool => "Tool",
+            ConversationItemRole::Unknown(role) => role,
+        };
+        let truncated = if item.truncated { " _(interrupted)_" } else { "" };
+
+        if let Some(chapter) = &item.chapter {
+            markdown.push_str(&format!("\n## {}\n", chapter));
+        }
+
+        if let Some(instructions) = &item.instructions {
+            markdown.push_str(&format!("\n_Instructions changed:_\n\n> {}\n", instructions.replace('\n', "\n> ")));
+        }
+
+        let sentiment = item.sentiment.as_ref().map(|sentiment| format!(" _[{}]_", sentiment.label)).unwrap_or_default();
+
+        let talk_overs: String = item
+            .talk_overs
+            .iter()
+            .map(|talk_over| {
+                let interrupted = if talk_over.interrupted { ", interrupted" } else { "" };
+                format!(" _(talked over at {:.1} s{})_", talk_over.at_ms as f64 / 1000.0, interrupted)
+            })
+            .collect();
+
+        // Calls and their outputs fold away, they are mostly JSON nobody reads
+        match &item.kind {
+            ItemKind::Message => markdown.push_str(&format!("\n**{}:**{} {}{}{}\n", speaker, sentiment, item.text().trim(), truncated, talk_overs)),
+            ItemKind::FunctionCall { name, .. } => {
+                markdown.push_str(&format!("\n<details><summary>Called <code>{}</code></summary>\n\n```json\n{}\n```\n\n</details>\n", name, pretty_json(&item.text())));
+            }
+            ItemKind::FunctionCallOutput { .. } => {
+                markdown.push_str(&format!("\n<details><summary>Function output</summary>\n\n```\n{}\n```\n\n</details>\n", pretty_json(&item.text())));
+            }
+        }
+        if let Some(translation) = &item.translation {
+            markdown.push_str(&format!("\n> {}\n", translation.replace('\n', "\n> ")));
+        }
+    }
+
+    markdown.push_str(&cost_table(items));
+    markdown
+}
+
+/// JSON pretty-printed, anything else trimmed as is
+pub fn pretty_json(text: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|json| serde_json::to_string_pretty(&json).ok())
+        .unwrap_or_else(|| text.trim().to_string())
+}
+
+/// What each turn cost, with a bar relative to the most expensive one, empty without usage
+///
+/// Turns are numbered in the order they were answered, and named by the user message they
+/// answer, or by their own text when there's none.
+fn cost_table(items: &[ConversationItem]) -> String {
+    const BAR_WIDTH: f64 = 20.0;
+
+    let turns: Vec<(usize, &TurnUsage)> = items.iter().enumerate().filter_map(|(index, item)| Some((index, item.usage.as_ref()?))).collect();
+    let Some(max_cost) = turns.iter().map(|(_, usage)| usage.cost_usd).reduce(f64::max) else {
+        return String::new();
+    };
+
+    let mut table = String::from("\n## Cost per turn\n\n| # | Turn | Tokens in | Tokens out | Cost | |\n|---:|---|---:|---:|---:|---|\n");
+    for (turn, (index, usage)) in turns.iter().enumerate() {
+        let prompt = items[..*index].iter().rev().take_while(|item| item.role != ConversationItemRole::Assistant).find(|item| item.role == ConversationItemRole::User);
+        let text = prompt.unwrap_or(&items[*index]).text();
+        let bar = "█".repeat((usage.cost_usd / max_cost.max(f64::EPSILON) * BAR_WIDTH).round() as usize);
+        table.push_str(&format!(
+            "| {} | {} | {} | {} | ${:.4} | {} |\n",
+            turn + 1,
+            truncate(text.trim(), 40).replace('\n', " ").replace('|', "\\|"),
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cost_usd,
+            bar
+        ));
+    }
+
+    let (input, output, cost) = turns.iter().fold((0, 0, 0.0), |(input, output, cost), (_, usage)| {
+        (input + usage.input_tokens, output + usage.output_tokens, cost + usage.cost_usd)
+    });
+    table.push_str(&format!("| | **Total** | {} | {} | ${:.4} | |\n", input, output, cost));
+    table
+}
+
+/// Converts a saved transcript to Markdown, printing it when no output path is given
+///
+/// With `translate_to` the messages are translated first, e.g. to share call notes in another language.
+/// With `bundle` everything about the call is packed into a zip archive instead, see `write_bundle`.
+pub async fn export(
+    input: &Path,
+    output: Option<&Path>,
+    translate_to: Option<&str>,
+    bundle: Option<(&Path, Option<&Path>)>,
+    encryption: Option<&Encryption>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut transcript = load_transcript(input, encryption)?;
+
+    let markdown = match translate_to {
+        Some(language) => {
+            transcript.items = translate_items(&transcript.items, language).await?;
+            transcript_markdown(&transcript.metadata, &transcript.items)
+                .replacen("# Transcript\n", &format!("# Transcript\n\n_Translated to {}_\n", language), 1)
+        }
+        None => transcript_markdown(&transcript.metadata, &transcript.items),
+    };
+
+    match (bundle, output) {
+        (Some((path, dump)), _) => write_bundle(path, &transcript, &markdown, dump, encryption),
+        (None, Some(path)) => write_file(path, markdown.as_bytes(), encryption),
+        (None, None) => {
+            print!("{}", markdown);
+            Ok(())
+        }
+    }
+}
+
+/// Translates the text of each item with a single Chat Completions request
+async fn translate_items(items: &[ConversationItem], language: &str) -> Result<Vec<ConversationItem>, Box<dyn std::error::Error>> {
+    // Chat Completions is plenty for a one-off translation, no need for a realtime session
+    let chat = ChatCompletions::new(Gateway::default());
+    // Function calls and outputs are left as they are, only their slots are kept
+    let texts = items.iter().map(|item| if item.kind.is_message() { item.text().trim().to_string() } else { String::new() }).collect::<Vec<_>>();
+
+    let request = serde_json::json!({
+        "model": DEFAULT_CHAT_MODEL,
+        "response_format": {"type": "json_object"},
+        "messages": [
+            {
+                "role": "system",
+                "content": format!(
+                    "Translate each string of the JSON array in `texts` into {}. Reply with a JSON object {{\"texts\": [...]}} \
+                     holding exactly as many strings, in the same order. Keep empty strings empty.",
+                    language
+                )
+            },
+            {"role": "user", "content": serde_json::json!({"texts": texts}).to_string()}
+        ]
+    });
+
+    let content = chat.complete(&request).await.map_err(|e| format!("Translating failed: {}", e))?;
+    let translated: serde_json::Value = serde_json::from_str(&content)?;
+    let translated = translated["texts"].as_array().ok_or("Translation response has no texts")?;
+    if translated.len() != items.len() {
+        return Err(format!("Translation returned {} messages instead of {}", translated.len(), items.len()).into());
+    }
+
+    Ok(items
+        .iter()
+        .zip(translated)
+        .map(|(item, text)| match item.kind.is_message() {
+            true => ConversationItem {
+                content: vec![ItemContent { content_type: ContentType::Text, text: text.as_str().unwrap_or_default().to_string() }],
+                ..item.clone()
+            },
+            false => item.clone(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(id: &str, role: &str, text: &str, usage: Option<(u64, u64, f64)>) -> ConversationItem {
+        let mut item = ConversationItem::new(&json!({"id": id, "type": "message", "role": role, "content": [{"type": "text", "text": text}]}));
+        item.usage = usage.map(|(input_tokens, output_tokens, cost_usd)| TurnUsage { input_tokens, output_tokens, cost_usd });
+        item
+    }
+
+    #[test]
+    fn cost_table_numbers_the_turns_and_names_them_by_their_prompt() {
+        let items = [
+            message("item_1", "system", "Be brief", None),
+            message("item_2", "user", "What's the weather | like?", None),
+            message("item_3", "assistant", "Sunny.", Some((100, 20, 0.002))),
+            message("item_4", "assistant", "And warm.", None),
+            message("item_5", "user", "Thanks", None),
+            message("item_6", "assistant", "You're welcome.", Some((150, 10, 0.001))),
+            // Started by the assistant, e.g. a greeting after a system message
+            message("item_7", "assistant", "Anything else?", Some((160, 5, 0.0005))),
+        ];
+        let table = cost_table(&items);
+
+        let rows: Vec<&str> = table.lines().filter(|line| line.starts_with("| ")).collect();
+        assert_eq!(rows[1], format!("| 1 | What's the weather \\| like? | 100 | 20 | $0.0020 | {} |", "█".repeat(20)));
+        assert_eq!(rows[2], format!("| 2 | Thanks | 150 | 10 | $0.0010 | {} |", "█".repeat(10)));
+        assert_eq!(rows[3], format!("| 3 | Anything else? | 160 | 5 | $0.0005 | {} |", "█".repeat(5)));
+        assert_eq!(rows[4], "| | **Total** | 410 | 35 | $0.0035 | |");
+    }
+
+    #[test]
+    fn cost_table_is_left_out_without_usage() {
+        let items = [message("item_1", "user", "Hi", None), message("item_2", "assistant", "Hello", None)];
+        assert_eq!(cost_table(&items), "");
+        assert!(!transcript_markdown(&SessionMetadata::default(), &items).contains("Cost per turn"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use crate::chat::{ChatCompletions, DEFAULT_CHAT_MODEL};
+use crate::conversation::{ConversationItem, ConversationItemRole, ItemKind};
+
+/// Model answering typed messages while the realtime service is down, unless another is named
+pub const DEFAULT_FALLBACK_MODEL: &str = DEFAULT_CHAT_MODEL;
+/// How often the realtime service is tried again meanwhile
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Carries a call on in text over Chat Completions when the realtime endpoint can't be reached
+///
+/// Each reply is a single request with the instructions and the conversation so far, as text: audio
+/// goes by its transcript, and tool calls and their outputs as notes, so the model knows of them.
+pub struct TextFallback {
+    model: String,
+    chat: ChatCompletions,
+}
+
+impl TextFallback {
+    pub fn new(model: &str, chat: ChatCompletions) -> Self {
+        Self { model: model.to_string(), chat }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Answers the user's message, following on from the conversation
+    pub async fn reply(&self, instructions: &str, items: &[ConversationItem], text: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let request = serde_json::json!({"model": self.model, "messages": messages(instructions, items, text)});
+        self.chat.complete(&request).await
+    }
+}
+
+/// The Chat Completions messages for a reply to `text`, after the instructions and the conversation
+fn messages(instructions: &str, items: &[ConversationItem], text: &str) -> Vec<serde_json::Value> {
+    let mut messages = Vec::new();
+    if !instructions.trim().is_empty() {
+        messages.push(serde_json::json!({"role": "system", "content": instructions}));
+    }
+    for item in items {
+        let content = match (&item.kind, &item.role) {
+            (ItemKind::Message, ConversationItemRole::User | ConversationItemRole::Assistant) => item.text(),
+            _ => item.plain_line(),
+        };
+        if content.trim().is_empty() {
+            continue;
+        }
+        let role = match (&item.kind, &item.role) {
+            (ItemKind::Message, ConversationItemRole::User) => "user",
+            (ItemKind::Message, ConversationItemRole::Assistant) => "assistant",
+            _ => "system",
+        };
+        messages.push(serde_json::json!({"role": role, "content": content.trim()}));
+    }
+    messages.push(serde_json::json!({"role": "user", "content": text}));
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn messages_carry_the_conversation_as_text() {
+        let items = [
+            ConversationItem::new(&json!({"type": "message", "role": "user", "content": [{"type": "input_audio", "transcript": "What's the weather?"}]})),
+            ConversationItem::new(&json!({"type": "function_call", "name": "weather", "arguments": "{\"city\":\"Oslo\"}"})),
+            ConversationItem::new(&json!({"type": "function_call_output", "output": "Rain"})),
+            ConversationItem::new(&json!({"type": "message", "role": "assistant", "content": [{"type": "audio", "transcript": " Rain in Oslo. "}]})),
+            // Nothing to say, e.g. audio that was never transcribed
+            ConversationItem::new(&json!({"type": "message", "role": "user", "content": [{"type": "input_audio"}]})),
+        ];
+
+        assert_eq!(
+            messages("Be brief.", &items, "And tomorrow?"),
+            vec![
+                json!({"role": "system", "content": "Be brief."}),
+                json!({"role": "user", "content": "What's the weather?"}),
+                json!({"role": "system", "content": "Assistant called weather({\"city\":\"Oslo\"})"}),
+                json!({"role": "system", "content": "Function output: Rain"}),
+                json!({"role": "assistant", "content": "Rain in Oslo."}),
+                json!({"role": "user", "content": "And tomorrow?"}),
+            ]
+        );
+        assert_eq!(messages(" ", &[], "Hi"), vec![json!({"role": "user", "content": "Hi"})]);
+    }
+}
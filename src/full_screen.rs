@@ -0,0 +1,140 @@
+use crossterm::cursor::MoveTo;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, Write};
+
+/// The terminal as a full-screen view draws on it
+pub type Terminal<'a> = io::BufWriter<&'a mut File>;
+
+/// Runs a view that takes the whole terminal, e.g. the inspector or the log pane
+///
+/// The view draws on the alternate screen in raw mode through the terminal it's given, and
+/// everything else the call prints is held meanwhile, see `hold_output`.
+pub fn show<T>(view: impl FnOnce(&mut Terminal) -> io::Result<T>) -> io::Result<T> {
+    hold_output(|tty| {
+        terminal::enable_raw_mode()?;
+        execute!(tty, EnterAlternateScreen)?;
+
+        // Frames are written whole, so they don't flicker
+        let result = view(&mut io::BufWriter::new(&mut *tty));
+
+        execute!(tty, LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    })
+}
+
+/// Replaces what the view shows with these lines, from the top
+pub fn draw(out: &mut Terminal, lines: impl IntoIterator<Item = impl Display>) -> io::Result<()> {
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+    for line in lines {
+        write!(out, "{}\r\n", line)?;
+    }
+    out.flush()
+}
+
+/// Holds back what the call prints while `body` has the terminal, printing it once it's done
+///
+/// The transcript and the other subscribers go on printing as the call goes on; their output is
+/// set aside at the descriptor level, like JSON Lines output does, so none of it ends up in the
+/// middle of the view. `body` gets the terminal itself, e.g. to hand to an editor.
+#[cfg(unix)]
+pub fn hold_output<T>(body: impl FnOnce(&mut File) -> io::Result<T>) -> io::Result<T> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+
+    // Unlinked right away, so nothing's left behind however the call ends
+    let held = |name: &str| -> io::Result<File> {
+        let path = std::env::temp_dir().join(format!("hotline-{}-{}", name, uuid::Uuid::new_v4()));
+        let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(file)
+    };
+    let (held_stdout, held_stderr) = (held("stdout")?, held("stderr")?);
+
+    // SAFETY: plain descriptor calls, each duplicate is owned by a File from here on
+    let duplicate = |fd| match unsafe { libc::dup(fd) } {
+        fd if fd >= 0 => Ok(unsafe { File::from_raw_fd(fd) }),
+        _ => Err(io::Error::last_os_error()),
+    };
+    let redirect = |from: &File, to| match unsafe { libc::dup2(from.as_raw_fd(), to) } {
+        fd if fd >= 0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    };
+    let mut terminal = duplicate(libc::STDOUT_FILENO)?;
+    let saved_stderr = duplicate(libc::STDERR_FILENO)?;
+    redirect(&held_stdout, libc::STDOUT_FILENO)?;
+    redirect(&held_stderr, libc::STDERR_FILENO)?;
+
+    let result = body(&mut terminal);
+
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    redirect(&terminal, libc::STDOUT_FILENO)?;
+    redirect(&saved_stderr, libc::STDERR_FILENO)?;
+    replay(held_stdout, &mut io::stdout())?;
+    replay(held_stderr, &mut io::stderr())?;
+    result
+}
+
+#[cfg(not(unix))]
+pub fn hold_output<T>(body: impl FnOnce(&mut File) -> io::Result<T>) -> io::Result<T> {
+    // Nothing to redirect, output goes on appearing over the view
+    let mut terminal = File::options().write(true).open("CONOUT$")?;
+    body(&mut terminal)
+}
+
+/// Prints what was held back, from the start
+#[cfg(unix)]
+fn replay(mut held: File, out: &mut impl Write) -> io::Result<()> {
+    use std::io::Seek;
+
+    held.rewind()?;
+    io::copy(&mut held, out)?;
+    out.flush()
+}
+
+/// The terminal handing over keys as they're pressed instead of once enter is, until dropped
+///
+/// Unlike raw mode, keys are still echoed and ctrl-c still interrupts, so the call goes on
+/// printing as usual meanwhile. Dropping it puts the terminal back as it was.
+#[cfg(unix)]
+pub struct KeysAtATime(libc::termios);
+
+#[cfg(unix)]
+pub fn keys_at_a_time() -> io::Result<KeysAtATime> {
+    // SAFETY: termios is plain data, filled in by tcgetattr before it's read
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut keys = saved;
+    keys.c_lflag &= !libc::ICANON;
+    keys.c_cc[libc::VMIN] = 1;
+    keys.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(KeysAtATime(saved))
+}
+
+#[cfg(unix)]
+impl Drop for KeysAtATime {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+
+#[cfg(not(unix))]
+pub struct KeysAtATime;
+
+#[cfg(not(unix))]
+pub fn keys_at_a_time() -> io::Result<KeysAtATime> {
+    // Lines it is, a number is sent with enter
+    Err(io::ErrorKind::Unsupported.into())
+}
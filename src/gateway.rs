@@ -0,0 +1,129 @@
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use url::Url;
+
+use crate::client::DEFAULT_URL;
+
+/// How the API key is presented in the handshake
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum AuthScheme {
+    Bearer,     // "Authorization: Bearer <key>", as the OpenAI API expects
+    Header,     // The bare key in a header of its own, e.g. "api-key: <key>"
+    None,       // No key, the gateway authenticates some other way (or not at all)
+}
+
+/// Where the realtime WebSocket is opened and how: the OpenAI API or a gateway proxying it
+#[derive(Debug, Clone)]
+pub struct Gateway {
+    pub url: String,                        // WebSocket URL, the model goes in its query
+    pub api_key: Option<String>,            // Needed unless auth is None
+    pub auth: AuthScheme,
+    pub auth_header: String,                // Header carrying the key with AuthScheme::Header
+    pub headers: Vec<(String, String)>,     // Extra handshake headers, replacing defaults of the same name
+    pub subprotocols: Vec<String>,          // Offered in Sec-WebSocket-Protocol, the server has to pick one
+}
+
+impl Default for Gateway {
+    /// The OpenAI API, with the key in OPENAI_API_KEY
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            auth: AuthScheme::Bearer,
+            auth_header: "api-key".to_string(),
+            headers: Vec::new(),
+            subprotocols: Vec::new(),
+        }
+    }
+}
+
+impl Gateway {
+    /// Builds the WebSocket handshake request for a session with this model
+    pub fn request(&self, model: &str) -> Result<Request, Box<dyn std::error::Error>> {
+        let mut url = Url::parse(&self.url)?;
+        url.query_pairs_mut().append_pair("model", model);
+
+        let mut request = url.into_client_request()?;
+        let headers = request.headers_mut();
+        headers.insert("OpenAI-Beta", "realtime=v1".parse().unwrap());
+
+        for (name, value) in self.auth_headers()? {
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(&value)?);
+        }
+        if !self.subprotocols.is_empty() {
+            headers.insert("Sec-WebSocket-Protocol", self.subprotocols.join(", ").parse()?);
+        }
+
+        Ok(request)
+    }
+
+    /// Headers presenting the API key, then the extra ones, for the handshake and any HTTP request alike
+    pub fn auth_headers(&self) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        let api_key = || {
+            self.api_key
+                .as_deref()
+                .ok_or("API key must be provided either as an argument or in the environment variable OPENAI_API_KEY")
+        };
+        let mut headers = match self.auth {
+            AuthScheme::Bearer => vec![("Authorization".to_string(), format!("Bearer {}", api_key()?))],
+            AuthScheme::Header => vec![(self.auth_header.clone(), api_key()?.to_string())],
+            AuthScheme::None => Vec::new(),
+        };
+        headers.extend(self.headers.iter().cloned());
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway(auth: AuthScheme) -> Gateway {
+        Gateway {
+            url: "wss://gateway.example/v1/realtime?tenant=ops".to_string(),
+            api_key: Some("sk-test".to_string()),
+            auth,
+            auth_header: "api-key".to_string(),
+            headers: Vec::new(),
+            subprotocols: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_key_is_presented_per_the_scheme() {
+        let header = |request: &Request, name| request.headers().get(name).map(|value| value.to_str().unwrap().to_string());
+
+        let request = gateway(AuthScheme::Bearer).request("gpt-realtime").unwrap();
+        assert_eq!(request.uri().to_string(), "wss://gateway.example/v1/realtime?tenant=ops&model=gpt-realtime");
+        assert_eq!(header(&request, "Authorization").as_deref(), Some("Bearer sk-test"));
+        assert_eq!(header(&request, "OpenAI-Beta").as_deref(), Some("realtime=v1"));
+        assert_eq!(header(&request, "Sec-WebSocket-Protocol"), None);
+
+        let request = gateway(AuthScheme::Header).request("gpt-realtime").unwrap();
+        assert_eq!((header(&request, "api-key").as_deref(), header(&request, "Authorization")), (Some("sk-test"), None));
+
+        // No key needed, nor sent
+        let request = Gateway { api_key: None, ..gateway(AuthScheme::None) }.request("gpt-realtime").unwrap();
+        assert_eq!((header(&request, "api-key"), header(&request, "Authorization")), (None, None));
+        assert!(Gateway { api_key: None, ..gateway(AuthScheme::Bearer) }.request("gpt-realtime").unwrap_err().to_string().contains("OPENAI_API_KEY"));
+    }
+
+    #[test]
+    fn extra_headers_and_subprotocols_go_in_the_handshake() {
+        let request = Gateway {
+            headers: vec![("X-Team".to_string(), "voice".to_string()), ("OpenAI-Beta".to_string(), "realtime=v2".to_string())],
+            subprotocols: vec!["realtime".to_string(), "openai-insecure-api-key.sk-test".to_string()],
+            ..gateway(AuthScheme::Bearer)
+        }
+        .request("gpt-realtime")
+        .unwrap();
+        assert_eq!(request.headers()["X-Team"], "voice");
+        // Replacing a default, not added next to it
+        assert_eq!(request.headers().get_all("OpenAI-Beta").iter().collect::<Vec<_>>(), ["realtime=v2"]);
+        assert_eq!(request.headers()["Sec-WebSocket-Protocol"], "realtime, openai-insecure-api-key.sk-test");
+
+        let bad = Gateway { headers: vec![("Bad Name".to_string(), "x".to_string())], ..gateway(AuthScheme::Bearer) };
+        assert!(bad.request("gpt-realtime").is_err());
+    }
+}
@@ -1,54 +1,394 @@
-use tokio::sync::mpsc;
-use std::io::{self, Write};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use serde_json::Value;
 
-use crate::audio_utils::{base64_decode_audio, initialize_audio_stream, resample_audio, SERVER_SAMPLE_RATE};
+use crate::audio_utils::AudioOutput;
+use crate::commands::Command;
+use crate::conversation::ConversationTracker;
+use crate::pending::PendingOperations;
+use crate::recorder::Recorder;
+use crate::session_summary::{ErrorKind, ErrorTracker};
+use crate::usage::UsageTracker;
 
+#[cfg(target_os = "linux")]
+mod ducking;
+mod audio_buffer;
+mod auto_commit;
+mod banner;
+mod captions;
+mod chapters;
+mod chat;
+mod cues;
+mod empty_response;
+mod errors;
+mod heartbeat;
+mod idle;
+mod jsonl;
+mod latency;
+mod logger;
+mod loop_guard;
+mod metrics;
+mod notifications;
+mod pending;
+mod playback;
+mod screen;
+mod sentiment;
+mod status_bar;
+mod talk_over;
+mod talk_time;
+mod tools;
+mod transcript;
+mod transcript_ws;
+mod translation;
+mod turn_response;
+#[cfg(all(feature = "tray", target_os = "linux"))]
+mod tray;
 
-pub async fn handle_events(mut event_receiver: mpsc::Receiver<Value>) {
-    // Initialize the audio stream
-    let (audio_sender, output_sample_rate) = initialize_audio_stream();
+pub use chat::TextStyle;
+pub use cues::Cue;
+pub use empty_response::EMPTY_RESPONSE_RETRIES;
+pub use heartbeat::HEARTBEAT_INTERVAL;
+pub use loop_guard::LoopGuard;
+pub use notifications::{NotificationSettings, NotifyOn};
+pub use playback::{InterruptionMode, MicPolicy};
 
+// How often falling behind is pointed out at most
+const BEHIND_WARNING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Dispatches events (received from the server, sent by us, or raised locally) to independent subscribers
+///
+/// Each subscriber runs in its own task with its own channel, so adding a consumer means adding a
+/// module and subscribing it here. Each channel holds `capacity` events; a subscriber that lets
+/// its channel fill up (e.g. printing to a slow terminal) holds up the others, which is counted
+/// and pointed out so the capacity can be raised.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_events(
+    mut event_receiver: mpsc::Receiver<Value>,
+    command_sender: mpsc::Sender<Command>,
+    usage: Arc<Mutex<UsageTracker>>,
+    errors: Arc<Mutex<ErrorTracker>>,
+    conversation: Arc<Mutex<ConversationTracker>>,
+    recorder: Arc<Mutex<Recorder>>,
+    pending: Arc<Mutex<PendingOperations>>,
+    mut audio: AudioOutput,
+    capacity: usize,
+) {
+    let playback_thread = audio.thread.take();
+
+    let mut subscribers = Vec::new();
+    let mut tasks: Vec<JoinHandle<()>> = Vec::new();
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(logger::run(receiver, recorder)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(errors::run(receiver, errors.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(transcript::run(receiver, conversation.clone(), errors.clone(), screen::Screen::stdout())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(transcript_ws::run(receiver)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(jsonl::run(receiver)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(metrics::run(receiver, usage, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(tools::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(translation::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(captions::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(sentiment::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(chapters::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(notifications::run(receiver)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(banner::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(pending::run(receiver, pending)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(audio_buffer::run(receiver)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(heartbeat::run(receiver)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(talk_time::run(receiver)));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(idle::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(latency::run(receiver, audio.played_samples.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(cues::run(receiver, audio.played_samples.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(loop_guard::run(receiver, audio.played_samples.clone(), command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(empty_response::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(auto_commit::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(turn_response::run(receiver, command_sender.clone())));
+    subscribers.push(sender);
+
+    #[cfg(target_os = "linux")]
+    {
+        let (sender, receiver) = mpsc::channel(capacity);
+        tasks.push(tokio::spawn(ducking::run(receiver, audio.played_samples.clone())));
+        subscribers.push(sender);
+    }
+
+    #[cfg(all(feature = "tray", target_os = "linux"))]
+    {
+        let (sender, receiver) = mpsc::channel(capacity);
+        tasks.push(tokio::spawn(tray::run(receiver, command_sender.clone())));
+        subscribers.push(sender);
+    }
+
+    let (sender, receiver) = mpsc::channel(capacity);
+    tasks.push(tokio::spawn(playback::Player::new(audio, command_sender, conversation).run(receiver)));
+    subscribers.push(sender);
+
+    let mut blocked = 0;        // Sends that found a subscriber's channel full and waited
+    let mut dropped = 0;        // Sends to a subscriber that had stopped
+    let mut warned_at: Option<Instant> = None;
     while let Some(event) = event_receiver.recv().await {
-        if let Some(event_type) = event.get("type").and_then(Value::as_str) {
-            match event_type {
-                "conversation.item.create" => {
-                    // Handle conversation item creation
-                },
-                "response.create" => {
-                    // Handle response creation
-                },
-                "response.audio_transcript.delta" => {
-                    // Handle audio transcript delta events
-                    let transcript = event["delta"].as_str().unwrap();
-
-                    // Print the transcript
-                    print!("{}", transcript);
-                    io::stdout().flush().unwrap();
-                },
-                "response.audio.delta" => {
-                    // Handle audio delta events
-                    let base64_audio_data = event["delta"].as_str().unwrap();
-
-                    // Decode the base64 audio data
-                    let samples = base64_decode_audio(base64_audio_data);
-
-                    // Resample the audio data to the output sample rate
-                    let resampled_samples = resample_audio(&samples, SERVER_SAMPLE_RATE, output_sample_rate);
-
-                    // Send the resampled samples to the audio thread
-                    if let Err(e) = audio_sender.send(resampled_samples) {
-                        eprintln!("Failed to send audio samples: {}", e);
+        // Shared rather than cloned, audio deltas are large
+        let event = Arc::new(event);
+
+        for subscriber in &subscribers {
+            let sent = match subscriber.try_send(event.clone()) {
+                Err(TrySendError::Full(event)) => {
+                    blocked += 1;
+                    if warned_at.is_none_or(|warned_at| warned_at.elapsed() >= BEHIND_WARNING_INTERVAL) {
+                        eprintln!("\n[event handling is falling behind, {} events had to wait; a larger --event-buffer, or event-buffer in the config, may help]", blocked);
+                        warned_at = Some(Instant::now());
                     }
+                    subscriber.send(event).await.is_ok()
                 }
-                "error" => {
-                    // Handle error events
-                    println!("Error event: {:?}", event);
-                },
-                // Add more event types as needed
-                _ => println!("Unhandled event type: {}", event_type),
-                // _ => (),
+                result => result.is_ok(),
+            };
+            if !sent {
+                dropped += 1;
+                errors.lock().unwrap().count(ErrorKind::DroppedEvents);
+                eprintln!("Event subscriber stopped unexpectedly");
             }
         }
     }
-}
\ No newline at end of file
+    if blocked > 0 || dropped > 0 {
+        tracing::info!(blocked, dropped, capacity, "events held up by subscribers");
+        eprintln!("[events: {} waited for a subscriber to catch up, {} were dropped]", blocked, dropped);
+    }
+
+    // Let the subscribers drain their channels before returning
+    drop(subscribers);
+    for task in tasks {
+        if let Err(e) = task.await {
+            eprintln!("Event subscriber failed: {}", e);
+        }
+    }
+
+    // The player is gone and with it the playback thread's sender
+    if let Some(playback_thread) = playback_thread {
+        if tokio::task::spawn_blocking(move || playback_thread.join()).await.is_err() {
+            eprintln!("Audio playback thread failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_utils::{PlaybackCommand, SERVER_SAMPLE_RATE};
+    use crate::commands::InternalCommand;
+    use crate::tools::ToolCall;
+    use std::sync::atomic::AtomicUsize;
+    use crate::conversation::{ConversationItem, ConversationItemRole, ConversationItemStatus};
+
+    /// Everything the event handler produced for a fixture
+    struct Outcome {
+        items: Vec<ConversationItem>,
+        playback: Vec<PlaybackCommand>,
+        commands: Vec<Command>,
+        total_tokens: u64,
+    }
+
+    /// Drives handle_events through a recorded event sequence, one JSON event per line
+    async fn run_fixture(fixture: &str) -> Outcome {
+        let (event_sender, event_receiver) = mpsc::channel(100);
+        let (command_sender, mut command_receiver) = mpsc::channel(100);
+        let (audio_sender, audio_receiver) = std::sync::mpsc::channel();
+        let usage = Arc::new(Mutex::new(UsageTracker::default()));
+        let conversation = Arc::new(Mutex::new(ConversationTracker::default()));
+
+        // Nothing is ever played, as if the fixture arrived faster than real time
+        let audio = AudioOutput {
+            sender: audio_sender,
+            sample_rate: SERVER_SAMPLE_RATE,
+            played_samples: Arc::new(AtomicUsize::new(0)),
+            thread: None,
+        };
+
+        let handler = tokio::spawn(handle_events(
+            event_receiver,
+            command_sender,
+            usage.clone(),
+            Arc::new(Mutex::new(ErrorTracker::default())),
+            conversation.clone(),
+            Arc::new(Mutex::new(Recorder::default())),
+            Arc::new(Mutex::new(PendingOperations::default())),
+            audio,
+            100,
+        ));
+
+        for line in fixture.lines().filter(|line| !line.trim().is_empty()) {
+            event_sender.send(serde_json::from_str(line).unwrap()).await.unwrap();
+        }
+        drop(event_sender);
+        handler.await.unwrap();
+
+        let mut commands = Vec::new();
+        while let Ok(command) = command_receiver.try_recv() {
+            commands.push(command);
+        }
+
+        let items = conversation.lock().unwrap().items().to_vec();
+        let total_tokens = usage.lock().unwrap().total_tokens();
+
+        Outcome {
+            items,
+            playback: audio_receiver.try_iter().collect(),
+            commands,
+            total_tokens,
+        }
+    }
+
+    fn played_chunks(outcome: &Outcome) -> usize {
+        outcome.playback.iter().filter(|command| matches!(command, PlaybackCommand::Play(_))).count()
+    }
+
+    #[tokio::test]
+    async fn text_turn() {
+        let outcome = run_fixture(include_str!("../fixtures/events/text_turn.jsonl")).await;
+
+        assert_eq!(outcome.items.len(), 2);
+        assert_eq!(outcome.items[0].role, ConversationItemRole::User);
+        assert_eq!(outcome.items[0].text(), "What is the capital of France?");
+        assert_eq!(outcome.items[1].role, ConversationItemRole::Assistant);
+        assert_eq!(outcome.items[1].status, ConversationItemStatus::Completed);
+        assert_eq!(outcome.items[1].text(), "The capital of France is Paris.");
+
+        assert!(outcome.playback.is_empty());
+        assert!(outcome.commands.is_empty());
+        assert_eq!(outcome.total_tokens, 120);
+    }
+
+    #[tokio::test]
+    async fn audio_turn() {
+        let outcome = run_fixture(include_str!("../fixtures/events/audio_turn.jsonl")).await;
+
+        assert_eq!(outcome.items.len(), 2);
+        assert_eq!(outcome.items[0].text(), "Tell me a joke.");
+        assert_eq!(outcome.items[1].text(), "Why did the scarecrow win an award?");
+        assert!(!outcome.items[1].truncated);
+
+        assert_eq!(played_chunks(&outcome), 3);
+        assert!(outcome.commands.is_empty());
+        assert_eq!(outcome.total_tokens, 300);
+    }
+
+    #[tokio::test]
+    async fn duplicate_audio_is_played_once() {
+        let outcome = run_fixture(include_str!("../fixtures/events/duplicate_audio.jsonl")).await;
+
+        // event_014 comes twice and is played once, event_015 repeats its audio and is played too
+        assert_eq!(played_chunks(&outcome), 4);
+        assert_eq!(outcome.items[1].text(), "Why did the scarecrow win an award?");
+    }
+
+    #[tokio::test]
+    async fn interrupted_turn() {
+        let outcome = run_fixture(include_str!("../fixtures/events/interrupted_turn.jsonl")).await;
+
+        // Playback stops and the late delta after the interruption is dropped
+        assert_eq!(played_chunks(&outcome), 2);
+        assert!(matches!(outcome.playback.last(), Some(PlaybackCommand::Stop)));
+
+        // Nothing was heard, so the whole item is truncated
+        assert_eq!(
+            outcome.commands,
+            vec![
+                Command::Internal(InternalCommand::CancelResponse),
+                Command::Internal(InternalCommand::TruncateItem { item_id: "item_asst_1".to_string(), content_index: 0, audio_end_ms: 0 }),
+            ]
+        );
+        assert_eq!(outcome.items[1].text(), "");
+    }
+
+    #[tokio::test]
+    async fn tool_call_turn() {
+        let outcome = run_fixture(include_str!("../fixtures/events/tool_call_turn.jsonl")).await;
+
+        // The spoken question, the call, its output and the answer, in order
+        let texts: Vec<String> = outcome.items.iter().map(|item| item.text()).collect();
+        assert_eq!(texts, ["What's on my shopping list?", "{\"path\":\"shopping.txt\"}", "    1  eggs\n    2  bread\n", "Eggs and bread."]);
+        assert_eq!(outcome.items[2].role, ConversationItemRole::Tool);
+
+        // The call is asked to be run once its response is done, the answer is played
+        assert_eq!(
+            outcome.commands,
+            vec![Command::Internal(InternalCommand::RunTools(vec![ToolCall {
+                call_id: "call_REDACTED1".to_string(),
+                name: "read_file".to_string(),
+                arguments: "{\"path\":\"shopping.txt\"}".to_string(),
+            }]))]
+        );
+        assert_eq!(played_chunks(&outcome), 2);
+        assert_eq!(outcome.total_tokens, 900);
+    }
+}
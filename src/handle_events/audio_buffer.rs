@@ -0,0 +1,77 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use crate::audio_utils::{base64_audio_bytes, SERVER_SAMPLE_RATE};
+
+// The server refuses to commit an input audio buffer larger than this, 15 MiB of pcm16 or about 5.5 min
+const MAX_BUFFER_BYTES: usize = 15 * 1024 * 1024;
+// Share of it at which the user is warned
+const WARN_PERCENT: usize = 80;
+
+/// Audio buffer subscriber: follows the microphone audio sent upstream and what's still uncommitted
+///
+/// Without server VAD, or with the user talking without a pause, the input audio buffer grows
+/// until the server rejects it. The user is told before then, and at the end of the call how
+/// much audio went out and what base64 added to it.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>) {
+    let mut uncommitted_bytes = 0;
+    let mut warned = false;
+    let mut appends = 0;
+    let mut pcm16_bytes = 0;
+    let mut base64_bytes = 0;
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Our own audio on its way to the server, checked before it was sent
+            "input_audio_buffer.append" => {
+                let audio = event["audio"].as_str().unwrap_or_default();
+                let bytes = base64_audio_bytes(audio);
+                appends += 1;
+                pcm16_bytes += bytes;
+                base64_bytes += audio.len();
+                uncommitted_bytes += bytes;
+
+                if !warned && uncommitted_bytes * 100 >= MAX_BUFFER_BYTES * WARN_PERCENT {
+                    warned = true;
+                    eprintln!(
+                        "\n{}",
+                        format!(
+                            "[{} of audio uncommitted, the server rejects the buffer past {}: pause, or commit it]",
+                            clock(uncommitted_bytes),
+                            clock(MAX_BUFFER_BYTES)
+                        )
+                        .yellow()
+                    );
+                }
+            },
+            "input_audio_buffer.committed" | "input_audio_buffer.cleared" | "local.connected" => {
+                uncommitted_bytes = 0;
+                warned = false;
+            },
+            _ => {}
+        }
+    }
+
+    if appends > 0 {
+        println!(
+            "Upstream: {} of audio in {} appends, {:.1} MB pcm16 sent as {:.1} MB base64 (+{}%)",
+            clock(pcm16_bytes),
+            appends,
+            megabytes(pcm16_bytes),
+            megabytes(base64_bytes),
+            (base64_bytes * 100 / pcm16_bytes.max(1)).saturating_sub(100)
+        );
+    }
+}
+
+/// Duration of pcm16 mono audio at SERVER_SAMPLE_RATE, as m:ss
+fn clock(bytes: usize) -> String {
+    let seconds = bytes / 2 / SERVER_SAMPLE_RATE as usize;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
@@ -0,0 +1,177 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::Value;
+
+use super::status_bar::{self, Section};
+use crate::audio_utils::base64_decode_audio;
+use crate::commands::{Command, InternalCommand};
+use crate::mic_gate::{is_speech, FRAME, FRAME_MS};
+
+// Quiet shorter than this is a pause between words, not worth a countdown
+const COUNTDOWN_AFTER_MS: u64 = 500;
+
+/// Auto-commit subscriber: ends a manual turn after a stretch of silence, in case /commit is forgotten
+///
+/// Silence is judged by the local voice detector on the microphone audio as it's sent, so it's
+/// counted in audio rather than wall time and stands still while the microphone is muted or
+/// paused. Audio the --local-vad gate holds back counts as silence too, as it isn't sent at all
+/// once the gate closes. Only a turn with speech in it is sent, and the status bar counts down to it.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut silence = Silence::default();
+    let mut shown: Option<u64> = None;  // Seconds left, as in the status bar
+
+    while let Some(event) = events.recv().await {
+        if !silence.handle_event(&event) {
+            continue;
+        }
+
+        if silence.left_ms() == Some(0) {
+            silence.spoke = false;
+            println!("\n[sent after {:.1} s of silence, /commit sends sooner]", silence.quiet_ms as f64 / 1000.0);
+            if command_sender.send(Command::Internal(InternalCommand::Commit)).await.is_err() {
+                break;
+            }
+        }
+
+        let countdown = silence.countdown();
+        if countdown != shown {
+            shown = countdown;
+            status_bar::set(Section::Turn, countdown.map(|seconds| format!("sending in {} s", seconds)));
+        }
+    }
+}
+
+/// How long the user has been quiet in the turn, in microphone audio
+#[derive(Debug, Default)]
+struct Silence {
+    after_ms: Option<u64>,      // Quiet that sends the turn, None while off
+    spoke: bool,                // Since the turn was last sent
+    quiet_ms: u64,              // Since the user last spoke
+    pending: Vec<f32>,          // Samples sent short of a whole frame
+    gated: usize,               // Samples held back short of a whole frame
+}
+
+impl Silence {
+    /// Takes an event, returning whether it changed anything
+    fn handle_event(&mut self, event: &Value) -> bool {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_auto_commit()
+            "local.auto_commit" => self.after_ms = event["seconds"].as_f64().map(|seconds| (seconds * 1000.0) as u64),
+            // Sent by /commit, or ended some other way
+            "input_audio_buffer.commit" | "input_audio_buffer.committed" | "input_audio_buffer.cleared" | "local.disconnected" => {
+                self.spoke = false;
+                self.quiet_ms = 0;
+                self.pending.clear();
+                self.gated = 0;
+            }
+            "input_audio_buffer.append" if self.after_ms.is_some() => {
+                self.pending.extend(base64_decode_audio(event["audio"].as_str().unwrap_or_default()));
+                for frame in self.pending.chunks_exact(FRAME) {
+                    if is_speech(frame) {
+                        self.spoke = true;
+                        self.quiet_ms = 0;
+                    } else {
+                        self.quiet_ms += FRAME_MS;
+                    }
+                }
+                self.pending.drain(..self.pending.len() / FRAME * FRAME);
+            }
+            // Raised locally for audio the gate didn't send, quiet by its judgement
+            "local.gated_audio" if self.after_ms.is_some() => {
+                self.gated += event["samples"].as_u64().unwrap_or_default() as usize;
+                self.quiet_ms += (self.gated / FRAME) as u64 * FRAME_MS;
+                self.gated %= FRAME;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Quiet left before the turn is sent, None unless there's a turn to send
+    fn left_ms(&self) -> Option<u64> {
+        self.after_ms.filter(|_| self.spoke).map(|after_ms| after_ms.saturating_sub(self.quiet_ms))
+    }
+
+    /// Seconds left for the status bar, once the quiet is more than a pause between words
+    fn countdown(&self) -> Option<u64> {
+        self.left_ms().filter(|left_ms| *left_ms > 0 && self.quiet_ms >= COUNTDOWN_AFTER_MS).map(|left_ms| left_ms.div_ceil(1000))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_utils::base64_encode_audio;
+    use serde_json::json;
+
+    fn append(ms: u64, level: f32) -> Value {
+        let samples = vec![level; FRAME * (ms / FRAME_MS) as usize];
+        json!({"type": "input_audio_buffer.append", "audio": base64_encode_audio(&samples)})
+    }
+
+    fn gated(ms: u64) -> Value {
+        json!({"type": "local.gated_audio", "samples": FRAME * (ms / FRAME_MS) as usize})
+    }
+
+    #[test]
+    fn counts_down_through_the_quiet_after_speech() {
+        let mut silence = Silence::default();
+        assert!(!silence.handle_event(&append(1000, 0.5)));     // Off
+        silence.handle_event(&json!({"type": "local.auto_commit", "seconds": 3.0}));
+
+        // Nothing to send before the user speaks
+        silence.handle_event(&append(2000, 0.0));
+        assert_eq!(silence.left_ms(), None);
+
+        silence.handle_event(&append(500, 0.5));
+        assert_eq!(silence.left_ms(), Some(3000));
+        silence.handle_event(&append(400, 0.0));
+        assert_eq!((silence.left_ms(), silence.countdown()), (Some(2600), None));
+        silence.handle_event(&append(600, 0.0));
+        assert_eq!((silence.left_ms(), silence.countdown()), (Some(2000), Some(2)));
+
+        // Speaking again starts the quiet over
+        silence.handle_event(&append(100, 0.5));
+        assert_eq!(silence.left_ms(), Some(3000));
+    }
+
+    #[test]
+    fn audio_held_back_by_the_gate_counts_as_quiet() {
+        let mut silence = Silence::default();
+        silence.handle_event(&json!({"type": "local.auto_commit", "seconds": 3.0}));
+        silence.handle_event(&append(500, 0.5));
+        // The gate's hangover is sent, then nothing is
+        silence.handle_event(&append(1000, 0.0));
+        silence.handle_event(&gated(1500));
+        assert_eq!(silence.left_ms(), Some(500));
+
+        // Chunks shorter than a frame add up
+        for _ in 0..50 {
+            silence.handle_event(&json!({"type": "local.gated_audio", "samples": FRAME / 2}));
+        }
+        assert_eq!(silence.left_ms(), Some(0));
+
+        // Committing starts over
+        silence.handle_event(&json!({"type": "input_audio_buffer.committed"}));
+        assert_eq!((silence.left_ms(), silence.quiet_ms), (None, 0));
+    }
+
+    #[tokio::test]
+    async fn commits_once_the_quiet_is_long_enough() {
+        let (event_sender, events) = mpsc::channel(16);
+        let (command_sender, mut commands) = mpsc::channel(16);
+        let subscriber = tokio::spawn(run(events, command_sender));
+
+        event_sender.send(Arc::new(json!({"type": "local.auto_commit", "seconds": 2.0}))).await.unwrap();
+        event_sender.send(Arc::new(append(300, 0.5))).await.unwrap();
+        event_sender.send(Arc::new(append(1000, 0.0))).await.unwrap();
+        event_sender.send(Arc::new(gated(1000))).await.unwrap();
+        // Sent once, more quiet doesn't send an empty turn
+        event_sender.send(Arc::new(gated(5000))).await.unwrap();
+        drop(event_sender);
+        subscriber.await.unwrap();
+
+        assert_eq!(commands.recv().await, Some(Command::Internal(InternalCommand::Commit)));
+        assert_eq!(commands.recv().await, None);
+    }
+}
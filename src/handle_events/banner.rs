@@ -0,0 +1,137 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use crate::client::matches_acknowledged;
+use crate::commands::{Command, InternalCommand};
+
+/// Banner subscriber: shows the session the server actually set up, and warns where it differs from the request
+///
+/// When the server rejects a session update over its voice, the call is asked to fall back to
+/// the default voice, or it would silently never produce audio.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut requested_model: Option<String> = None;
+    let mut requested_session: Option<Value> = None;
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::connect()
+            "local.connected" => {
+                requested_model = event["model"].as_str().map(str::to_string);
+            },
+            "session.update" => {
+                requested_session = Some(event["session"].clone());
+            },
+            "session.created" => {
+                print_banner(&event["session"]);
+
+                let served = event["session"]["model"].as_str().unwrap_or_default();
+                if let Some(requested) = requested_model.as_deref().filter(|requested| *requested != served) {
+                    warn(&format!("requested model {} but the server is running {}", requested, served));
+                }
+            },
+            "session.updated" => {
+                let Some(requested) = requested_session.take() else { continue };
+                let Some(requested) = requested.as_object() else { continue };
+
+                for (field, value) in requested {
+                    let acknowledged = &event["session"][field];
+                    if !matches_acknowledged(value, acknowledged) {
+                        warn(&format!("requested {} {} but the server has {}", field, value, acknowledged));
+                    }
+                }
+            },
+            // Only while an update asking for a voice is unanswered
+            "error" if requested_session.as_ref().is_some_and(|requested| !requested["voice"].is_null()) && rejects_voice(&event["error"]) => {
+                requested_session = None;
+                let message = event["error"]["message"].as_str().unwrap_or("invalid voice").to_string();
+                if command_sender.send(Command::Internal(InternalCommand::FallbackVoice(message))).await.is_err() {
+                    break;
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Whether an error is about the requested voice being invalid or not supported by the session
+///
+/// Changing the voice after the assistant has spoken is refused too, but no other voice would fix that.
+fn rejects_voice(error: &Value) -> bool {
+    let param = error["param"].as_str().unwrap_or_default();
+    let message = error["message"].as_str().unwrap_or_default().to_lowercase();
+    let code = error["code"].as_str().unwrap_or_default();
+
+    (param.contains("voice") || message.contains("voice")) && code != "cannot_update_voice" && !message.contains("cannot update")
+}
+
+fn print_banner(session: &Value) {
+    let text = |field: &str| session[field].as_str().unwrap_or("unknown").to_string();
+
+    let vad = match session["turn_detection"]["type"].as_str() {
+        Some(kind) => kind.to_string(),
+        None => "off (manual turns)".to_string(),
+    };
+    let expires = session["expires_at"].as_u64().map_or("unknown".to_string(), |expires_at| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        format!("in {} min", expires_at.saturating_sub(now) / 60)
+    });
+
+    println!("{}", "Connected".green().bold());
+    println!("  model:    {}", text("model"));
+    println!("  session:  {}", text("id"));
+    println!("  voice:    {}", text("voice"));
+    println!("  vad:      {}", vad);
+    println!("  expires:  {}", expires);
+}
+
+fn warn(message: &str) {
+    eprintln!("{} {}", "[session]".yellow().bold(), message.yellow());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn voice_error(message: &str) -> Value {
+        json!({"type": "error", "error": {"type": "invalid_request_error", "code": "invalid_value", "param": "session.voice", "message": message}})
+    }
+
+    #[test]
+    fn voice_errors_are_told_apart() {
+        assert!(rejects_voice(&voice_error("Invalid value: 'marin'. Supported values are: 'alloy', 'ash'")["error"]));
+        assert!(rejects_voice(&json!({"message": "The voice 'cedar' is not supported by this model"})));
+        assert!(!rejects_voice(&json!({"code": "cannot_update_voice", "param": "session.voice", "message": "Cannot update a conversation's voice if assistant audio is present."})));
+        assert!(!rejects_voice(&json!({"param": "session.temperature", "message": "Temperature must be at least 0.6"})));
+    }
+
+    #[tokio::test]
+    async fn a_rejected_voice_asks_for_the_fallback_once() {
+        let (event_sender, events) = mpsc::channel(16);
+        let (command_sender, mut commands) = mpsc::channel(16);
+        let subscriber = tokio::spawn(run(events, command_sender));
+
+        // Not after an update that asked for no voice, nor once the update was acknowledged
+        for event in [
+            json!({"type": "session.update", "session": {"instructions": "Be brief"}}),
+            voice_error("Invalid voice"),
+            json!({"type": "session.update", "session": {"voice": "verse"}}),
+            json!({"type": "session.updated", "session": {"voice": "verse"}}),
+            voice_error("Invalid voice"),
+            // The one that counts, and the same error again without a new update
+            json!({"type": "session.update", "session": {"voice": "marin"}}),
+            voice_error("Invalid value: 'marin'"),
+            voice_error("Invalid value: 'marin'"),
+        ] {
+            event_sender.send(Arc::new(event)).await.unwrap();
+        }
+        drop(event_sender);
+        subscriber.await.unwrap();
+
+        assert_eq!(commands.recv().await, Some(Command::Internal(InternalCommand::FallbackVoice("Invalid value: 'marin'".to_string()))));
+        assert_eq!(commands.recv().await, None);
+    }
+}
@@ -0,0 +1,166 @@
+use tokio::sync::mpsc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::captions;
+use crate::commands::{Command, InternalCommand};
+use crate::conversation::{captioned_sentence, is_side_channel_response};
+
+// Ends of sentences, once followed by a space
+const SENTENCE_ENDS: [char; 7] = ['.', '!', '?', '…', '。', '！', '？'];
+
+/// Captions subscriber: has the assistant's speech translated sentence by sentence, for the captions pane
+///
+/// Off until RealtimeClient::set_captions() picks a language. Each sentence of the audio
+/// transcript is sent off for translation as soon as it's complete, so captions keep up with
+/// the speech rather than waiting for the whole answer like the tutoring translations.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut enabled = false;
+    let mut transcripts: HashMap<String, String> = HashMap::new();     // Not yet sent part of each item's transcript
+    let mut queue = CaptionQueue::default();
+
+    while let Some(event) = events.recv().await {
+        let sentence = match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_captions()
+            "local.captions" => {
+                enabled = event["language"].is_string();
+                captions::set_language(event["language"].as_str());
+                queue = CaptionQueue::default();
+                None
+            },
+            "response.audio_transcript.delta" if enabled => {
+                let transcript = transcripts.entry(event["item_id"].as_str().unwrap_or_default().to_string()).or_default();
+                transcript.push_str(event["delta"].as_str().unwrap_or_default());
+                split_sentence(transcript)
+            },
+            "response.audio_transcript.done" => {
+                transcripts.remove(event["item_id"].as_str().unwrap_or_default()).filter(|rest| enabled && !rest.trim().is_empty())
+            },
+            "response.done" if is_side_channel_response(&event["response"]) => {
+                if let Some(index) = captioned_sentence(&event["response"]).and_then(caption_index) {
+                    // A failed translation still lets the captions after it through
+                    let translation = event["response"]["output"][0]["content"][0]["text"].as_str().unwrap_or_default();
+                    for (original, translation) in queue.translated(index, translation) {
+                        captions::add(original.trim(), translation.trim());
+                    }
+                }
+                None
+            },
+            _ => None,
+        };
+
+        let Some(text) = sentence else { continue };
+        let caption_id = format!("caption_{}", queue.sent(&text));
+        if command_sender.send(Command::Internal(InternalCommand::TranslateCaption { caption_id, text })).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Sentences sent for translation, let through to the pane in the order they were spoken
+///
+/// Each is translated by a response of its own, and a short sentence can come back before the
+/// long one ahead of it.
+#[derive(Default)]
+struct CaptionQueue {
+    next_sent: usize,
+    next_shown: usize,
+    waiting: BTreeMap<usize, (String, Option<String>)>,    // Original and translation, by index
+}
+
+impl CaptionQueue {
+    /// Queues a sentence and returns its index
+    fn sent(&mut self, original: &str) -> usize {
+        let index = self.next_sent;
+        self.next_sent += 1;
+        self.waiting.insert(index, (original.to_string(), None));
+        index
+    }
+
+    /// Notes a translation, returning the captions it lets through, in order
+    fn translated(&mut self, index: usize, translation: &str) -> Vec<(String, String)> {
+        if let Some((_, slot)) = self.waiting.get_mut(&index) {
+            *slot = Some(translation.to_string());
+        }
+
+        let mut ready = Vec::new();
+        while let Some(entry) = self.waiting.first_entry().filter(|entry| *entry.key() == self.next_shown && entry.get().1.is_some()) {
+            let (original, translation) = entry.remove();
+            ready.push((original, translation.unwrap_or_default()));
+            self.next_shown += 1;
+        }
+        ready
+    }
+}
+
+/// The index in a caption id, as made by `run`
+fn caption_index(caption_id: &str) -> Option<usize> {
+    caption_id.strip_prefix("caption_")?.parse().ok()
+}
+
+/// Takes the complete sentences off the front of a transcript, None until there's one
+fn split_sentence(transcript: &mut String) -> Option<String> {
+    let end = transcript
+        .char_indices()
+        .zip(transcript.chars().skip(1))
+        .filter(|((_, c), next)| SENTENCE_ENDS.contains(c) && next.is_whitespace())
+        .map(|((index, c), _)| index + c.len_utf8())
+        .last()?;
+    let rest = transcript.split_off(end);
+    Some(std::mem::replace(transcript, rest.trim_start().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentences_are_split_off_once_complete() {
+        let mut transcript = "Hello there".to_string();
+        assert_eq!(split_sentence(&mut transcript), None);
+
+        transcript.push_str(". How are you? I'm");
+        assert_eq!(split_sentence(&mut transcript).as_deref(), Some("Hello there. How are you?"));
+        assert_eq!(transcript, "I'm");
+
+        // The end of a sentence only counts once a space follows, 3.5 isn't one
+        transcript.push_str(" 3.5 years old!");
+        assert_eq!(split_sentence(&mut transcript), None);
+        transcript.push(' ');
+        assert_eq!(split_sentence(&mut transcript).as_deref(), Some("I'm 3.5 years old!"));
+        assert_eq!(transcript, "");
+    }
+
+    #[test]
+    fn wide_sentence_ends_split_too() {
+        let mut transcript = "こんにちは。 元気？ まだ".to_string();
+        assert_eq!(split_sentence(&mut transcript).as_deref(), Some("こんにちは。 元気？"));
+        assert_eq!(transcript, "まだ");
+    }
+
+    #[test]
+    fn captions_are_shown_in_spoken_order() {
+        let mut queue = CaptionQueue::default();
+        let first = queue.sent("A long first sentence.");
+        let second = queue.sent("Short.");
+        let third = queue.sent("Third.");
+
+        assert!(queue.translated(second, "Kurz.").is_empty());
+        assert!(queue.translated(third, "Dritte.").is_empty());
+        assert_eq!(
+            queue.translated(first, "Ein langer erster Satz."),
+            [
+                ("A long first sentence.".to_string(), "Ein langer erster Satz.".to_string()),
+                ("Short.".to_string(), "Kurz.".to_string()),
+                ("Third.".to_string(), "Dritte.".to_string()),
+            ]
+        );
+        assert!(queue.waiting.is_empty());
+
+        // Unknown or repeated translations change nothing
+        assert!(queue.translated(first, "Again").is_empty());
+        assert!(queue.translated(42, "Nothing").is_empty());
+        assert_eq!(caption_index(&format!("caption_{}", queue.sent("Next."))), Some(3));
+    }
+}
@@ -0,0 +1,136 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+use crate::conversation::{chapter_item, is_side_channel_response};
+
+// Responses per chapter, long enough for a topic to develop
+const CHAPTER_RESPONSES: usize = 8;
+
+/// Chapters subscriber: every few turns, asks for a title for the part of the call since the last one
+///
+/// Off until RealtimeClient::set_chapters() turns it on. Titles come back as out-of-band
+/// responses, marking the first item of the chapter, so long calls can be navigated in the
+/// transcript, its exports and replays. The chapter in progress when the call ends is titled
+/// before hanging up, and the call hangs up once its title is in.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut enabled = false;
+    let mut first_item: Option<String> = None;     // Of the chapter in progress
+    let mut responses = 0;
+    let mut last_chapter: Option<String> = None;   // First item of the one titled before hanging up
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_chapters()
+            "local.chapters" => enabled = event["enabled"].as_bool().unwrap_or_default(),
+            // Raised locally by RealtimeClient::title_last_chapter()
+            "local.last_chapter" => {
+                let command = match first_item.take().filter(|_| enabled) {
+                    Some(item_id) => {
+                        last_chapter = Some(item_id.clone());
+                        Command::Internal(InternalCommand::TitleChapter(item_id))
+                    }
+                    None => Command::Quit,  // Nothing said since the last title
+                };
+                if command_sender.send(command).await.is_err() {
+                    break;
+                }
+            }
+            "response.done" if last_chapter.is_some() && chapter_item(&event["response"]) == last_chapter.as_deref() => {
+                last_chapter = None;
+                if command_sender.send(Command::Quit).await.is_err() {
+                    break;
+                }
+            }
+            "conversation.item.created" if first_item.is_none() => {
+                first_item = event["item"]["id"].as_str().map(str::to_string);
+            }
+            "response.done" if !is_side_channel_response(&event["response"]) => {
+                responses += 1;
+                if !enabled || responses < CHAPTER_RESPONSES {
+                    continue;
+                }
+
+                if let Some(item_id) = first_item.take() {
+                    responses = 0;
+                    if command_sender.send(Command::Internal(InternalCommand::TitleChapter(item_id))).await.is_err() {
+                        eprintln!("Failed to request a chapter title");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::{side_channel_tag, CHAPTER_METADATA, SIDE_CHANNEL_METADATA};
+    use serde_json::json;
+
+    fn item(id: &str) -> Arc<Value> {
+        Arc::new(json!({"type": "conversation.item.created", "item": {"id": id}}))
+    }
+
+    fn response_done() -> Arc<Value> {
+        Arc::new(json!({"type": "response.done", "response": {"metadata": null}}))
+    }
+
+    fn title_done(item_id: &str) -> Arc<Value> {
+        let metadata = json!({SIDE_CHANNEL_METADATA.0: side_channel_tag(CHAPTER_METADATA, item_id)});
+        Arc::new(json!({"type": "response.done", "response": {"metadata": metadata}}))
+    }
+
+    async fn commands_for(events: Vec<Arc<Value>>) -> Vec<Command> {
+        let (event_sender, receiver) = mpsc::channel(64);
+        let (command_sender, mut commands) = mpsc::channel(64);
+        let subscriber = tokio::spawn(run(receiver, command_sender));
+        for event in events {
+            event_sender.send(event).await.unwrap();
+        }
+        drop(event_sender);
+        subscriber.await.unwrap();
+
+        let mut sent = Vec::new();
+        while let Some(command) = commands.recv().await {
+            sent.push(command);
+        }
+        sent
+    }
+
+    #[tokio::test]
+    async fn titles_every_few_responses_and_the_last_chapter_before_hanging_up() {
+        let mut events = vec![Arc::new(json!({"type": "local.chapters", "enabled": true})), item("item_1")];
+        for n in 2..=CHAPTER_RESPONSES {
+            events.push(response_done());
+            events.push(item(&format!("item_{}", n)));
+            // Titles and the like don't count towards a chapter
+            events.push(title_done("item_0"));
+        }
+        events.push(response_done());
+        events.extend([item("item_9"), response_done(), Arc::new(json!({"type": "local.last_chapter"}))]);
+        // Titles of other chapters don't end the call, the last one's does
+        events.extend([title_done("item_1"), title_done("item_9")]);
+
+        assert_eq!(commands_for(events).await, [
+            Command::Internal(InternalCommand::TitleChapter("item_1".to_string())),
+            Command::Internal(InternalCommand::TitleChapter("item_9".to_string())),
+            Command::Quit,
+        ]);
+    }
+
+    #[tokio::test]
+    async fn hangs_up_at_once_with_no_chapter_to_title() {
+        // Nothing said since the last title
+        let mut events = vec![Arc::new(json!({"type": "local.chapters", "enabled": true})), item("item_1")];
+        events.extend((0..CHAPTER_RESPONSES).map(|_| response_done()));
+        events.push(Arc::new(json!({"type": "local.last_chapter"})));
+        assert_eq!(commands_for(events).await, [Command::Internal(InternalCommand::TitleChapter("item_1".to_string())), Command::Quit]);
+
+        // Or chapters off
+        let events = vec![item("item_1"), response_done(), Arc::new(json!({"type": "local.last_chapter"}))];
+        assert_eq!(commands_for(events).await, [Command::Quit]);
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::Write;
+use crossterm::cursor::MoveToColumn;
+use crossterm::queue;
+use crossterm::style::Stylize;
+use crossterm::terminal::{Clear, ClearType};
+use super::screen::Screen;
+use crate::markdown::{fence_language, render_inline, CodeHighlighter};
+use crate::text_layout::display_width;
+
+// Shown before assistant text in the chat style
+const ASSISTANT_LABEL: &str = "assistant ›";
+
+/// How streamed assistant text is printed
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TextStyle {
+    Raw,        // Deltas as they arrive
+    Chat,       // Word-wrapped under a speaker label, markdown rendered line by line
+}
+
+impl TextStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Chat => "chat",
+        }
+    }
+}
+
+impl std::str::FromStr for TextStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        match style {
+            "raw" => Ok(Self::Raw),
+            "chat" => Ok(Self::Chat),
+            _ => Err(format!("Unknown text style: {} (expected raw or chat)", style)),
+        }
+    }
+}
+
+/// Prints streamed text word by word, wrapped under a speaker label
+///
+/// The line being typed shows raw, once it's complete it is redrawn in place with its
+/// markdown rendered. Lines are kept narrower than the terminal so they never wrap on
+/// their own, which would break the redraw. Lines of fenced code blocks aren't wrapped,
+/// they are printed highlighted once complete.
+pub struct ChatPrinter {
+    item_id: Option<String>,        // Item being printed
+    width: usize,                   // Columns for text, after the label
+    line: String,                   // Raw markdown of the line being typed
+    word: String,                   // Not printed until complete, so it can move to the next line
+    first_line: bool,               // The label, rather than an indent, starts the line
+    code: Option<CodeHighlighter>,  // Inside a fenced code block
+}
+
+impl ChatPrinter {
+    pub fn new() -> Self {
+        Self {
+            item_id: None,
+            width: 0,
+            line: String::new(),
+            word: String::new(),
+            first_line: true,
+            code: None,
+        }
+    }
+
+    /// Adds a text delta of an item, starting a new message when the item changes
+    pub fn push(&mut self, screen: &mut Screen, item_id: &str, delta: &str) {
+        if self.item_id.as_deref() != Some(item_id) {
+            self.finish(screen);
+            self.item_id = Some(item_id.to_string());
+            self.first_line = true;
+
+            self.width = screen.columns().saturating_sub(display_width(ASSISTANT_LABEL) + 2).max(20);
+            screen.print(format!("\n{} ", ASSISTANT_LABEL.green().bold()));
+        }
+
+        for c in delta.chars() {
+            match c {
+                '\n' if self.code.is_some() => self.end_code_line(screen),
+                c if self.code.is_some() => self.line.push(c),
+                '\n' => {
+                    self.push_word(screen);
+                    self.break_line(screen);
+                }
+                c if c.is_whitespace() => self.push_word(screen),
+                c => self.word.push(c),
+            }
+        }
+        let _ = screen.flush();
+    }
+
+    /// Ends the message being printed, if any
+    pub fn finish(&mut self, screen: &mut Screen) {
+        if self.item_id.take().is_some() {
+            if self.code.is_some() {
+                if !self.line.is_empty() {
+                    self.end_code_line(screen);
+                }
+                self.code = None;
+            } else {
+                self.push_word(screen);
+                self.redraw_line(screen);
+            }
+            screen.println("");
+            self.line.clear();
+        }
+    }
+
+    /// Prints a complete line of a code block, or leaves the block on its closing fence
+    fn end_code_line(&mut self, screen: &mut Screen) {
+        match fence_language(&self.line) {
+            Some(_) => {
+                screen.print(self.line.as_str().dim());
+                self.code = None;
+            }
+            None => screen.print(self.code.as_mut().unwrap().highlight(&self.line)),
+        }
+        screen.print(format!("\n{}", " ".repeat(display_width(ASSISTANT_LABEL) + 1)));
+        self.line.clear();
+        self.first_line = false;
+    }
+
+    fn push_word(&mut self, screen: &mut Screen) {
+        if self.word.is_empty() {
+            return;
+        }
+
+        if !self.line.is_empty() && display_width(&self.line) + 1 + display_width(&self.word) > self.width {
+            self.break_line(screen);
+        }
+        if !self.line.is_empty() {
+            self.line.push(' ');
+            screen.print(" ");
+        }
+        screen.print(&self.word);
+        self.line.push_str(&self.word);
+        self.word.clear();
+    }
+
+    /// Renders the completed line and starts the next one under it
+    fn break_line(&mut self, screen: &mut Screen) {
+        self.redraw_line(screen);
+        if let Some(language) = fence_language(&self.line) {
+            self.code = Some(CodeHighlighter::new(language));
+        }
+        screen.print(format!("\n{}", " ".repeat(display_width(ASSISTANT_LABEL) + 1)));
+        self.line.clear();
+        self.first_line = false;
+    }
+
+    fn redraw_line(&self, screen: &mut Screen) {
+        let prefix = if self.first_line {
+            format!("{} ", ASSISTANT_LABEL.green().bold())
+        } else {
+            " ".repeat(display_width(ASSISTANT_LABEL) + 1)
+        };
+        let _ = queue!(screen, MoveToColumn(0), Clear(ClearType::CurrentLine));
+        match fence_language(&self.line) {
+            Some(_) => screen.print(format!("{}{}", prefix, self.line.as_str().dim())),
+            None => screen.print(format!("{}{}", prefix, render_inline(&self.line))),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use tokio::sync::mpsc;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::status_bar::{self, Section};
+use crate::conversation::is_side_channel_response;
+
+// How often playback is checked for having gone quiet
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// How long the screen stays inverted for a flash, and the pause between two bells
+const FLASH: Duration = Duration::from_millis(100);
+const BELL_GAP: Duration = Duration::from_millis(200);
+
+/// Ways of telling the user the assistant started or stopped speaking
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cue {
+    Bell,       // Ring the terminal bell, once when it starts and twice when it stops
+    Flash,      // Flash the terminal, inverting it for a moment
+    Status,     // Show "assistant speaking" in the status bar while it speaks
+}
+
+/// Cues subscriber: marks when the assistant starts and stops speaking, for users looking away
+///
+/// Speaking starts with the first audio of a response and stops once the response is done and
+/// playback has gone quiet, so an answer that is generated faster than it plays isn't cut short.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, played_samples: Arc<AtomicUsize>) {
+    let mut cues: Vec<Cue> = Vec::new();
+    let mut speaking = false;
+    let mut response_done = false;
+    let mut last_played = played_samples.load(Ordering::Relaxed);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+
+                match event["type"].as_str().unwrap_or_default() {
+                    // Raised locally by RealtimeClient::set_cues()
+                    "local.cues" => cues = serde_json::from_value(event["cues"].clone()).unwrap_or_default(),
+                    "response.created" if !is_side_channel_response(&event["response"]) => response_done = false,
+                    "response.done" if !is_side_channel_response(&event["response"]) => response_done = true,
+                    "response.audio.delta" if !speaking && !cues.is_empty() => {
+                        speaking = true;
+                        last_played = played_samples.load(Ordering::Relaxed);
+                        signal(&cues, true).await;
+                    },
+                    _ => {}
+                }
+            }
+            _ = interval.tick(), if speaking => {
+                let played = played_samples.load(Ordering::Relaxed);
+                if response_done && played == last_played {
+                    speaking = false;
+                    signal(&cues, false).await;
+                }
+                last_played = played;
+            }
+        }
+    }
+}
+
+/// Gives the cues for the assistant starting or stopping to speak
+async fn signal(cues: &[Cue], started: bool) {
+    let mut stdout = std::io::stdout();
+    if cues.contains(&Cue::Status) {
+        status_bar::set(Section::Speaking, started.then(|| "assistant speaking".to_string()));
+    }
+
+    // Piped, bells and escape sequences would only end up in the output
+    if !stdout.is_terminal() {
+        return;
+    }
+    if cues.contains(&Cue::Bell) {
+        for bell in 0..if started { 1 } else { 2 } {
+            if bell > 0 {
+                tokio::time::sleep(BELL_GAP).await;
+            }
+            let _ = write!(stdout, "\x07");
+            let _ = stdout.flush();
+        }
+    }
+    if cues.contains(&Cue::Flash) {
+        // Reverse video for the whole screen, then back
+        let _ = write!(stdout, "\x1b[?5h");
+        let _ = stdout.flush();
+        tokio::time::sleep(FLASH).await;
+        let _ = write!(stdout, "\x1b[?5l");
+        let _ = stdout.flush();
+    }
+}
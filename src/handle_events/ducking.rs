@@ -0,0 +1,131 @@
+use tokio::sync::mpsc;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+// How often playback is checked, and how long it must be quiet before volumes are restored
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const RESTORE_AFTER: Duration = Duration::from_millis(600);
+
+/// Ducking subscriber: turns other applications down while the assistant is speaking (Linux only)
+///
+/// Goes through `pactl`, which both PulseAudio and PipeWire (pipewire-pulse) provide. Playing is
+/// detected from the played samples counter, so ducking follows what's actually audible.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, played_samples: Arc<AtomicUsize>) {
+    let mut ducker: Option<Ducker> = None;
+    let mut last_played = played_samples.load(Ordering::Relaxed);
+    let mut last_change = Instant::now();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+
+                // Raised locally by RealtimeClient::set_ducking()
+                if event["type"] == "local.ducking" {
+                    ducker = event["level"].as_f64().map(Ducker::new);
+                }
+            }
+            _ = interval.tick() => {
+                let Some(ducker) = ducker.as_mut() else { continue };
+
+                let played = played_samples.load(Ordering::Relaxed);
+                if played != last_played {
+                    last_played = played;
+                    last_change = Instant::now();
+                    ducker.duck();
+                } else if last_change.elapsed() >= RESTORE_AFTER {
+                    ducker.restore();
+                }
+            }
+        }
+    }
+}
+
+/// Lowers the volume of other applications' streams and puts it back
+struct Ducker {
+    level: f64,                             // Fraction of their volume other applications keep
+    ducked: Vec<(String, u32)>,             // Sink inputs turned down, with their original volume in percent
+}
+
+impl Ducker {
+    fn new(level: f64) -> Self {
+        Self { level: level.clamp(0.0, 1.0), ducked: Vec::new() }
+    }
+
+    fn duck(&mut self) {
+        if !self.ducked.is_empty() {
+            return;
+        }
+
+        for (index, volume) in other_sink_inputs() {
+            let ducked_volume = (volume as f64 * self.level).round() as u32;
+            if set_volume(&index, ducked_volume) {
+                self.ducked.push((index, volume));
+            }
+        }
+    }
+
+    fn restore(&mut self) {
+        // Streams that ended in the meantime just fail to update
+        for (index, volume) in self.ducked.drain(..) {
+            set_volume(&index, volume);
+        }
+    }
+}
+
+impl Drop for Ducker {
+    /// Never leave other applications turned down, even when hanging up mid-sentence
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+/// Playback streams of other processes, with their volume in percent
+fn other_sink_inputs() -> Vec<(String, u32)> {
+    let output = match Command::new("pactl").args(["list", "sink-inputs"]).output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+        Ok(output) => {
+            eprintln!("Ducking failed, pactl: {}", String::from_utf8_lossy(&output.stderr).trim());
+            return Vec::new();
+        }
+        Err(e) => {
+            eprintln!("Ducking failed, could not run pactl: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let own_pid = format!("\"{}\"", std::process::id());
+    let mut inputs = Vec::new();
+
+    // Blocks start with "Sink Input #<index>", then indented "Volume:" and property lines
+    for block in output.split("Sink Input #").skip(1) {
+        let index = block.lines().next().unwrap_or_default().trim().to_string();
+        let is_own = block
+            .lines()
+            .any(|line| line.trim().starts_with("application.process.id") && line.trim().ends_with(&own_pid));
+
+        // e.g. "Volume: front-left: 65536 /  100% / 0.00 dB,   front-right: ..."
+        let volume = block
+            .lines()
+            .find(|line| line.trim().starts_with("Volume:"))
+            .and_then(|line| line.split('/').nth(1))
+            .and_then(|percent| percent.trim().trim_end_matches('%').parse().ok());
+
+        if let (false, Some(volume)) = (is_own, volume) {
+            inputs.push((index, volume));
+        }
+    }
+
+    inputs
+}
+
+fn set_volume(index: &str, percent: u32) -> bool {
+    Command::new("pactl")
+        .args(["set-sink-input-volume", index, &format!("{}%", percent)])
+        .status()
+        .is_ok_and(|status| status.success())
+}
@@ -0,0 +1,142 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+use crate::conversation::is_out_of_band_response;
+
+/// Times a turn is asked again by default when its response comes back empty
+pub const EMPTY_RESPONSE_RETRIES: u8 = 1;
+
+// Added to the instructions of the retry only
+const NUDGE: &str = "Your previous response was empty. Answer the user now.";
+
+/// Empty response subscriber: asks again when a response completes with neither audio nor text
+///
+/// It happens now and then, and leaves the user waiting in silence for an answer that isn't
+/// coming. A response that calls a tool isn't empty, nor is one that was cancelled or cut short.
+/// The retries are per turn, so a model that keeps answering with nothing isn't asked forever.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut retries = EMPTY_RESPONSE_RETRIES;
+    let mut retried = 0;            // This turn
+    let mut response_id = String::new();
+    let mut produced = false;       // Whether the current response sent any audio or text
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_empty_response_retries()
+            "local.empty_response_retries" => retries = event["retries"].as_u64().unwrap_or_default().min(u8::MAX as u64) as u8,
+            // Out-of-band responses, e.g. translations or the summary, are no answer to retry
+            "response.created" if !is_out_of_band_response(&event["response"]) => {
+                response_id = event["response"]["id"].as_str().unwrap_or_default().to_string();
+                produced = false;
+            }
+            "response.audio.delta" | "response.audio_transcript.delta" | "response.text.delta" if event["response_id"] == response_id.as_str() => {
+                produced = true;
+            }
+            // A new turn of the user's
+            "input_audio_buffer.committed" => retried = 0,
+            "conversation.item.created" if event["item"]["role"] == "user" => retried = 0,
+            "response.done" if event["response"]["id"] == response_id.as_str() => {
+                let response = &event["response"];
+                let calls_tool = response["output"].as_array().is_some_and(|output| output.iter().any(|item| item["type"] == "function_call"));
+                if produced || calls_tool || response["status"] != "completed" {
+                    continue;
+                }
+
+                tracing::warn!(response_id, retried, "response completed with no audio or text");
+                if retried >= retries {
+                    println!("\n[the assistant gave no answer]");
+                    continue;
+                }
+                retried += 1;
+                println!("\n[the assistant gave no answer, asking again]");
+                if command_sender.send(Command::Internal(InternalCommand::RetryResponse(NUDGE.to_string()))).await.is_err() {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::{side_channel_tag, CAPTION_METADATA, SIDE_CHANNEL_METADATA, SUMMARY_METADATA};
+    use serde_json::json;
+
+    /// Commands raised for these events
+    async fn commands_for(events: Vec<Value>) -> Vec<Command> {
+        let (event_sender, receiver) = mpsc::channel(events.len().max(1));
+        let (command_sender, mut commands) = mpsc::channel(16);
+        let subscriber = tokio::spawn(run(receiver, command_sender));
+        for event in events {
+            event_sender.send(Arc::new(event)).await.unwrap();
+        }
+        drop(event_sender);
+        subscriber.await.unwrap();
+        std::iter::from_fn(|| commands.try_recv().ok()).collect()
+    }
+
+    fn created(id: &str, response: Value) -> Value {
+        let mut response = response;
+        response["id"] = id.into();
+        json!({"type": "response.created", "response": response})
+    }
+
+    fn done(id: &str) -> Value {
+        json!({"type": "response.done", "response": {"id": id, "status": "completed", "output": []}})
+    }
+
+    fn retry() -> Command {
+        Command::Internal(InternalCommand::RetryResponse(NUDGE.to_string()))
+    }
+
+    #[tokio::test]
+    async fn empty_answers_are_asked_again_once_per_turn() {
+        let commands = commands_for(vec![
+            created("resp_1", json!({"conversation_id": "conv_1"})),
+            done("resp_1"),
+            created("resp_2", json!({"conversation_id": "conv_1"})),
+            done("resp_2"),
+            // The user speaks again, so their next turn may be retried too
+            json!({"type": "input_audio_buffer.committed"}),
+            created("resp_3", json!({})),
+            done("resp_3"),
+        ]).await;
+        assert_eq!(commands, [retry(), retry()]);
+    }
+
+    #[tokio::test]
+    async fn answers_with_content_or_calls_are_left_alone() {
+        let commands = commands_for(vec![
+            created("resp_1", json!({})),
+            json!({"type": "response.audio_transcript.delta", "response_id": "resp_1", "delta": "Hi"}),
+            done("resp_1"),
+            created("resp_2", json!({})),
+            json!({"type": "response.done", "response": {"id": "resp_2", "status": "completed", "output": [{"type": "function_call"}]}}),
+            created("resp_3", json!({})),
+            json!({"type": "response.done", "response": {"id": "resp_3", "status": "cancelled", "output": []}}),
+        ]).await;
+        assert!(commands.is_empty());
+    }
+
+    #[tokio::test]
+    async fn out_of_band_responses_are_not_mistaken_for_the_answer() {
+        let metadata = |pairs: &[(&str, &str)]| Value::Object(pairs.iter().map(|(key, value)| (key.to_string(), (*value).into())).collect());
+        let commands = commands_for(vec![
+            created("resp_1", json!({"conversation_id": "conv_1"})),
+            // Started while the answer streams in, each would otherwise take over its id
+            created("resp_caption", json!({"metadata": metadata(&[(SIDE_CHANNEL_METADATA.0, &side_channel_tag(CAPTION_METADATA, "caption_1"))])})),
+            created("resp_summary", json!({"metadata": metadata(&[SUMMARY_METADATA])})),
+            created("resp_other", json!({"conversation_id": null})),
+            json!({"type": "response.text.delta", "response_id": "resp_1", "delta": "Hi"}),
+            done("resp_caption"),
+            done("resp_summary"),
+            done("resp_other"),
+            done("resp_1"),
+        ]).await;
+        assert!(commands.is_empty());
+    }
+}
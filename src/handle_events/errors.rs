@@ -0,0 +1,122 @@
+use tokio::sync::mpsc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use crossterm::style::Stylize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::session_summary::{ErrorKind, ErrorTracker};
+
+// Events we sent that a late error can still be traced back to
+const SENT_CAPACITY: usize = 100;
+
+/// Errors subscriber: explains server errors in terms of the event we sent that caused them
+///
+/// The server names the offending client event by its event_id, so the last events sent are kept
+/// to say e.g. "your session.update failed because of session.voice" instead of dumping the JSON.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, errors: Arc<Mutex<ErrorTracker>>) {
+    let mut sent: VecDeque<Arc<Value>> = VecDeque::with_capacity(SENT_CAPACITY);
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            "error" => {
+                let error = &event["error"];
+                tracing::debug!(error = %error, "server error");
+                errors.lock().unwrap().count(ErrorKind::Server);
+
+                let cause = error["event_id"].as_str().and_then(|event_id| sent.iter().find(|sent| sent["event_id"] == event_id));
+                eprintln!("\n{} {}", "[error]".red().bold(), describe(error, cause.map(|cause| cause.as_ref())).red());
+            },
+            // Audio streams in many times a second and would crowd out the rest, the server names no param of it anyway
+            "input_audio_buffer.append" => {}
+            // Ours carry a UUID, the server's are named `event_…`
+            _ if event["event_id"].as_str().is_some_and(|event_id| Uuid::parse_str(event_id).is_ok()) => {
+                if sent.len() == SENT_CAPACITY {
+                    sent.pop_front();
+                }
+                sent.push_back(event);
+            },
+            _ => {}
+        }
+    }
+}
+
+/// One line about an error, e.g. `your session.update failed because of session.voice ("bob"): Invalid value (invalid_value)`
+fn describe(error: &Value, cause: Option<&Value>) -> String {
+    let message = error["message"].as_str().unwrap_or("no details given");
+    let code = error["code"].as_str().or(error["type"].as_str()).unwrap_or("error");
+    let param = error["param"].as_str().filter(|param| !param.is_empty());
+
+    match (cause, param) {
+        (Some(cause), Some(param)) => {
+            let value = lookup(cause, param).map(|value| format!(" ({})", abbreviate(value))).unwrap_or_default();
+            format!("your {} failed because of {}{}: {} ({})", cause["type"].as_str().unwrap_or("event"), param, value, message, code)
+        }
+        (Some(cause), None) => format!("your {} failed: {} ({})", cause["type"].as_str().unwrap_or("event"), message, code),
+        (None, Some(param)) => format!("{} ({}, param {})", message, code, param),
+        (None, None) => format!("{} ({})", message, code),
+    }
+}
+
+/// The value at a param path such as `session.voice` or `item.content[0].text` in a sent event
+fn lookup<'a>(event: &'a Value, param: &str) -> Option<&'a Value> {
+    let mut value = event;
+    for segment in param.split(['.', '[']) {
+        value = match segment.strip_suffix(']') {
+            Some(index) => value.get(index.parse::<usize>().ok()?)?,
+            None => value.get(segment)?,
+        };
+    }
+    Some(value)
+}
+
+/// The value as JSON, cut short when it's long (instructions, tool schemas)
+fn abbreviate(value: &Value) -> String {
+    let json = value.to_string();
+    match json.char_indices().nth(60) {
+        Some((end, _)) => format!("{}…", &json[..end]),
+        None => json,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn lookup_follows_param_paths() {
+        let sent = json!({
+            "type": "conversation.item.create",
+            "item": {"content": [{"type": "input_text", "text": "Hello"}, {"type": "input_audio"}]},
+            "session": {"voice": "bob"}
+        });
+        assert_eq!(lookup(&sent, "session.voice"), Some(&json!("bob")));
+        assert_eq!(lookup(&sent, "item.content[0].text"), Some(&json!("Hello")));
+        assert_eq!(lookup(&sent, "item.content[1]"), Some(&json!({"type": "input_audio"})));
+
+        assert_eq!(lookup(&sent, "item.content[2].text"), None);
+        assert_eq!(lookup(&sent, "item.content[x]"), None);
+        assert_eq!(lookup(&sent, "session.speed"), None);
+    }
+
+    #[test]
+    fn describe_names_the_event_and_param_it_can() {
+        let error = json!({"type": "invalid_request_error", "code": "invalid_value", "message": "Invalid value", "param": "session.voice"});
+        let cause = json!({"type": "session.update", "session": {"voice": "bob"}});
+        assert_eq!(describe(&error, Some(&cause)), "your session.update failed because of session.voice (\"bob\"): Invalid value (invalid_value)");
+        assert_eq!(describe(&error, None), "Invalid value (invalid_value, param session.voice)");
+
+        // No param, or one that isn't in what was sent
+        let error = json!({"type": "invalid_request_error", "message": "Too fast", "param": ""});
+        assert_eq!(describe(&error, Some(&cause)), "your session.update failed: Too fast (invalid_request_error)");
+        let error = json!({"code": "x", "message": "Odd", "param": "session.tools"});
+        assert_eq!(describe(&error, Some(&cause)), "your session.update failed because of session.tools: Odd (x)");
+        assert_eq!(describe(&json!({}), None), "no details given (error)");
+
+        // Long values are cut short
+        let cause = json!({"type": "session.update", "session": {"instructions": "word ".repeat(50)}});
+        let error = json!({"code": "x", "message": "Odd", "param": "session.instructions"});
+        assert!(describe(&error, Some(&cause)).contains(&format!("(\"{}…)", &"word ".repeat(12)[..59])));
+    }
+}
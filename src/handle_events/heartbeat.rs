@@ -0,0 +1,102 @@
+use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use super::status_bar::{self, Section};
+
+/// How often the client pings the server
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Round trips up to this are healthy, slower ones are shown as slow
+const SLOW_RTT: Duration = Duration::from_millis(500);
+// With no pong for this long the connection is probably gone
+const DEAD_AFTER: Duration = Duration::from_secs(12);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Health {
+    Good,
+    Slow,
+    Dead,
+}
+
+/// Heartbeat subscriber: times pings to their pongs and shows the connection health
+///
+/// The round trip and a health symbol go in the status bar. Changes in health are also
+/// printed, so a silent assistant can be told apart from a dead network.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>) {
+    let mut pending: HashMap<u64, Instant> = HashMap::new();   // Pings waiting for their pong
+    let mut last_pong = Instant::now();
+    let mut health = Health::Good;
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    loop {
+        let update = tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+
+                match event["type"].as_str().unwrap_or_default() {
+                    // Raised locally by RealtimeClient::ping()
+                    "local.ping" => {
+                        pending.insert(event["id"].as_u64().unwrap_or_default(), Instant::now());
+                        None
+                    },
+                    // Raised locally by the read task when the pong arrives
+                    "local.pong" => {
+                        last_pong = Instant::now();
+                        pending
+                            .remove(&event["id"].as_u64().unwrap_or_default())
+                            .map(|sent| {
+                                let rtt = sent.elapsed();
+                                (if rtt > SLOW_RTT { Health::Slow } else { Health::Good }, Some(rtt))
+                            })
+                    },
+                    // A new connection starts with a clean slate
+                    "local.connected" => {
+                        pending.clear();
+                        last_pong = Instant::now();
+                        None
+                    },
+                    _ => None,
+                }
+            }
+            _ = interval.tick() => {
+                let waiting = pending.values().min().is_some_and(|sent| sent.elapsed() > SLOW_RTT);
+                match last_pong.elapsed() {
+                    silence if silence > DEAD_AFTER && waiting => Some((Health::Dead, None)),
+                    _ => None,
+                }
+            }
+        };
+
+        let Some((new_health, rtt)) = update else { continue };
+        set_title(new_health, rtt);
+
+        if new_health != health {
+            match (new_health, rtt) {
+                (Health::Dead, _) => println!(
+                    "\n{}",
+                    format!("[no answer from the server for {} s, the network may be down]", last_pong.elapsed().as_secs()).red()
+                ),
+                (Health::Slow, Some(rtt)) => println!("\n{}", format!("[slow connection: {} ms round trip]", rtt.as_millis()).yellow()),
+                (_, Some(rtt)) if health == Health::Dead || health == Health::Slow => {
+                    println!("\n{}", format!("[connection healthy again: {} ms round trip]", rtt.as_millis()).green())
+                }
+                _ => {}
+            }
+            health = new_health;
+        }
+    }
+}
+
+fn set_title(health: Health, rtt: Option<Duration>) {
+    let status = match (health, rtt) {
+        (Health::Dead, _) => Some("○ no connection".to_string()),
+        (Health::Slow, Some(rtt)) => Some(format!("◐ slow, {} ms", rtt.as_millis())),
+        (_, Some(rtt)) => Some(format!("● {} ms", rtt.as_millis())),
+        (_, None) => None,
+    };
+    status_bar::set(Section::Connection, status);
+}
@@ -0,0 +1,58 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+
+// How often the time since the last activity is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Idle subscriber: asks for the audio devices to be closed once nothing has happened for a while
+///
+/// Open audio streams keep the CPU from sleeping, which drains a laptop battery over a long
+/// quiet call. Speech, typed messages and responses count as activity, and activity while
+/// asleep asks for the devices to be opened again.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut timeout: Option<Duration> = None;
+    let mut last_activity = Instant::now();
+    let mut asleep = false;
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        let command = tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+
+                match event["type"].as_str().unwrap_or_default() {
+                    // Raised locally by RealtimeClient::set_idle_timeout()
+                    "local.idle_timeout" => {
+                        timeout = event["seconds"].as_u64().map(Duration::from_secs);
+                        None
+                    },
+                    // Raised locally by RealtimeClient::set_audio_asleep()
+                    "local.idle" => {
+                        asleep = event["idle"] == true;
+                        last_activity = Instant::now();
+                        None
+                    },
+                    "input_audio_buffer.speech_started" | "conversation.item.create" | "response.created" | "response.audio.delta" => {
+                        last_activity = Instant::now();
+                        asleep.then_some(Command::Internal(InternalCommand::Wake))
+                    },
+                    _ => None,
+                }
+            }
+            _ = interval.tick() => match timeout {
+                Some(timeout) if !asleep && last_activity.elapsed() >= timeout => Some(Command::Internal(InternalCommand::Sleep)),
+                _ => None,
+            }
+        };
+
+        if let Some(command) = command {
+            if command_sender.send(command).await.is_err() {
+                break;
+            }
+        }
+    }
+}
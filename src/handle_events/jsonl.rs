@@ -0,0 +1,60 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::{json, Value};
+
+use crate::output::emit;
+use crate::tools::ToolCall;
+
+
+/// JSON Lines subscriber: writes one line per semantic event for scripts, with `--output jsonl`
+///
+/// Lines have a `type` of user_transcript, user_text, assistant_text, tool_call, usage or
+/// error. Assistant text comes complete rather than as deltas.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>) {
+    while let Some(event) = events.recv().await {
+        let line = match event["type"].as_str().unwrap_or_default() {
+            "conversation.item.input_audio_transcription.completed" => json!({
+                "type": "user_transcript",
+                "item_id": event["item_id"],
+                "text": event["transcript"].as_str().unwrap_or_default().trim(),
+            }),
+            // Typed messages, the server echoes them back once they're in the conversation
+            "conversation.item.created" if event["item"]["role"] == "user" && event["item"]["content"][0]["type"] == "input_text" => json!({
+                "type": "user_text",
+                "item_id": event["item"]["id"],
+                "text": event["item"]["content"][0]["text"],
+            }),
+            "response.text.done" | "response.audio_transcript.done" => json!({
+                "type": "assistant_text",
+                "item_id": event["item_id"],
+                "response_id": event["response_id"],
+                "text": event.get("text").unwrap_or(&event["transcript"]),
+                "spoken": event["type"] == "response.audio_transcript.done",
+            }),
+            "response.done" => {
+                for call in event["response"]["output"].as_array().into_iter().flatten().filter_map(ToolCall::from_item) {
+                    emit(&json!({
+                        "type": "tool_call",
+                        "call_id": call.call_id,
+                        "name": call.name,
+                        "arguments": serde_json::from_str::<Value>(&call.arguments).unwrap_or(Value::String(call.arguments.clone())),
+                    }));
+                }
+                json!({
+                    "type": "usage",
+                    "response_id": event["response"]["id"],
+                    "status": event["response"]["status"],
+                    "usage": event["response"]["usage"],
+                })
+            },
+            "error" => json!({
+                "type": "error",
+                "code": event["error"]["code"],
+                "message": event["error"]["message"],
+                "param": event["error"]["param"],
+            }),
+            _ => continue,
+        };
+        emit(&line);
+    }
+}
@@ -0,0 +1,113 @@
+use tokio::sync::mpsc;
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+use crate::conversation::is_side_channel_response;
+
+// How often playback is checked for having started
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+const HEADER: &str = "turn,response_id,turn_end,turn_end_at_ms,response_created_ms,first_audio_delta_ms,playback_start_ms,response_done_ms,status,input_tokens,output_tokens,total_tokens";
+
+/// Timestamps of the turn in progress
+#[derive(Default)]
+struct Turn {
+    start: Option<(Instant, &'static str)>,     // When the user finished (speech, text) or the previous response did
+    response_id: String,
+    response_created: Option<Instant>,
+    first_audio_delta: Option<Instant>,
+    played_before_audio: usize,                 // Played samples counter when the first delta arrived
+    playback_start: Option<Instant>,
+}
+
+/// Latency subscriber: writes a CSV row per turn, for comparing how responsive configurations are
+///
+/// Times are milliseconds since the user finished their turn: the end of speech as detected by
+/// the server VAD, or sending a typed message. That moment itself is given since the start of the call.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, played_samples: Arc<AtomicUsize>) {
+    let call_start = Instant::now();
+    let mut log: Option<File> = None;
+    let mut turn = Turn::default();
+    let mut turns = 0;
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+
+                match event["type"].as_str().unwrap_or_default() {
+                    // Raised locally by RealtimeClient::set_latency_log()
+                    "local.latency_log" => {
+                        let path = event["path"].as_str().unwrap_or_default();
+                        match File::create(path).and_then(|mut file| writeln!(file, "{}", HEADER).map(|_| file)) {
+                            Ok(file) => log = Some(file),
+                            Err(e) => eprintln!("Failed to open latency log {}: {}", path, e),
+                        }
+                    },
+                    "input_audio_buffer.speech_stopped" => {
+                        turn = Turn { start: Some((Instant::now(), "speech")), ..Turn::default() };
+                    },
+                    "conversation.item.create" if event["item"]["role"] == "user" => {
+                        turn = Turn { start: Some((Instant::now(), "text")), ..Turn::default() };
+                    },
+                    "response.created" if !is_side_channel_response(&event["response"]) => {
+                        turn.response_id = event["response"]["id"].as_str().unwrap_or_default().to_string();
+                        turn.response_created = Some(Instant::now());
+                    },
+                    "response.audio.delta" if turn.first_audio_delta.is_none() && turn.response_created.is_some() => {
+                        turn.first_audio_delta = Some(Instant::now());
+                        turn.played_before_audio = played_samples.load(Ordering::Relaxed);
+                    },
+                    "response.done" if event["response"]["id"] == turn.response_id.as_str() && turn.response_created.is_some() => {
+                        turns += 1;
+                        if let Some(file) = log.as_mut() {
+                            if let Err(e) = writeln!(file, "{}", row(turns, &turn, &event["response"], call_start)) {
+                                eprintln!("Failed to write latency log: {}", e);
+                            }
+                        }
+
+                        // A follow-up response (e.g. after a tool call) is timed from here
+                        turn = Turn { start: Some((Instant::now(), "response")), ..Turn::default() };
+                    },
+                    _ => {}
+                }
+            }
+            _ = interval.tick(), if turn.first_audio_delta.is_some() && turn.playback_start.is_none() => {
+                // Anything played after the first delta arrived is this response's audio
+                if played_samples.load(Ordering::Relaxed) > turn.played_before_audio {
+                    turn.playback_start = Some(Instant::now());
+                }
+            }
+        }
+    }
+}
+
+fn row(number: usize, turn: &Turn, response: &Value, call_start: Instant) -> String {
+    let (start, kind) = turn.start.unzip();
+    let since_start = |time: Option<Instant>| match (start, time) {
+        (Some(start), Some(time)) => time.saturating_duration_since(start).as_millis().to_string(),
+        _ => String::new(),
+    };
+
+    let usage = &response["usage"];
+    [
+        number.to_string(),
+        turn.response_id.clone(),
+        kind.unwrap_or_default().to_string(),
+        start.map(|start| start.duration_since(call_start).as_millis().to_string()).unwrap_or_default(),
+        since_start(turn.response_created),
+        since_start(turn.first_audio_delta),
+        since_start(turn.playback_start),
+        since_start(Some(Instant::now())),
+        response["status"].as_str().unwrap_or_default().to_string(),
+        usage["input_tokens"].as_u64().unwrap_or(0).to_string(),
+        usage["output_tokens"].as_u64().unwrap_or(0).to_string(),
+        usage["total_tokens"].as_u64().unwrap_or(0).to_string(),
+    ]
+    .join(",")
+}
@@ -0,0 +1,23 @@
+use tokio::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use serde_json::Value;
+
+use crate::audio_utils::base64_decode_audio;
+use crate::recorder::Recorder;
+
+
+/// Logger subscriber: feeds the protocol dump and audio recording
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, recorder: Arc<Mutex<Recorder>>) {
+    while let Some(event) = events.recv().await {
+        let mut recorder = recorder.lock().unwrap();
+        recorder.handle_event(&event);
+
+        match event["type"].as_str().unwrap_or_default() {
+            "response.audio.delta" if recorder.records_audio() => {
+                // Record what the server sent, before any deduplication by the player
+                recorder.add_audio(&base64_decode_audio(event["delta"].as_str().unwrap_or_default()));
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use tokio::sync::mpsc;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use crate::audio_utils::{base64_decode_audio, SERVER_SAMPLE_RATE};
+use crate::commands::{Command, InternalCommand};
+use super::MicPolicy;
+
+// Loudness is compared 10 ms at a time
+const FRAME: usize = SERVER_SAMPLE_RATE as usize / 100;
+// Microphone audio compared at once, 1.5 s
+const WINDOW_FRAMES: usize = 150;
+// Assistant audio it's compared against, 30 s as the server sends it faster than it plays
+const REFERENCE_FRAMES: usize = 3000;
+// Compared every 0.5 s of microphone audio
+const CHECK_FRAMES: usize = 50;
+// Quieter than this the microphone hears nothing worth comparing
+const SILENCE_DB: f32 = -50.0;
+// Loudness that hardly changes, e.g. steady noise, would correlate with anything
+const MIN_SPREAD_DB: f32 = 3.0;
+// Alike enough to be the assistant's voice coming back, in this many checks in a row
+const LOOP_CORRELATION: f32 = 0.8;
+const LOOP_STRIKES: usize = 2;
+
+/// What to do when the microphone picks up the assistant
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum LoopGuard {
+    Warn,       // Say so, once
+    Mute,       // Switch to half duplex, holding the microphone while the assistant speaks
+}
+
+impl LoopGuard {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Warn => "warn",
+            Self::Mute => "mute",
+        }
+    }
+}
+
+/// Loop guard subscriber: notices the assistant's voice being re-captured by the microphone
+///
+/// With speakers and no echo cancellation the server hears the assistant, takes it for the user
+/// and answers itself, burning tokens. The loudness envelope of the microphone audio is
+/// cross-correlated with that of the recent assistant audio, at every offset, while playing.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, played_samples: Arc<AtomicUsize>, command_sender: mpsc::Sender<Command>) {
+    let mut guard: Option<LoopGuard> = None;
+    let mut detector = LoopDetector::default();
+    let mut last_played = played_samples.load(Ordering::Relaxed);
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_loop_guard()
+            "local.loop_guard" => {
+                guard = [LoopGuard::Warn, LoopGuard::Mute].into_iter().find(|guard| event["action"] == guard.as_str());
+            },
+            "response.audio.delta" if guard.is_some() => {
+                detector.played(&base64_decode_audio(event["delta"].as_str().unwrap_or_default()));
+            },
+            // Our own microphone audio on its way to the server
+            "input_audio_buffer.append" if guard.is_some() => {
+                let played = played_samples.load(Ordering::Relaxed);
+                let playing = played != last_played;
+                last_played = played;
+
+                let samples = base64_decode_audio(event["audio"].as_str().unwrap_or_default());
+                let Some(correlation) = detector.captured(&samples, playing) else { continue };
+
+                let alike = format!("{:.0}% alike", correlation * 100.0);
+                match guard.take() {
+                    Some(LoopGuard::Mute) => {
+                        eprintln!("\n{}", format!("[the microphone is picking up the assistant ({}), holding it while the assistant speaks]", alike).yellow());
+                        if command_sender.send(Command::Internal(InternalCommand::SetMicPolicy(MicPolicy::Half))).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ => eprintln!(
+                        "\n{}",
+                        format!("[the microphone is picking up the assistant ({}): use headphones, turn the volume down or try --duplex half]", alike).yellow()
+                    ),
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Loudness envelopes of the assistant audio and the microphone, in dB per FRAME
+#[derive(Default)]
+struct LoopDetector {
+    reference: VecDeque<f32>,
+    reference_pending: Vec<f32>,        // Assistant samples short of a whole frame
+    window: VecDeque<f32>,              // Microphone frames captured while playing
+    window_pending: Vec<f32>,
+    unchecked: usize,                   // Microphone frames since the last check
+    strikes: usize,
+}
+
+impl LoopDetector {
+    fn played(&mut self, samples: &[f32]) {
+        for frame in frames(&mut self.reference_pending, samples) {
+            if self.reference.len() == REFERENCE_FRAMES {
+                self.reference.pop_front();
+            }
+            self.reference.push_back(frame);
+        }
+    }
+
+    /// Takes microphone samples, returning the correlation once they keep matching the assistant
+    fn captured(&mut self, samples: &[f32], playing: bool) -> Option<f32> {
+        // Only what was captured during playback can be an echo of it
+        if !playing {
+            self.window.clear();
+            self.window_pending.clear();
+            self.strikes = 0;
+            return None;
+        }
+
+        for frame in frames(&mut self.window_pending, samples) {
+            if self.window.len() == WINDOW_FRAMES {
+                self.window.pop_front();
+            }
+            self.window.push_back(frame);
+            self.unchecked += 1;
+        }
+        if self.window.len() < WINDOW_FRAMES || self.unchecked < CHECK_FRAMES {
+            return None;
+        }
+        self.unchecked = 0;
+
+        let correlation = self.best_correlation();
+        self.strikes = if correlation >= LOOP_CORRELATION { self.strikes + 1 } else { 0 };
+        (self.strikes >= LOOP_STRIKES).then_some(correlation)
+    }
+
+    /// Highest normalized cross-correlation of the microphone window with the assistant audio
+    ///
+    /// Run every CHECK_FRAMES over thousands of offsets, so the segments of the assistant audio
+    /// aren't normalized one by one: as the window has zero mean, a segment's own mean drops out
+    /// of the product, and its spread comes from sums kept up as the segment slides along.
+    fn best_correlation(&self) -> f32 {
+        let window: Vec<f32> = self.window.iter().copied().collect();
+        let Some(window) = normalized(&window).filter(|_| window.iter().sum::<f32>() / WINDOW_FRAMES as f32 > SILENCE_DB) else {
+            return 0.0;
+        };
+
+        let reference: Vec<f32> = self.reference.iter().copied().collect();
+        if reference.len() < WINDOW_FRAMES {
+            return 0.0;
+        }
+        let frames = WINDOW_FRAMES as f64;
+        let mut sum: f64 = reference[..WINDOW_FRAMES - 1].iter().map(|db| *db as f64).sum();
+        let mut squares: f64 = reference[..WINDOW_FRAMES - 1].iter().map(|db| (*db as f64).powi(2)).sum();
+
+        let mut best = 0.0f32;
+        for (start, segment) in reference.windows(WINDOW_FRAMES).enumerate() {
+            let (entering, leaving) = (segment[WINDOW_FRAMES - 1] as f64, start.checked_sub(1).map_or(0.0, |before| reference[before] as f64));
+            sum += entering - leaving;
+            squares += entering.powi(2) - leaving.powi(2);
+
+            let mean = sum / frames;
+            let spread = (squares / frames - mean * mean).max(0.0).sqrt();
+            if spread < MIN_SPREAD_DB as f64 {
+                continue;
+            }
+            let product: f64 = segment.iter().zip(&window).map(|(a, b)| *a as f64 * *b as f64).sum();
+            best = best.max((product / (spread * frames)) as f32);
+        }
+        best
+    }
+}
+
+/// Cuts samples into frames, carrying the remainder over in `pending`, and gives their loudness in dB
+fn frames(pending: &mut Vec<f32>, samples: &[f32]) -> Vec<f32> {
+    pending.extend_from_slice(samples);
+    let loudness = pending
+        .chunks_exact(FRAME)
+        .map(|frame| {
+            let rms = (frame.iter().map(|sample| sample * sample).sum::<f32>() / FRAME as f32).sqrt();
+            20.0 * rms.max(1e-4).log10()
+        })
+        .collect::<Vec<_>>();
+    pending.drain(..loudness.len() * FRAME);
+    loudness
+}
+
+/// Zero mean and unit variance, None if the loudness hardly changes
+fn normalized(envelope: &[f32]) -> Option<Vec<f32>> {
+    let mean = envelope.iter().sum::<f32>() / envelope.len() as f32;
+    let spread = (envelope.iter().map(|db| (db - mean).powi(2)).sum::<f32>() / envelope.len() as f32).sqrt();
+    (spread >= MIN_SPREAD_DB).then(|| envelope.iter().map(|db| (db - mean) / spread).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Speech-like audio: a tone whose loudness rises and falls irregularly, `seed` picking the pattern
+    fn speech(frames: usize, seed: u32) -> Vec<f32> {
+        (0..frames * FRAME)
+            .map(|n| {
+                let hash = ((n / FRAME) as u32 ^ seed.wrapping_mul(0x9e37_79b9)).wrapping_mul(0x85eb_ca6b);
+                let level = 0.02 + 0.3 * ((hash ^ hash >> 13).wrapping_mul(0xc2b2_ae35) >> 16) as f32 / 65536.0;
+                level * (n as f32 * 0.07).sin()
+            })
+            .collect()
+    }
+
+    /// Feeds the microphone audio in 20 ms chunks, returning the first correlation reported
+    fn capture(detector: &mut LoopDetector, samples: &[f32], playing: bool) -> Option<f32> {
+        samples.chunks(2 * FRAME).find_map(|chunk| detector.captured(chunk, playing))
+    }
+
+    #[test]
+    fn notices_the_assistant_coming_back_through_the_microphone() {
+        let mut detector = LoopDetector::default();
+        let assistant = speech(1000, 1);
+        detector.played(&assistant);
+
+        // A quieter echo, a while later
+        let echo: Vec<f32> = assistant[300 * FRAME..600 * FRAME].iter().map(|sample| sample * 0.3).collect();
+        let correlation = capture(&mut detector, &echo, true).unwrap();
+        assert!(correlation > 0.95, "{}", correlation);
+    }
+
+    #[test]
+    fn other_speech_noise_and_silence_are_not_echoes() {
+        let mut detector = LoopDetector::default();
+        detector.played(&speech(1000, 1));
+
+        assert_eq!(capture(&mut detector, &speech(600, 7), true), None);
+        // Steady noise and silence don't vary enough to compare
+        let noise: Vec<f32> = (0..600 * FRAME).map(|n| 0.1 * (n as f32 * 1.3).sin()).collect();
+        assert_eq!(capture(&mut detector, &noise, true), None);
+        assert_eq!(capture(&mut detector, &vec![0.0; 600 * FRAME], true), None);
+    }
+
+    #[test]
+    fn only_audio_captured_while_playing_counts() {
+        let mut detector = LoopDetector::default();
+        let assistant = speech(1000, 1);
+        detector.played(&assistant);
+        let echo = &assistant[300 * FRAME..600 * FRAME];
+        assert_eq!(capture(&mut detector, echo, false), None);
+
+        // Stopping starts the comparison over
+        assert_eq!(capture(&mut detector, &echo[..100 * FRAME], true), None);
+        detector.captured(&echo[..FRAME], false);
+        assert!(detector.window.is_empty() && detector.strikes == 0);
+    }
+
+    #[test]
+    fn sliding_sums_match_normalizing_each_segment() {
+        let mut detector = LoopDetector::default();
+        detector.played(&speech(400, 3));
+        detector.window.extend(frames(&mut Vec::new(), &speech(WINDOW_FRAMES, 3)[..]));
+        let window = normalized(&detector.window.iter().copied().collect::<Vec<_>>()).unwrap();
+
+        let reference: Vec<f32> = detector.reference.iter().copied().collect();
+        let expected = reference
+            .windows(WINDOW_FRAMES)
+            .filter_map(normalized)
+            .map(|segment| segment.iter().zip(&window).map(|(a, b)| a * b).sum::<f32>() / WINDOW_FRAMES as f32)
+            .fold(0.0, f32::max);
+        assert!((detector.best_correlation() - expected).abs() < 1e-3, "{} vs {}", detector.best_correlation(), expected);
+    }
+}
@@ -0,0 +1,29 @@
+use tokio::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use serde_json::Value;
+
+use crate::commands::Command;
+use crate::usage::UsageTracker;
+
+
+/// Metrics subscriber: tracks token usage and enforces the budget
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, usage: Arc<Mutex<UsageTracker>>, command_sender: mpsc::Sender<Command>) {
+    while let Some(event) = events.recv().await {
+        if event["type"] != "response.done" {
+            continue;
+        }
+
+        // Watchdog against runaway spend, hang up as soon as the budget is used up
+        let exceeded = {
+            let mut usage = usage.lock().unwrap();
+            usage.add_response_usage(&event["response"]["usage"]);
+            usage.is_exceeded().then(|| usage.summary())
+        };
+        if let Some(summary) = exceeded {
+            eprintln!("\nBudget exceeded: {}. Hanging up.", summary);
+            if command_sender.send(Command::Quit).await.is_err() {
+                eprintln!("Failed to request hang up");
+            }
+        }
+    }
+}
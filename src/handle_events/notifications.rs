@@ -0,0 +1,99 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::conversation::is_side_channel_response;
+use crate::text_layout::truncate;
+
+// Longer notification bodies are cut short, most notification daemons show a few lines at best
+const MAX_BODY_WIDTH: usize = 200;
+
+// Responses taking at least this long are worth a notification
+const LONG_RESPONSE: Duration = Duration::from_secs(15);
+
+/// Events that can raise a desktop notification
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyOn {
+    LongResponse,   // A long response finished while you weren't interacting
+    Error,          // The API reported an error
+    Disconnect,     // The connection dropped
+    Keyword,        // A keyword came up in the conversation
+}
+
+/// Which notifications to raise, sent by RealtimeClient::set_notifications()
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    pub on: Vec<NotifyOn>,
+    pub keywords: Vec<String>,      // Matched case-insensitively against transcripts
+}
+
+/// Notifications subscriber: raises desktop notifications on important events
+///
+/// The terminal's focus can't be read while stdin is line buffered, so a long response counts
+/// as unattended when you neither typed nor spoke since it started.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>) {
+    let mut settings = NotificationSettings::default();
+    let mut response_started: Option<Instant> = None;
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            "local.notifications" => {
+                if let Ok(new_settings) = serde_json::from_value(event["settings"].clone()) {
+                    settings = new_settings;
+                }
+            },
+            "response.created" if !is_side_channel_response(&event["response"]) => {
+                response_started = Some(Instant::now());
+            },
+            // You're at the terminal
+            "conversation.item.create" | "input_audio_buffer.speech_started" => {
+                response_started = None;
+            },
+            "response.done" => {
+                let unattended = response_started.take().is_some_and(|started| started.elapsed() >= LONG_RESPONSE);
+                if unattended && settings.on.contains(&NotifyOn::LongResponse) {
+                    notify("Response finished", "The assistant finished a long response");
+                }
+            },
+            "error" if settings.on.contains(&NotifyOn::Error) => {
+                notify("Error", event["error"]["message"].as_str().unwrap_or("The API reported an error"));
+            },
+            "local.disconnected" if settings.on.contains(&NotifyOn::Disconnect) => {
+                notify("Disconnected", event["reason"].as_str().unwrap_or("The connection dropped"));
+            },
+            "response.output_item.done" | "conversation.item.input_audio_transcription.completed"
+                if settings.on.contains(&NotifyOn::Keyword) =>
+            {
+                let text = match event["item"]["content"].as_array() {
+                    Some(content) => content
+                        .iter()
+                        .filter_map(|part| part["transcript"].as_str().or(part["text"].as_str()))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    None => event["transcript"].as_str().unwrap_or_default().to_string(),
+                };
+                let text = text.to_lowercase();
+
+                if let Some(keyword) = settings.keywords.iter().find(|keyword| text.contains(&keyword.to_lowercase())) {
+                    notify(&format!("\"{}\" came up", keyword), text.trim());
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Shows a notification without holding up the subscriber, D-Bus calls can be slow
+fn notify(summary: &str, body: &str) {
+    let summary = format!("hotline: {}", summary);
+    let body = truncate(body, MAX_BODY_WIDTH);
+
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = notify_rust::Notification::new().summary(&summary).body(&body).show() {
+            eprintln!("Failed to show notification: {}", e);
+        }
+    });
+}
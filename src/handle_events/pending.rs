@@ -0,0 +1,40 @@
+use tokio::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use crate::pending::{PendingOperations, ACK_TIMEOUT};
+use super::status_bar::{self, Section};
+
+// How often unanswered operations are looked for
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pending subscriber: tracks the operations the server hasn't acknowledged yet
+///
+/// Ones left unanswered past ACK_TIMEOUT are printed, e.g. a commit that never produced an item,
+/// and counted in the status bar until the answer arrives after all.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, pending: Arc<Mutex<PendingOperations>>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        let overdue = tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+                pending.lock().unwrap().handle_event(&event);
+                Vec::new()
+            }
+            _ = interval.tick() => pending.lock().unwrap().expire(),
+        };
+
+        for operation in &overdue {
+            println!(
+                "\n{}",
+                format!("[{} unanswered: no {} after {} s]", operation.operation, operation.awaiting, ACK_TIMEOUT.as_secs()).yellow()
+            );
+        }
+
+        let count = pending.lock().unwrap().overdue_count();
+        status_bar::set(Section::Pending, (count > 0).then(|| format!("⧗ {} unanswered", count)));
+    }
+}
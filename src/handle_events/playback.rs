@@ -0,0 +1,677 @@
+use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use crate::audio_sequencer::{AudioSequencer, DeltaVerdict, GAP_SILENCE_MS};
+use crate::audio_utils::{base64_decode_audio, AudioOutput, PlaybackCommand, SERVER_SAMPLE_RATE};
+use crate::commands::{Command, InternalCommand};
+use crate::dtmf;
+use crate::conversation::{is_side_channel_response, is_summary_response, ConversationTracker};
+use super::talk_over::TalkOverDetector;
+use crate::text_layout::split_at_fraction;
+use crate::thinking_sound::ThinkingSound;
+use crate::time_stretch::TimeStretcher;
+
+
+/// What happens when the user starts speaking while the assistant is talking
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum InterruptionMode {
+    Cancel,     // Stop playback and cancel the response on the server
+    Playback,   // Only stop local playback, the response keeps generating
+    Off,        // Do not interrupt, e.g. to take notes while the assistant talks
+}
+
+impl InterruptionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cancel => "cancel",
+            Self::Playback => "playback",
+            Self::Off => "off",
+        }
+    }
+}
+
+impl std::str::FromStr for InterruptionMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "cancel" => Ok(Self::Cancel),
+            "playback" => Ok(Self::Playback),
+            "off" => Ok(Self::Off),
+            _ => Err(format!("Unknown interruption mode: {} (expected cancel, playback or off)", mode)),
+        }
+    }
+}
+
+/// Whether the microphone streams while the assistant responds
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum MicPolicy {
+    Full,       // Always stream, needed to talk over (interrupt) the assistant
+    Half,       // Hold the microphone from response.created until the response finished playing
+}
+
+impl MicPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Half => "half",
+        }
+    }
+}
+
+impl std::str::FromStr for MicPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "full" => Ok(Self::Full),
+            "half" => Ok(Self::Half),
+            _ => Err(format!("Unknown duplex policy: {} (expected full or half)", policy)),
+        }
+    }
+}
+
+// Extra time the microphone stays held after playback, for the last of it to leave the speakers
+const MIC_REOPEN_DELAY: Duration = Duration::from_millis(200);
+
+// Quick answers don't get a thinking sound, only waits longer than this
+const THINKING_DELAY: Duration = Duration::from_millis(700);
+// The thinking sound is queued this much at a time, so little of it is left when the answer arrives
+const THINKING_CHUNK: Duration = Duration::from_millis(50);
+
+/// Assistant audio item that is (or was) being played back
+struct AudioItem {
+    item_id: String,
+    content_index: u64,
+    start: usize,               // Samples queued before this item started
+    end: usize,                 // Samples queued once this item's latest delta was queued
+    original_samples: usize,    // What they were before time-stretching, i.e. as the server sent them
+    transcript: String,         // Transcript generated so far
+    complete: bool,             // response.audio.done arrived, the next part can start
+}
+
+/// Audio part of the item being played that streams in before its turn
+///
+/// An item can have several audio content parts, and their deltas may interleave. Playing
+/// them as they arrive would mix the parts into each other, so the later ones wait here.
+struct HeldPart {
+    content_index: u64,
+    samples: Vec<f32>,          // At SERVER_SAMPLE_RATE
+    transcript: String,
+    complete: bool,
+}
+
+/// Audio player subscriber: plays assistant audio and handles interruptions
+pub struct Player {
+    audio: AudioOutput,
+    command_sender: mpsc::Sender<Command>,
+    conversation: Arc<Mutex<ConversationTracker>>,
+
+    queued_samples: usize,                      // Samples (at SERVER_SAMPLE_RATE) sent to the audio thread so far, after time-stretching
+    stretcher: TimeStretcher,                   // Plays the assistant faster or slower, keeping its pitch
+    response_in_progress: bool,
+    current_audio: Option<AudioItem>,
+    held_parts: Vec<HeldPart>,                  // Other parts of the current item, waiting for it to finish
+    interruption_mode: InterruptionMode,
+    sequencer: AudioSequencer,
+    mixing_input: bool,                         // A mirror wants the microphone mixed in
+    talk_over: Option<TalkOverDetector>,        // Watching the microphone for the user talking over the assistant
+    paused: bool,
+    mic_policy: MicPolicy,
+    mic_generation: Arc<AtomicUsize>,           // Bumped whenever the microphone is held, so stale reopen timers do nothing
+    thinking_sound: Vec<f32>,                   // One loop at SERVER_SAMPLE_RATE, empty when turned off
+    thinking_since: Option<Instant>,            // The user's turn ended and no answer was heard yet
+    thinking_position: usize,                   // Where in the loop the sound carries on
+}
+
+impl Player {
+    pub fn new(audio: AudioOutput, command_sender: mpsc::Sender<Command>, conversation: Arc<Mutex<ConversationTracker>>) -> Self {
+        Self {
+            audio,
+            command_sender,
+            conversation,
+            queued_samples: 0,
+            stretcher: TimeStretcher::default(),
+            response_in_progress: false,
+            current_audio: None,
+            held_parts: Vec::new(),
+            interruption_mode: InterruptionMode::Cancel,
+            sequencer: AudioSequencer::default(),
+            mixing_input: false,
+            talk_over: None,
+            paused: false,
+            mic_policy: MicPolicy::Full,
+            mic_generation: Arc::new(AtomicUsize::new(0)),
+            thinking_sound: Vec::new(),
+            thinking_since: None,
+            thinking_position: 0,
+        }
+    }
+
+    pub async fn run(mut self, mut events: mpsc::Receiver<Arc<Value>>) {
+        let mut interval = tokio::time::interval(THINKING_CHUNK);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => {
+                    let Some(event) = event else { break };
+                    self.handle_event(&event).await;
+                }
+                _ = interval.tick(), if self.thinking_since.is_some() => self.play_thinking_sound(),
+            }
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Value) {
+        match event["type"].as_str().unwrap_or_default() {
+            "response.created" if !is_side_channel_response(&event["response"]) => {
+                self.response_in_progress = true;
+                if self.mic_policy == MicPolicy::Half {
+                    self.hold_microphone().await;
+                }
+            },
+            "response.done" if !is_side_channel_response(&event["response"]) => {
+                self.response_in_progress = false;
+                self.thinking_since = None;
+                self.sequencer.reset_response();
+
+                // Parts whose audio.done never came are played rather than lost
+                self.flush_held_parts();
+                if self.mic_policy == MicPolicy::Half {
+                    self.reopen_microphone_after_playback();
+                }
+
+                // The summary is the last thing said on the call, hang up once it has been heard
+                if is_summary_response(&event["response"]) {
+                    let delay = self.remaining_playback();
+                    let command_sender = self.command_sender.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(delay).await;
+                        let _ = command_sender.send(Command::Quit).await;
+                    });
+                }
+            },
+            "response.audio_transcript.delta" => {
+                // Keep the transcript so we can tell what was heard if playback is interrupted
+                let content_index = event["content_index"].as_u64().unwrap_or(0);
+                let delta = event["delta"].as_str().unwrap_or_default();
+                match self.current_audio.as_mut().filter(|item| event["item_id"] == item.item_id.as_str()) {
+                    Some(item) if item.content_index == content_index => item.transcript.push_str(delta),
+                    Some(_) => self.held_part(content_index).transcript.push_str(delta),
+                    None => {}
+                }
+            },
+            "response.audio.delta" => {
+                self.thinking_since = None;
+                self.play_delta(event);
+            },
+            // The turn passes to the assistant: the user stopped talking, or we asked for a response
+            "input_audio_buffer.speech_stopped" => self.start_thinking(),
+            "response.create" if !is_side_channel_response(&event["response"]) => self.start_thinking(),
+            // A text-only answer has no audio to wait for
+            "response.text.delta" => self.thinking_since = None,
+            "local.thinking_sound" => {
+                // Raised locally by RealtimeClient::set_thinking_sound()
+                let sound = event["sound"].as_str().unwrap_or_default().parse::<ThinkingSound>();
+                match sound.map_err(|e| e.into()).and_then(|sound| sound.samples()) {
+                    Ok(samples) => self.thinking_sound = samples,
+                    Err(e) => eprintln!("Failed to load the thinking sound: {}", e),
+                }
+            },
+            "response.audio.done" => {
+                let item_id = event["item_id"].as_str().unwrap_or_default();
+                let content_index = event["content_index"].as_u64().unwrap_or(0);
+                self.sequencer.finish(item_id, content_index);
+
+                match self.current_audio.as_mut().filter(|item| item.item_id == item_id) {
+                    Some(item) if item.content_index == content_index => item.complete = true,
+                    Some(_) => self.held_part(content_index).complete = true,
+                    None => {}
+                }
+                self.play_held_parts();
+
+                // Nothing more is coming for the part being played, the stretcher can let go of its end
+                if self.current_audio.as_ref().is_some_and(|item| item.complete) {
+                    self.flush_stretcher();
+                }
+            },
+            "input_audio_buffer.speech_started" => {
+                self.thinking_since = None;
+
+                // The user started speaking, interrupt the assistant according to the current mode
+                match self.interruption_mode {
+                    InterruptionMode::Cancel => self.interrupt(true).await,
+                    InterruptionMode::Playback => self.interrupt(false).await,
+                    InterruptionMode::Off => {}
+                }
+            },
+            "local.interrupt" => {
+                // Stop the assistant, raised locally by RealtimeClient::interrupt()
+                self.interrupt(true).await;
+            },
+            "local.pause" => {
+                // Raised locally by RealtimeClient::set_paused(), queued audio is kept
+                self.paused = event["paused"] == true;
+                let command = if event["paused"] == true { PlaybackCommand::Pause } else { PlaybackCommand::Resume };
+                if let Err(e) = self.audio.sender.send(command) {
+                    eprintln!("Failed to send playback command: {}", e);
+                }
+            },
+            "local.idle" => {
+                // Raised locally by RealtimeClient::set_audio_asleep()
+                let command = if event["idle"] == true { PlaybackCommand::Sleep } else { PlaybackCommand::Wake };
+                if let Err(e) = self.audio.sender.send(command) {
+                    eprintln!("Failed to send playback command: {}", e);
+                }
+            },
+            "local.add_output" => {
+                // Raised locally by RealtimeClient::add_output_device()
+                if let Some(device) = event["device"].as_str() {
+                    let mix_input = event["mix_input"] == true;
+                    self.mixing_input |= mix_input;
+
+                    if let Err(e) = self.audio.sender.send(PlaybackCommand::AddOutput { device: device.to_string(), mix_input }) {
+                        eprintln!("Failed to send playback command: {}", e);
+                    }
+                }
+            },
+            "local.add_rtp_output" => {
+                // Raised locally by RealtimeClient::add_rtp_output()
+                match serde_json::from_value(event["output"].clone()) {
+                    Ok(output) => {
+                        if let Err(e) = self.audio.sender.send(PlaybackCommand::AddRtpOutput(output)) {
+                            eprintln!("Failed to send playback command: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Invalid RTP output: {}", e),
+                }
+            },
+            // Our own microphone audio on its way to the server
+            "input_audio_buffer.append" if self.mixing_input || self.talk_over.is_some() => {
+                let samples = base64_decode_audio(event["audio"].as_str().unwrap_or_default());
+                self.detect_talk_over(&samples).await;
+                if self.mixing_input {
+                    if let Err(e) = self.audio.sender.send(PlaybackCommand::MixInput(samples)) {
+                        eprintln!("Failed to send playback command: {}", e);
+                    }
+                }
+            },
+            "local.talk_over_detection" => {
+                // Raised locally by RealtimeClient::set_talk_over_detection()
+                self.talk_over = Some(TalkOverDetector::new(event["adapt"] == true));
+            },
+            "local.mic_policy" => {
+                // Raised locally by RealtimeClient::set_mic_policy()
+                if let Some(Ok(policy)) = event["policy"].as_str().map(str::parse) {
+                    self.mic_policy = policy;
+                    if policy == MicPolicy::Full {
+                        self.mic_generation.fetch_add(1, Ordering::Relaxed);
+                        self.send_command(Command::Internal(InternalCommand::SetMicOpen(true))).await;
+                    } else if self.response_in_progress || !self.remaining_playback().is_zero() {
+                        // Switched mid-response, the microphone is held from now on
+                        self.hold_microphone().await;
+                        if !self.response_in_progress {
+                            self.reopen_microphone_after_playback();
+                        }
+                    }
+                }
+            },
+            "local.dtmf" => {
+                // Raised locally by RealtimeClient::press_keys(), played after what's queued
+                match dtmf::tones(event["keys"].as_str().unwrap_or_default()) {
+                    Ok(tones) => {
+                        println!("\n[pressing {}]", event["keys"].as_str().unwrap_or_default());
+                        self.play_between_items(tones);
+                    }
+                    Err(e) => eprintln!("Failed to play the keys: {}", e),
+                }
+            },
+            "local.playback_speed" => {
+                // Raised locally by RealtimeClient::set_playback_speed()
+                if let Some(speed) = event["speed"].as_f64() {
+                    self.stretcher.set_speed(speed as f32);
+                    println!("\n[playback speed: {}×]", speed);
+                }
+            },
+            "local.interruption_mode" => {
+                // Raised locally by RealtimeClient::set_interruption_mode()
+                if let Some(Ok(mode)) = event["mode"].as_str().map(str::parse) {
+                    self.interruption_mode = mode;
+                    println!("\n[interruption mode: {}]", self.interruption_mode.as_str());
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn play_delta(&mut self, event: &Value) {
+        let verdict = self.sequencer.check(event);
+        if let DeltaVerdict::Drop(_) = verdict {
+            return;
+        }
+
+        let item_id = event["item_id"].as_str().unwrap_or_default();
+
+        // Decode the base64 audio data
+        let mut samples = base64_decode_audio(event["delta"].as_str().unwrap());
+
+        // Pad over gaps so the stream doesn't resume mid-waveform
+        if verdict == DeltaVerdict::PlayAfterGap {
+            let silence = (SERVER_SAMPLE_RATE * GAP_SILENCE_MS / 1000) as usize;
+            samples.splice(0..0, std::iter::repeat_n(0.0, silence));
+        }
+
+        // Another part of the item being played waits for its turn
+        let content_index = event["content_index"].as_u64().unwrap_or(0);
+        if let Some(item) = self.current_audio.as_ref().filter(|item| item.item_id == item_id && !item.complete) {
+            if item.content_index != content_index {
+                self.held_part(content_index).samples.extend(samples);
+                return;
+            }
+        }
+        // A new item starts, what the previous one held back goes first
+        if self.current_audio.as_ref().is_some_and(|item| item.item_id != item_id) {
+            self.flush_held_parts();
+        }
+
+        self.queue_samples(item_id, content_index, &samples);
+    }
+
+    /// Sends audio of a part to the audio thread, tracking it as the part being played
+    fn queue_samples(&mut self, item_id: &str, content_index: u64, samples: &[f32]) {
+        // Start tracking a new part when the first delta for it arrives
+        if self.current_audio.as_ref().is_none_or(|item| item.item_id != item_id || item.content_index != content_index) {
+            // The end of the previous part goes before it
+            self.flush_stretcher();
+
+            self.current_audio = Some(AudioItem {
+                item_id: item_id.to_string(),
+                content_index,
+                start: self.queued_samples,
+                end: self.queued_samples,
+                original_samples: 0,
+                transcript: String::new(),
+                complete: false,
+            });
+        }
+
+        if let Some(item) = self.current_audio.as_mut() {
+            item.original_samples += samples.len();
+        }
+        let samples = self.stretcher.process(samples);
+        self.send_samples(samples);
+    }
+
+    /// The assistant item being heard and how many ms into it (in the server's time), None when silent
+    fn playing(&self) -> Option<(String, u64)> {
+        let item = self.current_audio.as_ref()?;
+        let played = self.audio.played_samples.load(Ordering::Relaxed);
+        if self.paused || played < item.start || played >= item.end {
+            return None;
+        }
+
+        let fraction = (played - item.start) as f64 / (item.end - item.start) as f64;
+        Some((item.item_id.clone(), (fraction * item.original_samples as f64 * 1000.0 / SERVER_SAMPLE_RATE as f64) as u64))
+    }
+
+    /// Runs microphone audio through the talk-over detector, reporting what it finds
+    async fn detect_talk_over(&mut self, samples: &[f32]) {
+        let playing = self.playing();
+        let Some(detector) = self.talk_over.as_mut() else { return };
+        let Some((item_id, talk_over)) = detector.process(samples, playing.as_ref().map(|(item_id, ms)| (item_id.as_str(), *ms))) else {
+            return;
+        };
+
+        if let Some(mode) = detector.suggest_mode(self.interruption_mode, &talk_over) {
+            let reason = match mode {
+                InterruptionMode::Off => "short remarks kept cutting the assistant off",
+                _ => "you kept talking over the assistant",
+            };
+            println!("\n[talk-over: {}]", reason);
+            self.send_command(Command::SetInterruptionMode(mode)).await;
+        }
+        self.send_command(Command::Internal(InternalCommand::RecordTalkOver { item_id, talk_over })).await;
+    }
+
+    /// Plays whatever the stretcher still holds of the current part
+    fn flush_stretcher(&mut self) {
+        let samples = self.stretcher.flush();
+        if !samples.is_empty() {
+            self.send_samples(samples);
+        }
+    }
+
+    /// Sends stretched audio of the current part to the audio thread
+    fn send_samples(&mut self, samples: Vec<f32>) {
+        self.queued_samples += samples.len();
+        if let Some(item) = self.current_audio.as_mut() {
+            item.end = self.queued_samples;
+        }
+
+        // The audio thread resamples them for each device
+        if let Err(e) = self.audio.sender.send(PlaybackCommand::Play(samples)) {
+            eprintln!("Failed to send audio samples: {}", e);
+        }
+    }
+
+    /// The held back part of the current item with this content index, added if new
+    fn held_part(&mut self, content_index: u64) -> &mut HeldPart {
+        match self.held_parts.iter().position(|part| part.content_index == content_index) {
+            Some(index) => &mut self.held_parts[index],
+            None => {
+                self.held_parts.push(HeldPart { content_index, samples: Vec::new(), transcript: String::new(), complete: false });
+                self.held_parts.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Plays all held back parts, whether or not the parts before them completed
+    fn flush_held_parts(&mut self) {
+        self.held_parts.iter_mut().for_each(|part| part.complete = true);
+        if let Some(item) = self.current_audio.as_mut() {
+            item.complete = true;
+        }
+        self.play_held_parts();
+    }
+
+    /// Plays the held back parts in content order once the part before them is complete
+    fn play_held_parts(&mut self) {
+        while self.current_audio.as_ref().is_some_and(|item| item.complete) && !self.held_parts.is_empty() {
+            let next = (0..self.held_parts.len()).min_by_key(|&index| self.held_parts[index].content_index).unwrap();
+            let part = self.held_parts.remove(next);
+            let item_id = self.current_audio.as_ref().unwrap().item_id.clone();
+
+            self.queue_samples(&item_id, part.content_index, &part.samples);
+            if let Some(item) = self.current_audio.as_mut() {
+                item.transcript = part.transcript;
+                item.complete = part.complete;
+            }
+        }
+    }
+
+    /// Starts waiting for the assistant's answer, the thinking sound plays if it takes a while
+    fn start_thinking(&mut self) {
+        if !self.thinking_sound.is_empty() && self.thinking_since.is_none() {
+            self.thinking_since = Some(Instant::now());
+            self.thinking_position = 0;
+        }
+    }
+
+    /// Queues the next bit of the thinking sound, once everything queued before has been played
+    fn play_thinking_sound(&mut self) {
+        let Some(since) = self.thinking_since else { return };
+        if since.elapsed() < THINKING_DELAY || self.audio.played_samples.load(Ordering::Relaxed) < self.queued_samples {
+            return;
+        }
+
+        let chunk_len = (SERVER_SAMPLE_RATE as f64 * THINKING_CHUNK.as_secs_f64()) as usize;
+        let chunk: Vec<f32> = self.thinking_sound.iter().cycle().skip(self.thinking_position).take(chunk_len).copied().collect();
+        self.thinking_position = (self.thinking_position + chunk_len) % self.thinking_sound.len();
+
+        self.play_between_items(chunk);
+    }
+
+    /// Sends audio that belongs to no item, e.g. DTMF tones, to the audio thread after what's queued
+    ///
+    /// It's counted in queued_samples, it takes time to play all the same, but the part being played
+    /// has its end played first so its span doesn't take this audio in.
+    fn play_between_items(&mut self, samples: Vec<f32>) {
+        self.flush_stretcher();
+        self.queued_samples += samples.len();
+        if let Err(e) = self.audio.sender.send(PlaybackCommand::Play(samples)) {
+            eprintln!("Failed to send audio samples: {}", e);
+        }
+    }
+
+    /// Stops sending microphone audio, half-duplex style
+    async fn hold_microphone(&mut self) {
+        self.mic_generation.fetch_add(1, Ordering::Relaxed);
+        self.send_command(Command::Internal(InternalCommand::SetMicOpen(false))).await;
+    }
+
+    /// Opens the microphone again once the queued audio has been played
+    fn reopen_microphone_after_playback(&self) {
+        let delay = self.remaining_playback() + MIC_REOPEN_DELAY;
+
+        let generation = self.mic_generation.load(Ordering::Relaxed);
+        let mic_generation = self.mic_generation.clone();
+        let command_sender = self.command_sender.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+
+            // Another response started in the meantime
+            if mic_generation.load(Ordering::Relaxed) == generation && command_sender.send(Command::Internal(InternalCommand::SetMicOpen(true))).await.is_err() {
+                eprintln!("Failed to reopen the microphone");
+            }
+        });
+    }
+
+    /// How long the audio already queued takes to play
+    fn remaining_playback(&self) -> Duration {
+        let played = self.audio.played_samples.load(Ordering::Relaxed);
+        let remaining = self.queued_samples.saturating_sub(played);
+
+        Duration::from_secs_f64(remaining as f64 / SERVER_SAMPLE_RATE as f64)
+    }
+
+    async fn send_command(&self, command: Command) {
+        if self.command_sender.send(command).await.is_err() {
+            eprintln!("Failed to send command");
+        }
+    }
+
+    /// Stops the assistant, cancelling the response on the server too if asked
+    async fn interrupt(&mut self, cancel: bool) {
+        tracing::debug!(cancel, response_in_progress = self.response_in_progress, "interrupting");
+        if cancel && self.response_in_progress && self.command_sender.send(Command::Internal(InternalCommand::CancelResponse)).await.is_err() {
+            eprintln!("Failed to request response cancellation");
+        }
+
+        if let Some(item) = self.current_audio.take() {
+            self.sequencer.finish(&item.item_id, item.content_index);
+            self.stretcher.reset();
+
+            // Parts that never got their turn weren't heard at all
+            for part in std::mem::take(&mut self.held_parts) {
+                self.sequencer.finish(&item.item_id, part.content_index);
+                self.conversation.lock().unwrap().set_heard_text(&item.item_id, part.content_index as usize, "");
+                let truncate = Command::Internal(InternalCommand::TruncateItem { item_id: item.item_id.clone(), content_index: part.content_index, audio_end_ms: 0 });
+                if self.command_sender.send(truncate).await.is_err() {
+                    eprintln!("Failed to request item truncation");
+                }
+            }
+
+            self.interrupt_playback(item).await;
+        }
+    }
+
+    /// Stops playback of an item and truncates it on the server to what was actually heard
+    ///
+    /// Per the API semantics, the server drops the transcript beyond `audio_end_ms`, so the unheard
+    /// tail is shown struck out to make clear the model no longer thinks it said it.
+    async fn interrupt_playback(&mut self, item: AudioItem) {
+        let played = self.audio.played_samples.load(Ordering::Relaxed);
+
+        // Nothing to do if the item already finished playing
+        if played >= item.end {
+            return;
+        }
+
+        if let Err(e) = self.audio.sender.send(PlaybackCommand::Stop) {
+            eprintln!("Failed to stop playback: {}", e);
+        }
+        if let Some(detector) = self.talk_over.as_mut() {
+            detector.interrupted();
+        }
+
+        // Nothing is queued anymore, later items (and the duplex policy) count from here
+        self.queued_samples = played;
+
+        // Portion of the item that reached the speakers, in the server's time as playback may be sped up
+        let heard_fraction = played.saturating_sub(item.start) as f64 / (item.end - item.start) as f64;
+        let audio_end_ms = (heard_fraction * item.original_samples as f64 * 1000.0 / SERVER_SAMPLE_RATE as f64) as u64;
+
+        // Assume the transcript was spoken at a steady pace and cut it on a word boundary
+        let (heard, unheard) = split_at_fraction(&item.transcript, heard_fraction);
+        println!("\n[interrupted after {} ms] {}{}", audio_end_ms, heard, unheard.dim().crossed_out());
+
+        // Keep only what was heard in the transcript
+        self.conversation.lock().unwrap().set_heard_text(&item.item_id, item.content_index as usize, heard);
+
+        let truncate = Command::Internal(InternalCommand::TruncateItem {
+            item_id: item.item_id,
+            content_index: item.content_index,
+            audio_end_ms,
+        });
+        if self.command_sender.send(truncate).await.is_err() {
+            eprintln!("Failed to request item truncation");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_utils::base64_encode_audio;
+    use serde_json::json;
+
+    fn player() -> (Player, std::sync::mpsc::Receiver<PlaybackCommand>) {
+        let (sender, played) = std::sync::mpsc::channel();
+        let audio = AudioOutput { sender, sample_rate: SERVER_SAMPLE_RATE, played_samples: Arc::new(AtomicUsize::new(0)), thread: None };
+        let (command_sender, _) = mpsc::channel(16);
+        (Player::new(audio, command_sender, Arc::new(Mutex::new(ConversationTracker::default()))), played)
+    }
+
+    fn delta(item_id: &str, samples: usize) -> Value {
+        json!({"type": "response.audio.delta", "item_id": item_id, "content_index": 0, "delta": base64_encode_audio(&vec![0.1; samples])})
+    }
+
+    fn lengths(played: &std::sync::mpsc::Receiver<PlaybackCommand>) -> Vec<usize> {
+        played.try_iter().filter_map(|command| if let PlaybackCommand::Play(samples) = command { Some(samples.len()) } else { None }).collect()
+    }
+
+    #[tokio::test]
+    async fn dtmf_tones_are_left_out_of_the_items_around_them() {
+        let (mut player, played) = player();
+        // Sped up, the stretcher holds on to the end of what it's given
+        player.handle_event(&json!({"type": "local.playback_speed", "speed": 1.5})).await;
+        player.handle_event(&delta("item_1", 4800)).await;
+        let tones = dtmf::tones("12").unwrap().len();
+        player.handle_event(&json!({"type": "local.dtmf", "keys": "12"})).await;
+
+        // The item's end is played before the tones, and its span stops short of them
+        let sent = lengths(&played);
+        assert_eq!(sent.last(), Some(&tones));
+        let end = player.current_audio.as_ref().map(|item| (item.start, item.end));
+        assert_eq!(end, Some((0, sent[..sent.len() - 1].iter().sum::<usize>())));
+        assert_eq!(player.queued_samples, sent.iter().sum::<usize>());
+
+        // The next item starts after them
+        player.handle_event(&delta("item_2", 480)).await;
+        assert_eq!(player.current_audio.as_ref().unwrap().start, end.unwrap().1 + tones);
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+
+/// Where the display modes draw: the terminal, or a buffer standing in for one
+///
+/// Rendering goes through a screen rather than straight to stdout so the transcript can be laid
+/// out for any width, and drawn into a buffer to compare with golden frames in the tests.
+pub struct Screen {
+    out: Box<dyn Write + Send>,
+    columns: Option<usize>,     // Fixed width, None to follow the terminal
+}
+
+impl Screen {
+    pub fn stdout() -> Self {
+        Self { out: Box::new(io::stdout()), columns: None }
+    }
+
+    /// A screen of a fixed width, drawing into `out`
+    #[cfg(test)]
+    pub fn new(out: impl Write + Send + 'static, columns: usize) -> Self {
+        Self { out: Box::new(out), columns: Some(columns) }
+    }
+
+    /// Columns to lay text out in
+    pub fn columns(&self) -> usize {
+        self.columns.unwrap_or_else(|| crossterm::terminal::size().map_or(80, |(columns, _)| columns as usize))
+    }
+
+    // A terminal gone away isn't worth failing the call over, so errors writing are ignored
+
+    pub fn print(&mut self, text: impl Display) {
+        let _ = write!(self.out, "{}", text);
+    }
+
+    pub fn println(&mut self, text: impl Display) {
+        let _ = writeln!(self.out, "{}", text);
+    }
+}
+
+impl Write for Screen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Bytes drawn on a test screen, shared so they can be looked at once the subscriber is done
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Buffer {
+    /// What the bytes drawn so far look like on a terminal `columns` wide
+    pub fn frame(&self, columns: usize) -> String {
+        frame(&self.0.lock().unwrap(), columns)
+    }
+}
+
+#[cfg(test)]
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Plays terminal output onto a grid of cells and returns the lines, without styles
+///
+/// Knows what the display modes send: text wrapping at the right edge, newlines, moving to a
+/// column and clearing the line. Colors are dropped and the title is left out.
+#[cfg(test)]
+pub fn frame(bytes: &[u8], columns: usize) -> String {
+    use unicode_width::UnicodeWidthChar;
+
+    let text = String::from_utf8_lossy(bytes);
+    let mut rows: Vec<Vec<String>> = vec![Vec::new()];   // Cells, the second half of a wide character empty
+    let mut column = 0;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let row = rows.last_mut().unwrap();
+        match c {
+            '\x1b' => match chars.next() {
+                // Control sequence, up to a final byte
+                Some('[') => {
+                    let mut parameters = String::new();
+                    let final_byte = chars.by_ref().find(|c| {
+                        let done = ('@'..='~').contains(c);
+                        if !done {
+                            parameters.push(*c);
+                        }
+                        done
+                    });
+                    match final_byte {
+                        Some('G') => column = parameters.parse::<usize>().unwrap_or(1).saturating_sub(1),
+                        Some('K') if parameters == "2" => row.clear(),
+                        Some('K') => row.truncate(column),
+                        _ => {}
+                    }
+                }
+                // Operating system command, e.g. the title, up to BEL or ST
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' => {
+                rows.push(Vec::new());
+                column = 0;
+            }
+            '\r' => column = 0,
+            c => {
+                let width = c.width().unwrap_or(0);
+                if width == 0 {
+                    continue;
+                }
+                let row = if column + width > columns {
+                    rows.push(Vec::new());
+                    column = 0;
+                    rows.last_mut().unwrap()
+                } else {
+                    row
+                };
+                if row.len() < column + width {
+                    row.resize(column + width, " ".to_string());
+                }
+                row[column] = c.to_string();
+                if width == 2 {
+                    row[column + 1] = String::new();
+                }
+                column += width;
+            }
+        }
+    }
+
+    let lines: Vec<String> = rows.iter().map(|cells| cells.concat().trim_end().to_string()).collect();
+    lines.join("\n").trim_end().to_string()
+}
+
+/// Compares a frame with its golden copy in fixtures/frames, or writes it there with UPDATE_FRAMES=1
+#[cfg(test)]
+pub fn assert_frame(name: &str, frame: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/frames").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_FRAMES").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, format!("{}\n", frame)).unwrap();
+        return;
+    }
+
+    let golden = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("no golden frame at {} ({}), UPDATE_FRAMES=1 writes it", path.display(), e));
+    assert!(
+        golden.strip_suffix('\n') == Some(frame),
+        "frame {} differs from {}\n--- expected\n{}--- drawn\n{}\n",
+        name,
+        path.display(),
+        golden,
+        frame
+    );
+}
@@ -0,0 +1,136 @@
+use tokio::sync::mpsc;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+use crate::conversation::{is_side_channel_response, Sentiment};
+
+// A slow classifier shouldn't pile up requests
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sentiment subscriber: has each finished turn classified over HTTP and asks for the item to be labelled
+///
+/// Off until RealtimeClient::set_sentiment_endpoint() gives it a URL. The turn is POSTed as
+/// `{"inputs": "<text>", "role": "user"}` with HOTLINE_SENTIMENT_TOKEN as bearer token if set,
+/// and the answer is a `{"label", "score"}` object or a list of them (nested one level deep
+/// like Hugging Face's text classification), the highest score winning.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut endpoint: Option<String> = None;
+    let mut side_channel_responses = HashSet::new();
+    let http = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default();
+
+    while let Some(event) = events.recv().await {
+        let (item_id, role, text) = match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_sentiment_endpoint()
+            "local.sentiment_endpoint" => {
+                endpoint = event["url"].as_str().map(str::to_string);
+                continue;
+            }
+            "response.created" if is_side_channel_response(&event["response"]) => {
+                side_channel_responses.insert(event["response"]["id"].as_str().unwrap_or_default().to_string());
+                continue;
+            }
+            "conversation.item.input_audio_transcription.completed" => {
+                (event["item_id"].as_str(), "user", event["transcript"].as_str().unwrap_or_default().to_string())
+            }
+            // Typed messages, spoken ones are only known once transcribed
+            "conversation.item.created" if event["item"]["role"] == "user" => (event["item"]["id"].as_str(), "user", item_text(&event["item"])),
+            "response.output_item.done"
+                if event["item"]["role"] == "assistant" && !side_channel_responses.contains(event["response_id"].as_str().unwrap_or_default()) =>
+            {
+                (event["item"]["id"].as_str(), "assistant", item_text(&event["item"]))
+            }
+            _ => continue,
+        };
+
+        let (Some(url), Some(item_id)) = (endpoint.clone(), item_id) else { continue };
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        // Classified in the background, events keep flowing meanwhile
+        let (http, command_sender, item_id) = (http.clone(), command_sender.clone(), item_id.to_string());
+        tokio::spawn(async move {
+            match classify(&http, &url, role, &text).await {
+                Ok(sentiment) => {
+                    let command = Command::Internal(InternalCommand::SetSentiment { item_id, role: role.to_string(), sentiment });
+                    if command_sender.send(command).await.is_err() {
+                        eprintln!("Failed to label an item's sentiment");
+                    }
+                }
+                Err(e) => tracing::info!(error = %e, "sentiment classification failed"),
+            }
+        });
+    }
+}
+
+/// Text parts and transcripts of an item, joined
+fn item_text(item: &Value) -> String {
+    item["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| part["text"].as_str().or(part["transcript"].as_str()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+async fn classify(http: &reqwest::Client, url: &str, role: &str, text: &str) -> Result<Sentiment, Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = http.post(url).json(&serde_json::json!({"inputs": text, "role": role}));
+    if let Ok(token) = std::env::var("HOTLINE_SENTIMENT_TOKEN") {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(format!("classifier answered {}", response.status()).into());
+    }
+    let body: Value = response.json().await?;
+    parse_classification(&body).ok_or_else(|| format!("no label in the classifier's answer: {}", body).into())
+}
+
+fn parse_classification(value: &Value) -> Option<Sentiment> {
+    match value {
+        Value::Array(candidates) => candidates.iter().filter_map(parse_classification).max_by(|a, b| a.score.total_cmp(&b.score)),
+        Value::Object(_) => Some(Sentiment {
+            label: value["label"].as_str()?.to_lowercase(),
+            score: value["score"].as_f64().unwrap_or(1.0),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sentiment(label: &str, score: f64) -> Option<Sentiment> {
+        Some(Sentiment { label: label.to_string(), score })
+    }
+
+    #[test]
+    fn the_highest_scoring_label_wins() {
+        assert_eq!(parse_classification(&json!({"label": "POSITIVE", "score": 0.9})), sentiment("positive", 0.9));
+        // Without a score the label is taken as certain
+        assert_eq!(parse_classification(&json!({"label": "joy"})), sentiment("joy", 1.0));
+        assert_eq!(parse_classification(&json!([{"label": "neutral", "score": 0.2}, {"label": "anger", "score": 0.7}])), sentiment("anger", 0.7));
+        // Hugging Face's text classification, a list per input
+        let nested = json!([[{"label": "NEGATIVE", "score": 0.1}, {"label": "POSITIVE", "score": 0.85}]]);
+        assert_eq!(parse_classification(&nested), sentiment("positive", 0.85));
+        // Candidates without a label are passed over
+        assert_eq!(parse_classification(&json!([{"score": 0.99}, {"label": "sadness", "score": 0.4}])), sentiment("sadness", 0.4));
+    }
+
+    #[test]
+    fn answers_without_a_label_are_none() {
+        assert_eq!(parse_classification(&json!({"score": 0.9})), None);
+        assert_eq!(parse_classification(&json!({"label": 3, "score": 0.9})), None);
+        assert_eq!(parse_classification(&json!([])), None);
+        assert_eq!(parse_classification(&json!([[]])), None);
+        assert_eq!(parse_classification(&json!("positive")), None);
+        assert_eq!(parse_classification(&Value::Null), None);
+    }
+}
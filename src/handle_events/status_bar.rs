@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+
+/// Parts of the status bar, shown in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+    Connection,     // Heartbeat health and round trip
+    Speaking,       // The assistant is speaking, see --cues
+    Turn,           // Countdown to sending the turn, see --auto-commit
+    TalkTime,       // Share of the speaking time
+    Pending,        // Operations the server hasn't answered
+}
+
+static SECTIONS: Mutex<BTreeMap<Section, String>> = Mutex::new(BTreeMap::new());
+
+/// Sets the text of a section, or clears it with None
+///
+/// The status bar is the terminal title, which doesn't interleave with the transcript. Each
+/// subscriber owns a section, so they can update the title without overwriting each other.
+pub fn set(section: Section, text: Option<String>) {
+    let title = {
+        let mut sections = SECTIONS.lock().unwrap();
+        match text {
+            Some(text) => sections.insert(section, text),
+            None => sections.remove(&section),
+        };
+        title(&sections)
+    };
+
+    // Piped, the escape sequence would only end up in the output
+    let mut stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        return;
+    }
+    let _ = crossterm::execute!(stdout, crossterm::terminal::SetTitle(title));
+    let _ = stdout.flush();
+}
+
+/// The title showing the sections that are set, in order
+fn title(sections: &BTreeMap<Section, String>) -> String {
+    std::iter::once("hotline").chain(sections.values().map(String::as_str)).collect::<Vec<_>>().join(" · ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::screen::assert_frame;
+
+    #[test]
+    fn titles() {
+        let states: [&[(Section, &str)]; 3] = [
+            &[],
+            &[(Section::Connection, "connected, 85 ms"), (Section::TalkTime, "you 40% · assistant 60%")],
+            &[
+                (Section::Pending, "1 pending"),
+                (Section::Turn, "sending in 2 s"),
+                (Section::Speaking, "speaking"),
+                (Section::Connection, "reconnecting"),
+            ],
+        ];
+        let titles: Vec<String> = states
+            .iter()
+            .map(|state| title(&state.iter().map(|(section, text)| (*section, text.to_string())).collect()))
+            .collect();
+        assert_frame("status_bar", &titles.join("\n"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+use crate::conversation::TalkOver;
+use super::InterruptionMode;
+
+// The microphone is judged 20 ms at a time
+const FRAME: usize = SERVER_SAMPLE_RATE as usize / 50;
+const FRAME_MS: u64 = 20;
+// Louder than this is speech, meant for headsets: without echo cancellation speakers make the assistant count too
+const SPEECH_DBFS: f32 = -35.0;
+// Speech starts after this many loud frames in a row, so clicks don't count
+const ONSET_FRAMES: usize = 2;
+// And ends after this much quiet, pauses between words don't
+const HANGOVER_MS: u64 = 400;
+// Both talking for less than this is a coincidence rather than talking over
+const MIN_OVERLAP_MS: u64 = 100;
+// Speech this short is an "mm-hm" rather than an attempt to take the turn
+const BACKCHANNEL_MS: u64 = 700;
+// Talking over the assistant this long means the user wants the turn
+const LONG_OVERLAP_MS: u64 = 2000;
+// In a row before the interruption policy changes
+const BACKCHANNEL_STRIKES: usize = 3;
+const LONG_OVERLAP_STRIKES: usize = 2;
+
+/// A stretch of the user's speech, as heard by the local VAD
+#[derive(Default)]
+struct Burst {
+    speech_ms: u64,
+    quiet_ms: u64,                      // Since the last loud frame
+    overlap_ms: u64,                    // Of it while the assistant was playing
+    item: Option<(String, u64)>,        // Assistant item talked over and how far into it, when it started
+    interrupted: bool,
+}
+
+/// Detects the user and the assistant speaking at once
+///
+/// Fed with the microphone audio sent to the server along with what was playing at the time,
+/// an energy VAD tells when the user speaks. A burst of speech that overlapped playback is
+/// reported once it ends, as an interruption if the assistant was stopped meanwhile.
+pub struct TalkOverDetector {
+    adapt: bool,                    // Suggest interruption policy changes
+    pending: Vec<f32>,              // Samples short of a whole frame
+    loud_frames: usize,             // In a row, before speech starts
+    onset: Burst,                   // Overlap of those loud frames, it counts once they turn out to be speech
+    burst: Option<Burst>,
+    strikes: usize,                 // Talk-overs in a row pointing at another policy
+}
+
+impl TalkOverDetector {
+    pub fn new(adapt: bool) -> Self {
+        Self { adapt, pending: Vec::new(), loud_frames: 0, onset: Burst::default(), burst: None, strikes: 0 }
+    }
+
+    /// Takes microphone samples, with the assistant item playing meanwhile and the ms played of it
+    ///
+    /// Returns the item and its talk-over once a burst of speech that overlapped it has ended.
+    pub fn process(&mut self, samples: &[f32], playing: Option<(&str, u64)>) -> Option<(String, TalkOver)> {
+        self.pending.extend_from_slice(samples);
+        let frames = self.pending.len() / FRAME;
+        let mut finished = None;
+
+        for frame in 0..frames {
+            let frame = &self.pending[frame * FRAME..(frame + 1) * FRAME];
+            let rms = (frame.iter().map(|sample| sample * sample).sum::<f32>() / FRAME as f32).sqrt();
+            let loud = 20.0 * rms.max(1e-6).log10() > SPEECH_DBFS;
+
+            match self.burst.as_mut() {
+                None if loud => {
+                    self.loud_frames += 1;
+                    if let Some((item_id, played_ms)) = playing {
+                        self.onset.overlap_ms += FRAME_MS;
+                        self.onset.item.get_or_insert_with(|| (item_id.to_string(), played_ms));
+                    }
+                    if self.loud_frames >= ONSET_FRAMES {
+                        let onset = std::mem::take(&mut self.onset);
+                        self.burst = Some(Burst { speech_ms: ONSET_FRAMES as u64 * FRAME_MS, ..onset });
+                    }
+                }
+                None => {
+                    self.loud_frames = 0;
+                    self.onset = Burst::default();
+                }
+                Some(burst) => {
+                    burst.speech_ms += FRAME_MS;
+                    burst.quiet_ms = if loud { 0 } else { burst.quiet_ms + FRAME_MS };
+
+                    if let Some((item_id, played_ms)) = playing.filter(|_| burst.quiet_ms == 0) {
+                        burst.overlap_ms += FRAME_MS;
+                        burst.item.get_or_insert_with(|| (item_id.to_string(), played_ms));
+                    }
+
+                    if burst.quiet_ms >= HANGOVER_MS {
+                        let burst = self.burst.take().unwrap();
+                        self.loud_frames = 0;
+                        if let (Some((item_id, at_ms)), true) = (burst.item, burst.overlap_ms >= MIN_OVERLAP_MS) {
+                            let talk_over = TalkOver {
+                                at_ms,
+                                overlap_ms: burst.overlap_ms,
+                                speech_ms: burst.speech_ms - burst.quiet_ms,
+                                interrupted: burst.interrupted,
+                            };
+                            finished = Some((item_id, talk_over));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending.drain(..frames * FRAME);
+        finished
+    }
+
+    /// The assistant was just stopped, by the user talking or otherwise
+    pub fn interrupted(&mut self) {
+        if let Some(burst) = self.burst.as_mut().filter(|burst| burst.item.is_some()) {
+            burst.interrupted = true;
+        }
+    }
+
+    /// The interruption mode the talk-overs so far call for, if adapting and it's time to change
+    ///
+    /// Short bursts that keep cutting the assistant off are backchannels ("mm-hm", "right")
+    /// better left alone, long ones it kept talking through mean the user wants to cut in.
+    pub fn suggest_mode(&mut self, mode: InterruptionMode, talk_over: &TalkOver) -> Option<InterruptionMode> {
+        if !self.adapt {
+            return None;
+        }
+
+        let (points_away, strikes, suggestion) = match mode {
+            InterruptionMode::Cancel | InterruptionMode::Playback => {
+                (talk_over.interrupted && talk_over.speech_ms < BACKCHANNEL_MS, BACKCHANNEL_STRIKES, InterruptionMode::Off)
+            }
+            InterruptionMode::Off => (talk_over.overlap_ms >= LONG_OVERLAP_MS, LONG_OVERLAP_STRIKES, InterruptionMode::Cancel),
+        };
+
+        self.strikes = if points_away { self.strikes + 1 } else { 0 };
+        if self.strikes < strikes {
+            return None;
+        }
+        self.strikes = 0;
+        Some(suggestion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speech(ms: u64) -> Vec<f32> {
+        vec![0.3; FRAME * (ms / FRAME_MS) as usize]
+    }
+
+    fn quiet(ms: u64) -> Vec<f32> {
+        vec![0.0; FRAME * (ms / FRAME_MS) as usize]
+    }
+
+    fn talk_over(interrupted: bool, speech_ms: u64, overlap_ms: u64) -> TalkOver {
+        TalkOver { at_ms: 0, overlap_ms, speech_ms, interrupted }
+    }
+
+    #[test]
+    fn reports_speech_over_playback_once_it_ends() {
+        let mut detector = TalkOverDetector::new(false);
+        // Nothing until the hangover is over
+        assert_eq!(detector.process(&speech(300), Some(("item_1", 1500))), None);
+        detector.interrupted();
+        assert_eq!(detector.process(&quiet(200), None), None);
+
+        let (item_id, talk_over) = detector.process(&quiet(200), None).unwrap();
+        assert_eq!(item_id, "item_1");
+        // The onset frames count toward the overlap as well as the speech
+        assert_eq!((talk_over.at_ms, talk_over.speech_ms, talk_over.overlap_ms, talk_over.interrupted), (1500, 300, 300, true));
+    }
+
+    #[test]
+    fn chunks_shorter_than_a_frame_add_up() {
+        let mut detector = TalkOverDetector::new(false);
+        let samples = speech(200);
+        for chunk in samples.chunks(FRAME / 3) {
+            assert_eq!(detector.process(chunk, Some(("item_1", 0))), None);
+        }
+        let (_, talk_over) = detector.process(&quiet(400), None).unwrap();
+        assert_eq!((talk_over.speech_ms, talk_over.overlap_ms, talk_over.interrupted), (200, 200, false));
+    }
+
+    #[test]
+    fn clicks_silence_and_brief_overlaps_are_not_talk_overs() {
+        let mut detector = TalkOverDetector::new(false);
+        // A single loud frame isn't speech
+        detector.process(&speech(20), Some(("item_1", 0)));
+        assert_eq!(detector.process(&quiet(1000), Some(("item_1", 20))), None);
+        assert!(detector.burst.is_none());
+
+        // Speaking without anything playing
+        detector.process(&speech(500), None);
+        assert_eq!(detector.process(&quiet(400), None), None);
+
+        // Overlapping by less than MIN_OVERLAP_MS: the onset and one more frame
+        detector.process(&speech(60), Some(("item_2", 0)));
+        detector.process(&speech(500), None);
+        assert_eq!(detector.process(&quiet(400), None), None);
+
+        // The onset alone can be enough, e.g. the assistant stopped as the user started
+        detector.process(&speech(100), Some(("item_3", 700)));
+        detector.process(&speech(400), None);
+        let (item_id, talk_over) = detector.process(&quiet(400), None).unwrap();
+        assert_eq!((item_id.as_str(), talk_over.overlap_ms, talk_over.speech_ms), ("item_3", 100, 500));
+    }
+
+    #[test]
+    fn suggests_leaving_backchannels_alone() {
+        let mut detector = TalkOverDetector::new(true);
+        let backchannel = talk_over(true, 400, 400);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Cancel, &backchannel), None);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Cancel, &backchannel), None);
+        // A real interruption in between starts the count over
+        assert_eq!(detector.suggest_mode(InterruptionMode::Cancel, &talk_over(true, 1500, 1500)), None);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Cancel, &backchannel), None);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Playback, &backchannel), None);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Cancel, &backchannel), Some(InterruptionMode::Off));
+        // And counts afresh after suggesting
+        assert_eq!(detector.suggest_mode(InterruptionMode::Cancel, &backchannel), None);
+    }
+
+    #[test]
+    fn suggests_cutting_in_after_long_talk_overs() {
+        let mut detector = TalkOverDetector::new(true);
+        let long = talk_over(false, 2500, 2500);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Off, &long), None);
+        assert_eq!(detector.suggest_mode(InterruptionMode::Off, &long), Some(InterruptionMode::Cancel));
+
+        // Not when adapting is off
+        let mut detector = TalkOverDetector::new(false);
+        for _ in 0..5 {
+            assert_eq!(detector.suggest_mode(InterruptionMode::Off, &long), None);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::Value;
+
+use super::status_bar::{self, Section};
+use crate::audio_utils::{base64_audio_bytes, SERVER_SAMPLE_RATE};
+
+// The meter is redrawn at most this often, audio deltas arrive many times a second
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Talk time subscriber: shows how the speaking time splits between the user and the assistant
+///
+/// The user's time comes from the server VAD, the assistant's from the audio it sent, less
+/// what was cut off by interruptions. Handy for interview practice or tutoring, where the
+/// user wants to do most of the talking.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>) {
+    let mut talk_time = TalkTime::default();
+    let mut changed = false;
+    let mut interval = tokio::time::interval(UPDATE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+                changed |= talk_time.handle_event(&event);
+            }
+            _ = interval.tick(), if changed => {
+                changed = false;
+                if let Some(user_percent) = talk_time.user_percent() {
+                    status_bar::set(Section::TalkTime, Some(format!("you {}% / assistant {}%", user_percent, 100 - user_percent)));
+                }
+            }
+        }
+    }
+}
+
+/// Speaking time so far, the user's and the assistant's
+#[derive(Debug, Default)]
+struct TalkTime {
+    user_ms: u64,
+    speech_start_ms: Option<u64>,               // Into the input audio buffer
+    assistant: HashMap<String, u64>,            // Samples per item, truncations can shorten them later
+}
+
+impl TalkTime {
+    /// Takes an event, returning whether the times changed
+    fn handle_event(&mut self, event: &Value) -> bool {
+        match event["type"].as_str().unwrap_or_default() {
+            "input_audio_buffer.speech_started" => self.speech_start_ms = event["audio_start_ms"].as_u64(),
+            "input_audio_buffer.speech_stopped" => {
+                if let Some(start_ms) = self.speech_start_ms.take() {
+                    self.user_ms += event["audio_end_ms"].as_u64().unwrap_or_default().saturating_sub(start_ms);
+                    return true;
+                }
+            }
+            "response.audio.delta" => {
+                let bytes = base64_audio_bytes(event["delta"].as_str().unwrap_or_default());
+                let item_id = event["item_id"].as_str().unwrap_or_default().to_string();
+                *self.assistant.entry(item_id).or_default() += bytes as u64 / 2;
+                return true;
+            }
+            // Sent by the client when the user interrupted, the rest was never heard
+            "conversation.item.truncate" => {
+                if let Some(samples) = self.assistant.get_mut(event["item_id"].as_str().unwrap_or_default()) {
+                    let heard = event["audio_end_ms"].as_u64().unwrap_or_default() * SERVER_SAMPLE_RATE as u64 / 1000;
+                    *samples = (*samples).min(heard);
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn assistant_ms(&self) -> u64 {
+        self.assistant.values().sum::<u64>() * 1000 / SERVER_SAMPLE_RATE as u64
+    }
+
+    /// The user's share of the speaking time, rounded, None before anyone spoke
+    fn user_percent(&self) -> Option<u64> {
+        let total_ms = self.user_ms + self.assistant_ms();
+        (self.user_ms * 100 + total_ms / 2).checked_div(total_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_utils::base64_encode_audio;
+    use serde_json::json;
+
+    fn audio(item_id: &str, ms: usize) -> Value {
+        let samples = vec![0.1; SERVER_SAMPLE_RATE as usize * ms / 1000];
+        json!({"type": "response.audio.delta", "item_id": item_id, "delta": base64_encode_audio(&samples)})
+    }
+
+    fn speech(start_ms: u64, end_ms: u64) -> [Value; 2] {
+        [
+            json!({"type": "input_audio_buffer.speech_started", "audio_start_ms": start_ms}),
+            json!({"type": "input_audio_buffer.speech_stopped", "audio_end_ms": end_ms}),
+        ]
+    }
+
+    #[test]
+    fn splits_the_time_between_user_and_assistant() {
+        let mut talk_time = TalkTime::default();
+        assert_eq!(talk_time.user_percent(), None);
+
+        for event in speech(1000, 4000) {
+            talk_time.handle_event(&event);
+        }
+        assert_eq!(talk_time.user_percent(), Some(100));
+
+        // Many small deltas add up without losing the odd sample to rounding
+        for _ in 0..100 {
+            assert!(talk_time.handle_event(&audio("item_1", 10)));
+        }
+        assert_eq!(talk_time.assistant_ms(), 1000);
+        assert_eq!(talk_time.user_percent(), Some(75));
+    }
+
+    #[test]
+    fn deltas_are_counted_whatever_their_padding() {
+        let mut talk_time = TalkTime::default();
+        // 1, 2 and 3 samples: base64 with two, one and no padding characters
+        for samples in 1..=3 {
+            let delta = base64_encode_audio(&vec![0.1; samples]);
+            talk_time.handle_event(&json!({"type": "response.audio.delta", "item_id": "item_1", "delta": delta}));
+        }
+        assert_eq!(talk_time.assistant["item_1"], 6);
+    }
+
+    #[test]
+    fn interruptions_take_off_what_was_never_heard() {
+        let mut talk_time = TalkTime::default();
+        talk_time.handle_event(&audio("item_1", 3000));
+        talk_time.handle_event(&audio("item_2", 1000));
+        assert!(talk_time.handle_event(&json!({"type": "conversation.item.truncate", "item_id": "item_1", "audio_end_ms": 500})));
+        assert_eq!(talk_time.assistant_ms(), 1500);
+
+        // Truncating past the end, or an item without audio, changes nothing
+        talk_time.handle_event(&json!({"type": "conversation.item.truncate", "item_id": "item_2", "audio_end_ms": 5000}));
+        assert!(!talk_time.handle_event(&json!({"type": "conversation.item.truncate", "item_id": "item_3", "audio_end_ms": 0})));
+        assert_eq!(talk_time.assistant_ms(), 1500);
+
+        // Speech that stopped without starting isn't counted
+        assert!(!talk_time.handle_event(&json!({"type": "input_audio_buffer.speech_stopped", "audio_end_ms": 900})));
+        for event in speech(0, 500) {
+            talk_time.handle_event(&event);
+        }
+        assert_eq!(talk_time.user_percent(), Some(25));
+    }
+}
@@ -0,0 +1,29 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+use crate::tools::ToolCall;
+
+
+/// Tools subscriber: collects the function calls of each finished response and asks for them to be run
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    while let Some(event) = events.recv().await {
+        // Calls of cancelled or failed responses may be incomplete
+        if event["type"] != "response.done" || event["response"]["status"] != "completed" {
+            continue;
+        }
+
+        let calls = event["response"]["output"]
+            .as_array()
+            .map(|output| output.iter().filter_map(ToolCall::from_item).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if calls.is_empty() {
+            continue;
+        }
+
+        if command_sender.send(Command::Internal(InternalCommand::RunTools(calls))).await.is_err() {
+            eprintln!("Failed to request tool calls");
+        }
+    }
+}
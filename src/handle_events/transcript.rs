@@ -0,0 +1,302 @@
+use tokio::sync::mpsc;
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use crossterm::style::Stylize;
+use serde_json::Value;
+
+use super::chat::{ChatPrinter, TextStyle};
+use super::screen::Screen;
+use crate::conversation::{captioned_sentence, chapter_item, is_side_channel_response, translated_item, ConversationTracker};
+use crate::export::pretty_json;
+use crate::session_summary::{ErrorKind, ErrorTracker};
+use crate::text_layout::{display_width, wrap};
+
+// Space between the original and the translation
+const COLUMN_GAP: usize = 3;
+// Lines of function arguments or output printed, the rest is left to /inspect
+const COLLAPSED_LINES: usize = 3;
+
+
+/// Transcript subscriber: keeps the conversation model up to date and prints it as it streams in
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, conversation: Arc<Mutex<ConversationTracker>>, errors: Arc<Mutex<ErrorTracker>>, mut screen: Screen) {
+    let mut side_channel_responses = HashSet::new();   // Out-of-band responses, shown apart from the conversation
+    let mut translations = HashSet::new();             // Out-of-band translations, only shown once complete
+    let mut chapter_titles = HashSet::new();           // Out-of-band chapter titles, likewise
+    let mut captions = HashSet::new();                 // Out-of-band captions, only shown in their pane
+    let mut text_style = TextStyle::Raw;
+    let mut chat = ChatPrinter::new();
+
+    while let Some(event) = events.recv().await {
+        conversation.lock().unwrap().handle_event(&event);
+
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_text_style()
+            "local.text_style" => {
+                text_style = event["style"].as_str().unwrap_or_default().parse().unwrap_or(TextStyle::Raw);
+            },
+            // System messages steer the assistant, make them stand out in the transcript
+            "conversation.item.created" if event["item"]["role"] == "system" => {
+                let text = event["item"]["content"][0]["text"].as_str().unwrap_or_default();
+                let label = "[system]";
+
+                // Wrap under the label rather than letting the terminal break lines anywhere
+                let indent = " ".repeat(display_width(label) + 1);
+                let lines = wrap(text, screen.columns().saturating_sub(indent.len()).max(20));
+                screen.println(format!("\n{} {}", label.yellow().bold(), lines.join(&format!("\n{}", indent)).yellow()));
+            },
+            // Function calls and their outputs are set apart from what is said, folded to a few lines
+            "response.output_item.done" if event["item"]["type"] == "function_call" => {
+                let label = format!("⚙ {}", event["item"]["name"].as_str().unwrap_or_default());
+                print_collapsed(&mut screen, &label, event["item"]["arguments"].as_str().unwrap_or_default());
+            },
+            "conversation.item.created" if event["item"]["type"] == "function_call_output" => {
+                print_collapsed(&mut screen, "↳ output", event["item"]["output"].as_str().unwrap_or_default());
+            },
+            "response.created" if translated_item(&event["response"]).is_some() => {
+                translations.insert(event["response"]["id"].as_str().unwrap_or_default().to_string());
+            },
+            "response.done" if translations.remove(event["response"]["id"].as_str().unwrap_or_default()) => {
+                let item_id = translated_item(&event["response"]).unwrap_or_default();
+                let conversation = conversation.lock().unwrap();
+                if let Some(item) = conversation.items().iter().find(|item| item.id == item_id) {
+                    print_columns(&mut screen, item.text().trim(), item.translation.as_deref().unwrap_or_default());
+                }
+            },
+            // Raised locally by RealtimeClient::change_instructions()
+            "local.instructions" => {
+                screen.println(format!("\n{}", "── instructions changed ──".magenta()));
+            },
+            // Raised locally by RealtimeClient::set_sentiment()
+            "local.sentiment" => {
+                let label = event["sentiment"]["label"].as_str().unwrap_or_default();
+                let marker = format!("[{} {}: {}]", "●", event["role"].as_str().unwrap_or_default(), label);
+                let marker = match label {
+                    "positive" | "joy" | "love" | "happy" | "happiness" | "optimism" => marker.green(),
+                    "negative" | "anger" | "sadness" | "fear" | "disgust" | "annoyance" => marker.red(),
+                    "neutral" => marker.dim(),
+                    _ => marker.yellow(),
+                };
+                screen.println(format!("\n{}", marker));
+            },
+            // Raised locally by RealtimeClient::record_talk_over()
+            "local.talk_over" => {
+                let talk_over = &event["talk_over"];
+                let marker = format!(
+                    "[talked over for {:.1} s, {:.1} s into the answer{}]",
+                    talk_over["overlap_ms"].as_u64().unwrap_or_default() as f64 / 1000.0,
+                    talk_over["at_ms"].as_u64().unwrap_or_default() as f64 / 1000.0,
+                    if talk_over["interrupted"] == true { ", interrupted" } else { "" }
+                );
+                screen.println(format!("\n{}", if talk_over["interrupted"] == true { marker.yellow() } else { marker.dim() }));
+            },
+            "response.created" if chapter_item(&event["response"]).is_some() => {
+                chapter_titles.insert(event["response"]["id"].as_str().unwrap_or_default().to_string());
+            },
+            "response.done" if chapter_titles.remove(event["response"]["id"].as_str().unwrap_or_default()) => {
+                let item_id = chapter_item(&event["response"]).unwrap_or_default();
+                let conversation = conversation.lock().unwrap();
+                if let Some(title) = conversation.items().iter().find(|item| item.id == item_id).and_then(|item| item.chapter.as_deref()) {
+                    screen.println(format!("\n{}", format!("── chapter: {} ──", title).magenta()));
+                }
+            },
+            "response.created" if captioned_sentence(&event["response"]).is_some() => {
+                captions.insert(event["response"]["id"].as_str().unwrap_or_default().to_string());
+            },
+            "response.done" if captions.remove(event["response"]["id"].as_str().unwrap_or_default()) => {},
+            "response.text.delta" if translations.contains(event["response_id"].as_str().unwrap_or_default())
+                || chapter_titles.contains(event["response_id"].as_str().unwrap_or_default())
+                || captions.contains(event["response_id"].as_str().unwrap_or_default()) => {},
+            "response.created" if is_side_channel_response(&event["response"]) => {
+                side_channel_responses.insert(event["response"]["id"].as_str().unwrap_or_default().to_string());
+                screen.print(format!("\n{} ", "[side]".cyan().bold()));
+            },
+            "response.done" if side_channel_responses.remove(event["response"]["id"].as_str().unwrap_or_default()) => {
+                screen.println("");
+            },
+            "response.text.delta" if text_style == TextStyle::Chat
+                && !side_channel_responses.contains(event["response_id"].as_str().unwrap_or_default()) =>
+            {
+                chat.push(&mut screen, event["item_id"].as_str().unwrap_or_default(), event["delta"].as_str().unwrap_or_default());
+            },
+            "response.text.done" | "response.done" => chat.finish(&mut screen),
+            "response.text.delta" => {
+                // Handle text delta events (text-only responses)
+                let text = event["delta"].as_str().unwrap();
+
+                // Print the text, side channel answers stand out from the conversation
+                if side_channel_responses.contains(event["response_id"].as_str().unwrap_or_default()) {
+                    screen.print(text.cyan());
+                } else {
+                    screen.print(text);
+                }
+                let _ = screen.flush();
+            },
+            "response.audio_transcript.delta" => {
+                // Handle audio transcript delta events
+                let transcript = event["delta"].as_str().unwrap();
+
+                // Print the transcript
+                screen.print(transcript);
+                let _ = screen.flush();
+            },
+            "conversation.item.input_audio_transcription.failed" => {
+                // The model still heard the audio, only our transcript of it is missing
+                errors.lock().unwrap().count(ErrorKind::Transcription);
+                eprintln!(
+                    "\n{} item {}: {}",
+                    "[transcription failed]".red(),
+                    event["item_id"].as_str().unwrap_or_default(),
+                    event["error"]["message"].as_str().unwrap_or_default()
+                );
+                eprintln!("{}", event["error"]);
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Prints a message and its translation side by side, each wrapped to half the screen
+fn print_columns(screen: &mut Screen, original: &str, translation: &str) {
+    let width = (screen.columns().saturating_sub(COLUMN_GAP) / 2).max(20);
+    let (original, translation) = (wrap(original, width), wrap(translation, width));
+
+    screen.println("");
+    for row in 0..original.len().max(translation.len()) {
+        let left = original.get(row).map_or("", String::as_str);
+        let right = translation.get(row).map_or("", String::as_str);
+        let padding = " ".repeat(width.saturating_sub(display_width(left)) + COLUMN_GAP);
+        screen.println(format!("{}{}{}", left.dim(), padding, right.cyan()));
+    }
+}
+
+/// Prints a function call's arguments or output under a label, cut to a few lines
+fn print_collapsed(screen: &mut Screen, label: &str, body: &str) {
+    let columns = screen.columns();
+    let indent = " ".repeat(display_width(label) + 1);
+    let lines: Vec<String> = pretty_json(body).lines().flat_map(|line| wrap(line, columns.saturating_sub(indent.len()).max(20))).collect();
+
+    let shown = lines.iter().take(COLLAPSED_LINES).map(|line| line.as_str().dim().to_string()).collect::<Vec<_>>();
+    screen.println(format!("\n{} {}", label.blue().bold(), shown.join(&format!("\n{}", indent))));
+    if lines.len() > COLLAPSED_LINES {
+        screen.println(format!("{}{}", indent, format!("… {} more lines, see /inspect", lines.len() - COLLAPSED_LINES).dim().italic()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::screen::{assert_frame, Buffer};
+    use serde_json::json;
+
+    // Terminal widths the frames are drawn at
+    const WIDE: usize = 80;
+    const NARROW: usize = 40;
+
+    /// Draws the transcript of a sequence of events on a screen `columns` wide
+    async fn draw(events: &[Value], columns: usize) -> String {
+        let buffer = Buffer::default();
+        let (sender, receiver) = mpsc::channel(100);
+        let conversation = Arc::new(Mutex::new(ConversationTracker::default()));
+        let subscriber = tokio::spawn(run(receiver, conversation, Arc::default(), Screen::new(buffer.clone(), columns)));
+
+        for event in events {
+            sender.send(Arc::new(event.clone())).await.unwrap();
+        }
+        drop(sender);
+        subscriber.await.unwrap();
+        buffer.frame(columns)
+    }
+
+    /// Draws the events at each width and compares them with the golden frames named after the state
+    async fn assert_frames(state: &str, events: &[Value]) {
+        for columns in [WIDE, NARROW] {
+            assert_frame(&format!("{}_{}", state, columns), &draw(events, columns).await);
+        }
+    }
+
+    /// A text response streamed a word at a time
+    fn text_response(response_id: &str, item_id: &str, text: &str) -> Vec<Value> {
+        let mut events = vec![json!({"type": "response.created", "response": {"id": response_id, "status": "in_progress", "output": []}})];
+        events.extend(text.split_inclusive(' ').map(|delta| {
+            json!({"type": "response.text.delta", "response_id": response_id, "item_id": item_id, "delta": delta})
+        }));
+        events.push(json!({"type": "response.text.done", "response_id": response_id, "item_id": item_id, "text": text}));
+        events.push(json!({"type": "response.done", "response": {"id": response_id, "status": "completed", "output": []}}));
+        events
+    }
+
+    #[tokio::test]
+    async fn raw_text_turn() {
+        let events: Vec<Value> = include_str!("../../fixtures/events/text_turn.jsonl")
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_frames("raw_text_turn", &events).await;
+    }
+
+    #[tokio::test]
+    async fn chat_markdown() {
+        let mut events = vec![json!({"type": "local.text_style", "style": "chat"})];
+        events.extend(text_response(
+            "resp_1",
+            "item_asst_1",
+            "Paris is the **capital** of France, and with about two million people its largest city.\n\
+             ```rust\nfn main() {\n    println!(\"Bonjour\");\n}\n```\n\
+             Ask me about *anything* else.",
+        ));
+        events.extend(text_response("resp_2", "item_asst_2", "Anything else?"));
+        assert_frames("chat_markdown", &events).await;
+    }
+
+    #[tokio::test]
+    async fn function_call() {
+        let arguments = json!({"city": "Paris", "units": "metric", "days": 3, "include": ["wind", "humidity"]}).to_string();
+        let events = [
+            json!({"type": "response.output_item.done", "response_id": "resp_1", "item": {
+                "id": "item_call_1", "type": "function_call", "name": "get_weather", "call_id": "call_1", "arguments": arguments,
+            }}),
+            json!({"type": "conversation.item.created", "item": {
+                "id": "item_output_1", "type": "function_call_output", "call_id": "call_1", "output": "{\"temperature\": 18, \"sky\": \"clear\"}",
+            }}),
+        ];
+        assert_frames("function_call", &events).await;
+    }
+
+    #[tokio::test]
+    async fn markers() {
+        let events = [
+            json!({"type": "conversation.item.created", "item": {
+                "id": "item_system_1", "type": "message", "role": "system",
+                "content": [{"type": "input_text", "text": "The user is driving, keep the answers short and don't read out lists or links."}],
+            }}),
+            json!({"type": "local.instructions"}),
+            json!({"type": "local.sentiment", "role": "user", "sentiment": {"label": "positive"}}),
+            json!({"type": "local.talk_over", "talk_over": {"overlap_ms": 1200, "at_ms": 3400, "interrupted": true}}),
+        ];
+        assert_frames("markers", &events).await;
+    }
+
+    #[tokio::test]
+    async fn side_channel() {
+        let mut events = text_response("resp_side_1", "item_side_1", "The meeting is at three, in the large room.");
+        events[0]["response"]["metadata"] = json!({"hotline": "side_channel"});
+        assert_frames("side_channel", &events).await;
+    }
+
+    #[tokio::test]
+    async fn translation() {
+        let events = [
+            json!({"type": "conversation.item.created", "item": {
+                "id": "item_user_1", "type": "message", "role": "user", "status": "completed",
+                "content": [{"type": "input_text", "text": "Where is the nearest train station, and when does the last train leave?"}],
+            }}),
+            json!({"type": "response.created", "response": {"id": "resp_1", "metadata": {"hotline": "side_channel translation_of item_user_1"}}}),
+            json!({"type": "response.done", "response": {"id": "resp_1", "status": "completed", "metadata": {"hotline": "side_channel translation_of item_user_1"}, "output": [
+                {"type": "message", "content": [{"type": "text", "text": "Où est la gare la plus proche, et quand part le dernier train ?"}]},
+            ]}}),
+        ];
+        assert_frames("translation", &events).await;
+    }
+}
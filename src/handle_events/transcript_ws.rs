@@ -0,0 +1,166 @@
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::Message;
+use futures::SinkExt;
+use std::sync::{Arc, Mutex};
+use serde_json::Value;
+
+use crate::conversation::ConversationTracker;
+
+// Patches a slow viewer may fall behind by before it is resynchronized with a full snapshot
+const PATCH_BACKLOG: usize = 256;
+
+/// Transcript WebSocket subscriber: serves the live conversation to external viewers
+///
+/// Viewers get a document `{"items": [...]}` as JSON Patch (RFC 6902) messages, each an array
+/// of operations. The first message replaces `/items` as a whole, later ones add or replace
+/// single items as they stream in. It does nothing at all until --transcript-ws has it serving,
+/// which is before the call connects, so there's no conversation to miss.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>) {
+    // A model of its own, so patches are computed from exactly the events seen so far
+    let mut conversation = ConversationTracker::default();
+    let items = Arc::new(Mutex::new(Vec::new()));   // Last published items, for new viewers
+    let (patches, _) = broadcast::channel::<String>(PATCH_BACKLOG);
+    let mut servers = JoinSet::new();                // Stopped along with the subscriber
+    let mut serving = false;
+
+    while let Some(event) = events.recv().await {
+        // Raised locally by RealtimeClient::serve_transcript()
+        if event["type"] == "local.transcript_ws" {
+            let address = event["address"].as_str().unwrap_or_default();
+            match TcpListener::bind(address).await {
+                Ok(listener) => {
+                    println!("Serving the transcript on ws://{}", address);
+                    servers.spawn(accept_viewers(listener, items.clone(), patches.clone()));
+                    serving = true;
+                }
+                Err(e) => eprintln!("Failed to serve the transcript on {}: {}", address, e),
+            }
+            continue;
+        }
+        if !serving {
+            continue;
+        }
+
+        conversation.handle_event(&event);
+        let current: Vec<Value> = conversation.items().iter().map(|item| serde_json::to_value(item).unwrap()).collect();
+
+        let mut published = items.lock().unwrap();
+        let patch = items_patch(&published, &current);
+        if !patch.is_empty() {
+            *published = current;
+            // No viewers is not an error
+            let _ = patches.send(Value::Array(patch).to_string());
+        }
+    }
+}
+
+/// Operations turning the `old` items into the `new` ones
+fn items_patch(old: &[Value], new: &[Value]) -> Vec<Value> {
+    // Items are only ever appended or updated in place, anything else (deletes, a cleared
+    // conversation) is sent as a whole
+    let appended_or_updated = old.len() <= new.len() && old.iter().zip(new).all(|(old, new)| old["id"] == new["id"]);
+    if !appended_or_updated {
+        return vec![serde_json::json!({"op": "replace", "path": "/items", "value": new})];
+    }
+
+    let mut patch = Vec::new();
+    for (index, item) in new.iter().enumerate() {
+        match old.get(index) {
+            Some(previous) if previous == item => {}
+            Some(_) => patch.push(serde_json::json!({"op": "replace", "path": format!("/items/{}", index), "value": item})),
+            None => patch.push(serde_json::json!({"op": "add", "path": "/items/-", "value": item})),
+        }
+    }
+    patch
+}
+
+/// Serves viewers until aborted, which disconnects them all
+async fn accept_viewers(listener: TcpListener, items: Arc<Mutex<Vec<Value>>>, patches: broadcast::Sender<String>) {
+    let mut viewers = JoinSet::new();
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                // Forget viewers that left
+                while viewers.try_join_next().is_some() {}
+                viewers.spawn(serve_viewer(stream, items.clone(), patches.clone()));
+            }
+            Err(e) => eprintln!("Failed to accept a transcript viewer: {}", e),
+        }
+    }
+}
+
+/// Sends a viewer the current items, then every patch until it disconnects
+async fn serve_viewer(stream: TcpStream, items: Arc<Mutex<Vec<Value>>>, patches: broadcast::Sender<String>) {
+    let Ok(mut ws_stream) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+
+    // Subscribed under the lock, so no patch falls between the snapshot and the first update
+    let snapshot = |items: &Mutex<Vec<Value>>| {
+        let items = items.lock().unwrap();
+        (serde_json::json!([{"op": "replace", "path": "/items", "value": *items}]).to_string(), patches.subscribe())
+    };
+    let (mut message, mut receiver) = snapshot(&items);
+
+    loop {
+        if ws_stream.send(Message::Text(message)).await.is_err() {
+            return;
+        }
+
+        message = match receiver.recv().await {
+            Ok(patch) => patch,
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                let (message, resubscribed) = snapshot(&items);
+                receiver = resubscribed;
+                message
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(id: &str, text: &str) -> Value {
+        json!({"id": id, "text": text})
+    }
+
+    #[test]
+    fn items_patch_adds_new_items_at_the_end() {
+        let old = [item("a", "hi")];
+        let new = [item("a", "hi"), item("b", "hello"), item("c", "")];
+        assert_eq!(items_patch(&old, &new), vec![
+            json!({"op": "add", "path": "/items/-", "value": item("b", "hello")}),
+            json!({"op": "add", "path": "/items/-", "value": item("c", "")}),
+        ]);
+        assert_eq!(items_patch(&[], &old), vec![json!({"op": "add", "path": "/items/-", "value": item("a", "hi")})]);
+    }
+
+    #[test]
+    fn items_patch_replaces_changed_items_in_place() {
+        let old = [item("a", "hi"), item("b", "hel")];
+        let new = [item("a", "hi"), item("b", "hello")];
+        assert_eq!(items_patch(&old, &new), vec![json!({"op": "replace", "path": "/items/1", "value": item("b", "hello")})]);
+
+        // Nothing changed, nothing to send
+        assert!(items_patch(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn items_patch_sends_anything_else_whole() {
+        let old = [item("a", "hi"), item("b", "hello")];
+        // Deleted
+        let new = [item("b", "hello")];
+        assert_eq!(items_patch(&old, &new), vec![json!({"op": "replace", "path": "/items", "value": new})]);
+        // Cleared
+        assert_eq!(items_patch(&old, &[]), vec![json!({"op": "replace", "path": "/items", "value": []})]);
+        // Inserted before the end
+        let new = [item("a", "hi"), item("x", ""), item("b", "hello")];
+        assert_eq!(items_patch(&old, &new), vec![json!({"op": "replace", "path": "/items", "value": new})]);
+    }
+}
@@ -0,0 +1,46 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+use crate::conversation::is_side_channel_response;
+
+
+/// Translation subscriber: asks for a translation of each finished assistant message, for language tutoring
+///
+/// Off until RealtimeClient::set_translation() picks a language. Translations come back as
+/// out-of-band responses, which the transcript subscriber shows in a column next to the original.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut enabled = false;
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::set_translation()
+            "local.translation" => enabled = event["language"].is_string(),
+            "response.done" if enabled && !is_side_channel_response(&event["response"]) => {
+                for output in event["response"]["output"].as_array().into_iter().flatten() {
+                    if output["type"] != "message" || output["role"] != "assistant" {
+                        continue;
+                    }
+
+                    let text = output["content"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|part| part["text"].as_str().or(part["transcript"].as_str()))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    if text.trim().is_empty() {
+                        continue;
+                    }
+
+                    let item_id = output["id"].as_str().unwrap_or_default().to_string();
+                    if command_sender.send(Command::Internal(InternalCommand::TranslateItem { item_id, text })).await.is_err() {
+                        eprintln!("Failed to request a translation");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
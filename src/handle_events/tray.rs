@@ -0,0 +1,319 @@
+use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use serde_json::Value;
+use zbus::object_server::SignalEmitter;
+use serde::Serialize;
+use zbus::zvariant::{self, ObjectPath, OwnedValue, Type};
+use zbus::{interface, Connection};
+
+use crate::commands::Command;
+use crate::conversation::is_side_channel_response;
+
+// Where the item and its menu live on the bus, as tray hosts expect them
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+
+// Ids of the menu entries, 0 being the menu itself
+const MUTE_ENTRY: i32 = 1;
+const HANG_UP_ENTRY: i32 = 2;
+
+// Icon name, icon pixmaps as (width, height, ARGB data), title and text
+type ToolTip = (String, Vec<(i32, i32, Vec<u8>)>, String, String);
+
+/// What the tray shows of the call
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CallState {
+    connected: bool,
+    muted: bool,
+    speaking: bool,     // The assistant is answering out loud
+}
+
+impl CallState {
+    fn describe(&self) -> &'static str {
+        match self {
+            Self { connected: false, .. } => "disconnected",
+            Self { speaking: true, .. } => "assistant speaking",
+            Self { muted: true, .. } => "muted",
+            _ => "listening",
+        }
+    }
+
+    /// Named icons of the freedesktop icon theme
+    fn icon(&self) -> &'static str {
+        match self {
+            Self { connected: false, .. } => "network-offline",
+            Self { speaking: true, .. } => "audio-volume-high",
+            Self { muted: true, .. } => "microphone-sensitivity-muted",
+            _ => "audio-input-microphone",
+        }
+    }
+}
+
+/// Tray subscriber: shows the call in the system tray (StatusNotifierItem) with mute and hang up at hand
+///
+/// Nothing is put on the bus until the tray is enabled. The actions go through the command channel
+/// like typed commands do, so the tray only ever shows what the call actually did.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let state = Arc::new(Mutex::new(CallState::default()));
+    let mut connection: Option<Connection> = None;
+
+    while let Some(event) = events.recv().await {
+        let before = *state.lock().unwrap();
+        {
+            let mut state = state.lock().unwrap();
+            match event["type"].as_str().unwrap_or_default() {
+                "local.connected" => state.connected = true,
+                "local.disconnected" => *state = CallState { muted: state.muted, ..CallState::default() },
+                // Raised locally by RealtimeClient::set_muted()
+                "local.mute" => state.muted = event["muted"] == true,
+                "response.audio.delta" => state.speaking = true,
+                "response.done" if !is_side_channel_response(&event["response"]) => state.speaking = false,
+                "local.interrupt" => state.speaking = false,
+                _ => {}
+            }
+        }
+
+        // Raised locally by RealtimeClient::enable_tray()
+        if event["type"] == "local.tray" && connection.is_none() {
+            match serve(state.clone(), command_sender.clone()).await {
+                Ok(serving) => connection = Some(serving),
+                Err(e) => eprintln!("\n[tray unavailable: {}]", e),
+            }
+        }
+
+        let after = *state.lock().unwrap();
+        if let Some(connection) = connection.as_ref().filter(|_| after != before) {
+            if let Err(e) = announce(connection, before.muted != after.muted).await {
+                tracing::debug!(error = %e, "tray update failed");
+            }
+        }
+    }
+}
+
+/// Puts the item and its menu on the session bus and registers it with the tray host
+async fn serve(state: Arc<Mutex<CallState>>, command_sender: mpsc::Sender<Command>) -> zbus::Result<Connection> {
+    let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+    let connection = zbus::connection::Builder::session()?
+        .name(name.as_str())?
+        .serve_at(ITEM_PATH, Item { state: state.clone(), command_sender: command_sender.clone() })?
+        .serve_at(MENU_PATH, Menu { state, command_sender, revision: 1 })?
+        .build()
+        .await?;
+
+    // Without a host there's nothing to show the item, e.g. GNOME without the AppIndicator extension
+    let registered = connection
+        .call_method(
+            Some("org.kde.StatusNotifierWatcher"),
+            "/StatusNotifierWatcher",
+            Some("org.kde.StatusNotifierWatcher"),
+            "RegisterStatusNotifierItem",
+            &(name.as_str(),),
+        )
+        .await;
+    if let Err(e) = registered {
+        return Err(zbus::Error::Failure(format!("no system tray to show it in ({})", e)));
+    }
+    Ok(connection)
+}
+
+/// Tells the tray host the state changed, and the menu too if the mute entry now reads otherwise
+async fn announce(connection: &Connection, menu_changed: bool) -> zbus::Result<()> {
+    let item = connection.object_server().interface::<_, Item>(ITEM_PATH).await?;
+    let emitter = item.signal_emitter();
+    Item::new_icon(emitter).await?;
+    Item::new_title(emitter).await?;
+    Item::new_tool_tip(emitter).await?;
+
+    if menu_changed {
+        let menu = connection.object_server().interface::<_, Menu>(MENU_PATH).await?;
+        let revision = {
+            let mut menu = menu.get_mut().await;
+            menu.revision += 1;
+            menu.revision
+        };
+        Menu::layout_updated(menu.signal_emitter(), revision, 0).await?;
+    }
+    Ok(())
+}
+
+/// Toggles the microphone, as /mute and /unmute do
+async fn toggle_mute(state: &Mutex<CallState>, command_sender: &mpsc::Sender<Command>) {
+    let muted = state.lock().unwrap().muted;
+    let _ = command_sender.send(if muted { Command::Unmute } else { Command::Mute }).await;
+}
+
+/// The tray item, org.kde.StatusNotifierItem
+struct Item {
+    state: Arc<Mutex<CallState>>,
+    command_sender: mpsc::Sender<Command>,
+}
+
+#[interface(name = "org.kde.StatusNotifierItem")]
+impl Item {
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "Communications"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        "hotline"
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        format!("hotline: {}", self.state.lock().unwrap().describe())
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "Active"
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> &str {
+        self.state.lock().unwrap().icon()
+    }
+
+    #[zbus(property)]
+    fn tool_tip(&self) -> ToolTip {
+        let state = self.state.lock().unwrap();
+        (state.icon().to_string(), Vec::new(), "hotline".to_string(), state.describe().to_string())
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> ObjectPath<'_> {
+        ObjectPath::from_static_str_unchecked(MENU_PATH)
+    }
+
+    /// A click toggles the microphone
+    async fn activate(&self, _x: i32, _y: i32) {
+        toggle_mute(&self.state, &self.command_sender).await;
+    }
+
+    async fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    async fn context_menu(&self, _x: i32, _y: i32) {}
+
+    async fn scroll(&self, _delta: i32, _orientation: &str) {}
+
+    #[zbus(signal)]
+    async fn new_title(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_icon(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_tool_tip(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// A menu entry as dbusmenu lays it out, with each child in a variant
+#[derive(Serialize, Type, zvariant::Value)]
+struct Entry {
+    id: i32,
+    properties: HashMap<String, OwnedValue>,
+    children: Vec<zvariant::Value<'static>>,
+}
+
+/// The item's menu, com.canonical.dbusmenu: mute or unmute, and hang up
+struct Menu {
+    state: Arc<Mutex<CallState>>,
+    command_sender: mpsc::Sender<Command>,
+    revision: u32,      // Of the layout, bumped when an entry changes
+}
+
+impl Menu {
+    fn properties(&self, id: i32) -> HashMap<String, OwnedValue> {
+        let mut properties = HashMap::new();
+        let mut set = |name: &str, value: &str| {
+            properties.insert(name.to_string(), OwnedValue::try_from(zvariant::Value::from(value)).unwrap());
+        };
+        match id {
+            MUTE_ENTRY => set("label", if self.state.lock().unwrap().muted { "Unmute" } else { "Mute" }),
+            HANG_UP_ENTRY => set("label", "Hang up"),
+            _ => set("children-display", "submenu"),
+        }
+        properties
+    }
+
+    fn layout(&self, id: i32) -> Entry {
+        let children = match id {
+            0 => [MUTE_ENTRY, HANG_UP_ENTRY].into_iter().map(|child| self.layout(child).into()).collect(),
+            _ => Vec::new(),
+        };
+        Entry { id, properties: self.properties(id), children }
+    }
+}
+
+#[interface(name = "com.canonical.dbusmenu")]
+impl Menu {
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The whole menu whatever is asked for, it's two entries
+    fn get_layout(&self, _parent_id: i32, _recursion_depth: i32, _property_names: Vec<String>) -> (u32, Entry) {
+        (self.revision, self.layout(0))
+    }
+
+    fn get_group_properties(&self, ids: Vec<i32>, _property_names: Vec<String>) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        ids.into_iter().map(|id| (id, self.properties(id))).collect()
+    }
+
+    fn get_property(&self, id: i32, name: &str) -> zbus::fdo::Result<OwnedValue> {
+        self.properties(id).remove(name).ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("no property {} on entry {}", name, id)))
+    }
+
+    async fn event(&self, id: i32, event_id: &str, _data: OwnedValue, _timestamp: u32) {
+        if event_id != "clicked" {
+            return;
+        }
+        match id {
+            MUTE_ENTRY => toggle_mute(&self.state, &self.command_sender).await,
+            HANG_UP_ENTRY => {
+                let _ = self.command_sender.send(Command::Quit).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        for (id, event_id, data, timestamp) in events {
+            self.event(id, &event_id, data, timestamp).await;
+        }
+        Vec::new()
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(signal)]
+    async fn layout_updated(emitter: &SignalEmitter<'_>, revision: u32, parent: i32) -> zbus::Result<()>;
+}
@@ -0,0 +1,57 @@
+use tokio::sync::mpsc;
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::commands::{Command, InternalCommand};
+
+/// Turn response subscriber: asks for the response to a spoken turn the server was told not to respond to
+///
+/// A one-off modalities override (e.g. `/text`) goes with the response.create of the user's next
+/// turn, so RealtimeClient::set_next_response_modalities() has the server hold off its own
+/// responses until then. The server still detects and commits the turn, which is picked up here.
+pub async fn run(mut events: mpsc::Receiver<Arc<Value>>, command_sender: mpsc::Sender<Command>) {
+    let mut held = false;
+
+    while let Some(event) = events.recv().await {
+        match event["type"].as_str().unwrap_or_default() {
+            // Raised locally by RealtimeClient::hold_server_responses()
+            "local.hold_responses" => held = event["held"].as_bool().unwrap_or_default(),
+            "input_audio_buffer.committed" if held => {
+                held = false;
+                if command_sender.send(Command::Internal(InternalCommand::RespondToTurn)).await.is_err() {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn responds_to_the_turn_committed_while_held_only() {
+        let (event_sender, events) = mpsc::channel(16);
+        let (command_sender, mut commands) = mpsc::channel(16);
+        let subscriber = tokio::spawn(run(events, command_sender));
+
+        let committed = || Arc::new(json!({"type": "input_audio_buffer.committed"}));
+        // The server responds itself
+        event_sender.send(committed()).await.unwrap();
+        event_sender.send(Arc::new(json!({"type": "local.hold_responses", "held": true}))).await.unwrap();
+        event_sender.send(committed()).await.unwrap();
+        event_sender.send(committed()).await.unwrap();
+        // Let go of by a typed message before the user spoke
+        event_sender.send(Arc::new(json!({"type": "local.hold_responses", "held": true}))).await.unwrap();
+        event_sender.send(Arc::new(json!({"type": "local.hold_responses", "held": false}))).await.unwrap();
+        event_sender.send(committed()).await.unwrap();
+        drop(event_sender);
+        subscriber.await.unwrap();
+
+        assert_eq!(commands.recv().await, Some(Command::Internal(InternalCommand::RespondToTurn)));
+        assert_eq!(commands.recv().await, None);
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Only the most recent calls are kept
+const MAX_CALLS: usize = 50;
+
+/// Environment variable overriding where the call history is kept
+pub const HISTORY_ENV: &str = "HOTLINE_HISTORY";
+
+/// A finished call, with what it takes to dial it again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallRecord {
+    pub started_at: u64,                // Unix time
+    pub duration_secs: u64,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<PathBuf>,
+    pub directory: PathBuf,             // Where the call was made, relative paths in the arguments resolve from here
+    pub arguments: Vec<String>,         // Options given to `hotline dial`
+}
+
+impl CallRecord {
+    pub fn new(started: SystemTime, model: &str, purpose: Option<&str>, transcript: Option<PathBuf>, arguments: Vec<String>) -> Self {
+        Self {
+            started_at: started.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            duration_secs: started.elapsed().unwrap_or_default().as_secs(),
+            model: model.to_string(),
+            purpose: purpose.map(str::to_string),
+            transcript: transcript.map(|path| std::fs::canonicalize(&path).unwrap_or(path)),
+            directory: std::env::current_dir().unwrap_or_default(),
+            arguments,
+        }
+    }
+}
+
+/// The history file, `$HOTLINE_HISTORY` or hotline/history.json in the user's data directory
+fn history_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(path) = std::env::var_os(HISTORY_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .ok_or_else(|| format!("Cannot tell where to keep the call history, set {}", HISTORY_ENV))?;
+    Ok(data_dir.join("hotline").join("history.json"))
+}
+
+/// Past calls, oldest first
+pub fn load() -> Result<Vec<CallRecord>, Box<dyn std::error::Error>> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let data = std::fs::read(&path)?;
+    Ok(serde_json::from_slice(&data).map_err(|e| format!("Failed to read call history {}: {}", path.display(), e))?)
+}
+
+/// Adds a call to the history, forgetting the oldest beyond MAX_CALLS
+pub fn record(call: CallRecord) -> Result<(), Box<dyn std::error::Error>> {
+    let mut calls = load()?;
+    calls.push(call);
+    calls.drain(..calls.len().saturating_sub(MAX_CALLS));
+
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(&calls)?)?;
+    Ok(())
+}
+
+/// The call numbered `number` in `hotline history`, 1 being the latest
+pub fn get(number: usize) -> Result<CallRecord, Box<dyn std::error::Error>> {
+    let calls = load()?;
+    number
+        .checked_sub(1)
+        .and_then(|index| calls.iter().rev().nth(index))
+        .cloned()
+        .ok_or_else(|| format!("No call #{} in the history, there are {}", number, calls.len()).into())
+}
+
+/// Prints the recent calls, latest first
+pub fn list() -> Result<(), Box<dyn std::error::Error>> {
+    let calls = load()?;
+    if calls.is_empty() {
+        println!("No calls yet");
+        return Ok(());
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    for (number, call) in calls.iter().rev().enumerate() {
+        println!(
+            "{:>3}  {:<10} {:>5}  {}{}",
+            number + 1,
+            ago(now.saturating_sub(call.started_at)),
+            format!("{}:{:02}", call.duration_secs / 60, call.duration_secs % 60),
+            call.model,
+            call.purpose.as_deref().map(|purpose| format!("  \"{}\"", purpose)).unwrap_or_default(),
+        );
+        if let Some(transcript) = &call.transcript {
+            println!("     transcript: {}", transcript.display());
+        }
+    }
+    println!("\nhotline redial <n> starts a new call with the same options");
+
+    Ok(())
+}
+
+/// Rough age, e.g. "3h ago"
+fn ago(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
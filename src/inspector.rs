@@ -0,0 +1,293 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::Stylize;
+use crossterm::terminal;
+use serde_json::Value;
+use std::sync::Mutex;
+
+use crate::conversation::{ConversationItem, ConversationItemRole, ConversationTracker, ItemHistory, ItemKind};
+use crate::export::pretty_json;
+use crate::full_screen;
+use crate::markdown;
+use crate::text_layout::{truncate, wrap};
+
+// Lines of function arguments or output shown until expanded
+const COLLAPSED_LINES: usize = 6;
+
+/// Browses the transcript on the alternate screen until the user leaves
+///
+/// Up/down select an item (pinned ones are starred), enter opens its details (text, timing,
+/// token usage, status changes and raw events), escape goes back and `q` returns to the call.
+/// In the details of a function call or output, space expands or collapses its body.
+pub fn inspect(conversation: &Mutex<ConversationTracker>) -> std::io::Result<()> {
+    full_screen::show(|out| run(conversation, out))
+}
+
+fn run(conversation: &Mutex<ConversationTracker>, out: &mut full_screen::Terminal) -> std::io::Result<()> {
+    let mut selected = usize::MAX;     // Clamped to the last item on the first draw
+    let mut detail_scroll: Option<usize> = None;
+    let mut expanded = false;          // Function arguments or output shown in full
+
+    loop {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, (height as usize).max(2));
+
+        // Snapshot so the call isn't held up while drawing
+        let (items, history) = {
+            let conversation = conversation.lock().unwrap();
+            let items = conversation.items().to_vec();
+            selected = selected.min(items.len().saturating_sub(1));
+            let history = items.get(selected).and_then(|item| conversation.history(&item.id)).cloned();
+            (items, history)
+        };
+
+        let lines = match (detail_scroll, items.get(selected)) {
+            (Some(scroll), Some(item)) => {
+                let lines = detail_lines(item, history.as_ref(), call_cost(&items), width, expanded);
+                let scroll = scroll.min(lines.len().saturating_sub(height.saturating_sub(1)));
+                detail_scroll = Some(scroll);
+                let mut view = vec![truncate("Item details: ↑/↓ scroll, space expand/collapse, esc back, q return to the call", width).dim().to_string()];
+                view.extend(lines.into_iter().skip(scroll).take(height.saturating_sub(1)));
+                view
+            }
+            _ => {
+                detail_scroll = None;
+                list_lines(&items, selected, width, height)
+            }
+        };
+
+        full_screen::draw(out, lines)?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match (key.code, detail_scroll) {
+            (KeyCode::Char('q'), _) | (KeyCode::Esc, None) => return Ok(()),
+            (KeyCode::Esc | KeyCode::Backspace | KeyCode::Left, Some(_)) => {
+                detail_scroll = None;
+                expanded = false;
+            }
+            (KeyCode::Char(' '), Some(_)) => expanded = !expanded,
+            (KeyCode::Up, Some(scroll)) => detail_scroll = Some(scroll.saturating_sub(1)),
+            (KeyCode::Down, Some(scroll)) => detail_scroll = Some(scroll + 1),
+            (KeyCode::PageUp, Some(scroll)) => detail_scroll = Some(scroll.saturating_sub(height)),
+            (KeyCode::PageDown, Some(scroll)) => detail_scroll = Some(scroll + height),
+            (KeyCode::Up, None) => selected = selected.saturating_sub(1),
+            (KeyCode::Down, None) => selected += 1,
+            (KeyCode::Enter | KeyCode::Right, None) if !items.is_empty() => detail_scroll = Some(0),
+            _ => {}
+        }
+    }
+}
+
+/// One line per item, scrolled to keep the selection in view
+fn list_lines(items: &[ConversationItem], selected: usize, width: usize, height: usize) -> Vec<String> {
+    let mut lines = vec![truncate("Transcript: ↑/↓ select, enter details, q return to the call", width).dim().to_string()];
+    if items.is_empty() {
+        lines.push("(no items yet)".to_string());
+        return lines;
+    }
+
+    let rows = height.saturating_sub(1).max(1);
+    let first = (selected + 1).saturating_sub(rows);
+    let max_cost = items.iter().filter_map(|item| Some(item.usage.as_ref()?.cost_usd)).fold(0.0, f64::max);
+    for (index, item) in items.iter().enumerate().skip(first).take(rows) {
+        let line = truncate(
+            &format!(
+                "{:>3}{}{:?} [{:?}] {}",
+                index + 1,
+                if item.pinned { "*" } else { " " },
+                item.role,
+                item.status,
+                match &item.kind {
+                    ItemKind::Message => item.text().trim().replace('\n', " "),
+                    ItemKind::FunctionCall { name, .. } => format!("⚙ {}({})", name, item.text().trim().replace('\n', " ")),
+                    ItemKind::FunctionCallOutput { .. } => format!("↳ {}", item.text().trim().replace('\n', " ")),
+                }
+            ),
+            width.saturating_sub(10),
+        );
+        // The turns that cost the most stand out, like a heatmap
+        let cost = match &item.usage {
+            Some(usage) => {
+                let cost = format!("{:>7}", format!("${:.3}", usage.cost_usd));
+                match usage.cost_usd / max_cost.max(f64::EPSILON) {
+                    share if share >= 0.5 => cost.red().to_string(),
+                    share if share >= 0.2 => cost.yellow().to_string(),
+                    _ => cost.dim().to_string(),
+                }
+            }
+            None => " ".repeat(7),
+        };
+        if index == selected {
+            lines.push(format!("{} {}", cost, format!("> {}", line).reverse()));
+        } else {
+            lines.push(format!("{}   {}", cost, line));
+        }
+    }
+
+    lines
+}
+
+/// Estimated cost of all the turns so far
+fn call_cost(items: &[ConversationItem]) -> f64 {
+    items.iter().filter_map(|item| Some(item.usage.as_ref()?.cost_usd)).sum()
+}
+
+/// Everything known about an item, wrapped to the terminal width
+fn detail_lines(item: &ConversationItem, history: Option<&ItemHistory>, call_cost: f64, width: usize, expanded: bool) -> Vec<String> {
+    let mut lines = vec![
+        format!("{} {}", "Item".bold(), item.id),
+        format!(
+            "Role: {:?}, status: {:?}{}{}",
+            item.role,
+            item.status,
+            if item.truncated { ", truncated" } else { "" },
+            if item.pinned { ", pinned" } else { "" }
+        ),
+    ];
+
+    if let Some(chapter) = &item.chapter {
+        lines.push(format!("Starts chapter: {}", chapter));
+    }
+
+    if let Some(sentiment) = &item.sentiment {
+        lines.push(format!("Sentiment: {} ({:.0}%)", sentiment.label, sentiment.score * 100.0));
+    }
+
+    if let Some(usage) = &item.usage {
+        lines.push(format!(
+            "Turn cost: ${:.4} ({} tokens in, {} out), {:.0}% of the call so far",
+            usage.cost_usd,
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cost_usd / call_cost.max(f64::EPSILON) * 100.0
+        ));
+    }
+
+    for talk_over in &item.talk_overs {
+        lines.push(format!(
+            "Talked over {:.1}s in, for {:.1}s{}",
+            talk_over.at_ms as f64 / 1000.0,
+            talk_over.overlap_ms as f64 / 1000.0,
+            if talk_over.interrupted { ", interrupted" } else { "" }
+        ));
+    }
+
+    if let Some(history) = history {
+        lines.push(format!("Created {:.1}s into the call", history.created.as_secs_f64()));
+        for (after, status) in &history.statuses {
+            lines.push(format!("  +{:.2}s {}", after.as_secs_f64(), status));
+        }
+
+        match &history.usage {
+            Some(usage) => lines.push(format!(
+                "Tokens (whole response): {} in ({} text, {} audio), {} out ({} text, {} audio)",
+                usage["input_tokens"], usage["input_token_details"]["text_tokens"], usage["input_token_details"]["audio_tokens"],
+                usage["output_tokens"], usage["output_token_details"]["text_tokens"], usage["output_token_details"]["audio_tokens"],
+            )),
+            None => lines.push("Tokens: not reported for this item".to_string()),
+        }
+    }
+
+    lines.push(String::new());
+    match &item.kind {
+        ItemKind::Message => {
+            lines.push("Text".bold().to_string());
+            for part in &item.content {
+                lines.push(format!("[{:?}]", part.content_type).dim().to_string());
+                // Models answer in markdown, users rarely write it
+                if item.role == ConversationItemRole::Assistant {
+                    lines.extend(markdown::render(&part.text, width));
+                } else {
+                    lines.extend(wrap(&part.text, width));
+                }
+            }
+        }
+        ItemKind::FunctionCall { name, call_id } => {
+            lines.push(format!("Arguments of {}", name).bold().to_string());
+            lines.extend(body_lines(&item.text(), call_id, width, expanded));
+        }
+        ItemKind::FunctionCallOutput { call_id } => {
+            lines.push("Output".bold().to_string());
+            lines.extend(body_lines(&item.text(), call_id, width, expanded));
+        }
+    }
+
+    let events: &[Value] = history.map(|history| history.events.as_slice()).unwrap_or_default();
+    let dropped = history.map_or(0, |history| history.dropped_events);
+    lines.push(String::new());
+    match dropped {
+        0 => lines.push(format!("Events ({})", events.len()).bold().to_string()),
+        _ => lines.push(format!("Events ({}, the latest; {} earlier ones not kept)", events.len(), dropped).bold().to_string()),
+    }
+    for event in events {
+        let json = serde_json::to_string_pretty(event).unwrap_or_default();
+        lines.extend(json.lines().flat_map(|line| wrap(line, width)));
+    }
+
+    lines
+}
+
+/// Function arguments or output, pretty-printed if JSON and cut short unless expanded
+fn body_lines(text: &str, call_id: &str, width: usize, expanded: bool) -> Vec<String> {
+    let body: Vec<String> = pretty_json(text).lines().flat_map(|line| wrap(line, width)).collect();
+    let shown = if expanded { body.len() } else { COLLAPSED_LINES };
+    let hidden = body.len().saturating_sub(shown);
+
+    let mut lines = vec![format!("[call {}]", call_id).dim().to_string()];
+    lines.extend(body.into_iter().take(shown).map(|line| line.cyan().to_string()));
+    if hidden > 0 {
+        lines.push(format!("… {} more lines, space to expand", hidden).dim().to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(arguments: &str) -> ConversationItem {
+        ConversationItem::new(&json!({"id": "item_1", "type": "function_call", "name": "weather", "call_id": "call_1", "arguments": arguments}))
+    }
+
+    #[test]
+    fn function_bodies_fold_until_expanded() {
+        // Pretty-printed, one line per field
+        let arguments = serde_json::to_string(&json!({"a": 1, "b": 2, "c": 3, "d": 4, "e": 5, "f": 6, "g": 7, "h": 8})).unwrap();
+        let folded = body_lines(&arguments, "call_1", 80, false);
+        assert!(folded[0].contains("[call call_1]"));
+        assert_eq!(folded.len(), 1 + COLLAPSED_LINES + 1);
+        assert!(folded[1].contains('{') && folded[2].contains("\"a\": 1"));
+        assert!(folded.last().unwrap().contains("… 4 more lines, space to expand"));
+
+        let expanded = body_lines(&arguments, "call_1", 80, true);
+        assert_eq!(expanded.len(), 1 + 10);
+        assert!(expanded.last().unwrap().contains('}'));
+
+        // Short enough to show whole, not JSON shown as is
+        let short = body_lines("12°C and sunny", "call_1", 80, false);
+        assert_eq!(short.len(), 2);
+        assert!(short[1].contains("12°C and sunny"));
+    }
+
+    #[test]
+    fn details_label_calls_and_outputs() {
+        let lines = detail_lines(&call("{\"city\":\"Oslo\"}"), None, 0.0, 80, false);
+        assert!(lines.iter().any(|line| line.contains("Arguments of weather")));
+        assert!(lines.iter().any(|line| line.contains("\"city\": \"Oslo\"")));
+
+        let output = ConversationItem::new(&json!({"id": "item_2", "type": "function_call_output", "call_id": "call_1", "output": "12°C"}));
+        let lines = detail_lines(&output, None, 0.0, 80, false);
+        assert!(lines.iter().any(|line| line.contains("Role: Tool")));
+        assert!(lines.iter().any(|line| line.contains("Output")));
+
+        let list = list_lines(&[call("{}"), output], 1, 80, 10);
+        assert!(list[1].contains("Assistant") && list[1].contains("⚙ weather({})"));
+        assert!(list[2].contains("Tool") && list[2].contains("↳ 12°C"));
+    }
+}
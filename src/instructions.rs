@@ -0,0 +1,62 @@
+use crossterm::style::Stylize;
+use similar::{ChangeTag, TextDiff};
+use std::fs::File;
+use std::io::Write;
+use std::process::Command;
+
+use crate::full_screen;
+
+/// Lets the user edit the instructions in their editor, returning them if they changed
+///
+/// The editor is $VISUAL or $EDITOR, with any arguments they carry (e.g. `code --wait`), vi
+/// otherwise. What changed is shown as a diff once the editor closes.
+pub fn edit(current: &str) -> Result<Option<String>, String> {
+    // Made afresh under a name no one can guess, so no other file (or link) is edited in its place
+    let path = std::env::temp_dir().join(format!("hotline-instructions-{}.md", uuid::Uuid::new_v4()));
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(current.as_bytes()))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("$EDITOR is empty")?;
+
+    // The editor has the terminal to itself, what the call prints meanwhile waits until it's closed
+    let status = full_screen::hold_output(|tty| {
+        Command::new(program).args(words).arg(&path).stdout(tty.try_clone()?).stderr(tty.try_clone()?).status()
+    });
+
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let status = status.map_err(|e| format!("Could not run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}, the instructions are unchanged", program, status));
+    }
+
+    let edited = edited.map_err(|e| format!("Could not read {}: {}", path.display(), e))?.trim_end().to_string();
+    if edited == current.trim_end() {
+        return Ok(None);
+    }
+    print_diff(current, &edited);
+    Ok(Some(edited))
+}
+
+/// Prints a line diff of the instructions, removed lines red and added ones green
+fn print_diff(old: &str, new: &str) {
+    let diff = TextDiff::from_lines(old, new);
+    for group in diff.grouped_ops(2) {
+        for op in group {
+            for change in diff.iter_changes(&op) {
+                let line = change.value().trim_end_matches('\n');
+                match change.tag() {
+                    ChangeTag::Delete => println!("{}", format!("- {}", line).red()),
+                    ChangeTag::Insert => println!("{}", format!("+ {}", line).green()),
+                    ChangeTag::Equal => println!("{}", format!("  {}", line).dim()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Talk to the OpenAI Realtime API from the terminal: the `hotline` CLI and the client it's built on
+//!
+//! `client::RealtimeClient` is the place to start when embedding: build one with
+//! `RealtimeClient::builder()`, connect, and drive the call through its methods or a
+//! `ClientHandle`. The server's events go to the subscribers in `handle_events`. Without a
+//! local output device, e.g. on a server, build it `headless(true)`.
+
+pub mod audio_backlog;
+pub mod audio_check;
+pub mod audio_sequencer;
+pub mod audio_utils;
+pub mod bundle;
+pub mod captions;
+pub mod chat;
+pub mod client;
+pub mod clock_drift;
+pub mod commands;
+pub mod config;
+pub mod conversation;
+pub mod doctor;
+pub mod dtmf;
+pub mod export;
+pub mod fallback;
+pub mod full_screen;
+pub mod gateway;
+pub mod handle_events;
+pub mod history;
+pub mod inspector;
+pub mod instructions;
+pub mod logging;
+pub mod markdown;
+pub mod metadata;
+pub mod mic_gate;
+pub mod output;
+pub mod pending;
+pub mod preset;
+pub mod recap;
+pub mod recorder;
+pub mod replay;
+pub mod rtp;
+pub mod session_summary;
+pub mod storage;
+pub mod text_layout;
+pub mod thinking_sound;
+pub mod time_stretch;
+pub mod tools;
+pub mod transcribe;
+pub mod usage;
+pub mod virtual_mic;
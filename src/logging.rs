@@ -0,0 +1,180 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::Stylize;
+use crossterm::terminal;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::{Event as TracingEvent, Level, Subscriber};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::full_screen;
+use crate::text_layout::truncate;
+
+/// Environment variable with the log configuration, overridden by `--log`
+pub const LOG_ENV: &str = "HOTLINE_LOG";
+
+// Log lines kept for the pane
+const RECENT_CAPACITY: usize = 1000;
+// How often the pane redraws to show new lines
+const PANE_REFRESH: Duration = Duration::from_millis(250);
+
+/// A log line kept for the pane
+struct LogLine {
+    at: Duration,           // Since logging started
+    level: Level,
+    target: String,         // Module path, without the crate name
+    message: String,
+}
+
+static RECENT: OnceLock<Arc<Mutex<VecDeque<LogLine>>>> = OnceLock::new();
+
+/// Sets up logging from a level for everything and a configuration like `client=debug,audio=warn`
+///
+/// Keys are modules of hotline (a prefix is enough, `audio` covers every audio module), a bare
+/// level applies to everything, and the `log.` prefix of the config file syntax is accepted.
+/// Everything enabled is kept for the `/logs` pane, warnings and errors also go to stderr.
+pub fn init(level: Option<&str>, config: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config.map(str::to_string).or_else(|| std::env::var(LOG_ENV).ok()).unwrap_or_default();
+    // Module levels are more specific, the order doesn't matter
+    let config = level.map(|level| format!("{},{}", level, config)).unwrap_or(config);
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::WARN.into())
+        .parse(directives(&config))
+        .map_err(|e| format!("Invalid log configuration {:?}: {}", config, e))?;
+
+    let recent = RECENT.get_or_init(|| Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_CAPACITY)))).clone();
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(RecentLayer { recent, start: Instant::now() })
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_filter(LevelFilter::WARN))
+        .try_init()?;
+    Ok(())
+}
+
+/// Turns `log.client=debug, audio=warn` into filter directives on hotline's module paths
+fn directives(config: &str) -> String {
+    config
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| {
+            let directive = directive.strip_prefix("log.").unwrap_or(directive);
+            match directive.split_once('=') {
+                Some((module, level)) => format!("hotline::{}={}", module.trim().replace('.', "::"), level.trim()),
+                None => directive.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Keeps the most recent events for the pane
+struct RecentLayer {
+    recent: Arc<Mutex<VecDeque<LogLine>>>,
+    start: Instant,
+}
+
+impl<S: Subscriber> Layer<S> for RecentLayer {
+    fn on_event(&self, event: &TracingEvent<'_>, _: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(LogLine {
+            at: self.start.elapsed(),
+            level: *metadata.level(),
+            target: metadata.target().strip_prefix("hotline::").unwrap_or(metadata.target()).to_string(),
+            message: visitor.0,
+        });
+    }
+}
+
+/// Formats the message and any other fields of an event on one line
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, "{}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Shows the most recent log lines on the alternate screen until the user leaves
+///
+/// Only lines at `level` or above from modules starting with `module` are shown. The level
+/// can be changed with e/w/i/d/t, `q` returns to the call.
+pub fn pane(mut level: Level, module: Option<&str>) -> std::io::Result<()> {
+    let Some(recent) = RECENT.get() else {
+        println!("Logging is not set up");
+        return Ok(());
+    };
+
+    full_screen::show(|out| loop {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+
+        let lines = {
+            let recent = recent.lock().unwrap();
+            let mut lines = recent
+                .iter()
+                .rev()
+                .filter(|line| line.level <= level && module.is_none_or(|module| line.target.starts_with(module)))
+                .take(height.saturating_sub(1))
+                .map(|line| {
+                    let text = truncate(&format!("{:>8.3} {:>5} {}: {}", line.at.as_secs_f64(), line.level, line.target, line.message), width);
+                    match line.level {
+                        Level::ERROR => text.red().to_string(),
+                        Level::WARN => text.yellow().to_string(),
+                        Level::INFO => text,
+                        _ => text.dim().to_string(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            lines.reverse();
+            lines
+        };
+
+        let header = format!(
+            "Logs at {} and above{}: e/w/i/d/t change level, q return to the call",
+            level,
+            module.map(|module| format!(" from {}", module)).unwrap_or_default(),
+        );
+        full_screen::draw(out, std::iter::once(truncate(&header, width).dim().to_string()).chain(lines))?;
+
+        if !event::poll(PANE_REFRESH)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        level = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('e') => Level::ERROR,
+            KeyCode::Char('w') => Level::WARN,
+            KeyCode::Char('i') => Level::INFO,
+            KeyCode::Char('d') => Level::DEBUG,
+            KeyCode::Char('t') => Level::TRACE,
+            _ => level,
+        };
+    })
+}
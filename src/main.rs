@@ -1,22 +1,1900 @@
-mod client;
-mod handle_events;
-mod audio_utils;
+use hotline::{audio_check, audio_utils, captions, client, clock_drift, commands, config, conversation, doctor, export, fallback, full_screen, gateway, handle_events, history, inspector, instructions, logging, metadata, mic_gate, output, preset, recap, recorder, replay, rtp, session_summary, storage, text_layout, thinking_sound, time_stretch, tools, transcribe, usage, virtual_mic};
 
-use client::RealtimeClient;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use crossterm::style::Stylize;
+use audio_utils::{base64_encode_audio, downmix, initialize_input_stream, InputChunk, InputCommand, SERVER_SAMPLE_RATE};
+use client::{ClientHandle, ConversationSnapshot, MaxTokens, Modality, RealtimeClient, ReplayPolicy, Voice, DEFAULT_EVENT_BUFFER, DEFAULT_URL};
+use clock_drift::{DriftEstimator, StreamResampler};
+use commands::{parse_command, Command, Confirmation, InternalCommand};
+use config::Config;
+use conversation::ConversationItemRole;
+use export::Transcript;
+use fallback::TextFallback;
+use gateway::{AuthScheme, Gateway};
+use handle_events::{Cue, InterruptionMode, LoopGuard, MicPolicy, NotificationSettings, NotifyOn, TextStyle, EMPTY_RESPONSE_RETRIES, HEARTBEAT_INTERVAL};
+use history::CallRecord;
+use metadata::{parse_key_value, SessionMetadata};
+use mic_gate::MicGate;
+use output::OutputFormat;
+use recap::{Recap, RecapTransport};
+use recorder::Recorder;
+use rtp::{RtpFormat, RtpOutput};
+use session_summary::{ErrorKind, ExitReason, SessionSummary};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use storage::{write_file, Encryption};
+use thinking_sound::ThinkingSound;
+use tools::Tools;
+use transcribe::TranscriptFormat;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use usage::Budget;
 
+// Longest wait for the end-of-call summary before hanging up regardless
+const SUMMARY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(90);
+// Longest wait for the last chapter's title before hanging up regardless
+const CHAPTER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+// Typed lines don't come this close together, pasted ones do
+const PASTE_WINDOW: std::time::Duration = std::time::Duration::from_millis(30);
+// Over SSH a paste comes in packets, which can be a round trip or a delayed ack apart
+const REMOTE_PASTE_WINDOW: std::time::Duration = std::time::Duration::from_millis(250);
+// Width of the item text shown when pinning it
+const PIN_PREVIEW_WIDTH: usize = 60;
+// Least microphone audio sent in one append, 20 ms, so small device buffers don't each cost an event
+const MIN_APPEND_SAMPLES: usize = SERVER_SAMPLE_RATE as usize / 50;
+
+
+#[derive(Parser)]
+#[command(
+    name = "hotline",
+    about = "Talk to the OpenAI Realtime API from your terminal",
+    after_help = "Shortcuts defined in the config file are started by name, e.g. `hotline standup`."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+
+    /// Config file with defaults and profiles (default from HOTLINE_CONFIG, then hotline/config.toml in the config directory)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Use the options of this profile from the config file, on top of its [dial] defaults
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Level of everything logged, refined per module with --log
+    #[arg(long, global = true, value_name = "LEVEL", value_parser = ["off", "error", "warn", "info", "debug", "trace"])]
+    log_level: Option<String>,
+
+    /// Log levels per module, e.g. client=debug,audio=warn (default from HOTLINE_LOG), shown with /logs
+    #[arg(long, global = true, value_name = "CONFIG")]
+    log: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Start a call (the default)
+    Dial(Box<DialArgs>),
+    /// Start a text-only call: typed messages and written answers, no audio in either direction
+    Chat(Box<DialArgs>),
+    /// Record from the microphone and play it back to check the audio setup
+    TestAudio {
+        /// How long to record for
+        #[arg(long, default_value_t = 3.0)]
+        seconds: f32,
+    },
+    /// List the audio input and output devices
+    Devices,
+    /// Check the API key, network and audio devices and print a diagnosis
+    Doctor,
+    /// Show how to set up a virtual microphone for video calls on this platform
+    VirtualMic {
+        /// Create the device now (Linux with PulseAudio or PipeWire only)
+        #[arg(long)]
+        apply: bool,
+        /// Name of the virtual device
+        #[arg(long, default_value = virtual_mic::DEFAULT_NAME)]
+        name: String,
+    },
+    /// Convert a saved transcript to Markdown
+    Export {
+        /// Transcript saved with `dial --transcript`
+        transcript: PathBuf,
+        /// Where to write the Markdown, printed when omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Translate the messages into this language first (e.g. es, German)
+        #[arg(long, value_name = "LANGUAGE")]
+        translate: Option<String>,
+        /// Pack the transcript, audio, session and analytics into this zip archive instead
+        #[arg(long, value_name = "PATH", conflicts_with = "output")]
+        bundle: Option<PathBuf>,
+        /// Protocol dump saved with `dial --dump`, adds audio, session and analytics to the bundle
+        #[arg(long, value_name = "PATH", requires = "bundle")]
+        dump: Option<PathBuf>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// List the most recent calls
+    History,
+    /// Start a new call with the options of a past one
+    Redial {
+        /// Number of the call in `hotline history`, 1 being the latest
+        #[arg(default_value_t = 1)]
+        number: usize,
+    },
+    /// Transcribe audio files to text and SRT subtitles, without generating any responses
+    Transcribe {
+        /// Audio files to transcribe: WAV, FLAC, MP3 or Ogg Vorbis
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Transcription model
+        #[arg(long, default_value = "whisper-1")]
+        model: String,
+        /// Files to write next to each input, <file>.txt and/or <file>.srt
+        #[arg(long, value_enum, default_value_t = TranscriptFormat::Both)]
+        format: TranscriptFormat,
+        /// Write the outputs here instead of next to the inputs
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+    },
+    /// Print the events of a protocol dump and the resulting transcript
+    Replay {
+        /// Protocol dump saved with `dial --dump`
+        dump: PathBuf,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Work with the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Print the completion script for a shell, e.g. `hotline completions bash > /etc/bash_completion.d/hotline`
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// A shortcut from the config file, followed by more dial options
+    #[command(external_subcommand)]
+    Shortcut(Vec<String>),
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Check a config file offline: its keys, the options of each section, the files they name and the tools they enable
+    Lint {
+        /// Config file to check (default: the one --config or HOTLINE_CONFIG picks, then hotline/config.toml in the config directory)
+        file: Option<PathBuf>,
+    },
+}
+
+/// Reading files saved with `dial --encrypt`
+#[derive(Args)]
+struct KeyArgs {
+    /// Keyfile for encrypted files, otherwise the passphrase is read from HOTLINE_PASSPHRASE
+    #[arg(long)]
+    keyfile: Option<PathBuf>,
+}
+
+impl KeyArgs {
+    fn encryption(&self) -> Result<Option<Encryption>, Box<dyn std::error::Error>> {
+        Encryption::from_options(self.keyfile.as_deref())
+    }
+}
+
+// Options repeated by the config file and the command line go by the last one
+#[derive(Args)]
+#[command(args_override_self = true)]
+struct DialArgs {
+    /// Don't stream the microphone, only typed messages are sent
+    #[arg(long)]
+    no_mic: bool,
+
+    /// Take the microphone audio from RTP on this UDP address instead, e.g. 0.0.0.0:5004 for a softphone or an ESP32
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "no_mic")]
+    rtp: Option<SocketAddr>,
+
+    /// Format of RTP audio with a dynamic payload type (96-127), as L16, PCMU or PCMA/RATE[/CHANNELS]
+    #[arg(long, value_name = "FORMAT", requires = "rtp", default_value = rtp::DEFAULT_FORMAT)]
+    rtp_format: RtpFormat,
+
+    /// Only stream the microphone while you speak, as heard by a local voice detector, instead of all the time
+    #[arg(long, group = "gate")]
+    local_vad: bool,
+
+    /// Only stream the microphone from /talk, or an empty line, until the /commit or empty line that sends the turn, implies --manual-turns
+    #[arg(long, group = "gate", conflicts_with = "no_mic")]
+    push_to_talk: bool,
+
+    /// Audio from before the local voice detector noticed speech, or before /talk, that is sent along so first syllables aren't clipped
+    #[arg(long, value_name = "MS", requires = "gate", default_value_t = mic_gate::DEFAULT_PRE_ROLL.as_millis() as u64)]
+    pre_roll: u64,
+
+    /// End your turns yourself with /commit instead of leaving it to the server's voice detection
+    #[arg(long)]
+    manual_turns: bool,
+
+    /// With manual turns, end the turn anyway after this many seconds of silence, in case /commit is forgotten
+    #[arg(long, value_name = "SECONDS", requires = "manual_turns")]
+    auto_commit: Option<f64>,
+
+    /// Should the realtime service become unreachable mid-call, carry on in text with this Chat Completions model until it's back
+    #[arg(long, value_name = "MODEL", num_args = 0..=1, default_missing_value = fallback::DEFAULT_FALLBACK_MODEL)]
+    text_fallback: Option<String>,
+
+    /// Only answer in text, without spoken responses
+    #[arg(long)]
+    text_only: bool,
+
+    /// What happens when you start speaking while the assistant is talking
+    #[arg(long, value_enum, default_value_t = InterruptionMode::Cancel)]
+    interrupt: InterruptionMode,
+
+    /// Keep streaming the microphone while the assistant responds (full), or hold it until the response finished playing (half)
+    #[arg(long, value_enum, default_value_t = MicPolicy::Full)]
+    duplex: MicPolicy,
+
+    /// Watch for the microphone picking up the assistant (speakers without echo cancellation): say so (warn), or switch to half duplex (mute)
+    #[arg(long, value_enum, value_name = "ACTION")]
+    loop_guard: Option<LoopGuard>,
+
+    /// How many times a turn to ask again, with a nudge, when a response comes back with neither audio nor text (0 to never)
+    #[arg(long, value_name = "COUNT", default_value_t = EMPTY_RESPONSE_RETRIES)]
+    retry_empty: u8,
+
+    /// How assistant text is printed: deltas as they arrive (raw), or wrapped under a label with markdown emphasis (chat)
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = TextStyle::Raw)]
+    text_style: TextStyle,
+
+    /// Events queued for the event handler and each of its subscribers; raise it if the display keeps falling behind, e.g. on a slow terminal
+    #[arg(long, value_name = "EVENTS", default_value_t = DEFAULT_EVENT_BUFFER)]
+    event_buffer: usize,
+
+    /// What goes to stdout: the conversation (text), or one JSON object per event for scripts (jsonl), with the rest on stderr
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// What carries over when the connection drops: every message (full), a summary of all but the last few (summarized) or nothing (fresh)
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ReplayPolicy::Full)]
+    on_reconnect: ReplayPolicy,
+
+    /// Realtime endpoint to connect to, e.g. a self-hosted gateway proxying the API
+    #[arg(long, value_name = "URL", default_value = DEFAULT_URL)]
+    url: String,
+
+    /// How the key in OPENAI_API_KEY is sent: as a bearer token, bare in --auth-header, or not at all
+    #[arg(long, value_enum, value_name = "SCHEME", default_value_t = AuthScheme::Bearer)]
+    auth: AuthScheme,
+
+    /// Header carrying the key with --auth header
+    #[arg(long, value_name = "NAME", default_value = "api-key")]
+    auth_header: String,
+
+    /// Extra header for the WebSocket handshake, can be repeated; a table in the config, `header = { NAME = "VALUE" }`
+    #[arg(long = "header", value_name = "NAME=VALUE", value_parser = parse_key_value)]
+    headers: Vec<(String, String)>,
+
+    /// WebSocket subprotocol to ask the server for, can be repeated
+    #[arg(long, value_name = "PROTOCOL")]
+    subprotocol: Vec<String>,
+
+    /// Voice of spoken responses
+    #[arg(long, value_enum, default_value_t = Voice::Alloy)]
+    voice: Voice,
+
+    /// Play the assistant faster or slower, 0.75 to 2 (pitch is kept), changed during the call with /speed
+    #[arg(long, value_name = "FACTOR", value_parser = time_stretch::parse_speed)]
+    speed: Option<f32>,
+
+    /// Voice used instead when the server rejects --voice, e.g. one the model doesn't support
+    #[arg(long, value_enum, value_name = "VOICE", default_value_t = Voice::Alloy)]
+    fallback_voice: Voice,
+
+    /// Sampling temperature, checked against the model's range (0.6 to 1.2 for the realtime models)
+    #[arg(long, value_name = "TEMPERATURE")]
+    temperature: Option<f32>,
+
+    /// How fast the model speaks, 1 being its natural pace (0.25 to 1.5 for the realtime models); unlike --speed it changes the speech itself
+    #[arg(long, value_name = "FACTOR")]
+    voice_speed: Option<f32>,
+
+    /// Longest response allowed, 1 to 4096 tokens or "inf"
+    #[arg(long, value_name = "TOKENS")]
+    max_output_tokens: Option<MaxTokens>,
+
+    /// Hang up once the estimated cost reaches this many US dollars
+    #[arg(long, value_name = "USD")]
+    budget_usd: Option<f64>,
+
+    /// Hang up once this many tokens have been used
+    #[arg(long, value_name = "TOKENS", conflicts_with = "budget_usd")]
+    budget_tokens: Option<u64>,
+
+    /// Save the transcript (JSON) here when hanging up; {date} and {time} in saved file names are filled in
+    #[arg(long, value_name = "PATH")]
+    transcript: Option<PathBuf>,
+
+    /// Save the assistant audio (WAV) here when hanging up
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Save every protocol event (JSON Lines) here when hanging up
+    #[arg(long, value_name = "PATH")]
+    dump: Option<PathBuf>,
+
+    /// Save the microphone audio (WAV) here when hanging up, including what /mute kept from the model, marked off the record
+    #[arg(long, value_name = "PATH")]
+    record_mic: Option<PathBuf>,
+
+    /// Carry on the call checkpointed here, if there is one, replaying its conversation in its model with this run's settings; the call is checkpointed here again when hanging up
+    #[arg(long, value_name = "PATH")]
+    snapshot: Option<PathBuf>,
+
+    /// Write per-turn latencies (CSV) here, for comparing configurations
+    #[arg(long, value_name = "PATH")]
+    latency_log: Option<PathBuf>,
+
+    /// Write session-summary.json here on exit: why the call ended, how long it took, usage, error counts and the files saved
+    #[arg(long, value_name = "DIR")]
+    summary_dir: Option<PathBuf>,
+
+    /// Have the assistant open the call, following these instructions, e.g. "ask what everyone worked on yesterday"
+    #[arg(long, value_name = "INSTRUCTIONS")]
+    greeting: Option<String>,
+
+    /// Hang up after this many minutes, with a warning a minute before
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    time_limit: Option<u64>,
+
+    /// Serve the live transcript as JSON patches over WebSocket on this address, e.g. 127.0.0.1:9000
+    #[arg(long, value_name = "ADDRESS")]
+    transcript_ws: Option<SocketAddr>,
+
+    /// Encrypt saved files with the passphrase in HOTLINE_PASSPHRASE
+    #[arg(long)]
+    encrypt: bool,
+
+    /// Encrypt saved files with this keyfile instead of a passphrase
+    #[arg(long, value_name = "PATH")]
+    keyfile: Option<PathBuf>,
+
+    /// Also play the assistant on this output device (matched by name), can be repeated
+    #[arg(long, value_name = "DEVICE")]
+    mirror_output: Vec<String>,
+
+    /// Also send the assistant as RTP to this UDP address, e.g. 192.168.1.50:5004 for a networked speaker
+    #[arg(long, value_name = "ADDRESS")]
+    rtp_out: Option<SocketAddr>,
+
+    /// Format of the RTP sent, as L16, PCMU or PCMA/RATE[/CHANNELS]
+    #[arg(long, value_name = "FORMAT", requires = "rtp_out", default_value = rtp::DEFAULT_OUTPUT_FORMAT)]
+    rtp_out_format: RtpFormat,
+
+    /// RTP payload type to send with, by default the format's static one (0 for PCMU, 8 for PCMA) or 96
+    #[arg(long, value_name = "TYPE", requires = "rtp_out", value_parser = clap::value_parser!(u8).range(0..=127))]
+    rtp_out_payload_type: Option<u8>,
+
+    /// Audio per RTP packet
+    #[arg(long, value_name = "MS", requires = "rtp_out", default_value_t = rtp::DEFAULT_PACKET_MS, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    rtp_out_packet: u32,
+
+    /// Audio held back before the assistant starts sending, more rides out a server that streams unevenly
+    #[arg(long, value_name = "MS", requires = "rtp_out", default_value_t = rtp::DEFAULT_PREBUFFER_MS)]
+    rtp_out_prebuffer: u32,
+
+    /// Play the assistant into this virtual device so it can speak in video calls, see `hotline virtual-mic`
+    #[arg(long, value_name = "DEVICE")]
+    virtual_mic: Option<String>,
+
+    /// Mix your microphone into the virtual device too, so the call hears both of you
+    #[arg(long, requires = "virtual_mic")]
+    virtual_mic_mix: bool,
+
+    /// Turn other applications down to this percentage of their volume while the assistant speaks (Linux)
+    #[arg(long, value_name = "PERCENT", num_args = 0..=1, default_missing_value = "30", value_parser = clap::value_parser!(u8).range(0..=100))]
+    duck: Option<u8>,
+
+    /// Show the call in the system tray, with mute and hang up at hand (Linux, built with --features tray)
+    #[arg(long)]
+    tray: bool,
+
+    /// While the assistant is slow to answer, play a sound: tick, hum or an audio file
+    #[arg(long, value_name = "SOUND", num_args = 0..=1, default_missing_value = "tick")]
+    thinking_sound: Option<ThinkingSound>,
+
+    /// Close the audio devices after this many minutes without speech or messages, to save battery; enter wakes them
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    idle_after: Option<u64>,
+
+    /// Start from a ready-made kind of call: tutor:LANGUAGE for a language lesson, each assistant message shown next to its translation into LANGUAGE
+    #[arg(long, value_name = "PRESET", value_parser = preset::parse_preset)]
+    preset: Option<preset::Preset>,
+
+    /// Live captions: translate the assistant's speech sentence by sentence into this language, shown with /captions
+    #[arg(long, value_name = "LANGUAGE")]
+    captions: Option<String>,
+
+    /// Before sending a long or pasted message: show it in short (review), or ask first (ask)
+    #[arg(long, value_enum, value_name = "HOW")]
+    confirm_typed: Option<Confirmation>,
+
+    /// Typed messages longer than this many characters are checked by --confirm-typed, pasted ones always are
+    #[arg(long, value_name = "CHARS", requires = "confirm_typed", default_value_t = 280)]
+    confirm_over: usize,
+
+    /// Lines coming in this close together are one pasted message for --confirm-typed [default: 30, or 250 over SSH]
+    #[arg(long, value_name = "MS", requires = "confirm_typed")]
+    paste_window: Option<u64>,
+
+    /// Mark where you and the assistant spoke at once (use a headset, speakers make its own voice count)
+    #[arg(long)]
+    talk_over: bool,
+
+    /// With --talk-over, stop interrupting on short remarks like "mm-hm" and start on long talk-overs
+    #[arg(long)]
+    adaptive_interrupt: bool,
+
+    /// Label each turn with the sentiment or emotion this HTTP classifier gives it (bearer token in HOTLINE_SENTIMENT_TOKEN)
+    #[arg(long, value_name = "URL")]
+    sentiment: Option<String>,
+
+    /// Title the call in chapters every few turns, marked in the transcript, its exports and replays
+    #[arg(long)]
+    chapters: bool,
+
+    /// Before hanging up, have the assistant sum up the call and its action items out loud
+    #[arg(long)]
+    spoken_summary: bool,
+
+    /// After hanging up, email a summary of the call with its action items and the transcript to this address
+    #[arg(long, value_name = "ADDRESS")]
+    recap_to: Option<String>,
+
+    /// Sender of the recap (default: the recipient)
+    #[arg(long, value_name = "ADDRESS", requires = "recap_to")]
+    recap_from: Option<String>,
+
+    /// SMTP server to send the recap with, e.g. smtps://me@smtp.example.com (password from HOTLINE_SMTP_PASSWORD)
+    #[arg(long, value_name = "URL", requires = "recap_to", conflicts_with = "recap_webhook")]
+    smtp: Option<String>,
+
+    /// Post the recap as JSON to this webhook instead, for it to send
+    #[arg(long, value_name = "URL", requires = "recap_to")]
+    recap_webhook: Option<String>,
+
+    /// Tell the assistant with a system message when the call is paused and resumed
+    #[arg(long)]
+    announce_pause: bool,
+
+    /// Mark the assistant starting and stopping to speak with these cues (comma separated), for when you look away or the volume is low
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "CUES")]
+    cues: Vec<Cue>,
+
+    /// Raise desktop notifications on these events (comma separated)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "EVENTS")]
+    notify: Vec<NotifyOn>,
+
+    /// Notify when this word comes up in the conversation, can be repeated
+    #[arg(long, value_name = "WORD")]
+    notify_keyword: Vec<String>,
+
+    /// Let the model read files under this directory, can be repeated
+    #[arg(long, value_name = "DIR")]
+    allow_read: Vec<PathBuf>,
+
+    /// Let the model press phone keys, playing DTMF tones into the outgoing audio, e.g. to get through a phone menu over a SIP bridge
+    #[arg(long)]
+    dtmf: bool,
+
+    /// Let the model suggest replies, shown numbered so one is sent by pressing its number
+    #[arg(long)]
+    quick_actions: bool,
+
+    /// Ask before running each tool call the model makes
+    #[arg(long)]
+    approve_tools: bool,
+
+    /// Your name, stamped into responses and saved files
+    #[arg(long, value_name = "NAME")]
+    caller: Option<String>,
+
+    /// What the call is about, stamped into responses and saved files
+    #[arg(long, value_name = "TEXT")]
+    purpose: Option<String>,
+
+    /// Extra metadata, can be repeated
+    #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    metadata: Vec<(String, String)>,
+}
 
-// Example usage of the RealtimeClient
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let command_line: Vec<String> = std::env::args().collect();
+    let cli = Cli::parse_from(&command_line);
+
+    // Linted before it's loaded, a broken config is what it's for
+    if let Some(CliCommand::Config { command: ConfigCommand::Lint { file } }) = &cli.command {
+        return lint_config(file.as_deref().or(cli.config.as_deref()));
+    }
+
+    let config = Config::load(cli.config.as_deref())?;
+    if let Some(name) = config.shadowed_shortcuts(&command_names()).first() {
+        return Err(format!("The shortcut {} in {} is named like a command of hotline's and can't be started, rename it", name, config.path().display()).into());
+    }
+    let log = cli.log.clone().or_else(|| config.log());
+    logging::init(cli.log_level.as_deref().or(config.log_level.as_deref()), log.as_deref())?;
+
+    // Without a subcommand, dial with the default options
+    let command = cli.command.unwrap_or_else(|| Cli::parse_from(["hotline", "dial"]).command.unwrap());
+
+    match command {
+        CliCommand::Dial(_) => {
+            let (args, arguments) = dial_args(&config, config.dial_arguments(cli.profile.as_deref())?, subcommand_arguments(&command_line, "dial"))?;
+            dial(args, arguments).await
+        }
+        CliCommand::Chat(_) => {
+            let (mut args, mut arguments) = dial_args(&config, config.dial_arguments(cli.profile.as_deref())?, subcommand_arguments(&command_line, "chat"))?;
+            args.no_mic = true;
+            args.text_only = true;
+            // Redialed as `dial`, which needs to be told
+            arguments.splice(0..0, ["--no-mic".to_string(), "--text-only".to_string()]);
+            dial(args, arguments).await
+        }
+        CliCommand::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "hotline", &mut std::io::stdout());
+            Ok(())
+        }
+        CliCommand::Shortcut(arguments) => {
+            let (name, typed) = arguments.split_first().expect("external subcommands have a name");
+            // Global options typed after the name reach here unparsed, --profile among them
+            let typed_profile = Cli::try_parse_from(["hotline", "dial"].into_iter().map(String::from).chain(typed.iter().cloned()))
+                .ok()
+                .and_then(|cli| cli.profile);
+            let Some(options) = config.shortcut_arguments(name, typed_profile.as_deref().or(cli.profile.as_deref())) else {
+                let known: Vec<_> = config.shortcuts().iter().map(|(name, _)| *name).collect();
+                return Err(format!(
+                    "Unknown command or shortcut: {} (shortcuts: {}), see hotline --help",
+                    name,
+                    if known.is_empty() { "none".to_string() } else { known.join(", ") }
+                ).into());
+            };
+            let description = config.shortcuts().into_iter().find(|(shortcut, _)| shortcut == name).map(|(_, description)| description);
+            if let Some(description) = description.filter(|description| !description.is_empty()) {
+                println!("{}", description);
+            }
+
+            let (args, arguments) = dial_args(&config, options?, typed.to_vec())?;
+            dial(args, arguments).await
+        }
+        CliCommand::Devices => audio_check::list_devices(),
+        CliCommand::History => history::list(),
+        CliCommand::Redial { number } => {
+            let call = history::get(number)?;
+            std::env::set_current_dir(&call.directory)
+                .map_err(|e| format!("Cannot redial from {}: {}", call.directory.display(), e))?;
+            println!("Redialing: hotline dial {}", call.arguments.join(" "));
+
+            let cli = Cli::try_parse_from(["hotline", "dial"].into_iter().map(String::from).chain(call.arguments.clone()))?;
+            let Some(CliCommand::Dial(args)) = cli.command else {
+                unreachable!("parsed as a dial command");
+            };
+            dial(*args, call.arguments).await
+        }
+        CliCommand::TestAudio { seconds } => audio_check::test_audio(seconds),
+        CliCommand::Doctor => doctor::doctor().await,
+        CliCommand::VirtualMic { apply, name } => virtual_mic::setup(&name, apply),
+        CliCommand::Export { transcript, output, translate, bundle, dump, key } => {
+            let encryption = key.encryption()?;
+            let bundle = bundle.as_deref().map(|bundle| (bundle, dump.as_deref()));
+            export::export(&transcript, output.as_deref(), translate.as_deref(), bundle, encryption.as_ref()).await
+        }
+        CliCommand::Transcribe { files, model, format, output_dir } => {
+            transcribe::transcribe(&files, &model, format, output_dir.as_deref()).await
+        }
+        CliCommand::Replay { dump, key } => replay::replay(&dump, key.encryption()?.as_ref()),
+        CliCommand::Config { .. } => unreachable!("handled before the config is loaded"),
+    }
+}
+
+/// Checks a config file without connecting or opening devices, failing if it has errors
+///
+/// Each section is parsed as dial options on its own, so a profile is checked without the
+/// [dial] defaults it would be combined with.
+fn lint_config(path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load(path)?;
+    if config.path().as_os_str().is_empty() {
+        return Err(format!("No config file to check, name one or set {}", config::CONFIG_ENV).into());
+    }
+
+    let warnings = config.key_warnings();
+    let mut errors: Vec<String> = config
+        .shadowed_shortcuts(&command_names())
+        .iter()
+        .map(|name| format!("[shortcuts.{}] is named like a command of hotline's and can't be started", name))
+        .collect();
+    for (section, arguments) in config.sections() {
+        let arguments = match arguments {
+            Ok(arguments) => arguments,
+            Err(e) => {
+                errors.push(format!("[{}] {}", section, e));
+                continue;
+            }
+        };
+        let args = match Cli::try_parse_from(["hotline", "dial"].into_iter().map(String::from).chain(arguments)) {
+            Ok(Cli { command: Some(CliCommand::Dial(args)), .. }) => args,
+            Ok(_) => unreachable!("parsed as a dial command"),
+            Err(e) => {
+                // Only the message, not the usage clap adds
+                let message = e.to_string();
+                let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+                errors.push(format!("[{}] {}", section, message));
+                continue;
+            }
+        };
+
+        errors.extend(file_problems(&args).into_iter().map(|problem| format!("[{}] {}", section, problem)));
+        if let Err(e) = Tools::new(&args.allow_read, args.dtmf, args.quick_actions) {
+            errors.push(format!("[{}] {}", section, e));
+        }
+    }
+
+    for warning in &warnings {
+        println!("{} {}", "warning:".yellow(), warning);
+    }
+    for error in &errors {
+        println!("{} {}", "error:".red(), error);
+    }
+    match errors.len() {
+        0 => {
+            println!("{}: {} warning(s), no errors", config.path().display(), warnings.len());
+            Ok(())
+        }
+        count => Err(format!("{}: {} error(s), {} warning(s)", config.path().display(), count, warnings.len()).into()),
+    }
+}
+
+/// What's wrong with the files a call's options name: inputs that can't be read, and outputs
+/// whose directory doesn't exist, which would only come up when they're saved at hang up
+fn file_problems(args: &DialArgs) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(keyfile) = args.keyfile.as_ref().filter(|keyfile| !keyfile.is_file()) {
+        problems.push(format!("keyfile {} doesn't exist", keyfile.display()));
+    }
+    if let Some(sound @ ThinkingSound::File(path)) = &args.thinking_sound {
+        if let Err(e) = sound.samples() {
+            problems.push(format!("thinking sound {} can't be played: {}", path.display(), e));
+        }
+    }
+
+    let outputs = [
+        ("transcript", &args.transcript),
+        ("record", &args.record),
+        ("dump", &args.dump),
+        ("record-mic", &args.record_mic),
+        ("snapshot", &args.snapshot),
+        ("latency-log", &args.latency_log),
+    ];
+    for (option, path) in outputs {
+        let Some(path) = path.as_deref().map(config::expand_template) else { continue };
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty() && !directory.is_dir()) {
+            problems.push(format!("{} is saved in {}, which doesn't exist", option, directory.display()));
+        }
+    }
+    problems
+}
+
+/// Options of a call: those from the config (defaults, profile or shortcut), then the typed ones
+///
+/// Also returns them as arguments, kept in the history for redialing.
+fn dial_args(config: &Config, mut arguments: Vec<String>, typed: Vec<String>) -> Result<(DialArgs, Vec<String>), Box<dyn std::error::Error>> {
+    let from_config = !arguments.is_empty();
+    arguments.extend(typed);
+
+    let cli = Cli::try_parse_from(["hotline", "dial"].into_iter().map(String::from).chain(arguments.clone())).unwrap_or_else(|e| {
+        if from_config {
+            eprintln!("With the options from {}:", config.path().display());
+        }
+        e.exit()
+    });
+    let Some(CliCommand::Dial(args)) = cli.command else {
+        unreachable!("parsed as a dial command");
+    };
+    Ok((*args, arguments))
+}
+
+/// Names and aliases of hotline's own commands, which shortcuts can't take
+fn command_names() -> Vec<String> {
+    let command = Cli::command();
+    let mut names = vec!["help".to_string()];
+    for subcommand in command.get_subcommands() {
+        names.push(subcommand.get_name().to_string());
+        names.extend(subcommand.get_all_aliases().map(String::from));
+    }
+    names
+}
+
+/// The command line after the subcommand, empty without one
+///
+/// The subcommand is where what comes before it parses as global options alone, so clap rather
+/// than a list of options decides what's a value: `--profile dial dial` is dialing with a profile.
+fn subcommand_arguments(command_line: &[String], subcommand: &str) -> Vec<String> {
+    (1..command_line.len())
+        .find(|&at| command_line[at] == subcommand && Cli::try_parse_from(&command_line[..at]).is_ok_and(|cli| cli.command.is_none()))
+        .map_or_else(Vec::new, |at| command_line[at + 1..].to_vec())
+}
+
+/// Runs a call until the user hangs up, then writes its summary if asked to, failed or not
+async fn dial(mut args: DialArgs, arguments: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    // Templates are kept in the history, so a redial gets its own files
+    for path in [&mut args.transcript, &mut args.record, &mut args.record_mic, &mut args.dump, &mut args.latency_log, &mut args.summary_dir].into_iter().flatten() {
+        *path = config::expand_template(path);
+    }
+
+    let summary_dir = args.summary_dir.clone();
+    let mut summary = SessionSummary::new();
+    let result = call(args, arguments, &mut summary).await;
+
+    if let Some(directory) = summary_dir {
+        summary.finish(result.as_ref().err().map(|e| e.to_string()));
+        match summary.write(&directory) {
+            Ok(path) => println!("Saved {}", path.display()),
+            Err(e) => eprintln!("Failed to write the session summary: {}", e),
+        }
+    }
+    result
+}
+
+/// The call itself, noting in the summary what it got to
+async fn call(args: DialArgs, arguments: Vec<String>, summary: &mut SessionSummary) -> Result<(), Box<dyn std::error::Error>> {
+    let started = summary.started;
+    if args.output == OutputFormat::Jsonl {
+        output::reserve_stdout()?;
+    }
+
     // Connect to the WebSocket server
-    let mut client = RealtimeClient::new(None, None);
-    client.connect(None).await?;
+    let mut client = RealtimeClient::builder()
+        .transport(Gateway {
+            url: args.url.clone(),
+            auth: args.auth,
+            auth_header: args.auth_header.clone(),
+            headers: args.headers.clone(),
+            subprotocols: args.subprotocol.clone(),
+            ..Gateway::default()
+        })
+        .event_buffer(args.event_buffer)
+        .build()
+        .map_err(|e| e.to_string())?;
+    summary.track_usage(client.usage());
+    summary.track_errors(client.errors());
+
+    if let Some(usd) = args.budget_usd {
+        client.set_budget(Budget::Usd(usd));
+    } else if let Some(tokens) = args.budget_tokens {
+        client.set_budget(Budget::Tokens(tokens));
+    }
+
+    // Fail before the call rather than at hang up when there's no secret to encrypt with
+    let encryption = match (args.encrypt, &args.keyfile) {
+        (false, None) => None,
+        _ => Some(Encryption::from_options(args.keyfile.as_deref())?.ok_or_else(|| {
+            format!("--encrypt needs a passphrase in {} or a --keyfile", storage::PASSPHRASE_ENV)
+        })?),
+    };
+    let metadata = SessionMetadata::new(args.caller.clone(), args.purpose.clone(), args.metadata.clone())?;
+    client.set_session_metadata(metadata.clone());
+
+    let recap = RecapTransport::from_options(args.recap_to.is_some(), args.smtp.as_deref(), args.recap_webhook.as_deref(), encryption.is_some())?;
+
+    let recorder = client.recorder();
+    recorder.lock().unwrap().enable(args.dump.is_some(), args.record.is_some());
+    if args.record_mic.is_some() {
+        recorder.lock().unwrap().enable_microphone();
+    }
+    recorder.lock().unwrap().set_metadata(metadata.clone());
+
+    if let Some(preset) = &args.preset {
+        client.set_instructions(preset.instructions());
+    }
+    client.set_voice(args.voice);
+    if let Some(temperature) = args.temperature {
+        client.set_temperature(temperature);
+    }
+    if let Some(speed) = args.voice_speed {
+        client.set_speech_speed(speed);
+    }
+    if args.text_only {
+        client.set_modalities(vec![Modality::Text]);
+    }
+    if args.manual_turns || args.push_to_talk {
+        client.set_manual_turns();
+    }
+    if let Some(max_tokens) = args.max_output_tokens {
+        client.set_max_response_output_tokens(max_tokens);
+    }
+
+    let tools = Tools::new(&args.allow_read, args.dtmf, args.quick_actions)?;
+    client.set_tools(tools.definitions());
+
+    match args.snapshot.as_deref().filter(|path| path.exists()) {
+        Some(path) => {
+            let mut snapshot: ConversationSnapshot = serde_json::from_slice(&storage::read_file(path, Encryption::from_options(args.keyfile.as_deref())?.as_ref())?)
+                .map_err(|e| format!("{} is not a snapshot: {}", path.display(), e))?;
+            if !metadata.is_empty() {
+                snapshot.metadata = metadata.clone();
+            }
+            // This run's settings, rather than those the call was checkpointed with
+            snapshot.session = client.session_config().clone();
+            let count = snapshot.items.len();
+            client.restore(snapshot).await?;
+            println!("[carrying on the call from {}, {} messages replayed]", path.display(), count);
+        }
+        None => client.connect(None).await?,
+    }
+
+    if args.interrupt != InterruptionMode::Cancel {
+        client.set_interruption_mode(args.interrupt).await?;
+    }
+    if args.duplex != MicPolicy::Full {
+        client.set_mic_policy(args.duplex).await?;
+    }
+    if let Some(guard) = args.loop_guard {
+        client.set_loop_guard(guard).await?;
+    }
+    if let Some(seconds) = args.auto_commit {
+        client.set_auto_commit(std::time::Duration::from_secs_f64(seconds.max(0.0))).await?;
+    }
+    if args.retry_empty != EMPTY_RESPONSE_RETRIES {
+        client.set_empty_response_retries(args.retry_empty).await?;
+    }
+    if args.text_style != TextStyle::Raw {
+        client.set_text_style(args.text_style).await?;
+    }
+    if let Some(speed) = args.speed {
+        client.set_playback_speed(speed).await?;
+    }
+
+    for device in &args.mirror_output {
+        client.add_output_device(device, false).await?;
+    }
+    if let Some(address) = args.rtp_out {
+        client.add_rtp_output(&RtpOutput {
+            address,
+            format: args.rtp_out_format,
+            payload_type: args.rtp_out_payload_type.unwrap_or(args.rtp_out_format.payload_type()),
+            packet_ms: args.rtp_out_packet,
+            prebuffer_ms: args.rtp_out_prebuffer,
+        }).await?;
+    }
+
+    if let Some(device) = &args.virtual_mic {
+        client.add_output_device(device, args.virtual_mic_mix).await?;
+    }
+
+    if let Some(path) = &args.latency_log {
+        client.set_latency_log(path).await?;
+        summary.add_artifact("latency_log", path);
+    }
+
+    if let Some(address) = args.transcript_ws {
+        client.serve_transcript(address).await?;
+    }
+
+    if let Some(sound) = &args.thinking_sound {
+        client.set_thinking_sound(sound).await?;
+    }
+
+    if let Some(language) = args.preset.as_ref().and_then(|preset| preset.translation()) {
+        client.set_translation(Some(language)).await?;
+    }
+    if let Some(language) = &args.captions {
+        client.set_captions(Some(language)).await?;
+    }
+
+    if args.talk_over || args.adaptive_interrupt {
+        client.set_talk_over_detection(args.adaptive_interrupt).await?;
+    }
+
+    if let Some(url) = &args.sentiment {
+        client.set_sentiment_endpoint(url).await?;
+    }
+
+    if args.chapters {
+        client.set_chapters(true).await?;
+    }
+
+    if let Some(minutes) = args.idle_after {
+        client.set_idle_timeout(std::time::Duration::from_secs(minutes * 60)).await?;
+    }
+
+    if let Some(percent) = args.duck {
+        client.set_ducking(percent as f64 / 100.0).await?;
+    }
+
+    if args.tray {
+        client.enable_tray().await?;
+    }
+
+    if !args.cues.is_empty() {
+        client.set_cues(&args.cues).await?;
+    }
+
+    let mut notify_on = args.notify.clone();
+    if !args.notify_keyword.is_empty() && !notify_on.contains(&NotifyOn::Keyword) {
+        notify_on.push(NotifyOn::Keyword);
+    }
+    if !notify_on.is_empty() {
+        client.set_notifications(NotificationSettings { on: notify_on, keywords: args.notify_keyword.clone() }).await?;
+    }
+
+    let talking = Arc::new(AtomicBool::new(false));    // With --push-to-talk, between /talk and /commit
+    let mic_muted = Arc::new(AtomicBool::new(false));  // Told to the capture, which stops sending and marks the recording
+    let pre_roll = std::time::Duration::from_millis(args.pre_roll);
+    let gate = match (args.local_vad, args.push_to_talk) {
+        (true, _) => Some(MicGate::new(pre_roll)),
+        (_, true) => Some(MicGate::push_to_talk(pre_roll, talking.clone())),
+        _ => None,
+    };
+    let microphone = match (args.no_mic, args.rtp) {
+        (true, _) => None,
+        (false, Some(address)) => Some(start_microphone(client.handle(), client.recorder(), gate, mic_muted.clone(), rtp::listen(address, args.rtp_format)?)),
+        (false, None) => Some(start_microphone(client.handle(), client.recorder(), gate, mic_muted.clone(), initialize_input_stream())),
+    };
+
+    if let Some(greeting) = &args.greeting {
+        client.send_system_message(&format!("Open the call now: {}", greeting)).await?;
+        client.create_response().await?;
+    }
+
+    if let Some(minutes) = args.time_limit {
+        let handle = client.handle();
+        tokio::spawn(async move {
+            let limit = std::time::Duration::from_secs(minutes * 60);
+            let warning = std::time::Duration::from_secs(60);
+            if limit > warning {
+                tokio::time::sleep(limit - warning).await;
+                println!("\n[one minute left on the call]");
+            }
+            tokio::time::sleep(limit.min(warning)).await;
+            let _ = handle.send(Command::Internal(InternalCommand::TimeUp)).await;
+        });
+    }
+
+    // Ctrl-C hangs up like /quit, pressed again it skips what's waited on; once the call is over it exits
+    let handle = client.handle();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if handle.hang_up().await.is_err() {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    // Keep an eye on the connection, the event handler shows its health
+    let handle = client.handle();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if handle.send(Command::Internal(InternalCommand::Heartbeat)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Read user input line by line, each line is either a message or a /command
+    let handle = client.handle();
+    let conversation = client.conversation();
+    let session = client.shared_session();
+    let mut requested_instructions = client.instructions().to_string();    // Kept up with the changes made here
+    let (confirmation, confirm_over) = (args.confirm_typed, args.confirm_over);
+    let paste_window = paste_window(args.paste_window, std::env::var_os("SSH_CONNECTION").is_some());
+    let push_to_talk = args.push_to_talk;
+    let (quick_actions, mut quick_action_offers) = tokio::sync::watch::channel(None);    // Suggested by the model, with the count of user messages then
+    let picks_by_key = std::io::IsTerminal::is_terminal(&std::io::stdin());
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+        while let Some(mut line) = next_line_or_pick(&mut lines, &mut quick_action_offers, picks_by_key, &conversation).await {
+            // With push-to-talk, enter on its own starts and stops talking
+            if line.trim().is_empty() {
+                if !push_to_talk {
+                    continue;
+                }
+                line = "/talk".to_string();
+            }
+
+            // Lines coming in all at once were pasted, they make one message
+            let mut pasted = false;
+            if confirmation.is_some() && !line.trim_start().starts_with('/') {
+                pasted = read_paste(&mut lines, &mut line, paste_window).await;
+            }
+
+            // Pressing enter is enough to wake the audio devices
+            if handle.send(Command::Internal(InternalCommand::Wake)).await.is_err() {
+                break;
+            }
+
+            match parse_command(&line) {
+                Ok(Command::SendText(text)) if confirmation.is_some() && (pasted || text.chars().count() > confirm_over) => {
+                    let preview = text_layout::truncate(&text.replace('\n', " ⏎ "), 80);
+                    if confirmation == Some(Confirmation::Ask) {
+                        print!("Send {} characters \"{}\"? [y/N] ", text.chars().count(), preview);
+                        let _ = std::io::Write::flush(&mut std::io::stdout());
+                        let answer = lines.next_line().await.ok().flatten().unwrap_or_default();
+                        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                            println!("[not sent]");
+                            continue;
+                        }
+                    } else {
+                        println!("{}", format!("sending: {}", preview).dim());
+                    }
+                    if handle.send(Command::SendText(text)).await.is_err() {
+                        break;
+                    }
+                }
+                // The inspector reads keys from the terminal, so no lines are read meanwhile
+                Ok(Command::Inspect) => {
+                    let conversation = conversation.clone();
+                    if let Err(e) = tokio::task::spawn_blocking(move || inspector::inspect(&conversation)).await.unwrap() {
+                        eprintln!("Inspector failed: {}", e);
+                    }
+                }
+                // So is the editor, the change is applied once it's closed
+                // Before the server acknowledged a session, e.g. while reconnecting, the requested instructions are edited
+                Ok(Command::EditInstructions) => {
+                    let acknowledged = session.lock().unwrap().as_ref().and_then(|session| session["instructions"].as_str().map(str::to_string));
+                    let current = acknowledged.unwrap_or_else(|| requested_instructions.clone());
+                    match tokio::task::spawn_blocking(move || instructions::edit(&current)).await.unwrap() {
+                        Ok(Some(instructions)) => {
+                            requested_instructions = instructions.clone();
+                            if handle.send(Command::SetInstructions(instructions)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Ok(None) => println!("[instructions unchanged]"),
+                        Err(e) => eprintln!("Editing the instructions failed: {}", e),
+                    }
+                }
+                Ok(Command::Captions) => {
+                    if let Err(e) = tokio::task::spawn_blocking(captions::pane).await.unwrap() {
+                        eprintln!("Captions pane failed: {}", e);
+                    }
+                }
+                Ok(Command::Logs(level, module)) => {
+                    if let Err(e) = tokio::task::spawn_blocking(move || logging::pane(level, module.as_deref())).await.unwrap() {
+                        eprintln!("Log pane failed: {}", e);
+                    }
+                }
+                Ok(command) => {
+                    if let Command::SetInstructions(instructions) | Command::Switch(_, Some(instructions)) = &command {
+                        requested_instructions = instructions.clone();
+                    }
+                    if handle.send(command).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        // Hang up once stdin is closed
+        let _ = handle.hang_up().await;
+    });
+
+    // Commands come from both the user and the event handler
+    let mut commands = client.take_command_receiver().expect("Command receiver already taken");
+    let mut paused = false;
+    let mut muted = false;
+    let mut mic_open = true;    // Per the duplex policy
+    let mut summary_requested = false;
+    let mut last_chapter_requested = false;
+    let mut time_up = false;    // Hung up by --time-limit rather than anyone
+    let mut asleep = false;     // Audio devices closed while idle
+    let mut pending_tools = VecDeque::new();    // Tool calls waiting for approval, first one shown
+    let text_fallback = args.text_fallback.as_deref().map(|model| TextFallback::new(model, client.chat_completions()));
+    let mut falling_back = false;               // The realtime service is down, typed messages go to the text fallback
+
+    while let Some(command) = commands.recv().await {
+        // The time limit hangs up like anyone else, it's only told apart for the summary
+        let command = match command {
+            Command::Internal(InternalCommand::TimeUp) => {
+                time_up |= !summary_requested && !last_chapter_requested;
+                Command::Quit
+            }
+            command => command,
+        };
+        match command {
+            Command::SendText(text) if falling_back => {
+                let fallback = text_fallback.as_ref().unwrap();
+                match client.reply_in_text(fallback, &text).await {
+                    Ok(reply) => println!("\n{} {}", format!("[{}]", fallback.model()).dim(), reply),
+                    Err(e) => eprintln!("\n[no reply from the text fallback: {}]", e),
+                }
+            }
+            Command::Internal(InternalCommand::Reconnect) if falling_back => match client.reconnect(args.on_reconnect).await {
+                Ok(()) => {
+                    falling_back = false;
+                    println!("\n[the realtime service is back, {}]", carried_over(args.on_reconnect));
+                }
+                Err(e) => {
+                    tracing::info!(error = %e, "realtime service still unreachable");
+                    retry_later(client.handle());
+                }
+            },
+            Command::Quit if falling_back => break,
+            command if falling_back => {
+                if !matches!(command, Command::Internal(_)) {
+                    eprintln!("\n[only typed messages work until the realtime service is back]");
+                }
+            }
+            Command::SendText(text) => {
+                let text = pick_quick_action(quick_actions.send_replace(None), user_messages(&client.conversation()), text);
+                client.send_user_text(&text).await?
+            }
+            Command::SetModalities(modalities, message) => {
+                client.set_next_response_modalities(modalities).await?;
+
+                // Without a message the override waits for the next turn
+                if let Some(text) = message {
+                    client.send_user_text(&text).await?;
+                }
+            }
+            Command::SendAudio(path) => {
+                // A bad path is a typo, not a reason to end the call
+                match client.send_user_audio(&path).await {
+                    Ok(duration) => println!("\n[sent {}, {:.1} s]", path.display(), duration.as_secs_f64()),
+                    Err(e) => eprintln!("\n[{}]", e),
+                }
+            }
+            Command::System(text) => client.send_system_message(&text).await?,
+            Command::Ask(question) => client.ask_side_channel(&question).await?,
+            Command::Interrupt => client.interrupt().await?,
+            Command::SetInterruptionMode(mode) => client.set_interruption_mode(mode).await?,
+            Command::Switch(model, instructions) => {
+                client.interrupt().await?;
+                client.switch_session(&model, instructions.as_deref()).await?;
+                println!("\n[switched to {}, the conversation so far was replayed]", client.model());
+            }
+            // A ping that can't be sent means the connection is going, the read task reports it and reconnecting takes over
+            Command::Internal(InternalCommand::Heartbeat) => {
+                if let Err(e) = client.ping().await {
+                    tracing::warn!(error = %e, "heartbeat ping failed");
+                }
+            }
+            Command::Internal(InternalCommand::Reconnect) => {
+                println!("\n[connection lost, reconnecting]");
+                client.errors().lock().unwrap().count(ErrorKind::ConnectionLost);
+                match client.reconnect(args.on_reconnect).await {
+                    Ok(()) => println!("\n[reconnected, {}]", carried_over(args.on_reconnect)),
+                    Err(e) if text_fallback.is_some() => {
+                        falling_back = true;
+                        println!("\n[{}; carrying on in text until the realtime service is back, type your messages]", e);
+                        retry_later(client.handle());
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Command::Internal(InternalCommand::FallbackVoice(reason)) => {
+                let rejected = client.voice().as_str();
+                if client.voice() == args.fallback_voice {
+                    eprintln!("\n[the server rejected the fallback voice {} too ({}), responses may have no audio]", rejected, reason);
+                } else {
+                    eprintln!("\n[voice {} rejected ({}), falling back to {}]", rejected, reason, args.fallback_voice.as_str());
+                    client.set_voice(args.fallback_voice);
+                    client.update_session().await?;
+                }
+            }
+            Command::Internal(InternalCommand::Sleep | InternalCommand::Wake) => {
+                let sleep = command == Command::Internal(InternalCommand::Sleep);
+                if sleep != asleep {
+                    asleep = sleep;
+                    if let Some(microphone) = &microphone {
+                        let _ = microphone.send(if asleep { InputCommand::Close } else { InputCommand::Open });
+                    }
+                    client.set_audio_asleep(asleep).await?;
+
+                    match asleep {
+                        true => println!("\n[idle, audio devices closed to save power, press enter to wake them]"),
+                        false => println!("\n[audio devices reopened]"),
+                    }
+                }
+            }
+            Command::Pin(number) | Command::Unpin(number) => {
+                let pin = matches!(command, Command::Pin(_));
+                let item = {
+                    let conversation = client.conversation();
+                    let conversation = conversation.lock().unwrap();
+                    let items = conversation.items();
+                    number.unwrap_or(items.len()).checked_sub(1).and_then(|index| items.get(index)).map(|item| (item.id.clone(), item.text()))
+                };
+
+                match item {
+                    Some((item_id, text)) => {
+                        client.set_pinned(&item_id, pin).await?;
+                        println!("\n[{}: {}]", if pin { "pinned" } else { "unpinned" }, text_layout::truncate(text.trim(), PIN_PREVIEW_WIDTH));
+                    }
+                    None => eprintln!("\n[no such item, /inspect lists them by number]"),
+                }
+            }
+            Command::Internal(InternalCommand::TranslateItem { item_id, text }) => {
+                // A missing translation isn't worth ending the lesson over
+                if let Err(e) = client.translate_item(&item_id, &text).await {
+                    eprintln!("\n[could not translate: {}]", e);
+                }
+            }
+            Command::SetSpeed(speed) => client.set_playback_speed(speed).await?,
+            Command::Internal(InternalCommand::RecordTalkOver { item_id, talk_over }) => client.record_talk_over(&item_id, &talk_over).await?,
+            Command::Internal(InternalCommand::SetSentiment { item_id, role, sentiment }) => client.set_sentiment(&item_id, &role, &sentiment).await?,
+            Command::Internal(InternalCommand::TitleChapter(item_id)) => {
+                if let Err(e) = client.title_chapter(&item_id).await {
+                    eprintln!("\n[could not title the chapter: {}]", e);
+                    // No title is coming to hang up on
+                    if last_chapter_requested {
+                        break;
+                    }
+                }
+            }
+            Command::SetInstructions(instructions) => {
+                client.change_instructions(&instructions).await?;
+                println!("\n[instructions changed, from the next response on]");
+            }
+            Command::Inspect | Command::Logs(..) | Command::Captions | Command::EditInstructions => {}  // Handled by the stdin reader
+            Command::SetCaptions(language) => {
+                client.set_captions(language.as_deref()).await?;
+                match language {
+                    Some(language) => println!("\n[captions in {}, /captions shows them]", language),
+                    None => println!("\n[captions off]"),
+                }
+            }
+            Command::Internal(InternalCommand::TranslateCaption { caption_id, text }) => {
+                if let Err(e) = client.translate_caption(&caption_id, &text).await {
+                    eprintln!("\n[could not caption: {}]", e);
+                }
+            }
+            Command::ShowSession => match client.session() {
+                Some(session) => println!("\n{}", serde_json::to_string_pretty(&session)?),
+                None => println!("\n[the server hasn't acknowledged a session yet]"),
+            },
+            Command::ShowPending => {
+                let pending = client.pending();
+                let pending = pending.lock().unwrap();
+                println!();
+                for operation in pending.operations() {
+                    println!(
+                        "{} {} {:.1}s ago, awaiting {}{}",
+                        if operation.overdue { "!" } else { " " },
+                        operation.operation,
+                        operation.sent.elapsed().as_secs_f64(),
+                        operation.awaiting,
+                        operation.item_id.as_ref().map(|item_id| format!(" for {}", item_id)).unwrap_or_default()
+                    );
+                }
+                if pending.operations().next().is_none() {
+                    println!("[nothing waiting for the server]");
+                }
+            }
+            Command::Pause | Command::Resume => {
+                let pause = command == Command::Pause;
+                if pause != paused {
+                    paused = pause;
+                    client.set_paused(paused).await?;
+                    println!("\n[call {}]", if paused { "paused, /resume to carry on" } else { "resumed" });
+
+                    if args.announce_pause {
+                        let note = if paused { "The user paused the call." } else { "The user resumed the call." };
+                        client.send_system_message(note).await?;
+                    }
+                }
+            }
+            Command::Mute | Command::Unmute => {
+                let mute = command == Command::Mute;
+                if mute != muted {
+                    muted = mute;
+                    mic_muted.store(muted, Ordering::Relaxed);
+                    client.set_muted(muted).await?;
+                    let recorded = if args.record_mic.is_some() { ", still recorded off the record" } else { "" };
+                    println!("\n[microphone {}]", if muted { format!("muted{}, /unmute to be heard again", recorded) } else { "unmuted".to_string() });
+                }
+            }
+            Command::Talk if !args.push_to_talk => eprintln!("\n[/talk is for --push-to-talk, /commit ends a manual turn]"),
+            // The microphone ends the turn once the gate has let out the last of it
+            Command::Talk | Command::Commit if talking.load(Ordering::Relaxed) => talking.store(false, Ordering::Relaxed),
+            Command::Talk => {
+                talking.store(true, Ordering::Relaxed);
+                println!("\n[talking, enter or /commit sends it]");
+            }
+            Command::Internal(InternalCommand::RespondToTurn) => client.create_response().await?,
+            Command::Commit | Command::Internal(InternalCommand::Commit) => {
+                if let Err(e) = client.commit_turn().await {
+                    eprintln!("\n[not sent: {}]", e);
+                }
+            }
+            // The microphone keeps capturing while paused, its audio is dropped here (muted audio isn't sent at all)
+            Command::Internal(InternalCommand::AppendAudio(_) | InternalCommand::GatedAudio(_)) if paused || !mic_open => {}
+            Command::Internal(InternalCommand::GatedAudio(samples)) => client.note_gated_audio(samples).await?,
+            Command::Internal(InternalCommand::SetMicOpen(open)) => mic_open = open,
+            Command::Internal(InternalCommand::SetMicPolicy(policy)) => client.set_mic_policy(policy).await?,
+            // A bad chunk is dropped rather than ending the call
+            Command::Internal(InternalCommand::AppendAudio(base64_audio_data)) => match client.input_audio_buffer_append(&base64_audio_data).await {
+                Err(e) if e.is::<client::BadAudioChunk>() => eprintln!("\n[microphone audio dropped: {}]", e),
+                result => result?,
+            },
+            Command::Internal(InternalCommand::CancelResponse) => client.cancel_response().await?,
+            Command::Internal(InternalCommand::RetryResponse(nudge)) => client.retry_response(&nudge).await?,
+            Command::Internal(InternalCommand::TruncateItem { item_id, content_index, audio_end_ms }) => {
+                client.truncate_item(&item_id, content_index, audio_end_ms).await?
+            }
+            Command::Internal(InternalCommand::RunTools(calls)) if args.approve_tools => {
+                pending_tools.extend(calls);
+                prompt_tool_approval(pending_tools.front());
+            }
+            Command::Internal(InternalCommand::RunTools(calls)) => {
+                for call in &calls {
+                    let output = run_tool(&mut client, &tools, call, &quick_actions).await?;
+                    client.send_function_call_output(&call.call_id, &output).await?;
+                }
+                client.create_response().await?;
+            }
+            Command::ApproveTool(_) | Command::DenyTool(_) if pending_tools.is_empty() => {
+                eprintln!("No tool call is waiting for approval");
+            }
+            Command::ApproveTool(arguments) => {
+                let mut call = pending_tools.pop_front().unwrap();
+                if let Some(arguments) = arguments {
+                    call.arguments = arguments;
+                }
+                let output = run_tool(&mut client, &tools, &call, &quick_actions).await?;
+                client.send_function_call_output(&call.call_id, &output).await?;
 
-    // Send a user message
-    client.send_user_message_content(serde_json::json!({"text": "Hello, AI!"})).await?;
+                // Respond once every call of the turn has been settled
+                match pending_tools.front() {
+                    Some(next) => prompt_tool_approval(Some(next)),
+                    None => client.create_response().await?,
+                }
+            }
+            Command::DenyTool(reason) => {
+                let call = pending_tools.pop_front().unwrap();
+                let output = match reason {
+                    Some(reason) => format!("The user denied this call: {}", reason),
+                    None => "The user denied this call.".to_string(),
+                };
+                client.send_function_call_output(&call.call_id, &output).await?;
+
+                match pending_tools.front() {
+                    Some(next) => prompt_tool_approval(Some(next)),
+                    None => client.create_response().await?,
+                }
+            }
+            Command::Quit if args.spoken_summary && !summary_requested => {
+                summary_requested = true;
+                client.interrupt().await?;
+
+                match client.request_summary().await {
+                    Ok(()) => {
+                        println!("\n[summing up the call before hanging up, /quit again to skip]");
+
+                        // Don't hang on forever if the summary never arrives
+                        let handle = client.handle();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(SUMMARY_TIMEOUT).await;
+                            let _ = handle.hang_up().await;
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("Skipping the summary: {}", e);
+                        break;
+                    }
+                }
+            }
+            Command::Quit if args.chapters && !last_chapter_requested => {
+                last_chapter_requested = true;
+                client.title_last_chapter().await?;
+
+                // Don't hang on forever if the title never arrives
+                let handle = client.handle();
+                tokio::spawn(async move {
+                    tokio::time::sleep(CHAPTER_TIMEOUT).await;
+                    let _ = handle.hang_up().await;
+                });
+            }
+            Command::Quit | Command::Internal(InternalCommand::TimeUp) => break,
+        }
+    }
+
+    // Ctrl-C from here on exits rather than waiting for the files to be saved
+    drop(commands);
+
+    summary.model = Some(client.model().to_string());
+    summary.exit_reason = if client.usage().lock().unwrap().is_exceeded() {
+        ExitReason::BudgetExceeded
+    } else if time_up {
+        ExitReason::TimeLimit
+    } else {
+        ExitReason::HungUp
+    };
+
+    if !falling_back {
+        client.disconnect().await?;
+    }
+    println!("Usage: {}", client.usage_summary());
+
+    save_artifacts(&client, &args, &metadata, encryption.as_ref(), summary)?;
+
+    // The call is over either way, a recap that can't be sent is only reported
+    if let (Some(transport), Some(to)) = (&recap, &args.recap_to) {
+        let items = client.transcript();
+        let recap = match items.is_empty() {
+            true => Err("nothing was said".into()),
+            false => Recap::new(&client.chat_completions(), &metadata, &items).await,
+        };
+        match recap {
+            Ok(recap) => match recap.send(transport, to, args.recap_from.as_deref()).await {
+                Ok(()) => println!("Sent the recap to {}", to),
+                Err(e) => eprintln!("Failed to send the recap: {}", e),
+            },
+            Err(e) => eprintln!("Failed to summarize the call: {}", e),
+        }
+    }
+
+    // Losing the history entry isn't worth failing the call over
+    let call = CallRecord::new(started, client.model(), args.purpose.as_deref(), args.transcript.clone(), arguments);
+    if let Err(e) = history::record(call) {
+        eprintln!("Failed to save the call history: {}", e);
+    }
+
+    client.shutdown().await?;
+    Ok(())
+}
+
+/// Writes the transcript, recording and protocol dump requested on the command line
+fn save_artifacts(
+    client: &RealtimeClient,
+    args: &DialArgs,
+    metadata: &SessionMetadata,
+    encryption: Option<&Encryption>,
+    summary: &mut SessionSummary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut save = |kind: &'static str, path: &Path, data: &[u8]| -> Result<(), Box<dyn std::error::Error>> {
+        write_file(path, data, encryption)?;
+        println!("Saved {}", path.display());
+        summary.add_artifact(kind, path);
+        Ok(())
+    };
+
+    if let Some(path) = &args.transcript {
+        let transcript = Transcript {
+            metadata: metadata.clone(),
+            items: client.transcript(),
+        };
+        save("transcript", path, &serde_json::to_vec_pretty(&transcript)?)?;
+    }
 
-    // Keep the main function alive to simulate continuous interaction
+    let recorder = client.recorder();
+    let recorder = recorder.lock().unwrap();
+    if let Some(path) = &args.record {
+        save("recording", path, &recorder.audio_wav()?)?;
+    }
+    if let Some(path) = &args.record_mic {
+        save("microphone_recording", path, &recorder.microphone_wav()?)?;
+    }
+    if let Some(path) = &args.dump {
+        save("dump", path, &recorder.events_jsonl())?;
+    }
+    if let Some(path) = &args.snapshot {
+        save("snapshot", path, &serde_json::to_vec_pretty(&client.snapshot())?)?;
+    }
+
+    Ok(())
+}
+
+/// What a reconnect kept of the conversation, for telling the user
+fn carried_over(policy: ReplayPolicy) -> &'static str {
+    match policy {
+        ReplayPolicy::Full => "the conversation so far was replayed",
+        ReplayPolicy::Summarized => "a summary of the conversation was replayed",
+        ReplayPolicy::Fresh => "starting fresh",
+    }
+}
+
+/// Tries the realtime service again in a while, when falling back to text
+fn retry_later(handle: ClientHandle) {
+    tokio::spawn(async move {
+        tokio::time::sleep(fallback::RETRY_INTERVAL).await;
+        let _ = handle.send(Command::Internal(InternalCommand::Reconnect)).await;
+    });
+}
+
+/// Runs a tool call, pressing the keys of a send_dtmf call in the call's audio and showing the actions of a suggest_actions call
+async fn run_tool(
+    client: &mut RealtimeClient,
+    tools: &Tools,
+    call: &tools::ToolCall,
+    quick_actions: &tokio::sync::watch::Sender<Option<(Vec<String>, usize)>>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(keys) = tools.dtmf_keys(call) {
+        client.press_keys(&keys).await?;
+    }
+    if let Some(actions) = tools.quick_actions(call) {
+        println!("\n[quick actions, press a number to send one]");
+        for (number, action) in actions.iter().enumerate() {
+            println!("  {} {}", (number + 1).to_string().bold(), action);
+        }
+        quick_actions.send_replace(Some((actions, user_messages(&client.conversation()))));
+    }
+    Ok(tools.call(call))
+}
+
+/// The quick action a typed number picks, or the text as typed
+///
+/// Only the latest actions can be picked, and only until the user says something else, so
+/// a number answering a later question isn't taken for a stale choice.
+fn pick_quick_action(quick_actions: Option<(Vec<String>, usize)>, user_messages: usize, text: String) -> String {
+    let Some((actions, _)) = quick_actions.filter(|(_, offered_at)| *offered_at == user_messages) else {
+        return text;
+    };
+    match text.trim().parse::<usize>() {
+        Ok(number) if (1..=actions.len()).contains(&number) => {
+            println!("{}", format!("sending: {}", actions[number - 1]).dim());
+            actions[number - 1].clone()
+        }
+        _ => text,
+    }
+}
+
+/// Messages of the user's in the conversation so far, spoken or typed
+fn user_messages(conversation: &Mutex<conversation::ConversationTracker>) -> usize {
+    let conversation = conversation.lock().unwrap();
+    conversation.items().iter().filter(|item| item.role == ConversationItemRole::User).count()
+}
+
+/// Shows a tool call waiting for approval and how to answer it
+fn prompt_tool_approval(call: Option<&tools::ToolCall>) {
+    if let Some(call) = call {
+        println!("\n[approve tool call] {} {}", call.name, call.arguments);
+        println!("  /approve, /deny [reason] or /edit <JSON arguments>");
+    }
+}
+
+/// How close together lines have to come to be one paste, `--paste-window` or a default for where the terminal is
+fn paste_window(milliseconds: Option<u64>, over_ssh: bool) -> std::time::Duration {
+    match (milliseconds, over_ssh) {
+        (Some(milliseconds), _) => std::time::Duration::from_millis(milliseconds),
+        (None, true) => REMOTE_PASTE_WINDOW,
+        (None, false) => PASTE_WINDOW,
+    }
+}
+
+/// Adds the lines following `line` within `window` of each other to it, returning whether there were any
+///
+/// The wait starts over with each line, so a long paste held up part way, e.g. by the network,
+/// still makes one message as long as no single gap is longer than the window.
+async fn read_paste<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut tokio::io::Lines<R>, line: &mut String, window: std::time::Duration) -> bool {
+    let mut pasted = false;
+    while let Ok(Ok(Some(more))) = tokio::time::timeout(window, lines.next_line()).await {
+        line.push('\n');
+        line.push_str(&more);
+        pasted = true;
+    }
+    pasted
+}
+
+/// The next line typed, or the number of a quick action on offer as soon as its key is pressed
+///
+/// While quick actions are on offer, and still current, the terminal hands over keys one at a
+/// time; a number picks one without enter, any other key starts a line as usual. None once the
+/// input is closed, ctrl-d included.
+async fn next_line_or_pick<R: tokio::io::AsyncBufRead + Unpin>(
+    lines: &mut tokio::io::Lines<R>,
+    offers: &mut tokio::sync::watch::Receiver<Option<(Vec<String>, usize)>>,
+    by_key: bool,
+    conversation: &Mutex<conversation::ConversationTracker>,
+) -> Option<String> {
+    enum Next {
+        Line(Option<String>),
+        Key(Option<u8>),
+        Offer,
+    }
+
+    let mut typing = false;     // A key other than a number started a line
     loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+        let offered_at = offers.borrow_and_update().as_ref().map(|(actions, offered_at)| (actions.len(), *offered_at));
+        let picking = by_key && !typing && offered_at.is_some_and(|(_, offered_at)| offered_at == user_messages(conversation));
+
+        let keys = picking.then(|| full_screen::keys_at_a_time().ok());
+        let read = async {
+            match picking {
+                true => Next::Key(lines.get_mut().fill_buf().await.ok().and_then(|buffered| buffered.first().copied())),
+                false => Next::Line(lines.next_line().await.ok().flatten()),
+            }
+        };
+        // A new offer, or one taken back, changes how the next key is read
+        let next = tokio::select! {
+            next = read => next,
+            Ok(()) = offers.changed() => Next::Offer,
+        };
+        drop(keys);
+
+        match next {
+            Next::Line(line) => return line,
+            Next::Key(None | Some(0x04)) => return None,
+            Next::Key(Some(key @ b'1'..=b'9'))
+                if offered_at.is_some_and(|(count, offered_at)| usize::from(key - b'0') <= count && offered_at == user_messages(conversation)) =>
+            {
+                lines.get_mut().consume(1);
+                println!();
+                return Some(char::from(key).to_string());
+            }
+            Next::Key(_) => typing = true,
+            Next::Offer => {}
+        }
+    }
+}
+
+/// Streams the microphone, or whatever stands in for it, to the call, converted to the server format
+///
+/// The conversion follows the measured rate of the device rather than the one it claims, so a
+/// drifting microphone clock doesn't slowly push the audio out of step with the call. The
+/// capture forks into two sinks: the recorder keeps all of it, muted or not, and of what goes to
+/// the call a gate, if any, lets through only speech, or only what's said while pushing to talk.
+/// Devices with small buffers call back every few milliseconds, so what goes to the call is
+/// gathered into appends of MIN_APPEND_SAMPLES.
+fn start_microphone(
+    handle: ClientHandle,
+    recorder: Arc<Mutex<Recorder>>,
+    mut gate: Option<MicGate>,
+    muted: Arc<AtomicBool>,
+    (sample_receiver, input_commands): (std::sync::mpsc::Receiver<InputChunk>, std::sync::mpsc::Sender<InputCommand>),
+) -> std::sync::mpsc::Sender<InputCommand> {
+
+    // The input stream delivers on a std channel, forward from a plain thread so it doesn't hold up runtime shutdown
+    std::thread::spawn(move || {
+        let mut clock: Option<((u32, u16), DriftEstimator)> = None;
+        let mut resampler = StreamResampler::default();
+        let mut unsent: Vec<f32> = Vec::with_capacity(MIN_APPEND_SAMPLES * 2);
+
+        while let Ok(chunk) = sample_receiver.recv() {
+            // A stream rebuilt with another format is another clock
+            let format = (chunk.sample_rate, chunk.channels);
+            if clock.as_ref().is_none_or(|(current, _)| *current != format) {
+                clock = Some((format, DriftEstimator::new("Input device", chunk.sample_rate)));
+                resampler = StreamResampler::default();
+            }
+            let (_, clock) = clock.as_mut().unwrap();
+            clock.add_frames(chunk.samples.len() / chunk.channels.max(1) as usize);
+
+            let ratio = SERVER_SAMPLE_RATE as f64 / (chunk.sample_rate as f64 * clock.rate_factor());
+            let mut server_samples = resampler.process(&downmix(&chunk.samples, chunk.channels), ratio);
+            let muted = muted.load(Ordering::Relaxed);
+            recorder.lock().unwrap().add_microphone(&server_samples, muted);
+            // Muted audio is only recorded, what was said before goes now
+            if muted {
+                if !unsent.is_empty() && handle.append_audio_blocking(base64_encode_audio(&unsent)).is_err() {
+                    break;
+                }
+                unsent.clear();
+                continue;
+            }
+            if let Some(gate) = gate.as_mut() {
+                let captured = server_samples.len();
+                server_samples = gate.process(&server_samples);
+                // Releasing push-to-talk ends the turn, after the last of the audio it let through
+                if gate.released() {
+                    unsent.extend(&server_samples);
+                    if !unsent.is_empty() && handle.append_audio_blocking(base64_encode_audio(&unsent)).is_err() {
+                        break;
+                    }
+                    unsent.clear();
+                    if handle.commit_blocking().is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                // Quiet all the same, e.g. for --auto-commit to time
+                if server_samples.is_empty() {
+                    // The end of the speech goes now rather than with the next
+                    if !unsent.is_empty() && handle.append_audio_blocking(base64_encode_audio(&unsent)).is_err() {
+                        break;
+                    }
+                    unsent.clear();
+                    if handle.gated_audio_blocking(captured).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            unsent.extend(&server_samples);
+            if unsent.len() < MIN_APPEND_SAMPLES {
+                continue;
+            }
+            if handle.append_audio_blocking(base64_encode_audio(&unsent)).is_err() {
+                break;
+            }
+            unsent.clear();
+        }
+    });
+
+    input_commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_line(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn subcommand_arguments_follow_the_subcommand() {
+        let arguments = |line: &str, subcommand| subcommand_arguments(&command_line(line), subcommand);
+        assert_eq!(arguments("hotline dial --voice verse", "dial"), ["--voice", "verse"]);
+        assert_eq!(arguments("hotline --config hotline.toml --log-level=debug chat --no-mic", "chat"), ["--no-mic"]);
+        assert!(arguments("hotline dial", "dial").is_empty());
+        assert!(arguments("hotline devices", "dial").is_empty());
+
+        // Global options' values that happen to be the subcommand's name
+        assert_eq!(arguments("hotline --profile dial dial --text-only", "dial"), ["--text-only"]);
+        assert_eq!(arguments("hotline --log chat chat --instructions chat", "chat"), ["--instructions", "chat"]);
+        assert_eq!(arguments("hotline --config=dial dial --instructions dial", "dial"), ["--instructions", "dial"]);
+    }
+
+    #[test]
+    fn command_names_include_every_subcommand() {
+        let names = command_names();
+        for name in ["dial", "chat", "devices", "history", "redial", "test-audio", "help"] {
+            assert!(names.iter().any(|known| known == name), "{} missing", name);
+        }
+    }
+
+    #[test]
+    fn paste_window_allows_for_ssh() {
+        assert_eq!(paste_window(None, false), PASTE_WINDOW);
+        assert_eq!(paste_window(None, true), REMOTE_PASTE_WINDOW);
+        assert_eq!(paste_window(Some(500), false), std::time::Duration::from_millis(500));
+        assert_eq!(paste_window(Some(0), true), std::time::Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn a_paste_held_up_part_way_is_one_message() {
+        use tokio::io::AsyncWriteExt;
+        let (mut terminal, input) = tokio::io::duplex(1024);
+        let mut lines = BufReader::new(input).lines();
+        let typing = tokio::spawn(async move {
+            terminal.write_all(b"first\nsecond\n").await.unwrap();
+            // A packet late, but within the window
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            terminal.write_all(b"third\n").await.unwrap();
+            // Typed well after
+            tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+            terminal.write_all(b"typed\n").await.unwrap();
+        });
+
+        let mut line = lines.next_line().await.unwrap().unwrap();
+        assert!(read_paste(&mut lines, &mut line, REMOTE_PASTE_WINDOW).await);
+        assert_eq!(line, "first\nsecond\nthird");
+
+        let mut line = lines.next_line().await.unwrap().unwrap();
+        typing.await.unwrap();
+        assert!(!read_paste(&mut lines, &mut line, REMOTE_PASTE_WINDOW).await);
+        assert_eq!(line, "typed");
+    }
+
+    #[test]
+    fn quick_actions_are_picked_by_number_while_current() {
+        let offered = || Some((vec!["Yes".to_string(), "No".to_string()], 2));
+        assert_eq!(pick_quick_action(offered(), 2, " 2 ".to_string()), "No");
+        assert_eq!(pick_quick_action(offered(), 2, "3".to_string()), "3");
+        assert_eq!(pick_quick_action(offered(), 2, "0".to_string()), "0");
+        assert_eq!(pick_quick_action(offered(), 2, "2 please".to_string()), "2 please");
+
+        // The user said something else since
+        assert_eq!(pick_quick_action(offered(), 3, "1".to_string()), "1");
+        assert_eq!(pick_quick_action(None, 2, "1".to_string()), "1");
+    }
+
+    #[tokio::test]
+    async fn a_number_picks_a_quick_action_without_enter() {
+        use tokio::io::AsyncWriteExt;
+        let (mut terminal, input) = tokio::io::duplex(1024);
+        let mut lines = BufReader::new(input).lines();
+        let conversation = Mutex::new(conversation::ConversationTracker::default());
+        let (offer, mut offers) = tokio::sync::watch::channel(None);
+        let actions = |offered_at| Some((vec!["Yes".to_string(), "No".to_string(), "Maybe".to_string()], offered_at));
+        let waiting = std::time::Duration::from_millis(100);
+
+        // Nothing on offer, a number waits for enter like any other line
+        terminal.write_all(b"2").await.unwrap();
+        assert!(tokio::time::timeout(waiting, next_line_or_pick(&mut lines, &mut offers, true, &conversation)).await.is_err());
+        terminal.write_all(b"\n").await.unwrap();
+        assert_eq!(next_line_or_pick(&mut lines, &mut offers, true, &conversation).await.as_deref(), Some("2"));
+
+        // Offered while a line is awaited
+        let (picked, _) = tokio::join!(next_line_or_pick(&mut lines, &mut offers, true, &conversation), async {
+            offer.send_replace(actions(0));
+            tokio::time::sleep(waiting).await;
+            terminal.write_all(b"3").await.unwrap();
+        });
+        assert_eq!(picked.as_deref(), Some("3"));
+
+        // A number past the actions, or any other key, starts a line
+        terminal.write_all(b"4").await.unwrap();
+        assert!(tokio::time::timeout(waiting, next_line_or_pick(&mut lines, &mut offers, true, &conversation)).await.is_err());
+        terminal.write_all(b" apples\n").await.unwrap();
+        assert_eq!(next_line_or_pick(&mut lines, &mut offers, true, &conversation).await.as_deref(), Some("4 apples"));
+
+        // Not once the user said something since, nor when keys can't be read one at a time
+        conversation.lock().unwrap().handle_event(&serde_json::json!({"type": "conversation.item.created", "item": {"id": "item_1", "type": "message", "role": "user"}}));
+        terminal.write_all(b"1").await.unwrap();
+        assert!(tokio::time::timeout(waiting, next_line_or_pick(&mut lines, &mut offers, true, &conversation)).await.is_err());
+        offer.send_replace(actions(1));
+        assert!(tokio::time::timeout(waiting, next_line_or_pick(&mut lines, &mut offers, false, &conversation)).await.is_err());
+        terminal.write_all(b"\n").await.unwrap();
+        assert_eq!(next_line_or_pick(&mut lines, &mut offers, false, &conversation).await.as_deref(), Some("1"));
+
+        // Ctrl-d still closes the input
+        terminal.write_all(&[0x04]).await.unwrap();
+        assert_eq!(next_line_or_pick(&mut lines, &mut offers, true, &conversation).await, None);
+    }
+
+    fn dial_args_of(line: &str) -> DialArgs {
+        match Cli::try_parse_from(command_line(line)) {
+            Ok(Cli { command: Some(CliCommand::Dial(args)), .. }) => *args,
+            _ => panic!("{} doesn't parse", line),
+        }
+    }
+
+    #[test]
+    fn file_problems_name_the_files_a_call_would_fail_on() {
+        let directory = std::env::temp_dir().join(format!("hotline-lint-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let not_audio = directory.join("tick.wav");
+        std::fs::write(&not_audio, "not audio").unwrap();
+        let missing = directory.join("missing");
+
+        let args = dial_args_of(&format!(
+            "hotline dial --keyfile {missing}/key --thinking-sound {not_audio} --transcript {missing}/{{date}}.json --dump {directory}/dump.jsonl --record call.wav",
+            missing = missing.display(),
+            not_audio = not_audio.display(),
+            directory = directory.display()
+        ));
+        let problems = file_problems(&args);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert_eq!(problems[0], format!("keyfile {}/key doesn't exist", missing.display()));
+        assert!(problems[1].starts_with(&format!("thinking sound {} can't be played", not_audio.display())));
+        assert_eq!(problems[2], format!("transcript is saved in {}, which doesn't exist", missing.display()));
+
+        // Built-in sounds and files in the working directory are fine
+        assert!(file_problems(&dial_args_of("hotline dial --thinking-sound hum --record call.wav")).is_empty());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn the_event_buffer_can_be_set_in_the_config() {
+        let config: Config = toml::from_str("[dial]\nevent-buffer = 500\n[profiles.remote]\nevent-buffer = 2000\n").unwrap();
+        let event_buffer = |profile, typed: &str| {
+            let (args, _) = dial_args(&config, config.dial_arguments(profile).unwrap(), command_line(typed)).unwrap();
+            args.event_buffer
+        };
+        assert_eq!(event_buffer(None, ""), 500);
+        assert_eq!(event_buffer(Some("remote"), ""), 2000);
+        // Typed wins
+        assert_eq!(event_buffer(Some("remote"), "--event-buffer 50"), 50);
+
+        let (args, _) = dial_args(&Config::default(), Vec::new(), Vec::new()).unwrap();
+        assert_eq!(args.event_buffer, DEFAULT_EVENT_BUFFER);
+    }
+
+    #[test]
+    fn gateway_headers_can_be_set_in_the_config() {
+        let config: Config = toml::from_str("[dial]\nurl = \"wss://gateway.example/v1/realtime\"\nheader = { X-Team = \"voice\", X-Route = \"eu=1\" }\n").unwrap();
+        let (args, _) = dial_args(&config, config.dial_arguments(None).unwrap(), command_line("--header X-Trace=on")).unwrap();
+        assert_eq!(args.url, "wss://gateway.example/v1/realtime");
+        assert_eq!(
+            args.headers,
+            [("X-Route", "eu=1"), ("X-Team", "voice"), ("X-Trace", "on")].map(|(name, value)| (name.to_string(), value.to_string()))
+        );
     }
 }
@@ -0,0 +1,151 @@
+use crossterm::style::Stylize;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+use termimad::MadSkin;
+
+use crate::text_layout::wrap;
+
+// Theme for fenced code blocks, one of syntect's defaults
+const CODE_THEME: &str = "base16-ocean.dark";
+
+// Loading the syntax definitions takes a moment, only done once code shows up
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// The language of a fence line such as "```rust", None if the line isn't a fence
+pub fn fence_language(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix("```").map(str::trim)
+}
+
+/// Renders one line of prose: emphasis, code spans and list bullets
+pub fn render_inline(line: &str) -> String {
+    let indent = line.len() - line.trim_start().len();
+    let (line, bullet) = match line.trim_start() {
+        rest if rest.starts_with("- ") || rest.starts_with("* ") || rest.starts_with("+ ") => (&rest[2..], "• "),
+        rest => (rest, ""),
+    };
+
+    format!("{}{}{}", &" ".repeat(indent), bullet, MadSkin::default().inline(line))
+}
+
+/// Highlights a fenced code block line by line, keeping the parser state across lines
+pub struct CodeHighlighter {
+    lines: HighlightLines<'static>,
+}
+
+impl CodeHighlighter {
+    /// Picks the syntax by the fence language, e.g. "rust" or "py", plain text when unknown
+    pub fn new(language: &str) -> Self {
+        let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+        let theme = THEME.get_or_init(|| ThemeSet::load_defaults().themes.remove(CODE_THEME).unwrap());
+
+        let syntax = syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+        Self { lines: HighlightLines::new(syntax, theme) }
+    }
+
+    pub fn highlight(&mut self, line: &str) -> String {
+        let syntaxes = SYNTAXES.get().unwrap();
+        match self.lines.highlight_line(&format!("{}\n", line), syntaxes) {
+            Ok(ranges) => format!("{}\x1b[0m", as_24_bit_terminal_escaped(&ranges, false).trim_end_matches('\n')),
+            Err(_) => line.to_string(),
+        }
+    }
+}
+
+/// Renders a whole message as terminal lines of at most `width` columns
+///
+/// Prose is wrapped, code blocks keep their lines as they are.
+pub fn render(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut code: Option<CodeHighlighter> = None;
+
+    for line in text.lines() {
+        match (fence_language(line), &mut code) {
+            (Some(_), Some(_)) => {
+                code = None;
+                lines.push(line.dim().to_string());
+            }
+            (Some(language), None) => {
+                code = Some(CodeHighlighter::new(language));
+                lines.push(line.dim().to_string());
+            }
+            (None, Some(highlighter)) => lines.push(highlighter.highlight(line)),
+            (None, None) if line.trim().is_empty() => lines.push(String::new()),
+            (None, None) => lines.extend(wrap(line, width).iter().map(|line| render_inline(line))),
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The text as it reads, without the escapes styling it
+    fn plain(line: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().skip(1).find(|c| ('@'..='~').contains(c));
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn fences_name_their_language() {
+        assert_eq!(fence_language("```rust"), Some("rust"));
+        assert_eq!(fence_language("  ``` py "), Some("py"));
+        assert_eq!(fence_language("```"), Some(""));
+        assert_eq!(fence_language("not ```code```"), None);
+    }
+
+    #[test]
+    fn prose_gets_emphasis_code_spans_and_bullets() {
+        let line = render_inline("- **Bold** and `code`");
+        assert_eq!(plain(&line), "• Bold and code");
+        assert_ne!(line, plain(&line));
+
+        assert_eq!(plain(&render_inline("  * nested")), "  • nested");
+        assert_eq!(plain(&render_inline("+ plus")), "• plus");
+        // Not a list without the space
+        assert_eq!(plain(&render_inline("-5 degrees")), "-5 degrees");
+    }
+
+    #[test]
+    fn code_blocks_are_highlighted_and_left_unwrapped() {
+        let text = "Here's how:\n\n```rust\nfn main() { println!(\"a line longer than the terminal is wide\"); }\n```\nThat prints a line that's long enough to wrap.";
+        let lines = render(text, 30);
+        let plain_lines: Vec<String> = lines.iter().map(|line| plain(line)).collect();
+        assert_eq!(
+            plain_lines,
+            [
+                "Here's how:",
+                "",
+                "```rust",
+                "fn main() { println!(\"a line longer than the terminal is wide\"); }",
+                "```",
+                "That prints a line that's long",
+                "enough to wrap.",
+            ]
+        );
+        // Colored by the syntax, in 24-bit color
+        assert!(lines[3].contains("\x1b[38;2;"));
+        assert_ne!(lines[3].matches("\x1b[38;2;").count(), 1);
+
+        // An unknown language, or none, is plain text, still kept as is
+        let mut highlighter = CodeHighlighter::new("no-such-language");
+        assert_eq!(plain(&highlighter.highlight("  **not markdown**")), "  **not markdown**");
+        // An unclosed block runs to the end
+        assert_eq!(render("```\n- one", 80).iter().map(|line| plain(line)).collect::<Vec<_>>(), ["```", "- one"]);
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::conversation::SIDE_CHANNEL_METADATA;
+
+// Limits the API puts on response metadata, one key is kept for marking out-of-band responses
+const MAX_KEYS: usize = 15;
+const MAX_KEY_LENGTH: usize = 64;
+const MAX_VALUE_LENGTH: usize = 512;
+
+/// Who called and why, stamped into responses and everything saved from the call
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,             // User name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,            // What the call is about
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,   // Free-form key/values
+}
+
+impl SessionMetadata {
+    /// Builds the metadata, checking it fits the API limits
+    pub fn new(caller: Option<String>, purpose: Option<String>, custom: Vec<(String, String)>) -> Result<Self, String> {
+        let metadata = Self { caller, purpose, custom: custom.into_iter().collect() };
+
+        for reserved in ["caller", "purpose", SIDE_CHANNEL_METADATA.0] {
+            if metadata.custom.contains_key(reserved) {
+                return Err(format!("Metadata key {:?} is reserved", reserved));
+            }
+        }
+
+        let pairs = metadata.pairs();
+        if pairs.len() > MAX_KEYS {
+            return Err(format!("Too much session metadata: {} keys, at most {} are allowed", pairs.len(), MAX_KEYS));
+        }
+        for (key, value) in &pairs {
+            if key.is_empty() || key.chars().count() > MAX_KEY_LENGTH {
+                return Err(format!("Metadata key {:?} must be 1 to {} characters", key, MAX_KEY_LENGTH));
+            }
+            if value.chars().count() > MAX_VALUE_LENGTH {
+                return Err(format!("Metadata value for {:?} is longer than {} characters", key, MAX_VALUE_LENGTH));
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs().is_empty()
+    }
+
+    /// All metadata as key/value pairs, caller and purpose first
+    pub fn pairs(&self) -> Vec<(&str, &str)> {
+        let mut pairs = Vec::new();
+        if let Some(caller) = &self.caller {
+            pairs.push(("caller", caller.as_str()));
+        }
+        if let Some(purpose) = &self.purpose {
+            pairs.push(("purpose", purpose.as_str()));
+        }
+        pairs.extend(self.custom.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+        pairs
+    }
+
+    /// Metadata object for `response.create`
+    pub fn to_json(&self) -> Map<String, Value> {
+        self.pairs()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect()
+    }
+}
+
+/// Parses a `key=value` command line argument
+pub fn parse_key_value(argument: &str) -> Result<(String, String), String> {
+    match argument.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("Expected key=value, got {:?}", argument)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(count: usize) -> Vec<(String, String)> {
+        (0..count).map(|n| (format!("key{}", n), "value".to_string())).collect()
+    }
+
+    #[test]
+    fn leaves_one_key_for_marking_out_of_band_responses() {
+        let metadata = SessionMetadata::new(Some("Ada".to_string()), Some("standup".to_string()), custom(13)).unwrap();
+        assert_eq!(metadata.pairs().len(), 15);
+        assert_eq!(metadata.pairs()[..2], [("caller", "Ada"), ("purpose", "standup")]);
+
+        let error = SessionMetadata::new(Some("Ada".to_string()), None, custom(15)).unwrap_err();
+        assert!(error.contains("16 keys, at most 15"));
+    }
+
+    #[test]
+    fn refuses_reserved_keys_and_oversized_pairs() {
+        for reserved in ["caller", "purpose", "hotline"] {
+            let error = SessionMetadata::new(None, None, vec![(reserved.to_string(), "x".to_string())]).unwrap_err();
+            assert!(error.contains("reserved"), "{}", reserved);
+        }
+        // Tags of side channel responses are no keys of their own
+        assert!(SessionMetadata::new(None, None, vec![("translation_of".to_string(), "x".to_string())]).is_ok());
+
+        assert!(SessionMetadata::new(None, None, vec![("k".repeat(65), "x".to_string())]).is_err());
+        assert!(SessionMetadata::new(None, None, vec![("k".to_string(), "v".repeat(513))]).is_err());
+        assert!(SessionMetadata::new(None, None, vec![("k".repeat(64), "v".repeat(512))]).is_ok());
+    }
+
+    #[test]
+    fn parses_key_value_arguments() {
+        assert_eq!(parse_key_value(" team =voice=2").unwrap(), ("team".to_string(), "voice=2".to_string()));
+        assert_eq!(parse_key_value("empty=").unwrap(), ("empty".to_string(), String::new()));
+        assert!(parse_key_value("=value").is_err());
+        assert!(parse_key_value("novalue").is_err());
+    }
+}
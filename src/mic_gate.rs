@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+
+/// Microphone audio kept from before speech starts, unless asked otherwise
+pub const DEFAULT_PRE_ROLL: Duration = Duration::from_millis(300);
+
+// Speech is told from silence 20 ms at a time
+pub const FRAME: usize = SERVER_SAMPLE_RATE as usize / 50;
+pub const FRAME_MS: u64 = 20;
+// Louder than this is speech, lower than for talk-over detection as the pre-roll covers soft onsets anyway
+const SPEECH_DBFS: f32 = -45.0;
+// Loud frames in a row before the gate opens, so clicks don't open it
+const ONSET_FRAMES: usize = 2;
+// Quiet after which it closes again, longer than the server VAD's silence so it still sees the turn end
+const HANGOVER_MS: u64 = 1000;
+
+/// Whether a frame of microphone audio is loud enough to be speech
+pub fn is_speech(frame: &[f32]) -> bool {
+    let rms = (frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len().max(1) as f32).sqrt();
+    20.0 * rms.max(1e-6).log10() > SPEECH_DBFS
+}
+
+/// What opens the gate
+enum Opener {
+    Voice,                  // The local energy VAD, closing again after HANGOVER_MS of quiet
+    Key(Arc<AtomicBool>),   // Push-to-talk, open while the flag is set
+}
+
+/// Lets microphone audio through only while the user speaks, as judged by a local energy VAD or
+/// by the user pressing to talk
+///
+/// While closed the gate keeps a rolling pre-roll of the last few hundred ms. When speech starts
+/// that pre-roll goes out first, so the first syllable isn't clipped by the time it takes to
+/// notice the speech, or to press after starting to talk. Works on mono audio at SERVER_SAMPLE_RATE.
+pub struct MicGate {
+    opener: Opener,
+    pre_roll: VecDeque<f32>,        // Last samples while closed, oldest first
+    capacity: usize,                // Of the pre-roll, in samples
+    pending: Vec<f32>,              // Samples short of a whole frame
+    loud_frames: usize,             // In a row while closed
+    quiet_ms: Option<u64>,          // Since the last loud frame while open, None while closed
+    released: bool,                 // Closed by push-to-talk since last asked
+}
+
+impl MicGate {
+    pub fn new(pre_roll: Duration) -> Self {
+        Self::with_opener(Opener::Voice, pre_roll)
+    }
+
+    /// A gate open while `talking` is set, e.g. between /talk and /commit
+    pub fn push_to_talk(pre_roll: Duration, talking: Arc<AtomicBool>) -> Self {
+        Self::with_opener(Opener::Key(talking), pre_roll)
+    }
+
+    fn with_opener(opener: Opener, pre_roll: Duration) -> Self {
+        let capacity = (pre_roll.as_secs_f64() * SERVER_SAMPLE_RATE as f64) as usize;
+        Self {
+            opener,
+            pre_roll: VecDeque::with_capacity(capacity + FRAME),
+            capacity,
+            pending: Vec::new(),
+            loud_frames: 0,
+            quiet_ms: None,
+            released: false,
+        }
+    }
+
+    /// Whether push-to-talk was released since last asked, the turn is over once what was let through is sent
+    pub fn released(&mut self) -> bool {
+        std::mem::take(&mut self.released)
+    }
+
+    /// Takes captured samples, returning those to send (nothing while the user is quiet)
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.pending.extend_from_slice(samples);
+        let frames = self.pending.len() / FRAME;
+        let mut output = Vec::new();
+
+        for frame in 0..frames {
+            let frame = &self.pending[frame * FRAME..(frame + 1) * FRAME];
+            let loud = is_speech(frame);
+            let talking = match &self.opener {
+                Opener::Voice => None,
+                Opener::Key(talking) => Some(talking.load(Ordering::Relaxed)),
+            };
+
+            match self.quiet_ms.as_mut() {
+                // Released, the frame is already part of the next press' pre-roll
+                Some(_) if talking == Some(false) => {
+                    tracing::debug!("microphone gate closed by push-to-talk");
+                    self.quiet_ms = None;
+                    self.loud_frames = 0;
+                    self.released = true;
+                    self.pre_roll.extend(frame);
+                }
+                Some(quiet_ms) => {
+                    output.extend_from_slice(frame);
+                    *quiet_ms = if loud { 0 } else { *quiet_ms + FRAME_MS };
+                    if talking.is_none() && *quiet_ms >= HANGOVER_MS {
+                        tracing::debug!("microphone gate closed");
+                        self.quiet_ms = None;
+                        self.loud_frames = 0;
+                    }
+                }
+                None => {
+                    self.pre_roll.extend(frame);
+                    let excess = self.pre_roll.len().saturating_sub(self.capacity.max(ONSET_FRAMES * FRAME));
+                    self.pre_roll.drain(..excess);
+
+                    self.loud_frames = if loud { self.loud_frames + 1 } else { 0 };
+                    if talking.unwrap_or(self.loud_frames >= ONSET_FRAMES) {
+                        tracing::debug!(pre_roll_ms = self.pre_roll.len() as u64 * 1000 / SERVER_SAMPLE_RATE as u64, "microphone gate opened");
+                        output.extend(self.pre_roll.drain(..));
+                        self.quiet_ms = Some(0);
+                    }
+                }
+            }
+        }
+
+        self.pending.drain(..frames * FRAME);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames counting up from `start` at a level, so what comes out shows where it was taken from
+    fn frames(start: usize, count: usize, loud: bool) -> Vec<f32> {
+        let level = if loud { 0.5 } else { 0.0001 };
+        (start * FRAME..(start + count) * FRAME).map(|n| level + n as f32 * 1e-9).collect()
+    }
+
+    #[test]
+    fn opens_on_speech_with_the_pre_roll_and_closes_after_the_hangover() {
+        let mut gate = MicGate::new(Duration::from_millis(100));
+        assert!(gate.process(&frames(0, 50, false)).is_empty());
+        // A click doesn't open it
+        assert!(gate.process(&frames(50, 1, true)).is_empty());
+        assert!(gate.process(&frames(51, 1, false)).is_empty());
+
+        // Two loud frames do, after the 100 ms before them
+        let opened = gate.process(&frames(52, 2, true));
+        assert_eq!(opened.len(), 5 * FRAME);
+        assert_eq!(opened[..FRAME], frames(49, 1, false)[..]);
+        assert_eq!(opened[3 * FRAME..], frames(52, 2, true)[..]);
+
+        // Quiet is let through until it lasts HANGOVER_MS
+        let hangover = (HANGOVER_MS / FRAME_MS) as usize;
+        assert_eq!(gate.process(&frames(54, hangover - 1, false)).len(), (hangover - 1) * FRAME);
+        assert_eq!(gate.process(&frames(60, 2, false)).len(), FRAME);
+        assert!(gate.process(&frames(70, 10, false)).is_empty());
+        assert!(!gate.released());
+    }
+
+    #[test]
+    fn partial_frames_wait_for_the_rest() {
+        let mut gate = MicGate::new(Duration::ZERO);
+        let speech = frames(0, 3, true);
+        assert!(gate.process(&speech[..FRAME + FRAME / 2]).is_empty());
+        // Without a pre-roll, the frames that opened the gate are still sent
+        assert_eq!(gate.process(&speech[FRAME + FRAME / 2..]), speech);
+    }
+
+    #[test]
+    fn push_to_talk_lets_everything_through_while_pressed() {
+        let talking = Arc::new(AtomicBool::new(false));
+        let mut gate = MicGate::push_to_talk(Duration::from_millis(60), talking.clone());
+        // Speech alone doesn't open it
+        assert!(gate.process(&frames(0, 10, true)).is_empty());
+
+        // Pressing sends the pre-roll, and quiet is sent for as long as it's held
+        talking.store(true, Ordering::Relaxed);
+        assert_eq!(gate.process(&frames(10, 1, false)), [frames(8, 2, true), frames(10, 1, false)].concat());
+        let held = (HANGOVER_MS / FRAME_MS) as usize * 2;
+        assert_eq!(gate.process(&frames(11, held, false)), frames(11, held, false));
+        assert!(!gate.released());
+
+        // Releasing closes it at once, and is told once
+        talking.store(false, Ordering::Relaxed);
+        assert!(gate.process(&frames(100, 2, true)).is_empty());
+        assert!(gate.released());
+        assert!(!gate.released());
+        talking.store(true, Ordering::Relaxed);
+        assert_eq!(gate.process(&frames(102, 1, true)), frames(100, 3, true));
+    }
+}
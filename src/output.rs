@@ -0,0 +1,45 @@
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+
+/// What goes to stdout during a call
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,       // The conversation, for people to read
+    Jsonl,      // One JSON object per semantic event, for other programs; everything else goes to stderr
+}
+
+// The original stdout, once it's reserved for JSON lines
+static JSON_OUT: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Reserves stdout for JSON lines, sending everything else printed from now on to stderr
+///
+/// The output is redirected at the descriptor level, so nothing printed by the rest of the
+/// program can end up in the middle of a line another program is parsing.
+#[cfg(unix)]
+pub fn reserve_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::fd::FromRawFd;
+
+    std::io::stdout().flush()?;
+    // SAFETY: plain descriptor calls, the duplicate is owned by the File from here on
+    let json_out = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if json_out < 0 || unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(format!("Failed to reserve stdout: {}", std::io::Error::last_os_error()).into());
+    }
+    let _ = JSON_OUT.set(Mutex::new(unsafe { File::from_raw_fd(json_out) }));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reserve_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    Err("JSON Lines output is only supported on Unix".into())
+}
+
+/// Writes one line to the reserved stdout, does nothing unless it was reserved
+pub fn emit(line: &Value) {
+    if let Some(out) = JSON_OUT.get() {
+        let mut out = out.lock().unwrap();
+        let _ = out.write_all(format!("{}\n", line).as_bytes());
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::conversation::is_out_of_band_response;
+
+/// How long the server may take to acknowledge an operation before it's reported
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Unanswered operations kept at most, the oldest are forgotten
+const CAPACITY: usize = 100;
+
+/// An operation sent to the server and not acknowledged yet
+#[derive(Debug, Clone)]
+pub struct PendingOperation {
+    pub event_id: String,
+    pub operation: String,              // Type of the event sent, e.g. input_audio_buffer.commit
+    pub awaiting: &'static str,         // Server event that acknowledges it, or its next step
+    pub item_id: Option<String>,        // Item the acknowledgement has to be about
+    pub sent: Instant,
+    pub overdue: bool,                  // Already reported as unanswered
+}
+
+/// Correlates the events sent to the server with the events acknowledging them
+///
+/// Each sent event that the server answers is kept by its event_id until its acknowledgement
+/// arrives, or an error naming it. A commit takes two steps: the buffer is committed, then the
+/// item for it created. What stays unanswered past ACK_TIMEOUT is overdue.
+///
+/// Only errors echo the event_id, so acknowledgements are matched by type and item. The server
+/// VAD commits and answers turns nobody asked for, and those are set aside so they don't pass
+/// for the acknowledgement of something still on its way.
+#[derive(Debug, Default)]
+pub struct PendingOperations {
+    operations: VecDeque<PendingOperation>,
+    responds_on_its_own: bool,          // The session's turn detection creates responses
+    unrequested_commits: usize,         // Expected from the server VAD, once speech stopped
+    unrequested_responses: usize,       // Expected for the turns the server VAD committed
+    unrequested_items: HashSet<String>, // Items of those turns, on their way
+}
+
+impl PendingOperations {
+    /// Takes an event sent, received or raised locally
+    pub fn handle_event(&mut self, event: &Value) {
+        let event_type = event["type"].as_str().unwrap_or_default();
+
+        if let Some((awaiting, item_id)) = acknowledgement(event_type, event) {
+            if self.operations.len() == CAPACITY {
+                self.operations.pop_front();
+            }
+            self.operations.push_back(PendingOperation {
+                event_id: event["event_id"].as_str().unwrap_or_default().to_string(),
+                operation: event_type.to_string(),
+                awaiting,
+                item_id,
+                sent: Instant::now(),
+                overdue: false,
+            });
+            return;
+        }
+
+        match event_type {
+            "error" => {
+                let event_id = event["error"]["event_id"].as_str().unwrap_or_default();
+                self.operations.retain(|operation| operation.event_id != event_id);
+            }
+            // Whatever was sent on the old connection won't be answered
+            "local.connected" | "local.disconnected" => {
+                self.operations.clear();
+                self.unrequested_commits = 0;
+                self.unrequested_responses = 0;
+                self.unrequested_items.clear();
+            }
+            "session.created" | "session.updated" => {
+                let turn_detection = &event["session"]["turn_detection"];
+                self.responds_on_its_own = turn_detection.is_object() && turn_detection["create_response"] != false;
+            }
+            "input_audio_buffer.speech_stopped" => self.unrequested_commits += 1,
+            "input_audio_buffer.committed" if self.unrequested_commits > 0 => {
+                self.unrequested_commits -= 1;
+                if let Some(item_id) = event["item_id"].as_str() {
+                    self.unrequested_items.insert(item_id.to_string());
+                }
+                if self.responds_on_its_own {
+                    self.unrequested_responses += 1;
+                }
+            }
+            "conversation.item.created" if event["item"]["id"].as_str().is_some_and(|item_id| self.unrequested_items.remove(item_id)) => {}
+            // Out-of-band responses are only ever requested
+            "response.created" if self.unrequested_responses > 0 && !is_out_of_band_response(&event["response"]) => {
+                self.unrequested_responses -= 1;
+            }
+            _ => {
+                let item_id = event["item"]["id"].as_str().or(event["item_id"].as_str());
+                let Some(index) = self.acknowledged(event_type, item_id, event["item"]["role"].as_str()) else { return };
+
+                if event_type == "input_audio_buffer.committed" {
+                    // Now the item it's committed into has to show up
+                    let operation = &mut self.operations[index];
+                    operation.awaiting = "conversation.item.created";
+                    operation.item_id = item_id.map(str::to_string);
+                } else {
+                    self.operations.remove(index);
+                }
+            }
+        }
+    }
+
+    /// Operations waiting for the server, oldest first
+    pub fn operations(&self) -> impl Iterator<Item = &PendingOperation> {
+        self.operations.iter()
+    }
+
+    /// Marks the operations unanswered for longer than ACK_TIMEOUT as overdue, returning those newly so
+    pub fn expire(&mut self) -> Vec<PendingOperation> {
+        self.operations
+            .iter_mut()
+            .filter(|operation| !operation.overdue && operation.sent.elapsed() > ACK_TIMEOUT)
+            .map(|operation| {
+                operation.overdue = true;
+                operation.clone()
+            })
+            .collect()
+    }
+
+    pub fn overdue_count(&self) -> usize {
+        self.operations.iter().filter(|operation| operation.overdue).count()
+    }
+
+    /// The oldest operation a server event acknowledges, one about its item if there's such
+    fn acknowledged(&self, event_type: &str, item_id: Option<&str>, role: Option<&str>) -> Option<usize> {
+        let candidates = || self.operations.iter().enumerate().filter(|(_, operation)| operation.awaiting == event_type);
+        candidates()
+            .find(|(_, operation)| operation.item_id.is_some() && operation.item_id.as_deref() == item_id)
+            // The server creates the assistant's items on its own, they answer nothing we sent
+            .or_else(|| candidates().find(|(_, operation)| operation.item_id.is_none() && role != Some("assistant")))
+            .map(|(index, _)| index)
+    }
+}
+
+/// The server event acknowledging a sent event and the item it has to be about, None if it isn't answered
+fn acknowledgement(event_type: &str, event: &Value) -> Option<(&'static str, Option<String>)> {
+    let item_id = |field: &Value| field.as_str().map(str::to_string);
+
+    // Only our own events carry a UUID, the server's are named `event_…`
+    if event["event_id"].as_str().is_none_or(|event_id| Uuid::parse_str(event_id).is_err()) {
+        return None;
+    }
+
+    match event_type {
+        "session.update" => Some(("session.updated", None)),
+        "conversation.item.create" => Some(("conversation.item.created", item_id(&event["item"]["id"]))),
+        "conversation.item.truncate" => Some(("conversation.item.truncated", item_id(&event["item_id"]))),
+        "conversation.item.delete" => Some(("conversation.item.deleted", item_id(&event["item_id"]))),
+        "input_audio_buffer.commit" => Some(("input_audio_buffer.committed", None)),
+        "input_audio_buffer.clear" => Some(("input_audio_buffer.cleared", None)),
+        "response.create" => Some(("response.created", None)),
+        "response.cancel" => Some(("response.done", None)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sent(event_type: &str, fields: Value) -> Value {
+        let mut event = json!({"type": event_type, "event_id": Uuid::new_v4().to_string()});
+        event.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        event
+    }
+
+    fn waiting(pending: &PendingOperations) -> Vec<(&str, &str)> {
+        pending.operations().map(|operation| (operation.operation.as_str(), operation.awaiting)).collect()
+    }
+
+    #[test]
+    fn commits_are_answered_in_two_steps() {
+        let mut pending = PendingOperations::default();
+        pending.handle_event(&sent("input_audio_buffer.commit", json!({})));
+        assert_eq!(waiting(&pending), [("input_audio_buffer.commit", "input_audio_buffer.committed")]);
+
+        pending.handle_event(&json!({"type": "input_audio_buffer.committed", "event_id": "event_1", "item_id": "item_1"}));
+        assert_eq!(waiting(&pending), [("input_audio_buffer.commit", "conversation.item.created")]);
+        // Another item doesn't do
+        pending.handle_event(&json!({"type": "conversation.item.created", "item": {"id": "item_2", "role": "user"}}));
+        pending.handle_event(&json!({"type": "conversation.item.created", "item": {"id": "item_1", "role": "user"}}));
+        assert!(waiting(&pending).is_empty());
+    }
+
+    #[test]
+    fn operations_about_an_item_wait_for_that_item() {
+        let mut pending = PendingOperations::default();
+        pending.handle_event(&sent("conversation.item.delete", json!({"item_id": "item_1"})));
+        pending.handle_event(&sent("conversation.item.create", json!({"item": {"type": "message", "role": "user"}})));
+        // Not ours, and the assistant's items answer nothing
+        pending.handle_event(&json!({"type": "conversation.item.deleted", "item_id": "item_2"}));
+        pending.handle_event(&json!({"type": "conversation.item.created", "item": {"id": "item_3", "role": "assistant"}}));
+        assert_eq!(waiting(&pending).len(), 2);
+
+        pending.handle_event(&json!({"type": "conversation.item.created", "item": {"id": "item_4", "role": "user"}}));
+        pending.handle_event(&json!({"type": "conversation.item.deleted", "item_id": "item_1"}));
+        assert!(waiting(&pending).is_empty());
+
+        // The server's own events, and events without an answer, aren't waited on
+        pending.handle_event(&json!({"type": "session.update", "event_id": "event_1"}));
+        pending.handle_event(&sent("input_audio_buffer.append", json!({"audio": ""})));
+        assert!(waiting(&pending).is_empty());
+    }
+
+    #[test]
+    fn turns_the_server_vad_takes_answer_nothing_sent() {
+        let mut pending = PendingOperations::default();
+        pending.handle_event(&json!({"type": "session.created", "session": {"turn_detection": {"type": "server_vad"}}}));
+        pending.handle_event(&sent("input_audio_buffer.commit", json!({})));
+        pending.handle_event(&sent("response.create", json!({"response": {}})));
+
+        // The user stopped speaking, the server commits the turn and answers it
+        pending.handle_event(&json!({"type": "input_audio_buffer.speech_stopped", "item_id": "item_vad"}));
+        pending.handle_event(&json!({"type": "input_audio_buffer.committed", "item_id": "item_vad"}));
+        pending.handle_event(&json!({"type": "conversation.item.created", "item": {"id": "item_vad", "role": "user"}}));
+        pending.handle_event(&json!({"type": "response.created", "response": {"id": "resp_vad"}}));
+        assert_eq!(waiting(&pending), [
+            ("input_audio_buffer.commit", "input_audio_buffer.committed"),
+            ("response.create", "response.created"),
+        ]);
+
+        // An out-of-band response is ours, even while one for the turn is expected
+        pending.handle_event(&json!({"type": "input_audio_buffer.speech_stopped"}));
+        pending.handle_event(&json!({"type": "input_audio_buffer.committed", "item_id": "item_vad_2"}));
+        pending.handle_event(&json!({"type": "response.created", "response": {"id": "resp_side", "metadata": {"hotline": "side_channel"}}}));
+        assert_eq!(waiting(&pending), [("input_audio_buffer.commit", "input_audio_buffer.committed")]);
+    }
+
+    #[test]
+    fn turn_detection_without_responses_leaves_them_to_us() {
+        let mut pending = PendingOperations::default();
+        pending.handle_event(&json!({"type": "session.updated", "session": {"turn_detection": {"type": "server_vad", "create_response": false}}}));
+        pending.handle_event(&json!({"type": "input_audio_buffer.speech_stopped"}));
+        pending.handle_event(&json!({"type": "input_audio_buffer.committed", "item_id": "item_1"}));
+        pending.handle_event(&sent("response.create", json!({"response": {}})));
+        pending.handle_event(&json!({"type": "response.created", "response": {"id": "resp_1"}}));
+        assert!(waiting(&pending).is_empty());
+    }
+
+    #[test]
+    fn errors_reconnects_and_timeouts() {
+        let mut pending = PendingOperations::default();
+        let update = sent("session.update", json!({"session": {}}));
+        pending.handle_event(&update);
+        pending.handle_event(&sent("response.cancel", json!({})));
+        pending.handle_event(&json!({"type": "error", "error": {"event_id": update["event_id"]}}));
+        assert_eq!(waiting(&pending), [("response.cancel", "response.done")]);
+
+        pending.operations[0].sent -= ACK_TIMEOUT + Duration::from_secs(1);
+        assert_eq!(pending.expire().len(), 1);
+        assert!(pending.expire().is_empty());   // Reported once
+        assert_eq!(pending.overdue_count(), 1);
+
+        pending.handle_event(&json!({"type": "local.disconnected"}));
+        assert!(waiting(&pending).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+/// Instructions of the tutor preset
+const TUTOR_INSTRUCTIONS: &str = "You are a patient language tutor. Speak in the language the user is practicing, in short, \
+simple sentences and a little slower than usual. Gently correct their mistakes by repeating what they said the right way, \
+then carry on the conversation.";
+
+/// A ready-made kind of call, picked with --preset, e.g. `tutor:English`
+///
+/// A preset sets what a kind of call needs in one go. Options given alongside it still apply,
+/// so it's a starting point rather than a mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Preset {
+    /// A language lesson, each of the assistant's messages shown next to its translation into the language the user knows
+    Tutor { translation: String },
+}
+
+impl Preset {
+    /// The instructions the assistant follows
+    pub fn instructions(&self) -> &str {
+        match self {
+            Self::Tutor { .. } => TUTOR_INSTRUCTIONS,
+        }
+    }
+
+    /// The language the assistant's messages are translated into, if any
+    pub fn translation(&self) -> Option<&str> {
+        match self {
+            Self::Tutor { translation } => Some(translation),
+        }
+    }
+}
+
+/// Parses a preset as `NAME[:ARGUMENT]`
+pub fn parse_preset(text: &str) -> Result<Preset, String> {
+    let (name, argument) = match text.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument.trim()).filter(|argument| !argument.is_empty())),
+        None => (text.trim(), None),
+    };
+    match (name, argument) {
+        ("tutor", Some(language)) => Ok(Preset::Tutor { translation: language.to_string() }),
+        ("tutor", None) => Err("The tutor preset needs the language to translate into, e.g. tutor:English".to_string()),
+        _ => Err(format!("No preset {:?} (known: tutor)", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presets_with_their_argument() {
+        let tutor = parse_preset("tutor:English").unwrap();
+        assert_eq!(tutor, Preset::Tutor { translation: "English".to_string() });
+        assert_eq!(tutor.translation(), Some("English"));
+        assert!(tutor.instructions().contains("tutor"));
+        assert_eq!(parse_preset(" tutor : Brazilian Portuguese ").unwrap().translation(), Some("Brazilian Portuguese"));
+
+        assert!(parse_preset("tutor").unwrap_err().contains("tutor:English"));
+        assert!(parse_preset("tutor:").is_err());
+        assert!(parse_preset("coach:English").unwrap_err().contains("No preset \"coach\""));
+    }
+}
@@ -0,0 +1,150 @@
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::chat::{ChatCompletions, DEFAULT_CHAT_MODEL};
+use crate::conversation::ConversationItem;
+use crate::export::transcript_markdown;
+use crate::metadata::SessionMetadata;
+
+/// Environment variable with the SMTP password, when the server URL names only the user
+pub const SMTP_PASSWORD_ENV: &str = "HOTLINE_SMTP_PASSWORD";
+
+/// Where a recap goes once the call is over
+#[derive(Debug)]
+pub enum RecapTransport {
+    Smtp(String),       // Server URL, e.g. smtps://user@smtp.example.com
+    Webhook(String),    // Posted the recap as JSON
+}
+
+impl RecapTransport {
+    /// The transport the flags name, if a recap is asked for
+    ///
+    /// A recap carries the whole transcript in the clear, so it's refused outright for a call
+    /// whose files are encrypted rather than undoing the encryption at hang up.
+    pub fn from_options(recap: bool, smtp: Option<&str>, webhook: Option<&str>, encrypted: bool) -> Result<Option<Self>, String> {
+        match (recap, smtp, webhook) {
+            (false, _, _) => Ok(None),
+            _ if encrypted => Err("--recap-to would send the transcript unencrypted, it can't be used with --encrypt or a --keyfile".to_string()),
+            (true, Some(url), _) => Ok(Some(Self::Smtp(url.to_string()))),
+            (true, None, Some(url)) => Ok(Some(Self::Webhook(url.to_string()))),
+            (true, None, None) => Err("--recap-to needs an --smtp server or a --recap-webhook to send with".to_string()),
+        }
+    }
+}
+
+/// A summary of the call to mail out
+pub struct Recap {
+    pub subject: String,
+    pub summary: String,        // With the action items
+    pub transcript: String,     // Markdown
+}
+
+impl Recap {
+    /// Summarizes the call with a single Chat Completions request
+    pub async fn new(chat: &ChatCompletions, metadata: &SessionMetadata, items: &[ConversationItem]) -> Result<Self, Box<dyn std::error::Error>> {
+        let transcript = transcript_markdown(metadata, items);
+
+        let request = serde_json::json!({
+            "model": DEFAULT_CHAT_MODEL,
+            "messages": [
+                {
+                    "role": "system",
+                    "content": "Summarize the call transcript in a short paragraph, then list its decisions and action items with who owns them. Plain text, no Markdown headings."
+                },
+                {"role": "user", "content": transcript}
+            ]
+        });
+        let summary = chat.complete(&request).await.map_err(|e| format!("Summarizing the call failed: {}", e))?;
+
+        let subject = match &metadata.purpose {
+            Some(purpose) => format!("Call recap: {}", purpose),
+            None => "Call recap".to_string(),
+        };
+        Ok(Self { subject, summary, transcript })
+    }
+
+    /// Mails the recap to an address, from `from` or the address itself
+    pub async fn send(&self, transport: &RecapTransport, to: &str, from: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        match transport {
+            RecapTransport::Smtp(url) => {
+                let to: Mailbox = to.parse().map_err(|e| format!("Invalid recap address {}: {}", to, e))?;
+                let from: Mailbox = match from {
+                    Some(from) => from.parse().map_err(|e| format!("Invalid sender address {}: {}", from, e))?,
+                    None => to.clone(),
+                };
+                let message = Message::builder()
+                    .from(from)
+                    .to(to)
+                    .subject(&self.subject)
+                    .header(ContentType::TEXT_PLAIN)
+                    .body(format!("{}\n\n{}", self.summary, self.transcript))?;
+
+                let mut mailer = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?;
+                // The password stays out of the config and the call history
+                let user = smtp_user(url)?;
+                if let Some(password) = std::env::var(SMTP_PASSWORD_ENV).ok().filter(|_| !user.is_empty()) {
+                    mailer = mailer.credentials(Credentials::new(user, password));
+                }
+                mailer.build().send(message).await?;
+            }
+            RecapTransport::Webhook(url) => {
+                let response = reqwest::Client::new()
+                    .post(url)
+                    .json(&serde_json::json!({
+                        "to": to,
+                        "from": from,
+                        "subject": self.subject,
+                        "summary": self.summary,
+                        "transcript": self.transcript,
+                    }))
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(format!("Recap webhook answered {}", response.status()).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The user an SMTP URL names, decoded as lettre decodes it, e.g. an email address written `me%40example.com`
+fn smtp_user(url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let url = url::Url::parse(url)?;
+    Ok(percent_encoding::percent_decode_str(url.username()).decode_utf8()?.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_follows_the_flags() {
+        assert!(RecapTransport::from_options(false, Some("smtps://smtp.example.com"), None, true).unwrap().is_none());
+        assert!(matches!(
+            RecapTransport::from_options(true, Some("smtps://smtp.example.com"), None, false),
+            Ok(Some(RecapTransport::Smtp(url))) if url == "smtps://smtp.example.com"
+        ));
+        assert!(matches!(
+            RecapTransport::from_options(true, None, Some("https://hooks.example.com"), false),
+            Ok(Some(RecapTransport::Webhook(_)))
+        ));
+        assert!(RecapTransport::from_options(true, None, None, false).is_err());
+    }
+
+    #[test]
+    fn no_recap_of_an_encrypted_call() {
+        let refused = RecapTransport::from_options(true, Some("smtps://smtp.example.com"), None, true).unwrap_err();
+        assert!(refused.contains("--encrypt"));
+        assert!(RecapTransport::from_options(true, None, Some("https://hooks.example.com"), true).is_err());
+    }
+
+    #[test]
+    fn smtp_user_is_decoded() {
+        assert_eq!(smtp_user("smtps://me%40example.com@smtp.example.com").unwrap(), "me@example.com");
+        assert_eq!(smtp_user("smtps://recaps@smtp.example.com:465").unwrap(), "recaps");
+        assert_eq!(smtp_user("smtps://smtp.example.com").unwrap(), "");
+        assert!(smtp_user("smtps://%FF@smtp.example.com").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use serde_json::Value;
+use std::io::Cursor;
+
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+use crate::metadata::SessionMetadata;
+
+// Label of the muted stretches of the microphone recording
+const OFF_THE_RECORD: &str = "off the record";
+
+/// Keeps the raw protocol events, assistant audio and microphone audio of a call so they can be saved at hang up
+#[derive(Debug, Default)]
+pub struct Recorder {
+    record_events: bool,
+    record_audio: bool,
+    record_microphone: bool,
+    events: Vec<Value>,         // Every event sent or received, in order
+    audio: Vec<f32>,            // Assistant audio at SERVER_SAMPLE_RATE
+    microphone: Vec<f32>,       // Everything captured at SERVER_SAMPLE_RATE, whether the model heard it or not
+    off_the_record: Vec<(usize, Option<usize>)>,    // Muted stretches of `microphone`, the last one open while still muted
+    metadata: SessionMetadata,  // Stamped into the dump and the WAV files
+}
+
+impl Recorder {
+    /// Turns on recording of protocol events and/or assistant audio
+    pub fn enable(&mut self, events: bool, audio: bool) {
+        self.record_events = events;
+        self.record_audio = audio;
+    }
+
+    /// Turns on recording of the microphone, muted or not
+    pub fn enable_microphone(&mut self) {
+        self.record_microphone = true;
+    }
+
+    pub fn set_metadata(&mut self, metadata: SessionMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn records_audio(&self) -> bool {
+        self.record_audio
+    }
+
+    pub fn handle_event(&mut self, event: &Value) {
+        if self.record_events {
+            self.events.push(event.clone());
+        }
+    }
+
+    /// Adds decoded assistant audio
+    pub fn add_audio(&mut self, samples: &[f32]) {
+        if self.record_audio {
+            self.audio.extend_from_slice(samples);
+        }
+    }
+
+    /// Adds captured microphone audio, also while muted
+    ///
+    /// The capture tells whether the samples were muted, rather than the local.mute event coming
+    /// round later, so the muted stretches start and end on the very samples the model stopped
+    /// and started hearing.
+    pub fn add_microphone(&mut self, samples: &[f32], muted: bool) {
+        if !self.record_microphone {
+            return;
+        }
+        match (muted, self.off_the_record.last_mut()) {
+            (true, Some((_, None))) => {}
+            (true, _) => self.off_the_record.push((self.microphone.len(), None)),
+            (false, Some((_, end @ None))) => *end = Some(self.microphone.len()),
+            (false, _) => {}
+        }
+        self.microphone.extend_from_slice(samples);
+    }
+
+    /// Protocol dump as JSON Lines, starting with a `local.session_metadata` line when there is any
+    pub fn events_jsonl(&self) -> Vec<u8> {
+        let header = (!self.metadata.is_empty())
+            .then(|| serde_json::json!({"type": "local.session_metadata", "metadata": self.metadata}));
+
+        header
+            .iter()
+            .chain(&self.events)
+            .map(|event| event.to_string() + "\n")
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// Recorded audio as a 16-bit mono WAV file
+    pub fn audio_wav(&self) -> Result<Vec<u8>, hound::Error> {
+        let mut wav = wav_bytes(&self.audio)?;
+        if !self.metadata.is_empty() {
+            append_info_chunk(&mut wav, &self.metadata);
+        }
+
+        Ok(wav)
+    }
+
+    /// Recorded microphone audio as a 16-bit mono WAV file, the muted stretches marked as regions
+    pub fn microphone_wav(&self) -> Result<Vec<u8>, hound::Error> {
+        let mut wav = wav_bytes(&self.microphone)?;
+        if !self.metadata.is_empty() {
+            append_info_chunk(&mut wav, &self.metadata);
+        }
+
+        let regions: Vec<(usize, usize)> = self
+            .off_the_record
+            .iter()
+            .map(|(start, end)| (*start, end.unwrap_or(self.microphone.len())))
+            .filter(|(start, end)| end > start)
+            .collect();
+        if !regions.is_empty() {
+            append_regions(&mut wav, &regions, OFF_THE_RECORD);
+        }
+
+        Ok(wav)
+    }
+}
+
+/// Encodes samples at SERVER_SAMPLE_RATE as a 16-bit mono WAV file
+pub fn wav_bytes(samples: &[f32]) -> Result<Vec<u8>, hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SERVER_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut cursor = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
+    for sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+
+    Ok(cursor.into_inner())
+}
+
+/// Appends the metadata as a RIFF `LIST/INFO` chunk, which most players and editors show
+fn append_info_chunk(wav: &mut Vec<u8>, metadata: &SessionMetadata) {
+    let comment = metadata
+        .pairs()
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    let mut info = b"INFO".to_vec();
+    let fields = [("IART", metadata.caller.as_deref()), ("INAM", metadata.purpose.as_deref()), ("ICMT", Some(comment.as_str()))];
+    for (id, text) in fields {
+        let Some(text) = text else { continue };
+
+        // Strings are NUL terminated and chunks padded to an even length
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        info.extend_from_slice(id.as_bytes());
+        info.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        info.extend_from_slice(&data);
+    }
+
+    wav.extend_from_slice(b"LIST");
+    wav.extend_from_slice(&(info.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&info);
+
+    // Fix up the RIFF size now that the file grew
+    let riff_size = (wav.len() - 8) as u32;
+    wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+}
+
+/// Appends labelled regions as RIFF `cue ` and `LIST/adtl` chunks, which audio editors show as markers
+fn append_regions(wav: &mut Vec<u8>, regions: &[(usize, usize)], label: &str) {
+    let mut cues = (regions.len() as u32).to_le_bytes().to_vec();
+    let mut labels = b"adtl".to_vec();
+
+    for (id, (start, end)) in (1u32..).zip(regions) {
+        cues.extend_from_slice(&id.to_le_bytes());
+        cues.extend_from_slice(&(*start as u32).to_le_bytes());
+        cues.extend_from_slice(b"data");
+        cues.extend_from_slice(&[0; 8]);                        // Chunk and block start, there's one data chunk
+        cues.extend_from_slice(&(*start as u32).to_le_bytes());
+
+        // The length of the region, then its name
+        labels.extend_from_slice(b"ltxt");
+        labels.extend_from_slice(&20u32.to_le_bytes());
+        labels.extend_from_slice(&id.to_le_bytes());
+        labels.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        labels.extend_from_slice(b"rgn ");
+        labels.extend_from_slice(&[0; 8]);                      // Country, language, dialect and code page
+
+        let mut text = label.as_bytes().to_vec();
+        text.push(0);
+        labels.extend_from_slice(b"labl");
+        labels.extend_from_slice(&(text.len() as u32 + 4).to_le_bytes());
+        labels.extend_from_slice(&id.to_le_bytes());
+        if text.len() % 2 == 1 {
+            text.push(0);
+        }
+        labels.extend_from_slice(&text);
+    }
+
+    wav.extend_from_slice(b"cue ");
+    wav.extend_from_slice(&(cues.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&cues);
+    wav.extend_from_slice(b"LIST");
+    wav.extend_from_slice(&(labels.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&labels);
+
+    let riff_size = (wav.len() - 8) as u32;
+    wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The chunks of a RIFF file after the format and data, by id
+    fn chunks(wav: &[u8]) -> Vec<(&[u8], &[u8])> {
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize, wav.len() - 8);
+        let mut chunks = Vec::new();
+        let mut rest = &wav[12..];
+        while rest.len() >= 8 {
+            let size = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            chunks.push((&rest[..4], &rest[8..8 + size]));
+            rest = &rest[(8 + size + size % 2).min(rest.len())..];
+        }
+        chunks
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn regions_are_cue_points_with_a_length_and_a_label() {
+        let mut wav = wav_bytes(&[0.0; 1000]).unwrap();
+        append_regions(&mut wav, &[(100, 250), (600, 1000)], OFF_THE_RECORD);
+
+        let chunks = chunks(&wav);
+        assert_eq!(chunks.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [b"fmt ", b"data", b"cue ", b"LIST"]);
+        // Still readable as audio
+        assert_eq!(hound::WavReader::new(Cursor::new(&wav)).unwrap().len(), 1000);
+
+        let cues = chunks[2].1;
+        assert_eq!((u32_at(cues, 0), cues.len()), (2, 4 + 2 * 24));
+        assert_eq!((u32_at(cues, 4), u32_at(cues, 8), &cues[12..16], u32_at(cues, 24)), (1, 100, &b"data"[..], 100));
+        assert_eq!((u32_at(cues, 28), u32_at(cues, 32)), (2, 600));
+
+        // ltxt with the length, then labl with the NUL terminated label, padded to an even length
+        let labels = chunks[3].1;
+        assert_eq!(&labels[..4], b"adtl");
+        let region = &labels[4..];
+        assert_eq!((&region[..4], u32_at(region, 4), u32_at(region, 8), u32_at(region, 12), &region[16..20]), (&b"ltxt"[..], 20, 1, 150, &b"rgn "[..]));
+        let label = &region[28..];
+        assert_eq!((&label[..4], u32_at(label, 4), u32_at(label, 8)), (&b"labl"[..], 4 + 15, 1));
+        assert_eq!(&label[12..28], b"off the record\0\0");
+        assert_eq!(u32_at(&label[28..], 12), 400);
+    }
+
+    #[test]
+    fn muted_stretches_fall_on_the_samples_captured_muted() {
+        let mut recorder = Recorder::default();
+        recorder.add_microphone(&[0.1; 10], true);
+        assert!(recorder.microphone.is_empty());     // Not recording the microphone
+
+        recorder.enable_microphone();
+        recorder.add_microphone(&[0.1; 100], false);
+        recorder.add_microphone(&[0.1; 50], true);
+        recorder.add_microphone(&[0.1; 50], true);
+        recorder.add_microphone(&[0.1; 100], false);
+        // Still muted at hang up
+        recorder.add_microphone(&[0.1; 30], true);
+        assert_eq!(recorder.off_the_record, [(100, Some(200)), (300, None)]);
+
+        let wav = recorder.microphone_wav().unwrap();
+        let cues = chunks(&wav)[2].1;
+        assert_eq!((u32_at(cues, 8), u32_at(cues, 32)), (100, 300));
+        let labels = chunks(&wav)[3].1;
+        assert_eq!((u32_at(labels, 16), u32_at(labels, 4 + 28 + 28 + 12)), (100, 30));
+    }
+
+    #[test]
+    fn without_muting_there_are_no_regions() {
+        let mut recorder = Recorder::default();
+        recorder.enable_microphone();
+        recorder.add_microphone(&[0.1; 100], false);
+        let wav = recorder.microphone_wav().unwrap();
+        assert_eq!(chunks(&wav).len(), 2);
+    }
+}
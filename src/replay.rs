@@ -0,0 +1,52 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::conversation::ConversationTracker;
+use crate::export::transcript_markdown;
+use crate::metadata::SessionMetadata;
+use crate::storage::{read_file, Encryption};
+
+/// Prints the events of a protocol dump and the transcript they add up to
+///
+/// Events are numbered, and chapters (see `--chapters`) are listed with the event their first
+/// item was created by, to find a point in a long call.
+pub fn replay(path: &Path, encryption: Option<&Encryption>) -> Result<(), Box<dyn std::error::Error>> {
+    let data = read_file(path, encryption)?;
+    let mut conversation = ConversationTracker::default();
+    let mut metadata = SessionMetadata::default();
+    let mut audio_deltas = 0;
+    let mut created_by: HashMap<String, usize> = HashMap::new();   // Event number per item id
+
+    for (number, line) in String::from_utf8(data)?.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+        let event: Value = serde_json::from_str(line)?;
+        conversation.handle_event(&event);
+        if let Some(item_id) = event["item"]["id"].as_str() {
+            created_by.entry(item_id.to_string()).or_insert(number + 1);
+        }
+
+        // Audio deltas are far too many to list
+        match event["type"].as_str().unwrap_or_default() {
+            "response.audio.delta" => audio_deltas += 1,
+            "local.session_metadata" => {
+                metadata = serde_json::from_value(event["metadata"].clone())?;
+                println!("{:>6} local.session_metadata", number + 1);
+            }
+            event_type => println!("{:>6} {}", number + 1, event_type),
+        }
+    }
+
+    println!("\n({} audio deltas omitted)\n", audio_deltas);
+
+    let chapters: Vec<_> = conversation.items().iter().filter_map(|item| Some((created_by.get(&item.id)?, item.chapter.as_ref()?))).collect();
+    if !chapters.is_empty() {
+        println!("Chapters:");
+        for (number, title) in chapters {
+            println!("  event {:>6}  {}", number, title);
+        }
+        println!();
+    }
+    print!("{}", transcript_markdown(&metadata, conversation.items()));
+
+    Ok(())
+}
@@ -0,0 +1,640 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::audio_utils::{fade_out, InputChunk, InputCommand, FADE_OUT, SERVER_SAMPLE_RATE};
+use crate::clock_drift::StreamResampler;
+
+/// Format of audio with a dynamic payload type, unless told otherwise
+pub const DEFAULT_FORMAT: &str = "L16/16000/1";
+
+/// Format of the assistant's audio sent out, the server's own so nothing is lost to resampling
+pub const DEFAULT_OUTPUT_FORMAT: &str = "L16/24000/1";
+
+/// Audio sent out per packet, unless told otherwise
+pub const DEFAULT_PACKET_MS: u32 = 20;
+
+/// Audio held back before a burst of speech is sent, unless told otherwise
+pub const DEFAULT_PREBUFFER_MS: u32 = 60;
+
+// Large enough for any UDP datagram, a truncated packet would be garbled audio
+const MAX_PACKET: usize = 65536;
+// How long the socket waits for a packet before checking for commands
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Lost packets are filled with silence up to this long, a longer gap is the sender pausing
+const MAX_GAP_MS: u32 = 1000;
+
+/// How RTP audio samples are encoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    L16,    // Signed 16 bit, big endian
+    Pcmu,   // G.711 µ-law
+    Pcma,   // G.711 A-law
+}
+
+/// Encoding, sample rate and channels of RTP audio, written as in SDP, e.g. L16/16000/1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RtpFormat {
+    pub encoding: Encoding,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl std::str::FromStr for RtpFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid RTP format: {} (expected ENCODING/RATE[/CHANNELS], e.g. {})", format, DEFAULT_FORMAT);
+        let mut fields = format.split('/');
+
+        let encoding = match fields.next().unwrap_or_default().to_ascii_uppercase().as_str() {
+            "L16" => Encoding::L16,
+            "PCMU" => Encoding::Pcmu,
+            "PCMA" => Encoding::Pcma,
+            _ => return Err(invalid()),
+        };
+        let sample_rate = fields.next().and_then(|rate| rate.parse().ok()).filter(|rate| *rate > 0).ok_or_else(invalid)?;
+        let channels = match fields.next() {
+            Some(channels) => channels.parse().ok().filter(|channels| *channels > 0).ok_or_else(invalid)?,
+            None => 1,
+        };
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self { encoding, sample_rate, channels })
+    }
+}
+
+impl std::fmt::Display for RtpFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let encoding = match self.encoding {
+            Encoding::L16 => "L16",
+            Encoding::Pcmu => "PCMU",
+            Encoding::Pcma => "PCMA",
+        };
+        write!(f, "{}/{}/{}", encoding, self.sample_rate, self.channels)
+    }
+}
+
+impl TryFrom<String> for RtpFormat {
+    type Error = String;
+
+    fn try_from(format: String) -> Result<Self, Self::Error> {
+        format.parse()
+    }
+}
+
+impl From<RtpFormat> for String {
+    fn from(format: RtpFormat) -> Self {
+        format.to_string()
+    }
+}
+
+impl RtpFormat {
+    /// The static payload type of the format, the first dynamic one (96) if it has none
+    pub fn payload_type(&self) -> u8 {
+        match (self.encoding, self.sample_rate, self.channels) {
+            (Encoding::Pcmu, 8000, 1) => 0,
+            (Encoding::Pcma, 8000, 1) => 8,
+            (Encoding::L16, 44100, 2) => 10,
+            (Encoding::L16, 44100, 1) => 11,
+            _ => 96,
+        }
+    }
+
+    /// Format of a payload type, the static ones as assigned by RFC 3551, None for anything but audio
+    fn of_payload_type(payload_type: u8, dynamic: RtpFormat) -> Option<Self> {
+        let format = |encoding, sample_rate, channels| Some(Self { encoding, sample_rate, channels });
+        match payload_type {
+            0 => format(Encoding::Pcmu, 8000, 1),
+            8 => format(Encoding::Pcma, 8000, 1),
+            10 => format(Encoding::L16, 44100, 2),
+            11 => format(Encoding::L16, 44100, 1),
+            96..=127 => Some(dynamic),
+            _ => None,
+        }
+    }
+
+    /// Samples of a payload, interleaved
+    fn decode(&self, payload: &[u8]) -> Vec<f32> {
+        match self.encoding {
+            Encoding::L16 => payload.chunks_exact(2).map(|bytes| i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / 32768.0).collect(),
+            Encoding::Pcmu => payload.iter().map(|&byte| ulaw_to_linear(byte) as f32 / 32768.0).collect(),
+            Encoding::Pcma => payload.iter().map(|&byte| alaw_to_linear(byte) as f32 / 32768.0).collect(),
+        }
+    }
+
+    /// Payload of interleaved samples
+    fn encode(&self, samples: &[f32]) -> Vec<u8> {
+        let linear = samples.iter().map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+        match self.encoding {
+            Encoding::L16 => linear.flat_map(i16::to_be_bytes).collect(),
+            Encoding::Pcmu => linear.map(linear_to_ulaw).collect(),
+            Encoding::Pcma => linear.map(linear_to_alaw).collect(),
+        }
+    }
+}
+
+/// The fields of an RTP packet that matter for playing it
+struct RtpPacket<'a> {
+    payload_type: u8,
+    sequence: u16,
+    timestamp: u32,     // Of the first sample, in samples
+    ssrc: u32,          // Identifies the sender's stream
+    payload: &'a [u8],
+}
+
+impl<'a> RtpPacket<'a> {
+    /// Reads an RTP version 2 packet, skipping CSRCs, the header extension and padding
+    fn parse(packet: &'a [u8]) -> Option<Self> {
+        if packet.len() < 12 || packet[0] >> 6 != 2 {
+            return None;
+        }
+
+        let mut start = 12 + 4 * (packet[0] & 0x0f) as usize;
+        if packet[0] & 0x10 != 0 {
+            let length = u16::from_be_bytes([*packet.get(start + 2)?, *packet.get(start + 3)?]) as usize;
+            start += 4 + 4 * length;
+        }
+        let padding = if packet[0] & 0x20 != 0 { *packet.last()? as usize } else { 0 };
+        let end = packet.len().checked_sub(padding)?;
+
+        Some(Self {
+            payload_type: packet[1] & 0x7f,
+            sequence: u16::from_be_bytes([packet[2], packet[3]]),
+            timestamp: u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]),
+            ssrc: u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]),
+            payload: packet.get(start..end)?,
+        })
+    }
+}
+
+/// Follows the sender's stream, telling late and repeated packets and filling in for lost ones
+#[derive(Debug, Default)]
+struct Depacketizer {
+    stream: Option<(u32, u16, u32)>,    // SSRC, last sequence number and the timestamp expected next
+}
+
+impl Depacketizer {
+    /// The samples of a packet, after silence for any lost right before it, and whether it's from a new sender
+    ///
+    /// None if the packet is late or repeated.
+    fn samples(&mut self, packet: &RtpPacket, format: RtpFormat) -> Option<(Vec<f32>, bool)> {
+        let mut samples = format.decode(packet.payload);
+        let frames = (samples.len() / format.channels as usize) as u32;
+        let new_sender = match self.stream {
+            Some((ssrc, sequence, expected)) if ssrc == packet.ssrc => {
+                // Sequence numbers wrap around, anything not ahead is late or repeated
+                if (packet.sequence.wrapping_sub(sequence) as i16) <= 0 {
+                    return None;
+                }
+                let gap = packet.timestamp.wrapping_sub(expected);
+                if gap > 0 && gap as u64 <= format.sample_rate as u64 * MAX_GAP_MS as u64 / 1000 {
+                    samples.splice(0..0, std::iter::repeat_n(0.0, gap as usize * format.channels as usize));
+                }
+                false
+            }
+            _ => true,
+        };
+        self.stream = Some((packet.ssrc, packet.sequence, packet.timestamp.wrapping_add(frames)));
+        Some((samples, new_sender))
+    }
+}
+
+/// Listens for RTP audio on a UDP address, delivering it like the microphone would
+///
+/// Works with anything that streams plain RTP without SIP, e.g. a softphone, GStreamer or an
+/// ESP32 microphone. PCMU, PCMA and L16 are understood, with `dynamic` giving the format of
+/// dynamic payload types (96-127). Late and duplicate packets are dropped, lost ones are filled
+/// with silence, and a new sender (SSRC) simply takes over. While closed, packets are ignored.
+/// The thread stops once the command sender is dropped.
+pub fn listen(address: SocketAddr, dynamic: RtpFormat) -> std::io::Result<(mpsc::Receiver<InputChunk>, mpsc::Sender<InputCommand>)> {
+    let socket = UdpSocket::bind(address)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    println!("Listening for RTP audio on {}", socket.local_addr()?);
+
+    let (sample_sender, sample_receiver) = mpsc::channel::<InputChunk>();
+    let (command_sender, command_receiver) = mpsc::channel::<InputCommand>();
+
+    thread::spawn(move || {
+        let mut buffer = vec![0u8; MAX_PACKET];
+        let mut closed = false;
+        let mut stream = Depacketizer::default();
+        let mut unsupported = HashSet::new();                // Payload types already warned about
+
+        loop {
+            loop {
+                match command_receiver.try_recv() {
+                    Ok(InputCommand::Close) => closed = true,
+                    Ok(InputCommand::Open) => closed = false,
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => return,
+                }
+            }
+
+            let (length, source) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+                Err(e) => {
+                    eprintln!("\n[RTP socket failed: {}]", e);
+                    return;
+                }
+            };
+            let Some(packet) = RtpPacket::parse(&buffer[..length]) else {
+                tracing::debug!(%source, length, "not an RTP packet");
+                continue;
+            };
+            if closed {
+                continue;
+            }
+            let Some(format) = RtpFormat::of_payload_type(packet.payload_type, dynamic) else {
+                if unsupported.insert(packet.payload_type) {
+                    tracing::warn!(%source, payload_type = packet.payload_type, "unsupported RTP payload type");
+                }
+                continue;
+            };
+
+            let Some((samples, new_sender)) = stream.samples(&packet, format) else { continue };
+            if new_sender {
+                println!("\n[RTP audio from {}, {}]", source, format);
+            }
+
+            let chunk = InputChunk { samples, sample_rate: format.sample_rate, channels: format.channels };
+            if sample_sender.send(chunk).is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok((sample_receiver, command_sender))
+}
+
+/// Where and how to send the assistant's audio as RTP, see `dial --rtp-out`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RtpOutput {
+    pub address: SocketAddr,
+    pub format: RtpFormat,
+    pub payload_type: u8,
+    pub packet_ms: u32,         // Audio per packet
+    pub prebuffer_ms: u32,      // Held back before a burst of speech starts, so gaps in what the server sends don't break it up
+}
+
+/// Sends playback as RTP, paced in real time
+///
+/// Audio goes out in bursts of speech: a burst starts once enough is queued (the prebuffer), is
+/// sent one packet per packet time, and ends when the queue runs dry, its last packet padded
+/// with silence. Nothing is sent between bursts, the next one starts with the marker bit set
+/// and a timestamp that accounts for the time in between, as RFC 3551 has it for silence.
+pub struct RtpSender {
+    socket: UdpSocket,
+    output: RtpOutput,
+    resampler: StreamResampler,
+    backlog: VecDeque<f32>,                 // Interleaved, at the output rate
+    ssrc: u32,
+    sequence: u16,
+    timestamp: u32,                         // Of the next packet
+    burst: Option<(Instant, u32)>,          // Start of the current burst, and packets sent since
+    last_sent: Option<Instant>,             // Of the last packet, to carry the timestamp over silence
+}
+
+impl RtpSender {
+    pub fn new(output: RtpOutput) -> std::io::Result<Self> {
+        let local: SocketAddr = if output.address.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(output.address)?;
+
+        // Random starting points, as RFC 3550 asks
+        let random = Uuid::new_v4().as_u128();
+        println!("Sending playback as RTP to {} ({}, payload type {})", output.address, output.format, output.payload_type);
+        Ok(Self {
+            socket,
+            output,
+            resampler: StreamResampler::default(),
+            backlog: VecDeque::new(),
+            ssrc: random as u32,
+            sequence: (random >> 32) as u16,
+            timestamp: (random >> 48) as u32,
+            burst: None,
+            last_sent: None,
+        })
+    }
+
+    /// Queues samples at SERVER_SAMPLE_RATE
+    pub fn queue(&mut self, samples: &[f32]) {
+        let ratio = self.output.format.sample_rate as f64 / SERVER_SAMPLE_RATE as f64;
+        let channels = self.output.format.channels as usize;
+        for sample in self.resampler.process(samples, ratio) {
+            self.backlog.extend(std::iter::repeat_n(sample, channels));
+        }
+    }
+
+    /// Drops everything that wasn't sent yet, but for a short fade out if audio is being sent
+    pub fn clear(&mut self) {
+        self.resampler = StreamResampler::default();
+        if self.burst.is_none() {
+            self.backlog.clear();
+            return;
+        }
+        let channels = self.output.format.channels as usize;
+        let frames = (self.output.format.sample_rate as f64 * FADE_OUT.as_secs_f64()) as usize;
+        self.backlog.truncate(frames * channels);
+        fade_out(self.backlog.make_contiguous(), channels);
+    }
+
+    /// Ends the current burst, the queued audio starts a new one later
+    pub fn pause(&mut self) {
+        self.burst = None;
+    }
+
+    /// Sends the packets that are due
+    pub fn send_due(&mut self) {
+        let frames = (self.output.format.sample_rate * self.output.packet_ms / 1000).max(1) as usize;
+        let samples = frames * self.output.format.channels as usize;
+        let packet_time = Duration::from_millis(self.output.packet_ms as u64);
+
+        if self.burst.is_none() {
+            let prebuffer = (self.output.format.sample_rate * self.output.prebuffer_ms / 1000) as usize * self.output.format.channels as usize;
+            if self.backlog.len() < samples.max(prebuffer) {
+                return;
+            }
+            if let Some(last_sent) = self.last_sent {
+                // The silence counts, less the packet time already added after the last packet
+                let silence = last_sent.elapsed().saturating_sub(packet_time).as_secs_f64();
+                self.timestamp = self.timestamp.wrapping_add((silence * self.output.format.sample_rate as f64) as u32);
+            }
+            self.burst = Some((Instant::now(), 0));
+        }
+
+        while let Some((started, sent)) = self.burst {
+            if started + packet_time * sent > Instant::now() {
+                return;
+            }
+            if self.backlog.is_empty() {
+                self.burst = None;
+                return;
+            }
+
+            let mut payload: Vec<f32> = self.backlog.drain(..samples.min(self.backlog.len())).collect();
+            payload.resize(samples, 0.0);
+            self.send(&payload, sent == 0);
+            self.burst = Some((started, sent + 1));
+        }
+    }
+
+    fn send(&mut self, samples: &[f32], marker: bool) {
+        let mut packet = Vec::with_capacity(12 + samples.len() * 2);
+        packet.push(0x80);
+        packet.push(self.output.payload_type | if marker { 0x80 } else { 0 });
+        packet.extend(self.sequence.to_be_bytes());
+        packet.extend(self.timestamp.to_be_bytes());
+        packet.extend(self.ssrc.to_be_bytes());
+        packet.extend(self.output.format.encode(samples));
+
+        // Nobody listening is no reason to stop, a speaker may come up later
+        if let Err(e) = self.socket.send(&packet) {
+            tracing::debug!(error = %e, "RTP packet not sent");
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        self.timestamp = self.timestamp.wrapping_add((samples.len() / self.output.format.channels as usize) as u32);
+        self.last_sent = Some(Instant::now());
+    }
+}
+
+/// Encodes a G.711 µ-law sample
+fn linear_to_ulaw(sample: i16) -> u8 {
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 32635;
+
+    let sign = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = (sample as i32).abs().min(CLIP) + BIAS;
+    // Segment of the highest bit above the 7th
+    let exponent = (8 - (magnitude as u16).leading_zeros() as i32).clamp(0, 7);
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0f;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+/// Encodes a G.711 A-law sample
+fn linear_to_alaw(sample: i16) -> u8 {
+    let (sign, magnitude) = if sample >= 0 { (0x80, sample as i32) } else { (0, -(sample as i32) - 1) };
+    let magnitude = magnitude.min(0x7fff) >> 4;
+    let byte = match magnitude {
+        0..=0x1f => magnitude as u8,
+        _ => {
+            let exponent = 31 - (magnitude as u32).leading_zeros() - 3;
+            ((exponent as u8) << 4) | ((magnitude >> (exponent - 1)) & 0x0f) as u8
+        }
+    };
+    (sign | byte) ^ 0x55
+}
+
+/// Decodes a G.711 µ-law sample
+fn ulaw_to_linear(byte: u8) -> i16 {
+    let byte = !byte;
+    let exponent = (byte >> 4) & 0x07;
+    let magnitude = ((((byte & 0x0f) as i16) << 3) + 0x84) << exponent;
+    if byte & 0x80 != 0 { 0x84 - magnitude } else { magnitude - 0x84 }
+}
+
+/// Decodes a G.711 A-law sample
+fn alaw_to_linear(byte: u8) -> i16 {
+    let byte = byte ^ 0x55;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = (byte & 0x0f) as i16;
+    let magnitude = match exponent {
+        0 => (mantissa << 4) + 8,
+        _ => ((mantissa << 4) + 0x108) << (exponent - 1),
+    };
+    if byte & 0x80 != 0 { magnitude } else { -magnitude }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RTP packet with a fixed header, the given first byte's flags and the rest appended
+    fn packet(flags: u8, payload_type: u8, sequence: u16, timestamp: u32, rest: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80 | flags, payload_type];
+        packet.extend(sequence.to_be_bytes());
+        packet.extend(timestamp.to_be_bytes());
+        packet.extend(0x1234_5678u32.to_be_bytes());
+        packet.extend(rest);
+        packet
+    }
+
+    #[test]
+    fn formats_parse_as_in_sdp() {
+        let format: RtpFormat = "pcmu/8000".parse().unwrap();
+        assert_eq!(format, RtpFormat { encoding: Encoding::Pcmu, sample_rate: 8000, channels: 1 });
+        assert_eq!((format.to_string(), format.payload_type()), ("PCMU/8000/1".to_string(), 0));
+        assert_eq!("L16/44100/2".parse::<RtpFormat>().unwrap().payload_type(), 10);
+        assert_eq!(DEFAULT_FORMAT.parse::<RtpFormat>().unwrap().payload_type(), 96);
+
+        for bad in ["", "OPUS/48000", "L16", "L16/0", "L16/16000/0", "L16/16000/1/1", "L16/fast"] {
+            assert!(bad.parse::<RtpFormat>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_skips_csrcs_extension_and_padding() {
+        let plain = packet(0, 0, 7, 160, &[1, 2, 3]);
+        let parsed = RtpPacket::parse(&plain).unwrap();
+        assert_eq!((parsed.payload_type, parsed.sequence, parsed.timestamp, parsed.ssrc), (0, 7, 160, 0x1234_5678));
+        assert_eq!(parsed.payload, [1, 2, 3]);
+
+        // Two CSRCs
+        let csrcs = packet(0x02, 8, 7, 160, &[0, 0, 0, 1, 0, 0, 0, 2, 4, 5]);
+        assert_eq!(RtpPacket::parse(&csrcs).unwrap().payload, [4, 5]);
+        // A header extension of one word, after a CSRC
+        let extension = packet(0x11, 8, 7, 160, &[0, 0, 0, 1, 0xbe, 0xde, 0, 1, 9, 9, 9, 9, 6]);
+        assert_eq!(RtpPacket::parse(&extension).unwrap().payload, [6]);
+        // Three bytes of padding, the last says how many
+        let padding = packet(0x20, 8, 7, 160, &[7, 8, 0, 0, 3]);
+        assert_eq!(RtpPacket::parse(&padding).unwrap().payload, [7, 8]);
+        // The marker bit isn't part of the payload type
+        assert_eq!(RtpPacket::parse(&packet(0, 0x80 | 96, 7, 160, &[])).unwrap().payload_type, 96);
+    }
+
+    #[test]
+    fn parse_refuses_what_isnt_rtp() {
+        assert!(RtpPacket::parse(&[0x80; 11]).is_none());
+        // Version 1
+        assert!(RtpPacket::parse(&[0x40; 20]).is_none());
+        // CSRCs, extension or padding past the end
+        assert!(RtpPacket::parse(&packet(0x03, 0, 1, 0, &[0; 8])).is_none());
+        assert!(RtpPacket::parse(&packet(0x10, 0, 1, 0, &[0, 0, 0, 2, 1])).is_none());
+        assert!(RtpPacket::parse(&packet(0x20, 0, 1, 0, &[1, 2, 9])).is_none());
+    }
+
+    #[test]
+    fn g711_decodes_to_the_standard_values() {
+        // µ-law: 0xff and 0x7f are the two zeros, 0x80 and 0x00 full scale
+        assert_eq!([0xff, 0x7f, 0x80, 0x00, 0xf0, 0x70].map(ulaw_to_linear), [0, 0, 32124, -32124, 120, -120]);
+        // A-law: 0xd5 and 0x55 the smallest steps either side of zero, 0xaa and 0x2a full scale
+        assert_eq!([0xd5, 0x55, 0xaa, 0x2a, 0xc5, 0x45].map(alaw_to_linear), [8, -8, 32256, -32256, 264, -264]);
+    }
+
+    #[test]
+    fn late_repeated_and_lost_packets_across_the_sequence_wrap() {
+        let format: RtpFormat = "L16/8000/1".parse().unwrap();
+        let payload = [0x40, 0x00, 0x40, 0x00];      // Two samples of 0.5
+        let mut stream = Depacketizer::default();
+        let mut next = |sequence, timestamp| {
+            let packet = packet(0, 96, sequence, timestamp, &payload);
+            stream.samples(&RtpPacket::parse(&packet).unwrap(), format).map(|(samples, new_sender)| (samples.len(), new_sender))
+        };
+
+        assert_eq!(next(65534, u32::MAX - 1), Some((2, true)));
+        assert_eq!(next(65535, 0), Some((2, false)));
+        // Wrapped around, still ahead
+        assert_eq!(next(0, 2), Some((2, false)));
+        // Repeated, or from before the wrap
+        assert_eq!(next(0, 2), None);
+        assert_eq!(next(65535, 0), None);
+        // One packet lost, filled with silence
+        assert_eq!(next(2, 6), Some((4, false)));
+        // A pause longer than MAX_GAP_MS isn't filled
+        assert_eq!(next(3, 8 + 8001), Some((2, false)));
+    }
+
+    #[test]
+    fn gaps_are_judged_without_overflowing_at_high_rates() {
+        let format = RtpFormat { encoding: Encoding::L16, sample_rate: 8_000_000, channels: 1 };
+        let mut stream = Depacketizer::default();
+        let mut next = |sequence, timestamp| {
+            let packet = packet(0, 96, sequence, timestamp, &[0, 0]);
+            stream.samples(&RtpPacket::parse(&packet).unwrap(), format).unwrap().0.len()
+        };
+        next(1, 0);
+        // Within a second at this rate, which u32 arithmetic would have overflowed
+        assert_eq!(next(2, 11), 11);
+    }
+
+    #[test]
+    fn g711_encodes_to_the_standard_values_and_round_trips() {
+        assert_eq!([0, 120, -120, i16::MAX, i16::MIN].map(linear_to_ulaw), [0xff, 0xf0, 0x70, 0x80, 0x00]);
+        assert_eq!([0, 8, -8, 264, i16::MAX, i16::MIN].map(linear_to_alaw), [0xd5, 0xd5, 0x55, 0xc5, 0xaa, 0x2a]);
+
+        // Every code decodes to a value that encodes back to it, but µ-law's negative zero
+        for code in 0..=255u8 {
+            let expected = if code == 0x7f { 0xff } else { code };
+            assert_eq!(linear_to_ulaw(ulaw_to_linear(code)), expected, "µ-law {:#04x}", code);
+            assert_eq!(linear_to_alaw(alaw_to_linear(code)), code, "A-law {:#04x}", code);
+        }
+
+        // Quantization only ever costs a fraction of the value, more for larger ones
+        for sample in (i16::MIN..=i16::MAX).step_by(97) {
+            let error = |decoded: i16| (decoded as i32 - sample as i32).abs();
+            let bound = (sample as i32).abs() / 16 + 132;
+            assert!(error(ulaw_to_linear(linear_to_ulaw(sample))) <= bound, "µ-law {}", sample);
+            assert!(error(alaw_to_linear(linear_to_alaw(sample))) <= bound, "A-law {}", sample);
+        }
+    }
+
+    /// Packets waiting on a socket
+    fn received(socket: &UdpSocket) -> Vec<Vec<u8>> {
+        let mut buffer = [0u8; MAX_PACKET];
+        std::iter::from_fn(|| socket.recv(&mut buffer).ok().map(|length| buffer[..length].to_vec())).collect()
+    }
+
+    #[test]
+    fn send_due_paces_bursts_in_real_time() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let output = RtpOutput {
+            address: listener.local_addr().unwrap(),
+            format: "PCMU/8000/1".parse().unwrap(),
+            payload_type: 0,
+            packet_ms: 20,
+            prebuffer_ms: 60,
+        };
+        let mut sender = RtpSender::new(output).unwrap();
+        let server_samples = |ms: usize| vec![0.25; SERVER_SAMPLE_RATE as usize * ms / 1000];
+
+        // Short of the prebuffer nothing goes
+        sender.queue(&server_samples(40));
+        sender.send_due();
+        assert!(received(&listener).is_empty());
+
+        // Then the first packet right away, marked, and the next only once its time has come
+        sender.queue(&server_samples(60));
+        sender.send_due();
+        sender.send_due();
+        let first = received(&listener);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0][1] & 0x80, 0x80);
+        let first = RtpPacket::parse(&first[0]).unwrap();
+        assert_eq!((first.payload_type, first.payload.len()), (0, 160));
+
+        std::thread::sleep(Duration::from_millis(25));
+        sender.send_due();
+        let mut burst = received(&listener);
+        assert!(!burst.is_empty());
+        assert_eq!(burst[0][1] & 0x80, 0);
+        let second = RtpPacket::parse(&burst[0]).unwrap();
+        assert_eq!((second.sequence, second.timestamp), (first.sequence.wrapping_add(1), first.timestamp.wrapping_add(160)));
+
+        // The burst ends as the queue runs dry, its last packet padded out
+        std::thread::sleep(Duration::from_millis(150));
+        sender.send_due();
+        burst.extend(received(&listener));
+        assert!(burst.iter().all(|packet| RtpPacket::parse(packet).unwrap().payload.len() == 160));
+        let last = RtpPacket::parse(burst.last().unwrap()).unwrap();
+        assert!(sender.burst.is_none() && sender.backlog.is_empty());
+
+        // The next burst is marked, its timestamp carried over the silence in between
+        std::thread::sleep(Duration::from_millis(200));
+        sender.queue(&server_samples(100));
+        sender.send_due();
+        let next = received(&listener);
+        assert_eq!(next[0][1] & 0x80, 0x80);
+        let next = RtpPacket::parse(&next[0]).unwrap();
+        assert_eq!(next.sequence, last.sequence.wrapping_add(1));
+        assert!(next.timestamp.wrapping_sub(last.timestamp) >= 160 + 8 * 200);
+    }
+}
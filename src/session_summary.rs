@@ -0,0 +1,221 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::usage::UsageTracker;
+
+/// Name of the summary in the --summary-dir
+pub const FILE_NAME: &str = "session-summary.json";
+
+/// Kinds of errors counted over a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorKind {
+    Server,             // Error events from the server
+    Transcription,      // Failed transcriptions of the user's audio
+    ConnectionLost,     // Reconnects, whether or not they worked
+    DroppedEvents,      // Events a subscriber stopped taking
+}
+
+impl ErrorKind {
+    const ALL: [ErrorKind; 4] = [Self::Server, Self::Transcription, Self::ConnectionLost, Self::DroppedEvents];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Server => "server",
+            Self::Transcription => "transcription",
+            Self::ConnectionLost => "connection_lost",
+            Self::DroppedEvents => "dropped_events",
+        }
+    }
+}
+
+/// Counts the errors of a call by kind, shared by the parts of the call that run into them
+#[derive(Debug, Default)]
+pub struct ErrorTracker {
+    counts: BTreeMap<ErrorKind, u64>,
+}
+
+impl ErrorTracker {
+    pub fn count(&mut self, kind: ErrorKind) {
+        *self.counts.entry(kind).or_default() += 1;
+    }
+
+    pub fn get(&self, kind: ErrorKind) -> u64 {
+        self.counts.get(&kind).copied().unwrap_or_default()
+    }
+}
+
+/// Why the call ended
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    HungUp,             // By the user, Ctrl-C, the tray, closing stdin or after the spoken summary
+    TimeLimit,          // --time-limit
+    BudgetExceeded,     // --budget-usd or --budget-tokens
+    Error,              // The call failed, see `error`
+}
+
+/// Tokens used and what they cost
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// What a call came to, written for scripts that wrap hotline to tell failures and find the files
+///
+/// Filled in as the call goes, so a call that fails part way still says what it got to.
+#[derive(Debug, Serialize)]
+pub struct SessionSummary {
+    pub exit_reason: ExitReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub started_at: u64,                        // Unix time
+    pub duration_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    pub errors: BTreeMap<&'static str, u64>,    // Every kind, zero when there were none
+    pub artifacts: BTreeMap<&'static str, PathBuf>,    // Files written, absolute once finished
+    #[serde(skip)]
+    pub started: SystemTime,
+    #[serde(skip)]
+    usage_tracker: Option<Arc<Mutex<UsageTracker>>>,
+    #[serde(skip)]
+    error_tracker: Option<Arc<Mutex<ErrorTracker>>>,
+}
+
+impl Default for SessionSummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionSummary {
+    pub fn new() -> Self {
+        let started = SystemTime::now();
+        Self {
+            exit_reason: ExitReason::HungUp,
+            error: None,
+            started_at: started.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            duration_secs: 0.0,
+            model: None,
+            usage: None,
+            errors: BTreeMap::new(),
+            artifacts: BTreeMap::new(),
+            started,
+            usage_tracker: None,
+            error_tracker: None,
+        }
+    }
+
+    /// Reads the usage off the call's tracker when finishing, failed or not
+    pub fn track_usage(&mut self, usage: Arc<Mutex<UsageTracker>>) {
+        self.usage_tracker = Some(usage);
+    }
+
+    /// Counts the errors off the call's tracker when finishing
+    pub fn track_errors(&mut self, errors: Arc<Mutex<ErrorTracker>>) {
+        self.error_tracker = Some(errors);
+    }
+
+    /// Notes a file the call wrote, or writes as it goes
+    pub fn add_artifact(&mut self, kind: &'static str, path: &Path) {
+        self.artifacts.insert(kind, path.to_path_buf());
+    }
+
+    /// Completes the summary once the call is over, with its error if it failed
+    pub fn finish(&mut self, error: Option<String>) {
+        if error.is_some() {
+            self.exit_reason = ExitReason::Error;
+        }
+        self.error = error;
+        self.duration_secs = self.started.elapsed().unwrap_or_default().as_secs_f64();
+        if let Some(usage) = &self.usage_tracker {
+            let usage = usage.lock().unwrap();
+            self.usage = Some(Usage { total_tokens: usage.total_tokens(), cost_usd: usage.cost_usd() });
+        }
+
+        let counted = self.error_tracker.as_ref().map(|errors| errors.lock().unwrap());
+        self.errors = ErrorKind::ALL.iter().map(|kind| (kind.as_str(), counted.as_ref().map_or(0, |counted| counted.get(*kind)))).collect();
+        drop(counted);
+
+        // Made absolute now the files are there, one never written (e.g. it couldn't be created) isn't listed
+        self.artifacts = std::mem::take(&mut self.artifacts)
+            .into_iter()
+            .filter_map(|(kind, path)| std::fs::canonicalize(path).ok().map(|path| (kind, path)))
+            .collect();
+    }
+
+    /// Writes the summary into `directory` and returns its path
+    ///
+    /// It's written aside and moved into place, so a script never reads half a summary.
+    pub fn write(&self, directory: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(FILE_NAME);
+        let partial = directory.join(format!(".{}.partial", FILE_NAME));
+        std::fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn finish_reads_the_trackers_and_the_files_written() {
+        let directory = std::env::temp_dir().join(format!("hotline-summary-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let written = directory.join("transcript.json");
+        std::fs::write(&written, "{}").unwrap();
+
+        let errors = Arc::new(Mutex::new(ErrorTracker::default()));
+        errors.lock().unwrap().count(ErrorKind::Server);
+        errors.lock().unwrap().count(ErrorKind::Server);
+        errors.lock().unwrap().count(ErrorKind::ConnectionLost);
+
+        let mut summary = SessionSummary::new();
+        summary.track_usage(Arc::new(Mutex::new(UsageTracker::default())));
+        summary.track_errors(errors.clone());
+        // A relative path is made absolute, one never created is left out
+        summary.add_artifact("transcript", &directory.join(".").join("transcript.json"));
+        summary.add_artifact("latency_log", &directory.join("latency.csv"));
+        // Counted after the summary was started, still in it
+        errors.lock().unwrap().count(ErrorKind::DroppedEvents);
+        summary.finish(None);
+
+        assert_eq!(summary.exit_reason, ExitReason::HungUp);
+        assert_eq!(summary.errors, BTreeMap::from([("server", 2), ("transcription", 0), ("connection_lost", 1), ("dropped_events", 1)]));
+        assert_eq!(summary.artifacts, BTreeMap::from([("transcript", std::fs::canonicalize(&written).unwrap())]));
+        assert_eq!(summary.usage.as_ref().map(|usage| usage.total_tokens), Some(0));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn write_leaves_a_whole_summary_in_place() {
+        let directory = std::env::temp_dir().join(format!("hotline-summary-{}", uuid::Uuid::new_v4()));
+        let mut summary = SessionSummary { model: Some("gpt-realtime".to_string()), ..SessionSummary::new() };
+        summary.finish(Some("connection refused".to_string()));
+
+        // Every kind of error is listed, even without a tracker
+        let path = summary.write(&directory.join("nested")).unwrap();
+        assert_eq!(path, directory.join("nested").join(FILE_NAME));
+        let written: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["exit_reason"], "error");
+        assert_eq!(written["error"], "connection refused");
+        assert_eq!(written["model"], "gpt-realtime");
+        assert_eq!(written["errors"], json!({"server": 0, "transcription": 0, "connection_lost": 0, "dropped_events": 0}));
+        assert!(written.get("usage").is_none());
+        // Nothing left over from writing it aside
+        assert_eq!(std::fs::read_dir(directory.join("nested")).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::ChaCha20Poly1305;
+use std::path::Path;
+
+// Header of encrypted files, followed by the salt, the nonce and the ciphertext
+const MAGIC: &[u8] = b"HOTLINE-ENC1";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+/// Environment variable holding the passphrase for encrypted files
+pub const PASSPHRASE_ENV: &str = "HOTLINE_PASSPHRASE";
+
+/// Secret used to encrypt saved transcripts, recordings and protocol dumps
+pub enum Encryption {
+    Passphrase(String),
+    Keyfile(Vec<u8>),
+}
+
+impl Encryption {
+    /// Picks the keyfile if given, otherwise the passphrase from the environment
+    pub fn from_options(keyfile: Option<&Path>) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if let Some(path) = keyfile {
+            let key = std::fs::read(path).map_err(|e| format!("Failed to read keyfile {}: {}", path.display(), e))?;
+            return Ok(Some(Self::Keyfile(key)));
+        }
+
+        Ok(std::env::var(PASSPHRASE_ENV).ok().filter(|passphrase| !passphrase.is_empty()).map(Self::Passphrase))
+    }
+
+    fn secret(&self) -> &[u8] {
+        match self {
+            Self::Passphrase(passphrase) => passphrase.as_bytes(),
+            Self::Keyfile(key) => key,
+        }
+    }
+
+    // Both passphrases and keyfiles go through Argon2 so a short secret isn't used as a key directly
+    fn cipher(&self, salt: &[u8]) -> Result<ChaCha20Poly1305, Box<dyn std::error::Error>> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.secret(), salt, &mut key)
+            .map_err(|e| format!("Failed to derive encryption key: {}", e))?;
+
+        Ok(ChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+/// Writes a file, encrypting it with ChaCha20-Poly1305 when a secret is given
+pub fn write_file(path: &Path, data: &[u8], encryption: Option<&Encryption>) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = match encryption {
+        Some(encryption) => {
+            let mut salt = [0u8; SALT_LENGTH];
+            OsRng.fill_bytes(&mut salt);
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+            let ciphertext = encryption
+                .cipher(&salt)?
+                .encrypt(&nonce, data)
+                .map_err(|_| "Failed to encrypt data")?;
+
+            [MAGIC, &salt, &nonce, &ciphertext].concat()
+        }
+        None => data.to_vec(),
+    };
+
+    std::fs::write(path, bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Reads a file written by `write_file`, transparently decrypting it if needed
+pub fn read_file(path: &Path, encryption: Option<&Encryption>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        return Ok(bytes);
+    };
+
+    let encryption = encryption.ok_or_else(|| {
+        format!("{} is encrypted, set {} or pass --keyfile", path.display(), PASSPHRASE_ENV)
+    })?;
+
+    if rest.len() < SALT_LENGTH + NONCE_LENGTH {
+        return Err(format!("{} is not a valid encrypted file", path.display()).into());
+    }
+    let (salt, rest) = rest.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+    let data = encryption
+        .cipher(salt)?
+        .decrypt(nonce.into(), ciphertext)
+        .map_err(|_| format!("Failed to decrypt {}, wrong passphrase or keyfile?", path.display()))?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("hotline-storage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn encrypted_files_read_back_with_the_same_secret_only() {
+        let directory = temp_dir();
+        let path = directory.join("transcript.json");
+        let passphrase = Encryption::Passphrase("correct horse".to_string());
+
+        write_file(&path, b"{\"items\": []}", Some(&passphrase)).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(written.starts_with(MAGIC));
+        assert!(!written.windows(5).any(|window| window == b"items"));
+        assert_eq!(read_file(&path, Some(&passphrase)).unwrap(), b"{\"items\": []}");
+
+        // The wrong secret, or none, fails rather than returning garbage
+        let wrong = Encryption::Passphrase("battery staple".to_string());
+        assert!(read_file(&path, Some(&wrong)).unwrap_err().to_string().contains("wrong passphrase or keyfile"));
+        assert!(read_file(&path, None).unwrap_err().to_string().contains(PASSPHRASE_ENV));
+
+        // A keyfile works alike
+        std::fs::write(directory.join("key"), [7u8; 32]).unwrap();
+        let keyfile = Encryption::from_options(Some(&directory.join("key"))).unwrap().unwrap();
+        write_file(&path, b"audio", Some(&keyfile)).unwrap();
+        assert_eq!(read_file(&path, Some(&Encryption::Keyfile(vec![7; 32]))).unwrap(), b"audio");
+        assert!(read_file(&path, Some(&passphrase)).is_err());
+        assert!(Encryption::from_options(Some(&directory.join("missing"))).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn plain_and_truncated_files() {
+        let directory = temp_dir();
+        let path = directory.join("notes.txt");
+        let secret = Encryption::Passphrase("correct horse".to_string());
+
+        // Written and read as they are without a secret, and read as they are with one
+        write_file(&path, b"plain", None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"plain");
+        assert_eq!(read_file(&path, None).unwrap(), b"plain");
+        assert_eq!(read_file(&path, Some(&secret)).unwrap(), b"plain");
+
+        // Too short for the salt and the nonce
+        std::fs::write(&path, [MAGIC, &[0u8; SALT_LENGTH + NONCE_LENGTH - 1]].concat()).unwrap();
+        assert!(read_file(&path, Some(&secret)).unwrap_err().to_string().contains("not a valid encrypted file"));
+
+        assert!(read_file(&directory.join("missing"), None).unwrap_err().to_string().contains("Failed to read"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Shown where text was cut short
+const ELLIPSIS: &str = "…";
+
+/// Columns the text takes up in a terminal, wide (CJK, emoji) characters count double
+pub fn display_width(text: &str) -> usize {
+    text.width()
+}
+
+/// Shortens text to fit in `max_width` columns, ending it with an ellipsis when cut
+///
+/// Cuts between grapheme clusters so emoji sequences and combining marks stay whole.
+pub fn truncate(text: &str, max_width: usize) -> String {
+    if display_width(text) <= max_width {
+        return text.to_string();
+    }
+
+    let budget = max_width.saturating_sub(display_width(ELLIPSIS));
+    let mut width = 0;
+    let mut truncated = String::new();
+    for grapheme in text.graphemes(true) {
+        width += grapheme.width();
+        if width > budget {
+            break;
+        }
+        truncated.push_str(grapheme);
+    }
+
+    truncated.push_str(ELLIPSIS);
+    truncated
+}
+
+/// Wraps text into lines of at most `width` columns
+///
+/// Breaks at word boundaries, which also falls between CJK characters, and splits words
+/// wider than a whole line between grapheme clusters.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(2);   // Room for at least one wide character
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0;
+
+        for word in paragraph.split_word_bounds() {
+            let word_width = display_width(word);
+
+            if line_width + word_width > width && !line.is_empty() {
+                lines.push(line.trim_end().to_string());
+                line = String::new();
+                line_width = 0;
+            }
+
+            // Whitespace isn't carried over to the start of a line
+            if line.is_empty() && word.trim().is_empty() {
+                continue;
+            }
+
+            if word_width > width {
+                for grapheme in word.graphemes(true) {
+                    let grapheme_width = grapheme.width();
+                    if line_width + grapheme_width > width {
+                        lines.push(std::mem::take(&mut line));
+                        line_width = 0;
+                    }
+                    line.push_str(grapheme);
+                    line_width += grapheme_width;
+                }
+            } else {
+                line.push_str(word);
+                line_width += word_width;
+            }
+        }
+
+        lines.push(line.trim_end().to_string());
+    }
+
+    lines
+}
+
+/// Splits text roughly `fraction` of the way through, moving forward to the end of the word
+///
+/// The fraction counts grapheme clusters rather than bytes, so a cut never lands inside a
+/// character and text heavy in multi-byte characters isn't skewed.
+pub fn split_at_fraction(text: &str, fraction: f64) -> (&str, &str) {
+    let graphemes = text.grapheme_indices(true).count();
+    let cut = (graphemes as f64 * fraction.clamp(0.0, 1.0)) as usize;
+    // Moving forward from nothing would count the first word as heard, when playback was cut
+    // before any of it reached the speakers
+    if cut == 0 {
+        return ("", text);
+    }
+
+    let (cut_offset, _) = text.grapheme_indices(true).nth(cut - 1).unwrap();
+    let split = text
+        .split_word_bound_indices()
+        .map(|(offset, word)| offset + word.len())
+        .find(|&end| end > cut_offset)
+        .unwrap_or(text.len());
+
+    // Leave the space before the next word with the unheard part
+    text.split_at(text[..split].trim_end().len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CJK: &str = "今日は天気がいいですね。散歩に行きましょう。";
+    const EMOJI: &str = "Great job 👍🏽 the family 👨‍👩‍👧 says hi 🇯🇵 and café";
+
+    #[test]
+    fn width_counts_wide_characters_double() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("今日"), 4);
+        assert_eq!(display_width("e\u{301}"), 1);   // Combining accent takes no column
+    }
+
+    #[test]
+    fn truncate_keeps_graphemes_whole() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate(CJK, 7), "今日は…");
+        assert_eq!(truncate("👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧", 4), "👨‍👩‍👧…");
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}e\u{301}", 3), "e\u{301}e\u{301}…");
+
+        for max_width in 1..40 {
+            assert!(display_width(&truncate(EMOJI, max_width)) <= max_width);
+        }
+    }
+
+    #[test]
+    fn wrap_fits_lines_to_width() {
+        for width in [4, 7, 10, 13, 20] {
+            for text in [CJK, EMOJI] {
+                let lines = wrap(text, width);
+                assert!(lines.iter().all(|line| display_width(line) <= width), "{:?} at width {}", lines, width);
+
+                // Nothing but the whitespace at line breaks goes missing
+                let strip = |text: &str| text.split_whitespace().collect::<String>();
+                assert_eq!(strip(&lines.concat()), strip(text));
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_breaks_between_words() {
+        assert_eq!(wrap("the quick brown fox", 10), ["the quick", "brown fox"]);
+        assert_eq!(wrap("今日は天気", 6), ["今日は", "天気"]);
+        assert_eq!(wrap("first\nsecond", 20), ["first", "second"]);
+    }
+
+    #[test]
+    fn split_moves_to_the_end_of_the_word() {
+        assert_eq!(split_at_fraction("hello there world", 0.0), ("", "hello there world"));
+        assert_eq!(split_at_fraction("hello there world", 0.05), ("", "hello there world"));   // Less than a character
+        assert_eq!(split_at_fraction("hello there world", 0.3), ("hello", " there world"));
+        assert_eq!(split_at_fraction("hello there world", 0.5), ("hello there", " world"));
+        assert_eq!(split_at_fraction("hello there world", 1.0), ("hello there world", ""));
+    }
+
+    #[test]
+    fn split_handles_cjk_and_emoji() {
+        // Ideographs are words of their own, so the cut doesn't run on to the end of the sentence
+        let (heard, unheard) = split_at_fraction(CJK, 0.5);
+        assert!(!heard.is_empty() && !unheard.is_empty());
+        assert_eq!(format!("{}{}", heard, unheard), CJK);
+
+        for fraction in [0.1, 0.3, 0.5, 0.7, 0.9] {
+            let (heard, unheard) = split_at_fraction(EMOJI, fraction);
+            assert_eq!(format!("{}{}", heard, unheard), EMOJI);
+            assert!(!unheard.starts_with('\u{200d}') && !unheard.starts_with('\u{1f3fd}'));
+        }
+    }
+}
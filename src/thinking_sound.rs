@@ -0,0 +1,62 @@
+use std::f32::consts::TAU;
+use std::path::PathBuf;
+
+use crate::audio_utils::{convert_audio_to_server, read_audio, SERVER_SAMPLE_RATE};
+
+// Volume of the built-in sounds, they should sit well below the assistant's voice
+const TICK_LEVEL: f32 = 0.08;
+const HUM_LEVEL: f32 = 0.03;
+
+/// Sound played while the assistant is thinking, between the end of the user's turn and its first audio
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThinkingSound {
+    Tick,           // A soft tick every second
+    Hum,            // A quiet tone swelling in and out
+    File(PathBuf),  // An audio file, looped
+}
+
+impl std::fmt::Display for ThinkingSound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Tick => write!(f, "tick"),
+            Self::Hum => write!(f, "hum"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl std::str::FromStr for ThinkingSound {
+    type Err = String;
+
+    fn from_str(sound: &str) -> Result<Self, Self::Err> {
+        match sound {
+            "tick" => Ok(Self::Tick),
+            "hum" => Ok(Self::Hum),
+            path if std::path::Path::new(path).is_file() => Ok(Self::File(PathBuf::from(path))),
+            _ => Err(format!("Unknown thinking sound: {} (expected tick, hum or an audio file)", sound)),
+        }
+    }
+}
+
+impl ThinkingSound {
+    /// One loop of the sound, mono at SERVER_SAMPLE_RATE
+    pub fn samples(&self) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let rate = SERVER_SAMPLE_RATE as f32;
+        match self {
+            // 20 ms of a fast decaying 1.5 kHz tone, then silence for the rest of the second
+            Self::Tick => Ok((0..SERVER_SAMPLE_RATE as usize)
+                .map(|n| n as f32 / rate)
+                .map(|t| if t < 0.02 { TICK_LEVEL * (-t * 250.0).exp() * (TAU * 1500.0 * t).sin() } else { 0.0 })
+                .collect()),
+            // 220 Hz fading in and out over two seconds
+            Self::Hum => Ok((0..2 * SERVER_SAMPLE_RATE as usize)
+                .map(|n| n as f32 / rate)
+                .map(|t| HUM_LEVEL * (TAU * t / 4.0).sin().powi(2) * (TAU * 220.0 * t).sin())
+                .collect()),
+            Self::File(path) => {
+                let (samples, sample_rate, channels) = read_audio(path)?;
+                Ok(convert_audio_to_server(&samples, sample_rate, channels))
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+
+/// Slowest and fastest playback allowed, beyond these WSOLA starts to sound choppy
+pub const MIN_SPEED: f32 = 0.75;
+pub const MAX_SPEED: f32 = 2.0;
+
+// Frames of 40 ms, each overlapping the previous one by half
+const FRAME: usize = SERVER_SAMPLE_RATE as usize / 25;
+const HOP: usize = FRAME / 2;
+// How far a frame may move from its nominal position to line up with the previous one, 10 ms
+const SEARCH: usize = SERVER_SAMPLE_RATE as usize / 100;
+
+/// Parses a playback speed such as 1.5, within MIN_SPEED and MAX_SPEED
+pub fn parse_speed(text: &str) -> Result<f32, String> {
+    let speed: f32 = text.trim().trim_end_matches(['x', '×']).parse().map_err(|_| format!("Invalid speed: {}", text))?;
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        return Err(format!("The speed must be between {} and {}", MIN_SPEED, MAX_SPEED));
+    }
+    Ok(speed)
+}
+
+/// Changes the speed of a mono stream at SERVER_SAMPLE_RATE without changing its pitch (WSOLA)
+///
+/// The output is built from overlapping windowed frames of the input, a hop apart. Reading the
+/// frames further apart than that speeds the speech up, closer together slows it down. Each frame
+/// is shifted by up to SEARCH samples to where it best continues the waveform of the previous one,
+/// so the overlaps add up in phase instead of smearing the voice. At speed 1 the stream passes
+/// through untouched, streams already being stretched carry on until flushed.
+pub struct TimeStretcher {
+    speed: f64,
+    window: Vec<f32>,       // Periodic Hann, overlapping halves add up to 1
+    input: Vec<f32>,        // Not consumed yet
+    position: f64,          // Nominal start of the next frame in `input`
+    template: Vec<f32>,     // Second half of the last frame, unwindowed, empty before the first frame
+    tail: Vec<f32>,         // Second half of the last frame, windowed, added to the next one
+    expected: f64,          // Output the input so far is worth, at the speed it was given at
+    produced: usize,        // Output given out so far
+}
+
+impl Default for TimeStretcher {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            window: (0..FRAME).map(|i| (0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / FRAME as f64).cos()) as f32).collect(),
+            input: Vec::new(),
+            position: 0.0,
+            template: Vec::new(),
+            tail: Vec::new(),
+            expected: 0.0,
+            produced: 0,
+        }
+    }
+}
+
+impl TimeStretcher {
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed as f64;
+    }
+
+    /// Stretches the next samples of the stream, holding back the last ~50 ms until more arrive
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        if self.speed == 1.0 && self.is_idle() {
+            return samples.to_vec();
+        }
+
+        self.input.extend_from_slice(samples);
+        self.expected += samples.len() as f64 / self.speed;
+
+        let mut output = Vec::new();
+        while let Some(hop) = self.next_hop() {
+            output.extend(hop);
+        }
+        self.produced += output.len();
+        output
+    }
+
+    /// Ends the stream: gives out what was held back and starts afresh
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.is_idle() {
+            return Vec::new();
+        }
+
+        // Silence after the end lets the last frames be taken as usual
+        let padding = FRAME + 2 * SEARCH;
+        self.input.extend(std::iter::repeat_n(0.0, padding));
+
+        let mut output = Vec::new();
+        while self.position < self.input.len().saturating_sub(padding) as f64 {
+            let Some(hop) = self.next_hop() else { break };
+            output.extend(hop);
+        }
+        output.append(&mut self.tail);
+        output.resize((self.expected.round() as usize).saturating_sub(self.produced), 0.0);
+
+        self.reset();
+        output
+    }
+
+    /// Drops everything held back, e.g. when playback is stopped
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.template.clear();
+        self.tail.clear();
+        self.expected = 0.0;
+        self.produced = 0;
+    }
+
+    fn is_idle(&self) -> bool {
+        self.input.is_empty() && self.template.is_empty()
+    }
+
+    /// Adds the next frame, None until there's enough input to search around its position
+    fn next_hop(&mut self) -> Option<Vec<f32>> {
+        let nominal = self.position.round() as usize;
+        if nominal + SEARCH + FRAME > self.input.len() {
+            return None;
+        }
+
+        let hop = if self.template.is_empty() {
+            // The stream starts as it is, rather than fading in
+            self.take_frame(0);
+            self.input[..HOP].to_vec()
+        } else {
+            let start = self.best_start(nominal);
+            self.take_frame(start)
+        };
+
+        self.position += HOP as f64 * self.speed;
+
+        // Input before the earliest place the next frame could start isn't needed anymore
+        let consumed = (self.position as usize).saturating_sub(SEARCH);
+        self.input.drain(..consumed);
+        self.position -= consumed as f64;
+
+        Some(hop)
+    }
+
+    /// Overlap-adds the frame starting here, returning the finished hop before it
+    fn take_frame(&mut self, start: usize) -> Vec<f32> {
+        let frame = &self.input[start..start + FRAME];
+        let hop = (0..HOP).map(|i| self.tail.get(i).copied().unwrap_or_default() + frame[i] * self.window[i]).collect();
+
+        self.tail = (HOP..FRAME).map(|i| frame[i] * self.window[i]).collect();
+        self.template = frame[HOP..].to_vec();
+        hop
+    }
+
+    /// Where near `nominal` a frame best continues the last one, by normalized cross-correlation
+    fn best_start(&self, nominal: usize) -> usize {
+        let score = |start: usize| {
+            let candidate = &self.input[start..start + HOP];
+            let correlation: f32 = candidate.iter().zip(&self.template).map(|(a, b)| a * b).sum();
+            let energy: f32 = candidate.iter().map(|a| a * a).sum();
+            correlation / (energy + 1e-9).sqrt()
+        };
+
+        // Silence scores the same everywhere, the nominal position wins ties
+        let (mut best, mut best_score) = (nominal, score(nominal));
+        for start in nominal.saturating_sub(SEARCH)..=nominal + SEARCH {
+            let candidate_score = score(start);
+            if candidate_score > best_score {
+                (best, best_score) = (start, candidate_score);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A second of a 220 Hz tone, about the pitch of a voice
+    fn voice() -> Vec<f32> {
+        (0..SERVER_SAMPLE_RATE).map(|n| (0.5 * (2.0 * std::f64::consts::PI * 220.0 * n as f64 / SERVER_SAMPLE_RATE as f64).sin()) as f32).collect()
+    }
+
+    /// Streams the samples through in uneven chunks, as audio deltas come, and flushes
+    fn stretch(stretcher: &mut TimeStretcher, samples: &[f32]) -> Vec<f32> {
+        let mut output = Vec::new();
+        for chunk in samples.chunks(1234) {
+            output.extend(stretcher.process(chunk));
+        }
+        output.extend(stretcher.flush());
+        output
+    }
+
+    #[test]
+    fn parses_speeds_within_the_limits() {
+        assert_eq!(parse_speed("1.5").unwrap(), 1.5);
+        assert_eq!(parse_speed(" 2x ").unwrap(), 2.0);
+        assert_eq!(parse_speed("0.75×").unwrap(), 0.75);
+        assert!(parse_speed("0.5").is_err());
+        assert!(parse_speed("fast").is_err());
+    }
+
+    #[test]
+    fn passes_through_untouched_at_normal_speed() {
+        let mut stretcher = TimeStretcher::default();
+        let samples = voice();
+        assert_eq!(stretcher.process(&samples[..1000]), &samples[..1000]);
+        assert!(stretcher.flush().is_empty());
+        assert_eq!(stretch(&mut stretcher, &samples), samples);
+    }
+
+    #[test]
+    fn output_lasts_the_input_over_the_speed() {
+        let samples = voice();
+        for speed in [MIN_SPEED, 1.25, 1.5, MAX_SPEED] {
+            let mut stretcher = TimeStretcher::default();
+            stretcher.set_speed(speed);
+            let output = stretch(&mut stretcher, &samples);
+            let expected = (samples.len() as f32 / speed).round() as usize;
+            assert_eq!(output.len(), expected, "speed {}", speed);
+
+            // Still the tone at full level, not smeared into something quieter
+            let middle = &output[output.len() / 4..output.len() * 3 / 4];
+            let rms = (middle.iter().map(|sample| sample * sample).sum::<f32>() / middle.len() as f32).sqrt();
+            assert!((rms - 0.5 / 2f32.sqrt()).abs() < 0.05, "speed {}: rms {}", speed, rms);
+        }
+    }
+
+    #[test]
+    fn a_stream_being_stretched_carries_on_back_at_normal_speed() {
+        let samples = voice();
+        let mut stretcher = TimeStretcher::default();
+        stretcher.set_speed(2.0);
+        let mut output = stretcher.process(&samples[..12_000]);
+        stretcher.set_speed(1.0);
+        output.extend(stretcher.process(&samples[12_000..]));
+        output.extend(stretcher.flush());
+        assert_eq!(output.len(), 6000 + 12_000);
+    }
+
+    #[test]
+    fn reset_drops_what_was_held_back() {
+        let samples = voice();
+        let mut stretcher = TimeStretcher::default();
+        stretcher.set_speed(1.5);
+        stretcher.process(&samples);
+        assert!(!stretcher.is_idle());
+
+        stretcher.reset();
+        assert!(stretcher.is_idle());
+        assert!(stretcher.flush().is_empty());
+        // And starts afresh, the next stream's length isn't thrown off by the last
+        let output = stretch(&mut stretcher, &samples[..24_000]);
+        assert_eq!(output.len(), 16_000);
+    }
+}
@@ -0,0 +1,406 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::audio_utils::SERVER_SAMPLE_RATE;
+use crate::dtmf;
+
+// Limits on what read_file returns, long files would eat the context window
+const MAX_FILE_LINES: usize = 500;
+const MAX_FILE_CHARS: usize = 20_000;
+
+// Quick actions offered at once, one per digit key
+pub const MAX_QUICK_ACTIONS: usize = 9;
+
+// Types a JSON Schema can give a value
+const SCHEMA_TYPES: [&str; 7] = ["object", "array", "string", "number", "integer", "boolean", "null"];
+
+/// A function call made by the model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub call_id: String,
+    pub name: String,
+    pub arguments: String,      // JSON encoded arguments, as sent by the model
+}
+
+impl ToolCall {
+    /// Reads a `function_call` output item
+    pub fn from_item(item: &Value) -> Option<Self> {
+        if item["type"] != "function_call" {
+            return None;
+        }
+
+        Some(Self {
+            call_id: item["call_id"].as_str()?.to_string(),
+            name: item["name"].as_str()?.to_string(),
+            arguments: item["arguments"].as_str().unwrap_or("{}").to_string(),
+        })
+    }
+}
+
+/// Built-in tools the model can call
+#[derive(Debug, Default)]
+pub struct Tools {
+    read_roots: Vec<PathBuf>,       // Directories read_file may read from, canonicalized
+    dtmf: bool,                     // send_dtmf is offered, the call's audio goes out to a phone line
+    quick_actions: bool,            // suggest_actions is offered, its suggestions shown numbered to pick from
+}
+
+impl Tools {
+    /// Enables read_file for the given directories, and send_dtmf and suggest_actions if asked
+    pub fn new(read_roots: &[PathBuf], dtmf: bool, quick_actions: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let read_roots = read_roots
+            .iter()
+            .map(|root| root.canonicalize().map_err(|e| format!("Cannot use {} for read_file: {}", root.display(), e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { read_roots, dtmf, quick_actions })
+    }
+
+    /// Function definitions for the session config
+    pub fn definitions(&self) -> Vec<Value> {
+        let mut definitions = Vec::new();
+
+        if !self.read_roots.is_empty() {
+            let roots = self.read_roots.iter().map(|root| root.display().to_string()).collect::<Vec<_>>().join(", ");
+            definitions.push(serde_json::json!({
+                "type": "function",
+                "name": "read_file",
+                "description": format!(
+                    "Reads a local text file and returns its content with line numbers. Only files under these directories can be read: {}. Relative paths are resolved against the first one.",
+                    roots
+                ),
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": {"type": "string", "description": "Path of the file to read"}
+                    },
+                    "required": ["path"]
+                }
+            }));
+        }
+
+        if self.dtmf {
+            definitions.push(serde_json::json!({
+                "type": "function",
+                "name": "send_dtmf",
+                "description": "Presses keys on the phone keypad, playing their DTMF tones into the call, e.g. to choose from a phone menu (\"press 2 for billing\") or enter a number followed by #. A comma waits half a second between keys.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "keys": {"type": "string", "description": format!("Keys to press in order, some of {}", dtmf::KEYS)}
+                    },
+                    "required": ["keys"]
+                }
+            }));
+        }
+
+        if self.quick_actions {
+            definitions.push(serde_json::json!({
+                "type": "function",
+                "name": "suggest_actions",
+                "description": format!(
+                    "Offers the user up to {} short replies or next steps, shown numbered on their screen so they can pick one by its number instead of saying or typing it. The one picked is sent as their next message, word for word. Use it when there are a few obvious ways to go on, e.g. the choices of a menu; there's no need to read the options out in full.",
+                    MAX_QUICK_ACTIONS
+                ),
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "actions": {
+                            "type": "array",
+                            "description": "The replies in order, each a few words, written as the user would say them",
+                            "items": {"type": "string"},
+                            "maxItems": MAX_QUICK_ACTIONS
+                        }
+                    },
+                    "required": ["actions"]
+                }
+            }));
+        }
+
+        definitions
+    }
+
+    /// Keys a send_dtmf call presses, None for any other call or keys that can't be pressed
+    pub fn dtmf_keys(&self, call: &ToolCall) -> Option<String> {
+        if !self.dtmf || call.name != "send_dtmf" {
+            return None;
+        }
+        let arguments: Value = serde_json::from_str(&call.arguments).ok()?;
+        let keys = arguments["keys"].as_str()?;
+        dtmf::tones(keys).ok().map(|_| keys.to_string())
+    }
+
+    /// Replies a suggest_actions call offers, None for any other call or one without any
+    pub fn quick_actions(&self, call: &ToolCall) -> Option<Vec<String>> {
+        if !self.quick_actions || call.name != "suggest_actions" {
+            return None;
+        }
+        let arguments: Value = serde_json::from_str(&call.arguments).ok()?;
+        let actions: Vec<String> = arguments["actions"]
+            .as_array()?
+            .iter()
+            .filter_map(|action| action.as_str().map(str::trim).filter(|action| !action.is_empty()).map(str::to_string))
+            .take(MAX_QUICK_ACTIONS)
+            .collect();
+        (!actions.is_empty()).then_some(actions)
+    }
+
+    /// Runs a tool call, errors are returned as text for the model to relay
+    pub fn call(&self, call: &ToolCall) -> String {
+        let arguments: Value = match serde_json::from_str(&call.arguments) {
+            Ok(arguments) => arguments,
+            Err(e) => return format!("Error: invalid arguments: {}", e),
+        };
+
+        let result = match call.name.as_str() {
+            "read_file" if !self.read_roots.is_empty() => match arguments["path"].as_str() {
+                Some(path) => self.read_file(Path::new(path)),
+                None => Err("missing the path argument".into()),
+            },
+            // The tones themselves are played by the caller, see dtmf_keys()
+            "send_dtmf" if self.dtmf => match arguments["keys"].as_str() {
+                Some(keys) => dtmf::tones(keys)
+                    .map(|tones| format!("Pressed {} ({:.1} s of tones)", keys, tones.len() as f32 / SERVER_SAMPLE_RATE as f32))
+                    .map_err(|e| e.into()),
+                None => Err("missing the keys argument".into()),
+            },
+            // Shown by the caller, see quick_actions()
+            "suggest_actions" if self.quick_actions => match self.quick_actions(call) {
+                Some(actions) => Ok(format!("Shown to the user as {} numbered quick actions", actions.len())),
+                None => Err("the actions argument needs at least one reply".into()),
+            },
+            name => Err(format!("unknown tool {}", name).into()),
+        };
+
+        result.unwrap_or_else(|e| format!("Error: {}", e))
+    }
+
+    /// Reads a file under one of the allowed directories, line numbered and truncated
+    fn read_file(&self, path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        let path = if path.is_relative() { self.read_roots[0].join(path) } else { path.to_path_buf() };
+
+        // Canonicalizing resolves `..` and symlinks, so the check can't be walked around
+        let path = path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !self.read_roots.iter().any(|root| path.starts_with(root)) {
+            return Err(format!("{} is outside the directories hotline may read", path.display()).into());
+        }
+
+        let content = std::fs::read(&path)?;
+        let content = String::from_utf8(content).map_err(|_| format!("{} is not a text file", path.display()))?;
+
+        let mut output = String::new();
+        let mut lines = content.lines().enumerate();
+        for (number, line) in lines.by_ref() {
+            output.push_str(&format!("{:>5}  {}\n", number + 1, line));
+            if number + 1 >= MAX_FILE_LINES || output.len() >= MAX_FILE_CHARS {
+                break;
+            }
+        }
+
+        let remaining = lines.count();
+        if remaining > 0 {
+            output.push_str(&format!("[truncated, {} more lines]\n", remaining));
+        }
+
+        Ok(output)
+    }
+}
+
+/// What's wrong with a function definition, nothing if the API would take it
+///
+/// Covers the name and the parts of JSON Schema function parameters use: types, properties,
+/// required properties, array items and enums.
+pub fn definition_problems(definition: &Value) -> Vec<String> {
+    let name = definition["name"].as_str().unwrap_or_default();
+    let mut problems = Vec::new();
+    if name.is_empty() || name.len() > 64 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        problems.push(format!("function name {:?} must be 1 to 64 letters, digits, _ or -", name));
+    }
+    if definition["parameters"]["type"] != "object" {
+        problems.push(format!("{}: parameters must be a schema of type object", name));
+    }
+    schema_problems(&definition["parameters"], &format!("{}.parameters", name), &mut problems);
+    problems
+}
+
+fn schema_problems(schema: &Value, at: &str, problems: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        problems.push(format!("{}: a schema must be an object", at));
+        return;
+    };
+
+    let types: Vec<&Value> = match &schema.get("type") {
+        Some(Value::Array(types)) => types.iter().collect(),
+        Some(kind) => vec![kind],
+        None => Vec::new(),
+    };
+    for kind in &types {
+        if !kind.as_str().is_some_and(|kind| SCHEMA_TYPES.contains(&kind)) {
+            problems.push(format!("{}: unknown type {}", at, kind));
+        }
+    }
+
+    if let Some(properties) = schema.get("properties") {
+        match properties.as_object() {
+            Some(properties) => {
+                for (name, property) in properties {
+                    schema_problems(property, &format!("{}.{}", at, name), problems);
+                }
+            }
+            None => problems.push(format!("{}: properties must be an object", at)),
+        }
+    }
+    if let Some(required) = schema.get("required") {
+        match required.as_array() {
+            Some(required) => {
+                for name in required {
+                    let known = name.as_str().and_then(|name| schema.get("properties")?.get(name)).is_some();
+                    if !known {
+                        problems.push(format!("{}: required {} isn't one of the properties", at, name));
+                    }
+                }
+            }
+            None => problems.push(format!("{}: required must be a list of property names", at)),
+        }
+    }
+    if let Some(items) = schema.get("items") {
+        schema_problems(items, &format!("{}[]", at), problems);
+    }
+    if schema.get("enum").is_some_and(|values| values.as_array().is_none_or(Vec::is_empty)) {
+        problems.push(format!("{}: enum must be a list of values", at));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn built_in_definitions_are_valid() {
+        let tools = Tools::new(&[std::env::temp_dir()], true, true).unwrap();
+        let definitions = tools.definitions();
+        assert_eq!(definitions.len(), 3);
+        for definition in &definitions {
+            assert_eq!(definition_problems(definition), Vec::<String>::new(), "{}", definition["name"]);
+        }
+        assert!(Tools::default().definitions().is_empty());
+    }
+
+    #[test]
+    fn read_file_stays_within_the_allowed_directories() {
+        let directory = std::env::temp_dir().join(format!("hotline-read-{}", uuid::Uuid::new_v4()));
+        let (first, second, outside) = (directory.join("first"), directory.join("second"), directory.join("outside"));
+        for dir in [&first, &second, &outside, &first.join("sub")] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(first.join("sub").join("notes.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(second.join("other.txt"), "other").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        let tools = Tools::new(&[first.clone(), second.clone()], false, false).unwrap();
+
+        // Relative paths are under the first directory, absolute ones may be under any
+        assert_eq!(tools.read_file(Path::new("sub/notes.txt")).unwrap(), "    1  one\n    2  two\n");
+        assert_eq!(tools.read_file(&second.join("other.txt")).unwrap(), "    1  other\n");
+        assert!(tools.read_file(Path::new("other.txt")).is_err());
+
+        // Neither `..` nor an absolute path gets out
+        let escaped = tools.read_file(Path::new("sub/../../outside/secret.txt")).unwrap_err();
+        assert!(escaped.to_string().contains("outside the directories"), "{}", escaped);
+        assert!(tools.read_file(&outside.join("secret.txt")).unwrap_err().to_string().contains("outside the directories"));
+        // Nor does a symlink out of one
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(outside.join("secret.txt"), first.join("link.txt")).unwrap();
+            assert!(tools.read_file(Path::new("link.txt")).unwrap_err().to_string().contains("outside the directories"));
+        }
+
+        std::fs::write(first.join("image.bin"), [0xff, 0xfe, 0x00, 0x80]).unwrap();
+        assert!(tools.read_file(Path::new("image.bin")).unwrap_err().to_string().contains("not a text file"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn read_file_truncates_long_files() {
+        let directory = std::env::temp_dir().join(format!("hotline-read-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let tools = Tools::new(std::slice::from_ref(&directory), false, false).unwrap();
+
+        // Cut at a number of lines
+        let lines: String = (1..=MAX_FILE_LINES + 100).map(|n| format!("line {}\n", n)).collect();
+        std::fs::write(directory.join("long.txt"), lines).unwrap();
+        let read = tools.read_file(Path::new("long.txt")).unwrap();
+        assert_eq!(read.lines().count(), MAX_FILE_LINES + 1);
+        assert!(read.contains(&format!("{:>5}  line {}\n", MAX_FILE_LINES, MAX_FILE_LINES)));
+        assert!(read.ends_with("[truncated, 100 more lines]\n"));
+
+        // Or at a number of characters, after the line that reaches it
+        let wide: String = (0..100).map(|_| format!("{}\n", "x".repeat(1000))).collect();
+        std::fs::write(directory.join("wide.txt"), wide).unwrap();
+        let read = tools.read_file(Path::new("wide.txt")).unwrap();
+        let shown = MAX_FILE_CHARS.div_ceil(1000 + 8);
+        assert_eq!(read.lines().count(), shown + 1);
+        assert!(read.ends_with(&format!("[truncated, {} more lines]\n", 100 - shown)));
+
+        // A short file is whole
+        std::fs::write(directory.join("short.txt"), "just this").unwrap();
+        assert!(!tools.read_file(Path::new("short.txt")).unwrap().contains("truncated"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn definition_problems_point_at_what_the_api_would_refuse() {
+        let definition = json!({
+            "name": "look up",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "query": {"type": "text"},
+                    "filters": {"type": "array", "items": {"type": ["string", "date"]}},
+                    "sort": {"enum": []},
+                    "limit": 10
+                },
+                "required": ["query", "page"]
+            }
+        });
+        assert_eq!(
+            definition_problems(&definition),
+            [
+                "function name \"look up\" must be 1 to 64 letters, digits, _ or -",
+                "look up.parameters.filters[]: unknown type \"date\"",
+                "look up.parameters.limit: a schema must be an object",
+                "look up.parameters.query: unknown type \"text\"",
+                "look up.parameters.sort: enum must be a list of values",
+                "look up.parameters: required \"page\" isn't one of the properties",
+            ]
+        );
+
+        assert_eq!(definition_problems(&json!({"name": "ping", "parameters": {"type": "string"}})), ["ping: parameters must be a schema of type object"]);
+        assert_eq!(definition_problems(&json!({"name": "ping"})), ["ping: parameters must be a schema of type object", "ping.parameters: a schema must be an object"]);
+        assert!(definition_problems(&json!({"name": "ping", "parameters": {"type": "object", "properties": {}}})).is_empty());
+    }
+
+    #[test]
+    fn quick_actions_come_from_suggest_actions_calls_only() {
+        let tools = Tools::new(&[], false, true).unwrap();
+        let call = |name: &str, arguments: Value| ToolCall { call_id: "call_1".to_string(), name: name.to_string(), arguments: arguments.to_string() };
+
+        // Trimmed, and no more than can be picked with one key
+        let actions: Vec<String> = (1..=12).map(|n| format!(" Option {} ", n)).collect();
+        let offered = tools.quick_actions(&call("suggest_actions", json!({"actions": actions}))).unwrap();
+        assert_eq!(offered.len(), MAX_QUICK_ACTIONS);
+        assert_eq!(offered[0], "Option 1");
+        assert_eq!(tools.definitions()[0]["parameters"]["properties"]["actions"]["maxItems"], MAX_QUICK_ACTIONS);
+
+        // Blank ones and anything but text are left out
+        assert_eq!(tools.quick_actions(&call("suggest_actions", json!({"actions": ["", " ", 3, "Yes"]}))), Some(vec!["Yes".to_string()]));
+        assert_eq!(tools.quick_actions(&call("suggest_actions", json!({"actions": []}))), None);
+        assert_eq!(tools.call(&call("suggest_actions", json!({"actions": []}))), "Error: the actions argument needs at least one reply");
+        assert_eq!(tools.call(&call("suggest_actions", json!({"actions": ["Yes", "No"]}))), "Shown to the user as 2 numbered quick actions");
+
+        assert_eq!(tools.quick_actions(&call("send_dtmf", json!({"actions": ["Yes"]}))), None);
+        assert_eq!(Tools::new(&[], false, false).unwrap().quick_actions(&call("suggest_actions", json!({"actions": ["Yes"]}))), None);
+    }
+}
@@ -0,0 +1,342 @@
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::audio_utils::{base64_encode_audio, convert_audio_to_server, normalize_loudness, read_audio, SERVER_SAMPLE_RATE};
+use crate::client::{build_event, realtime_request, DEFAULT_MODEL, DEFAULT_URL};
+
+// Audio is appended in chunks of this many server samples, 100 ms
+const CHUNK_SAMPLES: usize = SERVER_SAMPLE_RATE as usize / 10;
+// Trailing silence so the server VAD closes the last segment
+const TRAILING_SILENCE: usize = SERVER_SAMPLE_RATE as usize * 2;
+// Give up on outstanding transcriptions when the server goes quiet for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Which files to write next to each input
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TranscriptFormat {
+    Txt,
+    Srt,
+    Both,
+}
+
+/// A stretch of speech found by the server VAD
+#[derive(Debug, Default)]
+struct Segment {
+    start_ms: u64,
+    end_ms: u64,
+    text: Option<String>,   // None until transcribed
+}
+
+/// The segments of a file as the server finds and transcribes them, by item id
+#[derive(Debug, Default)]
+struct Segments {
+    segments: HashMap<String, Segment>,
+    caught_up: bool,        // The server went through all the audio
+}
+
+impl Segments {
+    /// Takes a server event, returning whether it changed anything
+    fn handle_event(&mut self, event: &Value) -> Result<bool, String> {
+        let item_id = event["item_id"].as_str().unwrap_or_default().to_string();
+        match event["type"].as_str().unwrap_or_default() {
+            "input_audio_buffer.speech_started" => {
+                self.segments.entry(item_id).or_default().start_ms = event["audio_start_ms"].as_u64().unwrap_or_default();
+            }
+            "input_audio_buffer.speech_stopped" => {
+                self.segments.entry(item_id).or_default().end_ms = event["audio_end_ms"].as_u64().unwrap_or_default();
+            }
+            "conversation.item.input_audio_transcription.completed" => {
+                let transcript = event["transcript"].as_str().unwrap_or_default().trim().to_string();
+                self.segments.entry(item_id).or_default().text = Some(transcript);
+            }
+            "conversation.item.input_audio_transcription.failed" => {
+                eprintln!("A segment failed to transcribe: {}", event["error"]["message"]);
+                self.segments.entry(item_id).or_default().text = Some(String::new());
+            }
+            "input_audio_buffer.cleared" => self.caught_up = true,
+            "error" => return Err(format!("the server reported an error: {}", event["error"]["message"])),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Whether every segment of the audio is transcribed
+    fn done(&self) -> bool {
+        self.caught_up && self.outstanding() == 0
+    }
+
+    fn outstanding(&self) -> usize {
+        self.segments.values().filter(|segment| segment.text.is_none()).count()
+    }
+
+    /// How far into the audio the server found speech
+    fn position_ms(&self) -> u64 {
+        self.segments.values().map(|segment| segment.start_ms.max(segment.end_ms)).max().unwrap_or_default()
+    }
+
+    /// The segments with text, in the order they were spoken
+    fn into_transcribed(self) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = self.segments.into_values().filter(|segment| segment.text.as_ref().is_some_and(|text| !text.is_empty())).collect();
+        segments.sort_by_key(|segment| segment.start_ms);
+        segments
+    }
+}
+
+/// Transcribes audio files through realtime sessions that never respond
+///
+/// Each file (WAV, FLAC, MP3 or Ogg Vorbis) is brought to a common loudness, resampled to the
+/// server rate and streamed into the input audio buffer of its own session, with server VAD
+/// splitting it into segments and response creation turned off, so only the input
+/// transcription events come back. Writes `<file>.txt` and/or `<file>.srt`.
+pub async fn transcribe(files: &[PathBuf], model: &str, format: TranscriptFormat, output_dir: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let api_key = std::env::var("OPENAI_API_KEY").map_err(|_| "OPENAI_API_KEY is not set")?;
+    let mut failures = 0;
+
+    for file in files {
+        let audio = match load(file) {
+            Ok(audio) => audio,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", file.display(), e);
+                failures += 1;
+                continue;
+            }
+        };
+
+        println!("Transcribing {} ({})...", file.display(), clock(duration_ms(audio.len())));
+        let segments = match transcribe_file(audio, &api_key, model).await {
+            Ok(segments) => segments,
+            Err(e) => {
+                eprintln!("Failed to transcribe {}: {}", file.display(), e);
+                failures += 1;
+                continue;
+            }
+        };
+
+        let base = match output_dir {
+            Some(dir) => dir.join(file.file_name().unwrap_or_default()),
+            None => file.clone(),
+        };
+        if format != TranscriptFormat::Srt {
+            write_output(&base, "txt", &text(&segments))?;
+        }
+        if format != TranscriptFormat::Txt {
+            write_output(&base, "srt", &srt(&segments))?;
+        }
+    }
+
+    if failures > 0 {
+        return Err(format!("{} of {} files failed", failures, files.len()).into());
+    }
+    Ok(())
+}
+
+fn write_output(base: &Path, extension: &str, contents: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = base.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    let path = PathBuf::from(path);
+
+    std::fs::write(&path, contents)?;
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+/// Reads a file as speech at a common loudness, mono at the server rate
+fn load(path: &Path) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    let (samples, sample_rate, channels) = read_audio(path)?;
+    let mut audio = convert_audio_to_server(&samples, sample_rate, channels);
+    normalize_loudness(&mut audio);
+    Ok(audio)
+}
+
+/// Streams one file's audio through a session and collects its transcribed segments in order
+///
+/// How far the server got through the audio is shown meanwhile, on a terminal.
+async fn transcribe_file(mut audio: Vec<f32>, api_key: &str, model: &str) -> Result<Vec<Segment>, Box<dyn std::error::Error>> {
+    let total_ms = duration_ms(audio.len());
+    audio.extend(std::iter::repeat_n(0.0, TRAILING_SILENCE));
+
+    let (ws_stream, _) = connect_async(realtime_request(DEFAULT_URL, api_key, DEFAULT_MODEL)?).await?;
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+
+    ws_write.send(client_event("session.update", Some(serde_json::json!({
+        "session": {
+            "modalities": ["text"],
+            "input_audio_transcription": {"model": model},
+            "turn_detection": {"type": "server_vad", "create_response": false},
+        }
+    })))?).await?;
+
+    // Writing and reading at once, or a long file would fill the socket buffers both ways.
+    // The final clear is answered once the server went through all the audio before it.
+    let mut writer = tokio::spawn(async move {
+        for chunk in audio.chunks(CHUNK_SAMPLES) {
+            ws_write.send(client_event("input_audio_buffer.append", Some(serde_json::json!({"audio": base64_encode_audio(chunk)})))?).await?;
+        }
+        ws_write.send(client_event("input_audio_buffer.clear", None)?).await?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(ws_write)
+    });
+
+    let mut segments = Segments::default();
+    let mut ws_write = None;    // Kept open until the last transcription arrives
+
+    loop {
+        if segments.done() {
+            break;
+        }
+
+        let message = tokio::select! {
+            result = &mut writer, if ws_write.is_none() => {
+                ws_write = Some(result?.map_err(|e| e.to_string())?);
+                continue;
+            }
+            message = timeout(IDLE_TIMEOUT, ws_read.next()) => message,
+        };
+        let message = match message {
+            Ok(Some(message)) => message?,
+            Ok(None) => return Err("the server closed the connection".into()),
+            Err(_) if segments.caught_up => {
+                eprintln!("Gave up waiting on {} segment(s)", segments.outstanding());
+                break;
+            }
+            Err(_) => continue,
+        };
+        let Message::Text(text) = message else { continue };
+        let event: Value = serde_json::from_str(&text)?;
+        if segments.handle_event(&event)? {
+            show_progress(segments.position_ms().min(total_ms), total_ms, segments.segments.len() - segments.outstanding());
+        }
+    }
+    if std::io::stdout().is_terminal() {
+        println!();
+    }
+
+    if let Some(mut ws_write) = ws_write {
+        let _ = ws_write.send(Message::Close(None)).await;
+    }
+
+    Ok(segments.into_transcribed())
+}
+
+fn client_event(event_type: &str, data: Option<Value>) -> Result<Message, String> {
+    Ok(Message::Text(build_event(event_type, data)?.to_string()))
+}
+
+fn text(segments: &[Segment]) -> String {
+    segments.iter().map(|segment| format!("{}\n", segment.text.as_deref().unwrap_or_default())).collect()
+}
+
+fn srt(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(index, segment)| {
+            format!(
+                "{}\n{} --> {}\n{}\n\n",
+                index + 1,
+                srt_time(segment.start_ms),
+                srt_time(segment.end_ms.max(segment.start_ms)),
+                segment.text.as_deref().unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+/// Overwrites the progress line, e.g. `  1:02 / 4:30, 12 segments transcribed`
+fn show_progress(position_ms: u64, total_ms: u64, transcribed: usize) {
+    let mut stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        return;
+    }
+    let _ = write!(stdout, "\r  {} / {}, {} segments transcribed", clock(position_ms), clock(total_ms), transcribed);
+    let _ = stdout.flush();
+}
+
+fn duration_ms(samples: usize) -> u64 {
+    samples as u64 * 1000 / SERVER_SAMPLE_RATE as u64
+}
+
+/// Formats milliseconds as minutes and seconds, e.g. 4:05
+fn clock(ms: u64) -> String {
+    format!("{}:{:02}", ms / 60_000, ms / 1000 % 60)
+}
+
+/// Formats milliseconds as an SRT timestamp, e.g. 00:01:02,345
+fn srt_time(ms: u64) -> String {
+    format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segment(start_ms: u64, end_ms: u64, text: &str) -> Segment {
+        Segment { start_ms, end_ms, text: Some(text.to_string()) }
+    }
+
+    #[test]
+    fn segments_are_collected_until_all_are_transcribed() {
+        let mut segments = Segments::default();
+        let events = [
+            json!({"type": "input_audio_buffer.speech_started", "item_id": "item_2", "audio_start_ms": 4200}),
+            json!({"type": "input_audio_buffer.speech_started", "item_id": "item_1", "audio_start_ms": 300}),
+            json!({"type": "input_audio_buffer.speech_stopped", "item_id": "item_1", "audio_end_ms": 2100}),
+            json!({"type": "conversation.item.input_audio_transcription.completed", "item_id": "item_1", "transcript": " Hello there. "}),
+            json!({"type": "input_audio_buffer.speech_stopped", "item_id": "item_2", "audio_end_ms": 5000}),
+            json!({"type": "input_audio_buffer.cleared"}),
+        ];
+        for event in &events {
+            assert!(segments.handle_event(event).unwrap());
+        }
+        assert!(!segments.handle_event(&json!({"type": "input_audio_buffer.committed", "item_id": "item_1"})).unwrap());
+        assert_eq!((segments.position_ms(), segments.outstanding()), (5000, 1));
+        assert!(!segments.done());
+
+        // A failed segment counts as done, and is left out
+        segments.handle_event(&json!({"type": "conversation.item.input_audio_transcription.failed", "item_id": "item_2", "error": {"message": "audio unclear"}})).unwrap();
+        assert!(segments.done());
+        let transcribed = segments.into_transcribed();
+        assert_eq!(transcribed.len(), 1);
+        assert_eq!((transcribed[0].start_ms, transcribed[0].end_ms, transcribed[0].text.as_deref()), (300, 2100, Some("Hello there.")));
+
+        let error = Segments::default().handle_event(&json!({"type": "error", "error": {"message": "invalid model"}})).unwrap_err();
+        assert_eq!(error, "the server reported an error: \"invalid model\"");
+    }
+
+    #[test]
+    fn segments_are_written_as_text_and_srt() {
+        let segments = [segment(300, 2100, "Hello there."), segment(61_005, 3_725_250, "Still here?"), segment(3_800_000, 0, "Cut off")];
+        assert_eq!(text(&segments), "Hello there.\nStill here?\nCut off\n");
+        assert_eq!(
+            srt(&segments),
+            "1\n00:00:00,300 --> 00:00:02,100\nHello there.\n\n\
+             2\n00:01:01,005 --> 01:02:05,250\nStill here?\n\n\
+             3\n01:03:20,000 --> 01:03:20,000\nCut off\n\n"
+        );
+        assert_eq!((clock(0), clock(245_999), clock(3_600_000)), ("0:00".to_string(), "4:05".to_string(), "60:00".to_string()));
+        assert_eq!(duration_ms(SERVER_SAMPLE_RATE as usize * 3 / 2), 1500);
+    }
+
+    #[test]
+    fn outputs_go_next_to_the_file_and_audio_is_loaded_at_the_server_rate() {
+        let directory = std::env::temp_dir().join(format!("hotline-transcribe-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        // The extension is added, so call.wav and call.mp3 don't share a transcript
+        write_output(&directory.join("call.wav"), "srt", "1\n").unwrap();
+        assert_eq!(std::fs::read_to_string(directory.join("call.wav.srt")).unwrap(), "1\n");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/audio");
+        let audio = load(&fixtures.join("tone_16k_stereo.wav")).unwrap();
+        assert_eq!(audio.len(), SERVER_SAMPLE_RATE as usize / 10);
+        assert!(load(&fixtures.join("not_audio.wav")).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use serde_json::Value;
+
+// Prices in USD per 1M tokens for gpt-4o-realtime-preview-2024-10-01
+const TEXT_INPUT_PRICE: f64 = 5.0;
+const TEXT_CACHED_INPUT_PRICE: f64 = 2.5;
+const TEXT_OUTPUT_PRICE: f64 = 20.0;
+const AUDIO_INPUT_PRICE: f64 = 100.0;
+const AUDIO_CACHED_INPUT_PRICE: f64 = 20.0;
+const AUDIO_OUTPUT_PRICE: f64 = 200.0;
+
+/// Hard limit on how much a session may spend
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    Usd(f64),
+    Tokens(u64),
+}
+
+/// Estimated cost of a single response, from the `usage` object of its `response.done` event
+pub fn response_cost(usage: &Value) -> f64 {
+    let mut tracker = UsageTracker::default();
+    tracker.add_response_usage(usage);
+    tracker.cost_usd()
+}
+
+/// Accumulates token usage reported in `response.done` events
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    text_input_tokens: u64,
+    text_cached_tokens: u64,
+    audio_input_tokens: u64,
+    audio_cached_tokens: u64,
+    text_output_tokens: u64,
+    audio_output_tokens: u64,
+    budget: Option<Budget>,
+}
+
+impl UsageTracker {
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = Some(budget);
+    }
+
+    /// Adds the `usage` object of a `response.done` event
+    pub fn add_response_usage(&mut self, usage: &Value) {
+        let tokens = |path: &str| usage.pointer(path).and_then(Value::as_u64).unwrap_or(0);
+
+        let text_cached = tokens("/input_token_details/cached_tokens_details/text_tokens");
+        let audio_cached = tokens("/input_token_details/cached_tokens_details/audio_tokens");
+
+        // Cached tokens are included in the input counts but billed at a lower rate
+        self.text_input_tokens += tokens("/input_token_details/text_tokens").saturating_sub(text_cached);
+        self.audio_input_tokens += tokens("/input_token_details/audio_tokens").saturating_sub(audio_cached);
+        self.text_cached_tokens += text_cached;
+        self.audio_cached_tokens += audio_cached;
+        self.text_output_tokens += tokens("/output_token_details/text_tokens");
+        self.audio_output_tokens += tokens("/output_token_details/audio_tokens");
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.text_input_tokens
+            + self.text_cached_tokens
+            + self.audio_input_tokens
+            + self.audio_cached_tokens
+            + self.text_output_tokens
+            + self.audio_output_tokens
+    }
+
+    /// Estimated cost of the session so far
+    pub fn cost_usd(&self) -> f64 {
+        let cost = self.text_input_tokens as f64 * TEXT_INPUT_PRICE
+            + self.text_cached_tokens as f64 * TEXT_CACHED_INPUT_PRICE
+            + self.audio_input_tokens as f64 * AUDIO_INPUT_PRICE
+            + self.audio_cached_tokens as f64 * AUDIO_CACHED_INPUT_PRICE
+            + self.text_output_tokens as f64 * TEXT_OUTPUT_PRICE
+            + self.audio_output_tokens as f64 * AUDIO_OUTPUT_PRICE;
+
+        cost / 1_000_000.0
+    }
+
+    /// Returns true once the budget (if any) has been used up
+    pub fn is_exceeded(&self) -> bool {
+        match self.budget {
+            Some(Budget::Usd(limit)) => self.cost_usd() >= limit,
+            Some(Budget::Tokens(limit)) => self.total_tokens() >= limit,
+            None => false,
+        }
+    }
+
+    /// Human readable summary of usage against the budget
+    pub fn summary(&self) -> String {
+        match self.budget {
+            Some(Budget::Usd(limit)) => format!("${:.4} of ${:.2} budget ({} tokens)", self.cost_usd(), limit, self.total_tokens()),
+            Some(Budget::Tokens(limit)) => format!("{} of {} token budget (${:.4})", self.total_tokens(), limit, self.cost_usd()),
+            None => format!("{} tokens (${:.4})", self.total_tokens(), self.cost_usd()),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::process::Command;
+
+/// Name used for the virtual device unless another is given
+pub const DEFAULT_NAME: &str = "hotline";
+
+/// Explains how to create a virtual microphone on this platform, or creates it on Linux
+///
+/// Playback mirrored to the virtual output comes out of a matching virtual input, which is
+/// what gets picked as the microphone in Zoom, Meet and the like.
+pub fn setup(name: &str, apply: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if apply && !cfg!(target_os = "linux") {
+        return Err("--apply is only supported on Linux, run without it for setup instructions".into());
+    }
+
+    if cfg!(target_os = "linux") {
+        let commands = [
+            vec!["load-module".to_string(), "module-null-sink".to_string(), format!("sink_name={}", name),
+                 format!("sink_properties=device.description={}", name)],
+            vec!["load-module".to_string(), "module-remap-source".to_string(), format!("master={}.monitor", name),
+                 format!("source_name={}_mic", name), format!("source_properties=device.description={}_mic", name)],
+        ];
+
+        if apply {
+            for args in &commands {
+                let status = Command::new("pactl").args(args).status()
+                    .map_err(|e| format!("Could not run pactl, is PulseAudio or PipeWire (pipewire-pulse) installed? {}", e))?;
+                if !status.success() {
+                    return Err(format!("pactl {} failed", args.join(" ")).into());
+                }
+            }
+            println!("Created the {} output and the {}_mic input.", name, name);
+        } else {
+            println!("Create a virtual device with PulseAudio or PipeWire (or rerun with --apply):\n");
+            for args in &commands {
+                println!("  pactl {}", args.join(" "));
+            }
+        }
+
+        println!("\nThen dial with --virtual-mic {} and pick \"{}_mic\" as the microphone in your video call.", name, name);
+        println!("The device lasts until the sound server restarts, `pactl unload-module module-null-sink` removes it.");
+    } else if cfg!(target_os = "macos") {
+        println!("Install BlackHole (https://existential.audio/blackhole/), for example with `brew install blackhole-2ch`.");
+        println!("\nThen dial with --virtual-mic BlackHole and pick \"BlackHole 2ch\" as the microphone in your video call.");
+    } else if cfg!(target_os = "windows") {
+        println!("Install VB-Cable (https://vb-audio.com/Cable/) and reboot.");
+        println!("\nThen dial with --virtual-mic \"CABLE Input\" and pick \"CABLE Output\" as the microphone in your video call.");
+    } else {
+        println!("No setup instructions for this platform, any loopback audio device works with --virtual-mic <name>.");
+    }
+
+    println!("Add --virtual-mic-mix to have your own voice in the call too.");
+    Ok(())
+}